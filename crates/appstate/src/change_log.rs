@@ -0,0 +1,110 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// What happened to an event in an event change notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventChange {
+    Created,
+    Updated,
+    Deleted,
+}
+
+/// One event change, numbered by the server so reconnecting clients can ask for what they missed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EventChangeRecord {
+    /// Increases by one with every change, starting at 1 when the server starts
+    pub seq: u64,
+    pub calendar_id: i64,
+    pub event_id: i64,
+    pub change: EventChange,
+}
+
+/// Ring buffer of the most recent event changes, oldest first.
+#[derive(Debug)]
+pub struct ChangeLog {
+    capacity: usize,
+    next_seq: u64,
+    changes: VecDeque<EventChangeRecord>,
+}
+
+impl ChangeLog {
+    /// An empty log keeping at most `capacity` changes.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            next_seq: 1,
+            changes: VecDeque::with_capacity(capacity),
+        }
+    }
+
+    /// Number the change and keep it, dropping the oldest change if the log is full.
+    pub fn record(
+        &mut self,
+        calendar_id: i64,
+        event_id: i64,
+        change: EventChange,
+    ) -> EventChangeRecord {
+        let record = EventChangeRecord {
+            seq: self.next_seq,
+            calendar_id,
+            event_id,
+            change,
+        };
+        self.next_seq += 1;
+        if self.capacity == 0 {
+            return record;
+        }
+        if self.changes.len() == self.capacity {
+            self.changes.pop_front();
+        }
+        self.changes.push_back(record);
+        record
+    }
+
+    /// The sequence number of the latest change, 0 if there hasn't been one.
+    pub fn latest_seq(&self) -> u64 {
+        self.next_seq - 1
+    }
+
+    /// Every change after `last_seq`, or `None` if some of them were already dropped (or
+    /// `last_seq` was never handed out, e.g. it's from before a restart). A client getting
+    /// `None` has to fetch its calendars again.
+    pub fn since(&self, last_seq: u64) -> Option<Vec<EventChangeRecord>> {
+        let oldest_kept = self.next_seq - self.changes.len() as u64;
+        if last_seq > self.latest_seq() || last_seq + 1 < oldest_kept {
+            return None;
+        }
+        Some(
+            self.changes
+                .iter()
+                .filter(|record| record.seq > last_seq)
+                .copied()
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_since_returns_only_missed_changes() {
+        let mut log = ChangeLog::new(3);
+        assert_eq!(log.since(0), Some(vec![]));
+        for event_id in 1..=3 {
+            log.record(7, event_id, EventChange::Updated);
+        }
+        let missed: Vec<i64> = log.since(1).unwrap().iter().map(|r| r.event_id).collect();
+        assert_eq!(missed, vec![2, 3]);
+        assert_eq!(log.since(3), Some(vec![]));
+
+        // Change 1 falls out of the buffer, so a client that saw nothing can't catch up
+        log.record(7, 4, EventChange::Deleted);
+        assert_eq!(log.since(0), None);
+        assert_eq!(log.since(1).unwrap().len(), 3);
+        // Nor can one ahead of the server
+        assert_eq!(log.since(5), None);
+    }
+}
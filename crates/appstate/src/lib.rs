@@ -1,17 +1,29 @@
-use axum::extract::ws::Message;
+use axum::extract::ws::{CloseFrame, Message, close_code};
 use config::Config;
 use db;
-use permissions;
-use std::collections::HashMap;
+use permissions::{self, UserId};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 use tokio::{sync::Mutex, sync::broadcast, sync::mpsc::UnboundedSender, task::JoinHandle};
+use tokio_util::sync::CancellationToken;
 use uuid::Uuid;
 
+pub mod change_log;
+pub mod metrics;
+
+pub use change_log::{ChangeLog, EventChange, EventChangeRecord};
+pub use metrics::{Metrics, MetricsSnapshot};
+
 #[derive(Clone)]
 pub struct AppState {
     pub config: Arc<Mutex<Config>>,
-    /// Database connection, initialized at startup
-    pub database: Arc<tokio::sync::Mutex<db::DatabaseConnection>>,
+    /// Database connection pool, initialized at startup
+    pub database: db::DbPool,
+    /// Authentication service (registration, login, tokens), shares the database pool
+    pub auth: Arc<auth::AuthService>,
     /// Permissions manager, initialized at startup (wrapped in Arc for Clone)
     pub permissions: Arc<permissions::PermissionsManager<permissions::DbPermissionBackend>>,
     /// Join handles for long-lived tasks (not meant to exit until app shutdown)
@@ -20,79 +32,547 @@ pub struct AppState {
     pub temp_join_handles: Arc<Mutex<HashMap<usize, JoinHandle<()>>>>,
     /// Next id for temporary tasks
     pub next_temp_id: Arc<Mutex<usize>>,
-    /// Global broadcast channel for messaging (binary)
-    pub global_sender: broadcast::Sender<Vec<u8>>,
+    /// Global broadcast channel for messages to every connection, encoded by each connection
+    pub global_sender: broadcast::Sender<Arc<GlobalMessage>>,
     /// Active websocket connections, keyed by UUID
     pub connections: Arc<Mutex<HashMap<Uuid, ConnectionInfo>>>,
+    /// How many websocket connections may be registered at once
+    pub max_connections: usize,
+    /// Average messages per second each connection may send, 0 for no limit
+    pub messages_per_second: u32,
+    /// Messages a connection may send at once before `messages_per_second` applies
+    pub message_burst: u32,
+    /// Binary messages to clients at least this many bytes are compressed, 0 to never compress
+    pub compression_threshold: usize,
+    /// The only colors calendars may use, from `calendars.allowed_palette`, `None` for any color
+    pub calendar_palette: Option<Arc<db::ColorPalette>>,
+    /// Connections subscribed to each calendar's event changes, keyed by calendar id
+    pub subscriptions: Arc<Mutex<HashMap<i64, HashSet<Uuid>>>>,
+    /// Recent event changes, numbered, for clients resuming after a reconnect
+    pub change_log: Arc<Mutex<ChangeLog>>,
+    /// Connection, message and auth failure counters, served at `/metrics`
+    pub metrics: Arc<Metrics>,
+    /// Cancelled by `shutdown()`, long-lived tasks (like the web server) stop when it fires
+    pub shutdown_token: CancellationToken,
+}
+
+/// Why a websocket connection couldn't be registered.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConnectionError {
+    /// `max_connections` connections are already open
+    AtCapacity { max_connections: usize },
+}
+
+impl fmt::Display for ConnectionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConnectionError::AtCapacity { max_connections } => {
+                write!(f, "connection limit of {max_connections} reached")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConnectionError {}
+
+/// A message for every websocket connection, sent through the global broadcast channel.
+/// Stays typed until the websocket edge encodes it for the wire.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GlobalMessage {
+    /// Text a client broadcast to everyone.
+    Broadcast { text: String },
+    /// A user's permissions changed.
+    PermissionChanged { user_id: UserId },
+    /// A user's first connection opened (`online`) or their last one closed.
+    PresenceChanged { user_id: UserId, online: bool },
+}
+
+/// Whether any of the connections belongs to the user.
+fn is_online(conns: &HashMap<Uuid, ConnectionInfo>, user_id: UserId) -> bool {
+    conns.values().any(|conn| conn.user_id == Some(user_id))
+}
+
+/// The configured palette, logging and leaving out entries that aren't colors.
+fn parse_palette(entries: &[String]) -> db::ColorPalette {
+    let colors = entries
+        .iter()
+        .filter_map(|entry| {
+            db::parse_calendar_color(entry)
+                .inspect_err(|e| tracing::warn!("Ignoring calendars.allowed_palette entry: {e}"))
+                .ok()
+        })
+        .collect();
+    db::ColorPalette::new(colors)
 }
 
 pub struct ConnectionInfo {
     pub sender: UnboundedSender<Message>,
+    /// The user the connection authenticated as, `None` until it has
+    pub user_id: Option<UserId>,
+    /// Limits how fast the client may send messages, `None` if it may send as fast as it likes
+    pub rate_limit: Option<TokenBucket>,
+    /// When the access token the connection authenticated with expires (unix seconds),
+    /// `None` until it has authenticated with one
+    pub token_expires_at: Option<u64>,
+    /// How the client encodes its messages, messages pushed to it are encoded the same way
+    pub encoding: WireEncoding,
+}
+
+/// How a websocket client's messages are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WireEncoding {
+    /// MessagePack in binary frames, assumed until the client sends something
+    #[default]
+    MessagePack,
+    /// JSON in text frames, for clients without MessagePack
+    Json,
+}
+
+/// A message for one or more connections.
+#[derive(Debug, Clone)]
+pub enum Outgoing {
+    /// The same frame for every connection (pings, closes, pre-encoded messages)
+    Frame(Message),
+    /// A message encoded both ways, each connection gets the one matching its `encoding`
+    Encoded { binary: Message, text: Message },
+}
+
+impl Outgoing {
+    /// The frame to send to a connection using `encoding`.
+    pub fn for_encoding(&self, encoding: WireEncoding) -> Message {
+        match (self, encoding) {
+            (Outgoing::Frame(msg), _) => msg.clone(),
+            (Outgoing::Encoded { binary, .. }, WireEncoding::MessagePack) => binary.clone(),
+            (Outgoing::Encoded { text, .. }, WireEncoding::Json) => text.clone(),
+        }
+    }
+}
+
+impl From<Message> for Outgoing {
+    fn from(msg: Message) -> Self {
+        Outgoing::Frame(msg)
+    }
+}
+
+impl ConnectionInfo {
+    /// Queue a message for the connection in its encoding, returning false if it's no longer
+    /// receiving.
+    fn send(&self, msg: &Outgoing) -> bool {
+        self.sender.send(msg.for_encoding(self.encoding)).is_ok()
+    }
+}
+
+/// Token bucket rate limiter: holds up to `burst` tokens, refilled at `per_second` tokens a
+/// second, and each message takes one.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    burst: f64,
+    per_second: f64,
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl TokenBucket {
+    /// A full bucket.
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        Self::new_at(per_second, burst, Instant::now())
+    }
+
+    /// A bucket that was full at `now`.
+    pub fn new_at(per_second: u32, burst: u32, now: Instant) -> Self {
+        // A burst below one would never let anything through
+        let burst = f64::from(burst.max(1));
+        Self {
+            burst,
+            per_second: f64::from(per_second),
+            tokens: burst,
+            refilled_at: now,
+        }
+    }
+
+    /// Take a token if there is one.
+    pub fn try_acquire(&mut self) -> bool {
+        self.try_acquire_at(Instant::now())
+    }
+
+    /// Take a token if there is one at `now`, which must not be before earlier calls.
+    pub fn try_acquire_at(&mut self, now: Instant) -> bool {
+        self.tokens = self.tokens_at(now);
+        self.refilled_at = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// How long after the last `try_acquire` until a token is available, `None` if it
+    /// never will be (a rate of 0).
+    pub fn retry_after(&self) -> Option<Duration> {
+        if self.tokens >= 1.0 {
+            return Some(Duration::ZERO);
+        }
+        (self.per_second > 0.0)
+            .then(|| Duration::from_secs_f64((1.0 - self.tokens) / self.per_second))
+    }
+
+    /// Whether the bucket has refilled completely by `now`, so forgetting it changes nothing.
+    pub fn is_full_at(&self, now: Instant) -> bool {
+        self.tokens_at(now) >= self.burst
+    }
+
+    fn tokens_at(&self, now: Instant) -> f64 {
+        let elapsed = now.saturating_duration_since(self.refilled_at);
+        (self.tokens + elapsed.as_secs_f64() * self.per_second).min(self.burst)
+    }
 }
 
 impl AppState {
     /// Create a new AppState with initialized database and permissions system.
     pub fn new(config: Config) -> Self {
-        let (global_sender, _) = broadcast::channel(1024);
-
-        // Initialize database connection and run all schema initialization
+        // Initialize the database pool and run all schema initialization
         let db_path = std::path::Path::new(&config.database.path);
-        let database =
-            db::DatabaseConnection::from_path(db_path).expect("Failed to initialize database");
-        let database = Arc::new(tokio::sync::Mutex::new(database));
+        let database = db::DbPool::new(db_path, config.database.pool_size)
+            .expect("Failed to initialize database");
+        Self::from_parts(config, database)
+    }
+
+    /// Create an AppState over a fresh in-memory database, ignoring `config.database.path`.
+    /// Nothing touches the filesystem and every call gets its own empty database, so tests can
+    /// build as many as they like.
+    pub fn new_in_memory(config: Config) -> Self {
+        let database = db::DbPool::new_in_memory(config.database.pool_size)
+            .expect("Failed to initialize in-memory database");
+        Self::from_parts(config, database)
+    }
+
+    /// Create an AppState around an already opened database pool (e.g. an in-memory one for tests).
+    pub fn from_parts(config: Config, database: db::DbPool) -> Self {
+        let (global_sender, _) = broadcast::channel(config.websocket.broadcast_capacity);
+
+        let auth = Arc::new(
+            auth::AuthService::new(
+                database.clone(),
+                config.auth.jwt_secret.clone(),
+                Some(config.auth.jwt_expiry_seconds),
+                None,
+                auth::HashingMode::default(),
+                auth::LockoutPolicy::default(),
+                None,
+                None,
+            )
+            .with_email_verification_required(config.auth.require_email_verification),
+        );
 
         // Initialize permissions system using the database backend
         let permissions_backend = permissions::DbPermissionBackend::new(database.clone());
         let permissions = Arc::new(permissions::PermissionsManager::new(permissions_backend));
 
+        let max_connections = config.websocket.max_connections;
+        let messages_per_second = config.websocket.messages_per_second;
+        let message_burst = config.websocket.message_burst;
+        let compression_threshold = config.websocket.compression_threshold;
+        let change_log = ChangeLog::new(config.websocket.change_log_capacity);
+        let calendar_palette = config
+            .calendars
+            .allowed_palette
+            .as_deref()
+            .map(|entries| Arc::new(parse_palette(entries)));
         AppState {
             config: Arc::new(Mutex::new(config)),
             database,
+            auth,
             permissions,
             join_handles: Arc::new(Mutex::new(Vec::new())),
             temp_join_handles: Arc::new(Mutex::new(HashMap::new())),
             next_temp_id: Arc::new(Mutex::new(0)),
             global_sender,
             connections: Arc::new(Mutex::new(HashMap::new())),
+            max_connections,
+            messages_per_second,
+            message_burst,
+            compression_threshold,
+            calendar_palette,
+            subscriptions: Arc::new(Mutex::new(HashMap::new())),
+            change_log: Arc::new(Mutex::new(change_log)),
+            metrics: Arc::new(Metrics::default()),
+            shutdown_token: CancellationToken::new(),
         }
     }
 
+    /// Parse a calendar color a user supplied (`#RGB`, `#RRGGBB` or a CSS name), rejecting it
+    /// if the deployment restricts calendars to a palette it isn't in.
+    pub fn parse_calendar_color(&self, input: &str) -> Result<colorlab::Color, db::ColorError> {
+        match &self.calendar_palette {
+            Some(palette) => palette.parse_color(input),
+            None => db::parse_calendar_color(input),
+        }
+    }
+
+    /// Begin a graceful shutdown: tell every websocket client the server is going away,
+    /// then cancel `shutdown_token` so the web server stops accepting and drains.
+    pub async fn shutdown(&self) {
+        let conns = self.connections.lock().await;
+        for conn in conns.values() {
+            let _ = conn.sender.send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "server shutting down".into(),
+            })));
+        }
+        drop(conns);
+        self.shutdown_token.cancel();
+    }
+
+    /// Shut down gracefully (see `shutdown`) once `signal` resolves to the name of the signal
+    /// that asked for it, normally `shutdown_signal()`.
+    pub async fn shutdown_on(&self, signal: impl Future<Output = &'static str>) {
+        let name = signal.await;
+        tracing::info!("Received {name}, shutting down...");
+        self.shutdown().await;
+    }
+
+    /// Keep `config` in sync with the file at `path`, swapping in each successfully parsed change.
+    /// Settings only read at startup (like the listen address) still need a restart.
+    /// Watching stops when the returned watcher is dropped.
+    pub fn watch_config(
+        &self,
+        path: &std::path::Path,
+    ) -> Result<config::ConfigWatcher, config::ConfigError> {
+        let shared = self.config.clone();
+        Config::watch(path, move |conf| {
+            // Called on the watcher's own thread, outside the async runtime
+            *shared.blocking_lock() = conf;
+        })
+    }
+
     /// Add a list of join handles to the app state's join_handles list.
     pub async fn add_join_handles(&self, handles: Vec<tokio::task::JoinHandle<()>>) {
         let mut guard = self.join_handles.lock().await;
         guard.extend(handles);
     }
 
-    /// Register a new connection and return its UUID.
-    pub async fn register_connection(&self, sender: UnboundedSender<Message>) -> Uuid {
+    /// Whether another connection could be registered right now.
+    pub async fn has_connection_capacity(&self) -> bool {
+        self.connections.lock().await.len() < self.max_connections
+    }
+
+    /// Register a new connection and return its UUID, or an error if `max_connections` are already open.
+    pub async fn register_connection(
+        &self,
+        sender: UnboundedSender<Message>,
+    ) -> Result<Uuid, ConnectionError> {
         let uuid = Uuid::new_v4();
         let mut conns = self.connections.lock().await;
-        conns.insert(uuid, ConnectionInfo { sender });
-        uuid
+        if conns.len() >= self.max_connections {
+            return Err(ConnectionError::AtCapacity {
+                max_connections: self.max_connections,
+            });
+        }
+        conns.insert(
+            uuid,
+            ConnectionInfo {
+                sender,
+                user_id: None,
+                rate_limit: (self.messages_per_second > 0)
+                    .then(|| TokenBucket::new(self.messages_per_second, self.message_burst)),
+                token_expires_at: None,
+                encoding: WireEncoding::default(),
+            },
+        );
+        self.metrics.connection_opened();
+        Ok(uuid)
+    }
+
+    /// Count a message received on a connection against its rate limit, returning whether it
+    /// may be handled. Connections that aren't registered aren't limited.
+    pub async fn allow_message(&self, uuid: &Uuid) -> bool {
+        let mut conns = self.connections.lock().await;
+        match conns
+            .get_mut(uuid)
+            .and_then(|conn| conn.rate_limit.as_mut())
+        {
+            Some(bucket) => bucket.try_acquire(),
+            None => true,
+        }
+    }
+
+    /// Record how a connection's client encodes its messages, so pushes to it match.
+    pub async fn set_connection_encoding(&self, uuid: &Uuid, encoding: WireEncoding) {
+        if let Some(conn) = self.connections.lock().await.get_mut(uuid) {
+            conn.encoding = encoding;
+        }
+    }
+
+    /// How a connection's client encodes its messages, the default if there's no such connection.
+    pub async fn connection_encoding(&self, uuid: &Uuid) -> WireEncoding {
+        let conns = self.connections.lock().await;
+        conns
+            .get(uuid)
+            .map(|conn| conn.encoding)
+            .unwrap_or_default()
     }
 
-    /// Remove a connection by UUID.
+    /// Remove a connection by UUID, along with its calendar subscriptions.
+    /// Announces the user went offline if it was their last connection.
     pub async fn remove_connection(&self, uuid: &Uuid) {
         let mut conns = self.connections.lock().await;
-        conns.remove(uuid);
+        if let Some(removed) = conns.remove(uuid) {
+            self.metrics.connection_closed();
+            if let Some(user_id) = removed.user_id
+                && !is_online(&conns, user_id)
+            {
+                self.announce_presence(user_id, false);
+            }
+        }
+        drop(conns);
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.retain(|_, subscribers| {
+            subscribers.remove(uuid);
+            !subscribers.is_empty()
+        });
+    }
+
+    /// Subscribe a connection to a calendar's event changes.
+    pub async fn subscribe_to_calendar(&self, uuid: Uuid, calendar_id: i64) {
+        let mut subscriptions = self.subscriptions.lock().await;
+        subscriptions.entry(calendar_id).or_default().insert(uuid);
+    }
+
+    /// The calendars a connection is subscribed to.
+    pub async fn subscribed_calendars(&self, uuid: &Uuid) -> HashSet<i64> {
+        let subscriptions = self.subscriptions.lock().await;
+        subscriptions
+            .iter()
+            .filter(|(_, subscribers)| subscribers.contains(uuid))
+            .map(|(calendar_id, _)| *calendar_id)
+            .collect()
+    }
+
+    /// Send a message to the connections subscribed to a calendar, returning how many it reached.
+    pub async fn notify_calendar(&self, calendar_id: i64, msg: impl Into<Outgoing>) -> usize {
+        let subscribers: Vec<Uuid> = match self.subscriptions.lock().await.get(&calendar_id) {
+            Some(subscribers) => subscribers.iter().copied().collect(),
+            None => return 0,
+        };
+        self.send_to_connections(&subscribers, msg).await
+    }
+
+    /// Send a message to one connection. Returns false if there's no such connection
+    /// (or it's already shutting down and no longer receiving).
+    pub async fn send_to_connection(&self, uuid: &Uuid, msg: impl Into<Outgoing>) -> bool {
+        let msg = msg.into();
+        let conns = self.connections.lock().await;
+        conns.get(uuid).is_some_and(|conn| conn.send(&msg))
+    }
+
+    /// Send a message to each of the given connections, returning how many it reached.
+    /// Unknown UUIDs are skipped.
+    pub async fn send_to_connections(&self, uuids: &[Uuid], msg: impl Into<Outgoing>) -> usize {
+        let msg = msg.into();
+        let conns = self.connections.lock().await;
+        uuids
+            .iter()
+            .filter_map(|uuid| conns.get(uuid))
+            .filter(|conn| conn.send(&msg))
+            .count()
+    }
+
+    /// Record which user a connection belongs to, once it has authenticated.
+    /// Announces the user came online if it's their first connection.
+    /// Returns false if there's no such connection.
+    pub async fn associate_user(&self, uuid: &Uuid, user_id: UserId) -> bool {
+        let mut conns = self.connections.lock().await;
+        let Some(conn) = conns.get_mut(uuid) else {
+            return false;
+        };
+        let previous = conn.user_id.replace(user_id);
+        if previous == Some(user_id) {
+            return true;
+        }
+        // Presence is announced under the lock so the edges go out in the order they happen
+        if let Some(previous) = previous
+            && !is_online(&conns, previous)
+        {
+            self.announce_presence(previous, false);
+        }
+        let connections_of_user = conns
+            .values()
+            .filter(|conn| conn.user_id == Some(user_id))
+            .count();
+        if connections_of_user == 1 {
+            self.announce_presence(user_id, true);
+        }
+        true
+    }
+
+    /// The user a connection authenticated as, `None` if it hasn't (or there's no such connection).
+    pub async fn connection_user(&self, uuid: &Uuid) -> Option<UserId> {
+        let conns = self.connections.lock().await;
+        conns.get(uuid).and_then(|conn| conn.user_id)
+    }
+
+    /// Record when the access token a connection authenticated (or re-authenticated) with
+    /// expires. Returns false if there's no such connection.
+    pub async fn set_token_expiry(&self, uuid: &Uuid, expires_at: u64) -> bool {
+        let mut conns = self.connections.lock().await;
+        match conns.get_mut(uuid) {
+            Some(conn) => {
+                conn.token_expires_at = Some(expires_at);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// When a connection's access token expires, `None` if it has no token (or there's no such
+    /// connection).
+    pub async fn token_expiry(&self, uuid: &Uuid) -> Option<u64> {
+        let conns = self.connections.lock().await;
+        conns.get(uuid).and_then(|conn| conn.token_expires_at)
+    }
+
+    /// The users with at least one open connection, in ascending order.
+    pub async fn online_users(&self) -> Vec<UserId> {
+        let conns = self.connections.lock().await;
+        let users: BTreeSet<UserId> = conns.values().filter_map(|conn| conn.user_id).collect();
+        users.into_iter().collect()
+    }
+
+    fn announce_presence(&self, user_id: UserId, online: bool) {
+        // Nobody listening just means nobody is connected to care
+        let _ = self.send_global_message(GlobalMessage::PresenceChanged { user_id, online });
+    }
+
+    /// Send a message to every connection of a user (all their devices/tabs),
+    /// returning how many connections it reached.
+    pub async fn send_to_user(&self, user_id: UserId, msg: impl Into<Outgoing>) -> usize {
+        let msg = msg.into();
+        let conns = self.connections.lock().await;
+        conns
+            .values()
+            .filter(|conn| conn.user_id == Some(user_id))
+            .filter(|conn| conn.send(&msg))
+            .count()
     }
 
     /// Send a message to the global broadcast channel.
     pub fn send_global_message(
         &self,
-        msg: Vec<u8>,
-    ) -> Result<usize, broadcast::error::SendError<Vec<u8>>> {
-        self.global_sender.send(msg)
+        msg: GlobalMessage,
+    ) -> Result<usize, broadcast::error::SendError<Arc<GlobalMessage>>> {
+        self.global_sender.send(Arc::new(msg))
     }
 
     /// Subscribe to the global broadcast channel.
-    pub fn subscribe_global_messages(&self) -> broadcast::Receiver<Vec<u8>> {
+    pub fn subscribe_global_messages(&self) -> broadcast::Receiver<Arc<GlobalMessage>> {
         self.global_sender.subscribe()
     }
 
-    /// Add a list of join handles to the app state's temp_join_handles list.
     /// Add a list of join handles to the app state's temp_join_handles HashMap, assigning unique ids.
+    /// Finished tasks are reaped first so the map only grows with tasks that are still running.
     pub async fn add_temp_join_handles(&self, handles: Vec<tokio::task::JoinHandle<()>>) {
+        self.reap_finished_temp_tasks().await;
         let mut guard = self.temp_join_handles.lock().await;
         let mut id_guard = self.next_temp_id.lock().await;
         for handle in handles {
@@ -100,9 +580,59 @@ impl AppState {
             *id_guard += 1;
         }
     }
+
+    /// Drop the temp_join_handles entries of tasks that have finished, logging any that panicked.
+    /// Returns how many were removed.
+    pub async fn reap_finished_temp_tasks(&self) -> usize {
+        let mut guard = self.temp_join_handles.lock().await;
+        let finished: Vec<usize> = guard
+            .iter()
+            .filter(|(_, handle)| handle.is_finished())
+            .map(|(id, _)| *id)
+            .collect();
+        for id in &finished {
+            if let Some(handle) = guard.remove(id) {
+                // Already finished, so this resolves immediately
+                if let Err(e) = handle.await
+                    && e.is_panic()
+                {
+                    tracing::error!("Temporary task {id} panicked: {e:?}");
+                }
+            }
+        }
+        finished.len()
+    }
+}
+
+/// Resolve once the process is asked to stop: Ctrl-C (SIGINT), or on Unix SIGTERM (what
+/// `docker stop` and systemd send). Returns the signal's name for logging.
+pub async fn shutdown_signal() -> &'static str {
+    let ctrl_c = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            tracing::warn!("Can't listen for Ctrl-C: {e}");
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                return tokio::select! {
+                    _ = ctrl_c => "SIGINT",
+                    _ = sigterm.recv() => "SIGTERM",
+                };
+            }
+            Err(e) => tracing::warn!("Can't listen for SIGTERM: {e}"),
+        }
+    }
+    ctrl_c.await;
+    "SIGINT"
 }
 
 /// Macro to await any join handle in AppState, aborting others and logging on exit.
+/// If `shutdown_token` was cancelled by then, the others were told to stop too, so they're
+/// awaited instead of aborted.
 /// Usage: await_any_task!(appstate);
 #[macro_export]
 macro_rules! await_any_task {
@@ -143,6 +673,18 @@ macro_rules! await_any_task {
 
             // Wait for the first task to finish
             if let Some((idx, res)) = rx.recv().await {
+                if $appstate.shutdown_token.is_cancelled() {
+                    use tracing::info;
+                    let mut next = Some((idx, res));
+                    while let Some((idx, res)) = next {
+                        match res {
+                            Ok(_) => info!("Task {} exited normally", idx),
+                            Err(e) => error!("Task {} exited with error: {:?}", idx, e),
+                        }
+                        next = rx.recv().await;
+                    }
+                    return;
+                }
                 match res {
                     Ok(_) => error!("Task {} exited normally", idx),
                     Err(e) => error!("Task {} exited with error: {:?}", idx, e),
@@ -162,6 +704,27 @@ macro_rules! await_any_task {
     };
 }
 
+/// Macro to await every join handle in AppState, logging each task's exit without aborting any.
+/// Use this for a clean shutdown, once the tasks have been told to stop (e.g. via `shutdown()`).
+/// Usage: await_all_tasks!(appstate);
+#[macro_export]
+macro_rules! await_all_tasks {
+    ($appstate:expr) => {
+        async {
+            use tracing::{error, info};
+            let handles = $appstate.join_handles.clone();
+            // Move out the join handles so the lock isn't held while they run
+            let join_handles = std::mem::take(&mut *handles.lock().await);
+            for (idx, handle) in join_handles.into_iter().enumerate() {
+                match handle.await {
+                    Ok(_) => info!("Task {} exited normally", idx),
+                    Err(e) => error!("Task {} exited with error: {:?}", idx, e),
+                }
+            }
+        }
+    };
+}
+
 /// Macro to spawn tasks and track their JoinHandles in AppState.
 /// Usage:
 ///   spawn_tasks!(appstate, f1, f2, ...);
@@ -249,3 +812,447 @@ macro_rules! spawn_temporary_tasks {
        ct
     }};
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    fn test_state() -> AppState {
+        AppState::new_in_memory(Config::default())
+    }
+
+    #[test]
+    fn test_calendar_palette_from_config() {
+        assert!(test_state().parse_calendar_color("orange").is_ok());
+
+        let mut config = Config::default();
+        config.calendars.allowed_palette = Some(vec![
+            "#1A237E".to_string(),
+            "green".to_string(),
+            "not a color".to_string(),
+        ]);
+        let state = AppState::new_in_memory(config);
+        assert_eq!(state.calendar_palette.as_ref().unwrap().colors().len(), 2);
+        assert!(state.parse_calendar_color("#1a237e").is_ok());
+        assert!(state.parse_calendar_color("Green").is_ok());
+        assert_eq!(
+            state.parse_calendar_color("orange"),
+            Err(db::ColorError::NotInPalette("orange".to_string()))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connection_limit() {
+        let mut config = Config::default();
+        config.websocket.max_connections = 2;
+        let state = AppState::new_in_memory(config);
+        let (tx, _rx) = mpsc::unbounded_channel();
+
+        let first = state.register_connection(tx.clone()).await.unwrap();
+        state.register_connection(tx.clone()).await.unwrap();
+        assert!(!state.has_connection_capacity().await);
+        assert_eq!(
+            state.register_connection(tx.clone()).await,
+            Err(ConnectionError::AtCapacity { max_connections: 2 })
+        );
+
+        // Closing a connection frees a slot
+        state.remove_connection(&first).await;
+        assert!(state.has_connection_capacity().await);
+        assert!(state.register_connection(tx).await.is_ok());
+        assert_eq!(state.metrics.snapshot().active_connections, 2);
+        // Removing it again doesn't count twice
+        state.remove_connection(&first).await;
+        assert_eq!(state.metrics.snapshot().active_connections, 2);
+    }
+
+    fn text(msg: &str) -> Message {
+        Message::Text(msg.into())
+    }
+
+    #[tokio::test]
+    async fn test_finished_temp_tasks_are_reaped() {
+        let state = test_state();
+        let (done_tx, done_rx) = tokio::sync::oneshot::channel::<()>();
+        let quick = tokio::spawn(async {});
+        let slow = tokio::spawn(async move {
+            let _ = done_rx.await;
+        });
+        state.add_temp_join_handles(vec![quick, slow]).await;
+        assert_eq!(state.temp_join_handles.lock().await.len(), 2);
+
+        // The quick task finishes almost immediately, the slow one waits to be told
+        let reaped = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if state.reap_finished_temp_tasks().await == 1 {
+                    break;
+                }
+                tokio::task::yield_now().await;
+            }
+        })
+        .await;
+        assert!(reaped.is_ok(), "quick task was never reaped");
+        assert_eq!(state.temp_join_handles.lock().await.len(), 1);
+
+        done_tx.send(()).unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            while !state.temp_join_handles.lock().await.is_empty() {
+                state.reap_finished_temp_tasks().await;
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("slow task was never reaped");
+    }
+
+    #[tokio::test]
+    async fn test_await_all_tasks_waits_for_every_task() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let state = test_state();
+        let finished = Arc::new(AtomicUsize::new(0));
+        let handles = [30, 10, 20]
+            .into_iter()
+            .map(|millis| {
+                let finished = finished.clone();
+                tokio::spawn(async move {
+                    tokio::time::sleep(std::time::Duration::from_millis(millis)).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        state.add_join_handles(handles).await;
+
+        await_all_tasks!(state).await;
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+        assert!(state.join_handles.lock().await.is_empty());
+    }
+
+    #[test]
+    fn test_in_memory_state_registers_and_logs_in() {
+        let state = AppState::new_in_memory(Config::default());
+        let registration = state
+            .auth
+            .register_user("alice", "hunter2", None, "alice@example.com", "127.0.0.1")
+            .unwrap();
+        let token = registration.access_token.unwrap();
+        state.auth.validate_jwt(&token, "alice").unwrap();
+
+        let token = state
+            .auth
+            .authenticate_user("alice", "hunter2", "127.0.0.1")
+            .unwrap();
+        state.auth.validate_jwt(&token, "alice").unwrap();
+        assert!(matches!(
+            state.auth.authenticate_user("alice", "wrong", "127.0.0.1"),
+            Err(auth::AuthError::InvalidPassword)
+        ));
+
+        // The user went through the shared pool, and another state starts out empty
+        let conn = state.database.get().unwrap();
+        assert!(conn.get_user_by_username("alice").unwrap().is_some());
+        let other = AppState::new_in_memory(Config::default());
+        let conn = other.database.get().unwrap();
+        assert!(conn.get_user_by_username("alice").unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_signal_shuts_down_cleanly() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        let state = test_state();
+        let (tx, mut client) = mpsc::unbounded_channel();
+        state.register_connection(tx).await.unwrap();
+
+        // Tasks that take a while to wind down once told to stop, none should be aborted
+        let finished = Arc::new(AtomicUsize::new(0));
+        let handles = [0, 20, 40]
+            .into_iter()
+            .map(|millis| {
+                let state = state.clone();
+                let finished = finished.clone();
+                tokio::spawn(async move {
+                    state.shutdown_token.cancelled().await;
+                    tokio::time::sleep(Duration::from_millis(millis)).await;
+                    finished.fetch_add(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        state.add_join_handles(handles).await;
+
+        let (trigger, signal) = tokio::sync::oneshot::channel::<()>();
+        let listener = state.clone();
+        tokio::spawn(async move {
+            listener
+                .shutdown_on(async {
+                    let _ = signal.await;
+                    "SIGTERM"
+                })
+                .await
+        });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!state.shutdown_token.is_cancelled());
+
+        trigger.send(()).unwrap();
+        tokio::time::timeout(Duration::from_secs(5), await_any_task!(state))
+            .await
+            .expect("tasks didn't stop");
+        assert_eq!(finished.load(Ordering::SeqCst), 3);
+        assert!(matches!(
+            client.try_recv(),
+            Ok(Message::Close(Some(frame))) if frame.code == close_code::AWAY
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_capacity_from_config() {
+        let mut config = Config::default();
+        config.websocket.broadcast_capacity = 8;
+        let state = AppState::new_in_memory(config);
+        let _rx = state.subscribe_global_messages();
+
+        // Only the newest `broadcast_capacity` messages are kept for a receiver that isn't reading
+        for i in 0..20 {
+            state
+                .send_global_message(GlobalMessage::PermissionChanged { user_id: i })
+                .unwrap();
+        }
+        assert_eq!(state.global_sender.len(), 8);
+    }
+
+    #[test]
+    fn test_token_bucket_throttles_bursts() {
+        let start = Instant::now();
+        let mut bucket = TokenBucket::new_at(10, 5, start);
+        // A burst gets through up to the bucket size and no further
+        assert_eq!((0..8).filter(|_| bucket.try_acquire_at(start)).count(), 5);
+
+        // Steady traffic at the rate keeps getting through
+        for i in 1..=50 {
+            assert!(bucket.try_acquire_at(start + Duration::from_millis(100 * i)));
+        }
+        // But not twice in the same tenth of a second
+        assert!(!bucket.try_acquire_at(start + Duration::from_millis(5050)));
+        let retry_after = bucket.retry_after().unwrap();
+        assert!(
+            retry_after > Duration::from_millis(40) && retry_after <= Duration::from_millis(51)
+        );
+
+        // Idle time refills no more than the burst
+        let later = start + Duration::from_secs(60);
+        assert!(bucket.is_full_at(later));
+        assert_eq!((0..8).filter(|_| bucket.try_acquire_at(later)).count(), 5);
+        assert!(!bucket.is_full_at(later));
+    }
+
+    #[tokio::test]
+    async fn test_connection_message_rate_limit() {
+        let mut config = Config::default();
+        config.websocket.messages_per_second = 1;
+        config.websocket.message_burst = 2;
+        let state = AppState::new_in_memory(config);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let limited = state.register_connection(tx).await.unwrap();
+
+        assert!(state.allow_message(&limited).await);
+        assert!(state.allow_message(&limited).await);
+        assert!(!state.allow_message(&limited).await);
+        // Unregistered connections have nothing to count against
+        assert!(state.allow_message(&Uuid::nil()).await);
+
+        let mut config = Config::default();
+        config.websocket.messages_per_second = 0;
+        let state = AppState::new_in_memory(config);
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let unlimited = state.register_connection(tx).await.unwrap();
+        for _ in 0..1000 {
+            assert!(state.allow_message(&unlimited).await);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscribers_receive_typed_messages() {
+        let state = test_state();
+        let mut first = state.subscribe_global_messages();
+        let mut second = state.subscribe_global_messages();
+
+        let msg = GlobalMessage::Broadcast {
+            text: "hello".to_string(),
+        };
+        assert_eq!(state.send_global_message(msg.clone()).unwrap(), 2);
+        let (a, b) = (first.recv().await.unwrap(), second.recv().await.unwrap());
+        assert_eq!(*a, msg);
+        // Every subscriber shares the one message rather than a copy
+        assert!(Arc::ptr_eq(&a, &b));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_connection_reaches_only_target() {
+        let state = test_state();
+        let (tx_a, mut rx_a) = mpsc::unbounded_channel();
+        let (tx_b, mut rx_b) = mpsc::unbounded_channel();
+        let a = state.register_connection(tx_a).await.unwrap();
+        state.register_connection(tx_b).await.unwrap();
+
+        assert!(state.send_to_connection(&a, text("hello a")).await);
+        assert_eq!(rx_a.try_recv().unwrap(), text("hello a"));
+        assert!(rx_b.try_recv().is_err());
+
+        assert!(
+            !state
+                .send_to_connection(&Uuid::new_v4(), text("nobody"))
+                .await
+        );
+        state.remove_connection(&a).await;
+        assert!(!state.send_to_connection(&a, text("gone")).await);
+    }
+
+    #[tokio::test]
+    async fn test_send_to_connections() {
+        let state = test_state();
+        let (tx_a, mut rx_a) = mpsc::unbounded_channel();
+        let (tx_b, mut rx_b) = mpsc::unbounded_channel();
+        let (tx_c, mut rx_c) = mpsc::unbounded_channel();
+        let a = state.register_connection(tx_a).await.unwrap();
+        let b = state.register_connection(tx_b).await.unwrap();
+        state.register_connection(tx_c).await.unwrap();
+
+        let reached = state
+            .send_to_connections(&[a, b, Uuid::new_v4()], text("hi"))
+            .await;
+        assert_eq!(reached, 2);
+        assert_eq!(rx_a.try_recv().unwrap(), text("hi"));
+        assert_eq!(rx_b.try_recv().unwrap(), text("hi"));
+        assert!(rx_c.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_encoded_messages_follow_each_connections_encoding() {
+        let state = test_state();
+        let (tx_binary, mut rx_binary) = mpsc::unbounded_channel();
+        let (tx_json, mut rx_json) = mpsc::unbounded_channel();
+        let binary = state.register_connection(tx_binary).await.unwrap();
+        let json = state.register_connection(tx_json).await.unwrap();
+        state
+            .set_connection_encoding(&json, WireEncoding::Json)
+            .await;
+        assert_eq!(
+            state.connection_encoding(&binary).await,
+            WireEncoding::MessagePack
+        );
+        assert_eq!(state.connection_encoding(&json).await, WireEncoding::Json);
+
+        let msg = Outgoing::Encoded {
+            binary: Message::Binary(vec![1, 2, 3].into()),
+            text: text("{}"),
+        };
+        assert_eq!(state.send_to_connections(&[binary, json], msg).await, 2);
+        assert_eq!(
+            rx_binary.try_recv().unwrap(),
+            Message::Binary(vec![1, 2, 3].into())
+        );
+        assert_eq!(rx_json.try_recv().unwrap(), text("{}"));
+
+        // Plain frames go out unchanged whatever the encoding
+        assert!(state.send_to_connection(&json, text("raw")).await);
+        assert_eq!(rx_json.try_recv().unwrap(), text("raw"));
+    }
+
+    #[tokio::test]
+    async fn test_send_to_user_reaches_all_their_connections() {
+        let state = test_state();
+        let (tx_phone, mut rx_phone) = mpsc::unbounded_channel();
+        let (tx_laptop, mut rx_laptop) = mpsc::unbounded_channel();
+        let (tx_other, mut rx_other) = mpsc::unbounded_channel();
+        let (tx_anon, mut rx_anon) = mpsc::unbounded_channel();
+        let phone = state.register_connection(tx_phone).await.unwrap();
+        let laptop = state.register_connection(tx_laptop).await.unwrap();
+        let other = state.register_connection(tx_other).await.unwrap();
+        state.register_connection(tx_anon).await.unwrap();
+
+        assert!(state.associate_user(&phone, 42).await);
+        assert!(state.associate_user(&laptop, 42).await);
+        assert!(state.associate_user(&other, 7).await);
+        assert!(!state.associate_user(&Uuid::new_v4(), 42).await);
+
+        assert_eq!(state.send_to_user(42, text("for 42")).await, 2);
+        assert_eq!(rx_phone.try_recv().unwrap(), text("for 42"));
+        assert_eq!(rx_laptop.try_recv().unwrap(), text("for 42"));
+        assert!(rx_other.try_recv().is_err());
+        assert!(rx_anon.try_recv().is_err());
+
+        state.remove_connection(&phone).await;
+        assert_eq!(state.send_to_user(42, text("again")).await, 1);
+        assert_eq!(state.send_to_user(99, text("nobody")).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_notify_calendar_reaches_only_subscribers() {
+        let state = test_state();
+        let (tx_one, mut rx_one) = mpsc::unbounded_channel();
+        let (tx_two, mut rx_two) = mpsc::unbounded_channel();
+        let one = state.register_connection(tx_one).await.unwrap();
+        let two = state.register_connection(tx_two).await.unwrap();
+        state.subscribe_to_calendar(one, 1).await;
+        state.subscribe_to_calendar(two, 2).await;
+
+        assert_eq!(state.notify_calendar(1, text("changed")).await, 1);
+        assert_eq!(rx_one.try_recv().unwrap(), text("changed"));
+        assert!(rx_two.try_recv().is_err());
+        assert_eq!(state.notify_calendar(3, text("nobody")).await, 0);
+
+        // Disconnecting drops the subscription
+        state.remove_connection(&one).await;
+        assert!(!state.subscriptions.lock().await.contains_key(&1));
+        assert_eq!(state.notify_calendar(1, text("changed")).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_presence_flips_only_on_first_and_last_connection() {
+        let state = test_state();
+        let mut global = state.subscribe_global_messages();
+        let mut presence = || {
+            let mut edges = Vec::new();
+            while let Ok(msg) = global.try_recv() {
+                if let GlobalMessage::PresenceChanged { user_id, online } = *msg {
+                    edges.push((user_id, online));
+                }
+            }
+            edges
+        };
+        let connect = || async {
+            let (tx, _rx) = mpsc::unbounded_channel();
+            state.register_connection(tx).await.unwrap()
+        };
+
+        let phone = connect().await;
+        let laptop = connect().await;
+        let other = connect().await;
+        // Connections only count once they know their user
+        assert!(state.online_users().await.is_empty());
+        assert_eq!(presence(), vec![]);
+
+        state.associate_user(&phone, 42).await;
+        state.associate_user(&laptop, 42).await;
+        state.associate_user(&other, 7).await;
+        assert_eq!(presence(), vec![(42, true), (7, true)]);
+        assert_eq!(state.online_users().await, vec![7, 42]);
+
+        // Associating again changes nothing
+        state.associate_user(&phone, 42).await;
+        state.remove_connection(&phone).await;
+        assert_eq!(presence(), vec![]);
+        assert_eq!(state.online_users().await, vec![7, 42]);
+
+        state.remove_connection(&laptop).await;
+        assert_eq!(presence(), vec![(42, false)]);
+        assert_eq!(state.online_users().await, vec![7]);
+
+        // A connection switching users moves presence with it
+        state.associate_user(&other, 42).await;
+        assert_eq!(presence(), vec![(7, false), (42, true)]);
+        state.remove_connection(&other).await;
+        state.remove_connection(&other).await;
+        assert_eq!(presence(), vec![(42, false)]);
+        assert!(state.online_users().await.is_empty());
+    }
+}
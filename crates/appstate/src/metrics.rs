@@ -0,0 +1,56 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Counters for operators, served at `/metrics`.
+/// Only ever read for reporting, so relaxed ordering is enough.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    /// Websocket connections currently registered
+    pub active_connections: AtomicU64,
+    /// Protocol messages received from websocket clients
+    pub messages_in: AtomicU64,
+    /// Messages written to websocket clients
+    pub messages_out: AtomicU64,
+    /// Requests rejected for missing or bad credentials
+    pub auth_failures: AtomicU64,
+}
+
+/// The counters' values at one moment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MetricsSnapshot {
+    pub active_connections: u64,
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub auth_failures: u64,
+}
+
+impl Metrics {
+    pub fn connection_opened(&self) {
+        self.active_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_closed(&self) {
+        self.active_connections.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn message_received(&self) {
+        self.messages_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn message_sent(&self) {
+        self.messages_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn auth_failed(&self) {
+        self.auth_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Read every counter.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+            messages_in: self.messages_in.load(Ordering::Relaxed),
+            messages_out: self.messages_out.load(Ordering::Relaxed),
+            auth_failures: self.auth_failures.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,55 @@
+//! Keys used to sign and verify JWTs.
+
+use crate::AuthError;
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+
+/// Signing keys for issued JWTs.
+/// `Hmac` signs and verifies with one shared secret (HS256). `Rsa` signs with the private key
+/// and verifies with the public one (RS256), so verifiers never need to hold the signing key.
+#[derive(Clone)]
+pub enum JwtKeys {
+    Hmac(String),
+    Rsa {
+        private_pem: String,
+        public_pem: String,
+    },
+}
+
+impl JwtKeys {
+    /// The JWT algorithm these keys sign with.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            JwtKeys::Hmac(_) => Algorithm::HS256,
+            JwtKeys::Rsa { .. } => Algorithm::RS256,
+        }
+    }
+
+    pub(crate) fn encoding_key(&self) -> Result<EncodingKey, AuthError> {
+        match self {
+            JwtKeys::Hmac(secret) => Ok(EncodingKey::from_secret(secret.as_bytes())),
+            JwtKeys::Rsa { private_pem, .. } => EncodingKey::from_rsa_pem(private_pem.as_bytes())
+                .map_err(|e| AuthError::JwtError(format!("{:?}", e))),
+        }
+    }
+
+    pub(crate) fn decoding_key(&self) -> Result<DecodingKey, AuthError> {
+        match self {
+            JwtKeys::Hmac(secret) => Ok(DecodingKey::from_secret(secret.as_bytes())),
+            JwtKeys::Rsa { public_pem, .. } => DecodingKey::from_rsa_pem(public_pem.as_bytes())
+                .map_err(|e| AuthError::JwtError(format!("{:?}", e))),
+        }
+    }
+}
+
+// A bare secret means HS256, as before RSA keys were supported
+impl From<String> for JwtKeys {
+    fn from(secret: String) -> Self {
+        JwtKeys::Hmac(secret)
+    }
+}
+
+impl From<&str> for JwtKeys {
+    fn from(secret: &str) -> Self {
+        JwtKeys::Hmac(secret.to_owned())
+    }
+}
@@ -2,14 +2,32 @@
 //! - Registration: stores username, password hash, salt, and email if user doesn't exist, returns JWT.
 //! - Salt retrieval: returns salt for username (if exists).
 //! - Authentication: compares provided hash to stored hash, returns JWT if correct.
+//!
+//! Depending on the `HashingMode` the "password" passed in is either a hash the client computed
+//! with its salt (`ClientHashed`) or the raw password, which the server hashes with Argon2id (`ServerHashed`).
 
-use db::{AuthUser, DatabaseConnection};
-use global_constants::DEFAULT_AUTH_RATE_LIMIT_PER_MINUTE;
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+mod keys;
+mod revocation;
+
+pub use keys::JwtKeys;
+pub use revocation::{InMemoryRevocationStore, RevocationStore};
+
+use argon2::password_hash::{PasswordHasher, PasswordVerifier, SaltString, rand_core::OsRng};
+use argon2::{Argon2, PasswordHash};
+use chrono::DateTime;
+pub use db::SafeUser;
+pub use db::Session;
+use db::{CalendarCapabilities, CalendarPermission, DbPool, PooledConnection};
+use jsonwebtoken::{Header, Validation, decode, encode};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::fmt;
+use std::hash::Hash;
+use std::net::IpAddr;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use subtle::ConstantTimeEq;
 
 /// Error type for authentication operations.
 #[derive(Debug)]
@@ -17,10 +35,216 @@ pub enum AuthError {
     UserAlreadyExists,
     UserNotFound,
     InvalidPassword,
-    DbError(String),
+    /// A database query failed
+    DbError(db::Error),
+    /// No database connection could be checked out of the pool
+    PoolError(r2d2::Error),
     JwtError(String),
     RateLimitExceeded,
     Unauthorized,
+    /// Too many consecutive failed logins, the account is locked until the lockout window passes
+    AccountLocked,
+    /// Not a well-formed `local@domain.tld` address
+    InvalidEmail,
+    /// Empty, too long, padded with whitespace or using characters the `UsernamePolicy` doesn't allow
+    InvalidUsername,
+    /// The calendar invite was already accepted, or withdrawn along with its calendar
+    InviteAlreadyUsed,
+    /// No such session, or it belongs to someone else and the caller isn't a global admin
+    SessionNotFound,
+    /// Logins require a verified email and the user hasn't verified theirs yet
+    EmailNotVerified,
+}
+
+impl AuthError {
+    /// The HTTP status an API should answer with when an operation fails with this error.
+    pub fn status_code(&self) -> u16 {
+        match self {
+            AuthError::UserAlreadyExists | AuthError::InviteAlreadyUsed => 409,
+            AuthError::UserNotFound | AuthError::SessionNotFound => 404,
+            AuthError::InvalidPassword | AuthError::Unauthorized => 401,
+            AuthError::EmailNotVerified => 403,
+            AuthError::InvalidEmail | AuthError::InvalidUsername => 400,
+            AuthError::RateLimitExceeded => 429,
+            AuthError::AccountLocked => 423,
+            AuthError::DbError(_) | AuthError::PoolError(_) | AuthError::JwtError(_) => 500,
+        }
+    }
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::UserAlreadyExists => write!(f, "user already exists"),
+            AuthError::UserNotFound => write!(f, "user not found"),
+            AuthError::InvalidPassword => write!(f, "invalid password"),
+            AuthError::DbError(e) => write!(f, "{e}"),
+            AuthError::PoolError(e) => write!(f, "database pool error: {e}"),
+            AuthError::JwtError(msg) => write!(f, "token error: {msg}"),
+            AuthError::RateLimitExceeded => write!(f, "too many attempts, try again later"),
+            AuthError::Unauthorized => write!(f, "unauthorized"),
+            AuthError::AccountLocked => write!(f, "account locked, try again later"),
+            AuthError::InvalidEmail => write!(f, "invalid email"),
+            AuthError::InvalidUsername => write!(f, "invalid username"),
+            AuthError::InviteAlreadyUsed => write!(f, "invite already used"),
+            AuthError::SessionNotFound => write!(f, "session not found"),
+            AuthError::EmailNotVerified => write!(f, "email not verified"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            AuthError::DbError(e) => Some(e),
+            AuthError::PoolError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<db::Error> for AuthError {
+    fn from(e: db::Error) -> Self {
+        AuthError::DbError(e)
+    }
+}
+
+impl From<r2d2::Error> for AuthError {
+    fn from(e: r2d2::Error) -> Self {
+        AuthError::PoolError(e)
+    }
+}
+
+/// How many consecutive failed logins lock an account, and for how long.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LockoutPolicy {
+    pub threshold: u32,
+    pub duration: Duration,
+}
+
+impl Default for LockoutPolicy {
+    fn default() -> Self {
+        Self {
+            threshold: global_constants::DEFAULT_AUTH_LOCKOUT_THRESHOLD,
+            duration: Duration::from_secs(global_constants::DEFAULT_AUTH_LOCKOUT_SECONDS),
+        }
+    }
+}
+
+/// Where password hashing happens.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HashingMode {
+    /// The client fetches its salt, hashes the password itself and sends the hash, which is stored and compared as is.
+    ClientHashed,
+    /// The client sends the raw password and the server stores an Argon2id hash of it with a generated salt.
+    #[default]
+    ServerHashed,
+}
+
+/// Which usernames `register_user` accepts.
+#[derive(Debug, Clone, Copy)]
+pub struct UsernamePolicy {
+    /// Maximum length in characters
+    pub max_length: usize,
+    /// Whether a character may appear in a username
+    pub allowed_char: fn(char) -> bool,
+}
+
+impl Default for UsernamePolicy {
+    /// ASCII letters and digits plus `_`, `-` and `.`, up to 64 characters.
+    fn default() -> Self {
+        Self {
+            max_length: global_constants::DEFAULT_USERNAME_MAX_LENGTH,
+            allowed_char: |c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.'),
+        }
+    }
+}
+
+/// Check a username against `policy`. Control characters and leading/trailing whitespace
+/// are rejected whatever the policy allows, they break the UI and logs.
+pub fn validate_username(username: &str, policy: &UsernamePolicy) -> Result<(), AuthError> {
+    let valid = !username.is_empty()
+        && username.chars().count() <= policy.max_length
+        && username.trim() == username
+        && !username.chars().any(char::is_control)
+        && username.chars().all(policy.allowed_char);
+    if valid {
+        Ok(())
+    } else {
+        Err(AuthError::InvalidUsername)
+    }
+}
+
+/// Count a request against `key` in `limits`, failing once `limit` requests were made in the current `window`.
+fn check_limit<K: Eq + Hash>(
+    limits: &Mutex<HashMap<K, (u32, Instant)>>,
+    key: K,
+    limit: u32,
+    window: Duration,
+) -> Result<(), AuthError> {
+    let mut limits = limits.lock().unwrap();
+    let now = Instant::now();
+    let entry = limits.entry(key).or_insert((0, now));
+
+    if now.duration_since(entry.1) > window {
+        // Reset window
+        entry.0 = 1;
+        entry.1 = now;
+        Ok(())
+    } else if entry.0 < limit {
+        entry.0 += 1;
+        Ok(())
+    } else {
+        Err(AuthError::RateLimitExceeded)
+    }
+}
+
+/// Loose email sanity check: a non-empty local part, an `@`, and a dotted domain.
+/// Deliverability is only really proven by sending mail to it.
+fn is_valid_email(email: &str) -> bool {
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return false;
+    };
+    !local.is_empty()
+        && !email.chars().any(char::is_whitespace)
+        && domain.contains('.')
+        && domain.split('.').all(|label| !label.is_empty())
+}
+
+/// Compare two password hashes in constant time, so how long a failed login takes
+/// doesn't reveal how much of the hash was right. Only the length can short-circuit.
+fn hashes_match(stored: &str, provided: &str) -> bool {
+    stored.as_bytes().ct_eq(provided.as_bytes()).into()
+}
+
+/// What a JWT may be used for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TokenType {
+    /// Short-lived, authorizes requests
+    #[default]
+    Access,
+    /// Long-lived, can only be exchanged for a new access token
+    Refresh,
+    /// Short-lived and single use, can only be used to reset the password
+    PasswordReset,
+    /// Single use, grants access to a calendar, see `InviteClaims`
+    CalendarInvite,
+    /// Proves the user received mail at their address, see `EmailVerificationClaims`
+    EmailVerification,
+}
+
+impl TokenType {
+    /// How the type is listed in `Session::token_type`, `None` for tokens that aren't sessions.
+    fn session_kind(self) -> Option<&'static str> {
+        match self {
+            TokenType::Access => Some("access"),
+            TokenType::Refresh => Some("refresh"),
+            TokenType::PasswordReset | TokenType::CalendarInvite | TokenType::EmailVerification => {
+                None
+            }
+        }
+    }
 }
 
 /// Claims for JWT tokens.
@@ -28,217 +252,1667 @@ pub enum AuthError {
 struct Claims {
     sub: String,
     exp: usize,
+    /// Tokens issued before refresh tokens existed have no type and are access tokens
+    #[serde(default)]
+    token_type: TokenType,
+    /// Unique token id, used for revocation. Tokens issued before revocation existed have none
+    /// and are rejected.
+    #[serde(default)]
+    jti: String,
 }
+
+/// Claims for email verification tokens.
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailVerificationClaims {
+    sub: String,
+    exp: usize,
+    /// Always `EmailVerification`
+    token_type: TokenType,
+    jti: String,
+    /// The address the token was sent to, so it can't verify one the user switched to later
+    email: String,
+}
+
+/// What `register_user` hands back.
+#[derive(Debug)]
+pub struct Registration {
+    /// Access token for the new account, `None` while logins require a verified email
+    pub access_token: Option<String>,
+    /// Token to send to the user's email address, redeemed with `verify_email`
+    pub verification_token: String,
+}
+
+/// Claims for calendar invite tokens.
+#[derive(Debug, Serialize, Deserialize)]
+struct InviteClaims {
+    exp: usize,
+    /// Always `CalendarInvite`
+    token_type: TokenType,
+    /// Unique invite id, recorded in the database so the invite can only be accepted once
+    jti: String,
+    calendar_id: i64,
+    issued_by: i64,
+    caps: CalendarCapabilities,
+}
+
+fn unix_now() -> usize {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as usize
+}
+
+/// A user's consecutive failed logins: how many, when the first one happened, and when the
+/// lockout they caused ends.
+type FailedLogins = (u32, Instant, Option<Instant>);
+
 /// AuthService provides secure authentication operations.
 pub struct AuthService {
-    db: Arc<DatabaseConnection>,
-    jwt_secret: String,
+    db: DbPool,
+    jwt_keys: JwtKeys,
     jwt_expiry_seconds: usize,
+    jwt_refresh_expiry_seconds: usize,
+    hashing_mode: HashingMode,
+    revocations: Arc<dyn RevocationStore>,
+    username_policy: UsernamePolicy,
+    lockout: LockoutPolicy,
+    failed_logins: Mutex<HashMap<String, FailedLogins>>, // username -> (consecutive failures, first failure, locked_until)
+    rate_limit: u32,
+    rate_limit_window: Duration,
     rate_limits: Mutex<HashMap<String, (u32, std::time::Instant)>>, // username -> (count, window_start)
     ip_rate_limits: Mutex<HashMap<String, (u32, std::time::Instant)>>, // ip -> (count, window_start)
+    registration_limit: u32,
+    registration_window: Duration,
+    registration_limits: Mutex<HashMap<IpAddr, (u32, Instant)>>, // ip -> (count, window_start)
+    require_email_verification: bool,
 }
 
 impl AuthService {
     /// Create a new AuthService.
+    /// `rate_limit_per_minute` requests are allowed per user and per IP in each `rate_limit_window`
+    /// (a minute unless configured otherwise).
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        db: Arc<DatabaseConnection>,
-        jwt_secret: impl Into<String>,
+        db: DbPool,
+        jwt_keys: impl Into<JwtKeys>,
         jwt_expiry_seconds: Option<usize>,
+        jwt_refresh_expiry_seconds: Option<usize>,
+        hashing_mode: HashingMode,
+        lockout: LockoutPolicy,
+        rate_limit_per_minute: Option<u32>,
+        rate_limit_window: Option<Duration>,
     ) -> Self {
         Self {
             db,
-            jwt_secret: jwt_secret.into(),
+            jwt_keys: jwt_keys.into(),
             jwt_expiry_seconds: jwt_expiry_seconds
                 .unwrap_or(global_constants::DEFAULT_JWT_EXPIRY_SECONDS),
+            jwt_refresh_expiry_seconds: jwt_refresh_expiry_seconds
+                .unwrap_or(global_constants::DEFAULT_JWT_REFRESH_EXPIRY_SECONDS),
+            hashing_mode,
+            revocations: Arc::new(InMemoryRevocationStore::new()),
+            username_policy: UsernamePolicy::default(),
+            lockout,
+            failed_logins: Mutex::new(HashMap::new()),
+            rate_limit: rate_limit_per_minute
+                .unwrap_or(global_constants::DEFAULT_AUTH_RATE_LIMIT_PER_MINUTE),
+            rate_limit_window: rate_limit_window.unwrap_or(Duration::from_secs(60)),
             rate_limits: Mutex::new(HashMap::new()),
             ip_rate_limits: Mutex::new(HashMap::new()),
+            registration_limit: global_constants::DEFAULT_REGISTRATION_LIMIT_PER_IP,
+            registration_window: Duration::from_secs(
+                global_constants::DEFAULT_REGISTRATION_WINDOW_SECONDS,
+            ),
+            registration_limits: Mutex::new(HashMap::new()),
+            require_email_verification: false,
         }
     }
 
+    /// Use `store` to track revoked tokens instead of the default in-memory store.
+    pub fn with_revocation_store(mut self, store: Arc<dyn RevocationStore>) -> Self {
+        self.revocations = store;
+        self
+    }
+
+    /// Accept usernames according to `policy` instead of the default one.
+    pub fn with_username_policy(mut self, policy: UsernamePolicy) -> Self {
+        self.username_policy = policy;
+        self
+    }
+
+    /// Refuse logins (with `EmailNotVerified`) until the user has verified their email.
+    pub fn with_email_verification_required(mut self, required: bool) -> Self {
+        self.require_email_verification = required;
+        self
+    }
+
+    /// Allow `limit` registrations per source IP in each `window` via `register_user_from`.
+    pub fn with_registration_limit(mut self, limit: u32, window: Duration) -> Self {
+        self.registration_limit = limit;
+        self.registration_window = window;
+        self
+    }
+
+    /// Register a new user coming from `ip`, like `register_user` but also capping how many
+    /// registrations one source IP can attempt per registration window.
+    /// Prefer this over `register_user` wherever the peer address is known.
+    pub fn register_user_from(
+        &self,
+        ip: IpAddr,
+        username: &str,
+        password: &str,
+        salt: Option<&str>,
+        email: &str,
+    ) -> Result<Registration, AuthError> {
+        check_limit(
+            &self.registration_limits,
+            ip,
+            self.registration_limit,
+            self.registration_window,
+        )?;
+        self.register_user(username, password, salt, email, &ip.to_string())
+    }
+
     /// Register a new user.
-    /// Returns a JWT if successful, or an error if the user already exists.
+    /// `password` is the raw password in `ServerHashed` mode, the client-side hash in `ClientHashed`
+    /// mode, in which case `salt` must be the salt it was hashed with (it is ignored otherwise).
+    /// The email starts out unverified. Returns a token to verify it with, and an access token
+    /// unless logins require a verified email, or an error if the user already exists.
     pub fn register_user(
         &self,
         username: &str,
-        password_hash: &str,
-        salt: &str,
+        password: &str,
+        salt: Option<&str>,
         email: &str,
         ip: &str,
-    ) -> Result<String, AuthError> {
+    ) -> Result<Registration, AuthError> {
         self.check_ip_rate_limit(ip)?;
+        validate_username(username, &self.username_policy)?;
+        if !is_valid_email(email) {
+            return Err(AuthError::InvalidEmail);
+        }
         // Check if user exists
-        match self.db.get_user_by_username(username) {
+        match self.conn()?.get_user_by_username(username) {
             Ok(Some(_)) => return Err(AuthError::UserAlreadyExists),
             Ok(None) => {}
-            Err(e) => return Err(AuthError::DbError(format!("{:?}", e))),
+            Err(e) => return Err(AuthError::DbError(e)),
         }
 
         // Insert user
-        if let Err(e) = self.db.insert_user(username, password_hash, salt, email) {
-            return Err(AuthError::DbError(format!("{:?}", e)));
+        let (password_hash, salt) = self.credentials_to_store(password, salt)?;
+        if let Err(e) = self
+            .conn()?
+            .insert_user(username, &password_hash, &salt, email)
+        {
+            return Err(AuthError::DbError(e));
+        }
+        let verification_token = self.sign_email_verification(username, email)?;
+        let access_token = if self.require_email_verification {
+            None
+        } else {
+            Some(self.issue_jwt(username)?)
+        };
+        Ok(Registration {
+            access_token,
+            verification_token,
+        })
+    }
+
+    /// Issue a fresh token for verifying the user's current email, meant to be sent to that
+    /// address, e.g. when the one from registration expired or the address changed.
+    pub fn create_email_verification_token(&self, username: &str) -> Result<String, AuthError> {
+        let user = self
+            .conn()?
+            .get_user_by_username(username)
+            .map_err(AuthError::DbError)?
+            .ok_or(AuthError::UserNotFound)?;
+        self.sign_email_verification(username, &user.email)
+    }
+
+    /// Mark the email a token from `register_user` or `create_email_verification_token` was
+    /// sent to as verified. Fails with `Unauthorized` if the token is invalid or expired, or the
+    /// user has changed their address since it was issued.
+    pub fn verify_email(&self, token: &str) -> Result<(), AuthError> {
+        let claims: EmailVerificationClaims = self.decode_token(token)?;
+        if claims.token_type != TokenType::EmailVerification {
+            return Err(AuthError::Unauthorized);
+        }
+        let verified = self
+            .conn()?
+            .mark_email_verified(&claims.sub, &claims.email)
+            .map_err(AuthError::DbError)?;
+        if verified {
+            Ok(())
+        } else {
+            Err(AuthError::Unauthorized)
         }
-        // Issue JWT
-        self.issue_jwt(username)
+    }
+
+    fn sign_email_verification(&self, username: &str, email: &str) -> Result<String, AuthError> {
+        self.sign_claims(&EmailVerificationClaims {
+            sub: username.to_owned(),
+            exp: unix_now() + self.token_lifetime(TokenType::EmailVerification),
+            token_type: TokenType::EmailVerification,
+            jti: uuid::Uuid::new_v4().to_string(),
+            email: email.to_owned(),
+        })
     }
 
     /// Retrieve the salt for a given username.
     pub fn get_salt(&self, username: &str, ip: &str) -> Result<String, AuthError> {
         self.check_ip_rate_limit(ip)?;
         self.check_rate_limit(username)?;
-        match self.db.get_salt_by_username(username) {
+        match self.conn()?.get_salt_by_username(username) {
             Ok(Some(salt)) => Ok(salt),
             Ok(None) => Err(AuthError::UserNotFound),
-            Err(e) => Err(AuthError::DbError(format!("{:?}", e))),
+            Err(e) => Err(AuthError::DbError(e)),
         }
     }
 
-    /// Authenticate a user by username and password hash.
+    /// Authenticate a user by username and password (raw or client-hashed, see `HashingMode`).
     /// Returns a JWT if successful, or an error if authentication fails.
+    /// After `LockoutPolicy::threshold` consecutive wrong passwords the account is locked
+    /// and every attempt returns `AccountLocked` until the lockout expires.
     pub fn authenticate_user(
         &self,
         username: &str,
-        password_hash: &str,
+        password: &str,
         ip: &str,
     ) -> Result<String, AuthError> {
+        // Checked first so a locked account doesn't also burn through its rate limit
+        self.check_lockout(username)?;
         self.check_ip_rate_limit(ip)?;
         self.check_rate_limit(username)?;
-        let user = match self.db.get_user_by_username(username) {
+        let user = match self.conn()?.get_user_by_username(username) {
             Ok(Some(user)) => user,
             Ok(None) => return Err(AuthError::UserNotFound),
-            Err(e) => return Err(AuthError::DbError(format!("{:?}", e))),
+            Err(e) => return Err(AuthError::DbError(e)),
         };
 
-        if user.password_hash == password_hash {
+        let matches = match self.hashing_mode {
+            HashingMode::ClientHashed => hashes_match(&user.password_hash, password),
+            HashingMode::ServerHashed => PasswordHash::new(&user.password_hash)
+                .map(|hash| {
+                    Argon2::default()
+                        .verify_password(password.as_bytes(), &hash)
+                        .is_ok()
+                })
+                .unwrap_or(false),
+        };
+        if matches {
+            self.failed_logins.lock().unwrap().remove(username);
+            // Only after the password matched, so it doesn't tell anyone the account exists
+            if self.require_email_verification && !user.email_verified {
+                return Err(AuthError::EmailNotVerified);
+            }
             self.issue_jwt(username)
         } else {
+            self.record_failed_login(username);
             Err(AuthError::InvalidPassword)
         }
     }
 
+    /// Fail if the account is currently locked out, clearing the lock once it has expired.
+    fn check_lockout(&self, username: &str) -> Result<(), AuthError> {
+        let mut failed = self.failed_logins.lock().unwrap();
+        match failed.get(username) {
+            Some((_, _, Some(locked_until))) if Instant::now() < *locked_until => {
+                Err(AuthError::AccountLocked)
+            }
+            Some((_, _, Some(_))) => {
+                failed.remove(username);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Count a wrong password, locking the account once the threshold is reached within the
+    /// lockout duration. Failures older than that are forgotten, as are expired locks.
+    fn record_failed_login(&self, username: &str) {
+        let now = Instant::now();
+        let mut failed = self.failed_logins.lock().unwrap();
+        failed.retain(|_, (_, first_failure, locked_until)| match locked_until {
+            Some(locked_until) => now < *locked_until,
+            None => now.duration_since(*first_failure) < self.lockout.duration,
+        });
+        let entry = failed.entry(username.to_string()).or_insert((0, now, None));
+        entry.0 += 1;
+        if entry.0 >= self.lockout.threshold {
+            entry.2 = Some(now + self.lockout.duration);
+        }
+    }
+
     /// Change a user's password (requires JWT for authentication).
+    /// `new_password` is raw or client-hashed depending on the `HashingMode`, as in `register_user`.
+    /// Every token previously issued to the user is revoked, including `jwt`.
     pub fn change_password(
         &self,
         username: &str,
-        new_password_hash: &str,
+        new_password: &str,
         jwt: &str,
     ) -> Result<(), AuthError> {
         // Validate JWT
         self.validate_jwt(jwt, username)?;
+        self.set_password(username, new_password)
+    }
+
+    /// Change a user's email (requires JWT for authentication).
+    /// The new address is unverified until the user verifies it, see `create_email_verification_token`.
+    pub fn change_email(
+        &self,
+        username: &str,
+        new_email: &str,
+        jwt: &str,
+    ) -> Result<(), AuthError> {
+        self.validate_jwt(jwt, username)?;
+        if !is_valid_email(new_email) {
+            return Err(AuthError::InvalidEmail);
+        }
+        self.conn()?
+            .update_user_email(username, new_email)
+            .map_err(AuthError::DbError)
+    }
+
+    /// Delete a user's account (requires JWT for authentication).
+    /// Revokes all of the user's tokens and removes their permissions along with the account.
+    pub fn delete_account(&self, username: &str, jwt: &str) -> Result<(), AuthError> {
+        self.validate_jwt(jwt, username)?;
+        let mut conn = self.conn()?;
+        let user = match conn.get_user_by_username(username) {
+            Ok(Some(user)) => user,
+            Ok(None) => return Err(AuthError::UserNotFound),
+            Err(e) => return Err(AuthError::DbError(e)),
+        };
+        conn.delete_user_account(user.id)
+            .map_err(AuthError::DbError)?;
+        self.revoke_all_sessions(&conn, username)
+    }
+
+    /// Issue a short-lived, single use token for resetting the password of the account with `email`,
+    /// meant to be sent to that address.
+    /// Returns `None` if no account has the email, or its owner hasn't verified it: mail to an
+    /// unverified address might reach someone else. Callers must respond the same way in every case,
+    /// and the work done here is the same either way, so accounts can't be enumerated.
+    pub fn create_password_reset_token(&self, email: &str) -> Result<Option<String>, AuthError> {
+        let user = self
+            .conn()?
+            .get_user_by_email(email)
+            .map_err(AuthError::DbError)?
+            .filter(|user| user.email_verified);
+        match user {
+            Some(user) => self
+                .issue_token(&user.username, TokenType::PasswordReset)
+                .map(Some),
+            None => {
+                // Sign a throwaway token to take about as long as the real path
+                self.sign_claims(&Claims {
+                    sub: String::new(),
+                    exp: 0,
+                    token_type: TokenType::PasswordReset,
+                    jti: String::new(),
+                })?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Set a new password using a token from `create_password_reset_token`.
+    /// The token is consumed, and every other token of the user is revoked as with `change_password`.
+    pub fn reset_password(&self, reset_token: &str, new_password: &str) -> Result<(), AuthError> {
+        let claims = self.decode_claims(reset_token)?;
+        if claims.token_type != TokenType::PasswordReset {
+            return Err(AuthError::Unauthorized);
+        }
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0)
+            .ok_or_else(|| AuthError::JwtError("token expiry out of range".to_owned()))?;
+        // Marking the token used is a single insert, so a concurrent reset with it loses here
+        let consumed = self
+            .conn()?
+            .consume_password_reset(&claims.jti, expires_at, chrono::Utc::now())
+            .map_err(AuthError::DbError)?;
+        if !consumed {
+            return Err(AuthError::Unauthorized);
+        }
+        self.set_password(&claims.sub, new_password)
+    }
+
+    /// Store a new password (raw or client-hashed, see `HashingMode`) and revoke the user's tokens.
+    fn set_password(&self, username: &str, new_password: &str) -> Result<(), AuthError> {
+        let new_password_hash = match self.hashing_mode {
+            HashingMode::ClientHashed => new_password.to_owned(),
+            // The PHC string carries its own fresh salt
+            HashingMode::ServerHashed => self.credentials_to_store(new_password, None)?.0,
+        };
 
         // Update password in DB
-        self.db
-            .update_user_password(username, new_password_hash)
-            .map_err(|e| AuthError::DbError(format!("{:?}", e)))
+        let conn = self.conn()?;
+        conn.update_user_password(username, &new_password_hash)
+            .map_err(AuthError::DbError)?;
+
+        self.revoke_all_sessions(&conn, username)
+    }
+
+    /// Revoke a token (e.g. on logout) so it fails validation from now on, even before it expires.
+    pub fn revoke_token(&self, jwt: &str) -> Result<(), AuthError> {
+        let claims = self.decode_claims(jwt)?;
+        self.revocations.revoke(&claims.jti, claims.exp);
+        self.conn()?
+            .delete_session(&claims.jti)
+            .map_err(AuthError::DbError)?;
+        Ok(())
+    }
+
+    /// The user's access and refresh tokens that are still valid, newest first.
+    pub fn list_sessions(&self, username: &str) -> Result<Vec<Session>, AuthError> {
+        self.conn()?
+            .list_sessions_for_user(username, chrono::Utc::now())
+            .map_err(AuthError::DbError)
+    }
+
+    /// Revoke the session whose token has the id `jti`, so the token fails validation from now on.
+    /// Users can revoke their own sessions and global admins anyone's, any other session
+    /// fails with `SessionNotFound` so its existence isn't given away.
+    pub fn revoke_session(&self, jti: &str, requested_by: &SafeUser) -> Result<(), AuthError> {
+        let conn = self.conn()?;
+        let session = conn
+            .get_session(jti)
+            .map_err(AuthError::DbError)?
+            .ok_or(AuthError::SessionNotFound)?;
+        if session.username != requested_by.username
+            && !conn
+                .is_global_admin(requested_by.id)
+                .map_err(AuthError::DbError)?
+        {
+            return Err(AuthError::SessionNotFound);
+        }
+        self.revocations
+            .revoke(jti, session.expires_at.timestamp().max(0) as usize);
+        conn.delete_session(jti).map_err(AuthError::DbError)?;
+        Ok(())
+    }
+
+    /// Revoke every token issued to the user and forget their sessions.
+    fn revoke_all_sessions(
+        &self,
+        conn: &PooledConnection,
+        username: &str,
+    ) -> Result<(), AuthError> {
+        self.revocations.revoke_all_for_user(username);
+        conn.delete_sessions_for_user(username)
+            .map_err(AuthError::DbError)?;
+        Ok(())
     }
 
-    /// Helper to issue a JWT for a username.
+    /// Work out the `(password_hash, salt)` pair to store for a new password.
+    fn credentials_to_store(
+        &self,
+        password: &str,
+        salt: Option<&str>,
+    ) -> Result<(String, String), AuthError> {
+        match self.hashing_mode {
+            HashingMode::ClientHashed => {
+                let salt = salt.ok_or(AuthError::InvalidPassword)?;
+                Ok((password.to_owned(), salt.to_owned()))
+            }
+            HashingMode::ServerHashed => {
+                let salt = SaltString::generate(&mut OsRng);
+                let hash = Argon2::default()
+                    .hash_password(password.as_bytes(), &salt)
+                    .map_err(|_| AuthError::InvalidPassword)?;
+                Ok((hash.to_string(), salt.as_str().to_owned()))
+            }
+        }
+    }
+
+    /// Check out a database connection from the pool.
+    fn conn(&self) -> Result<PooledConnection, AuthError> {
+        self.db.get().map_err(AuthError::PoolError)
+    }
+
+    /// Helper to issue an access JWT for a username.
     fn issue_jwt(&self, username: &str) -> Result<String, AuthError> {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .expect("Time went backwards")
-            .as_secs() as usize;
+        self.issue_token(username, TokenType::Access)
+    }
+
+    /// Issue a long-lived refresh token for a username, to be exchanged via `refresh_access_token`.
+    pub fn issue_refresh_token(&self, username: &str) -> Result<String, AuthError> {
+        self.issue_token(username, TokenType::Refresh)
+    }
+
+    /// Exchange a valid refresh token for a fresh access token.
+    /// Access tokens are rejected, they can't be used to extend themselves.
+    pub fn refresh_access_token(&self, refresh_jwt: &str) -> Result<String, AuthError> {
+        let claims = self.decode_claims(refresh_jwt)?;
+        if claims.token_type != TokenType::Refresh {
+            return Err(AuthError::Unauthorized);
+        }
+        self.issue_jwt(&claims.sub)
+    }
+
+    /// Sign a token of the given type, expiring after that type's configured lifetime.
+    fn issue_token(&self, username: &str, token_type: TokenType) -> Result<String, AuthError> {
         let claims = Claims {
             sub: username.to_owned(),
-            exp: now + self.jwt_expiry_seconds,
+            exp: unix_now() + self.token_lifetime(token_type),
+            token_type,
+            jti: uuid::Uuid::new_v4().to_string(),
         };
+        let token = self.sign_claims(&claims)?;
+        self.revocations
+            .record_issued(username, &claims.jti, claims.exp);
+        if let Some(kind) = token_type.session_kind() {
+            let expires_at = DateTime::from_timestamp(claims.exp as i64, 0)
+                .ok_or_else(|| AuthError::JwtError("token expiry out of range".to_owned()))?;
+            self.conn()?
+                .insert_session(&Session {
+                    jti: claims.jti,
+                    username: username.to_owned(),
+                    token_type: kind.to_owned(),
+                    issued_at: chrono::Utc::now(),
+                    expires_at,
+                })
+                .map_err(AuthError::DbError)?;
+        }
+        Ok(token)
+    }
+
+    /// How long a token of the given type is valid for, in seconds.
+    fn token_lifetime(&self, token_type: TokenType) -> usize {
+        match token_type {
+            TokenType::Access => self.jwt_expiry_seconds,
+            TokenType::Refresh => self.jwt_refresh_expiry_seconds,
+            TokenType::PasswordReset => global_constants::DEFAULT_PASSWORD_RESET_EXPIRY_SECONDS,
+            TokenType::CalendarInvite => global_constants::DEFAULT_CALENDAR_INVITE_EXPIRY_SECONDS,
+            TokenType::EmailVerification => {
+                global_constants::DEFAULT_EMAIL_VERIFICATION_EXPIRY_SECONDS
+            }
+        }
+    }
+
+    fn sign_claims(&self, claims: &impl Serialize) -> Result<String, AuthError> {
         encode(
-            &Header::default(),
-            &claims,
-            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
+            &Header::new(self.jwt_keys.algorithm()),
+            claims,
+            &self.jwt_keys.encoding_key()?,
         )
         .map_err(|e| AuthError::JwtError(format!("{:?}", e)))
     }
 
-    /// Validate a JWT for a given username.
-    pub fn validate_jwt(&self, jwt: &str, username: &str) -> Result<(), AuthError> {
-        let validation = Validation::default();
-        let token_data = decode::<Claims>(
-            jwt,
-            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
-            &validation,
-        )
-        .map_err(|_| AuthError::Unauthorized)?;
+    /// Check a JWT's signature, expiry and revocation and return its claims.
+    fn decode_claims(&self, jwt: &str) -> Result<Claims, AuthError> {
+        let claims: Claims = self.decode_token(jwt)?;
+        // A token without an id could never be revoked, so it isn't accepted at all
+        if claims.jti.is_empty() || self.revocations.is_revoked(&claims.jti) {
+            return Err(AuthError::Unauthorized);
+        }
+        Ok(claims)
+    }
 
-        if token_data.claims.sub == username {
-            Ok(())
-        } else {
-            Err(AuthError::Unauthorized)
+    /// Check a JWT's signature and expiry and return its claims.
+    fn decode_token<T: DeserializeOwned>(&self, jwt: &str) -> Result<T, AuthError> {
+        // Only the configured algorithm is accepted, so a token can't pick a weaker one
+        let validation = Validation::new(self.jwt_keys.algorithm());
+        decode::<T>(jwt, &self.jwt_keys.decoding_key()?, &validation)
+            .map(|token_data| token_data.claims)
+            .map_err(|_| AuthError::Unauthorized)
+    }
+
+    /// Issue a signed invite granting `granted_caps` on a calendar to whoever accepts it,
+    /// valid for a week and only once. `issued_by` must be able to administer the calendar
+    /// (or be a global admin), otherwise this fails with `Unauthorized`.
+    pub fn create_calendar_invite(
+        &self,
+        calendar_id: i64,
+        granted_caps: CalendarCapabilities,
+        issued_by: i64,
+    ) -> Result<String, AuthError> {
+        let conn = self.conn()?;
+        let can_admin = conn
+            .get_calendar_permission(issued_by, calendar_id)
+            .map_err(AuthError::DbError)?
+            .is_some_and(|perm| perm.can_admin);
+        if !can_admin
+            && !conn
+                .is_global_admin(issued_by)
+                .map_err(AuthError::DbError)?
+        {
+            return Err(AuthError::Unauthorized);
         }
+
+        let claims = InviteClaims {
+            exp: unix_now() + self.token_lifetime(TokenType::CalendarInvite),
+            token_type: TokenType::CalendarInvite,
+            jti: uuid::Uuid::new_v4().to_string(),
+            calendar_id,
+            issued_by,
+            caps: granted_caps,
+        };
+        let token = self.sign_claims(&claims)?;
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0)
+            .ok_or_else(|| AuthError::JwtError("invite expiry out of range".to_owned()))?;
+        conn.insert_calendar_invite(&claims.jti, calendar_id, issued_by, expires_at)
+            .map_err(AuthError::DbError)?;
+        Ok(token)
     }
 
-    /// Per-user rate limiting (requests per minute).
-    fn check_rate_limit(&self, username: &str) -> Result<(), AuthError> {
-        let mut limits = self.rate_limits.lock().unwrap();
-        let now = Instant::now();
-        let entry = limits.entry(username.to_string()).or_insert((0, now));
-        let window = Duration::from_secs(60);
+    /// Accept an invite from `create_calendar_invite`, adding its capabilities to whatever
+    /// `accepting_user_id` already held on the calendar, and return their permission.
+    /// Fails with `Unauthorized` if the token is invalid or expired, and `InviteAlreadyUsed`
+    /// if it was accepted before.
+    pub fn accept_calendar_invite(
+        &self,
+        token: &str,
+        accepting_user_id: i64,
+    ) -> Result<CalendarPermission, AuthError> {
+        let claims: InviteClaims = self.decode_token(token)?;
+        if claims.token_type != TokenType::CalendarInvite {
+            return Err(AuthError::Unauthorized);
+        }
+        let mut conn = self.conn()?;
+        let held = conn
+            .get_calendar_permission(accepting_user_id, claims.calendar_id)
+            .map_err(AuthError::DbError)?
+            .map(|perm| perm.capabilities())
+            .unwrap_or_default();
+        let perm = claims
+            .caps
+            .union(held)
+            .for_user(accepting_user_id, claims.calendar_id);
+        if conn
+            .accept_calendar_invite(&claims.jti, &perm)
+            .map_err(AuthError::DbError)?
+        {
+            Ok(perm)
+        } else {
+            Err(AuthError::InviteAlreadyUsed)
+        }
+    }
 
-        if now.duration_since(entry.1) > window {
-            // Reset window
-            entry.0 = 1;
-            entry.1 = now;
+    /// Validate an access JWT for a given username. Refresh tokens are rejected.
+    pub fn validate_jwt(&self, jwt: &str, username: &str) -> Result<(), AuthError> {
+        let claims = self.decode_claims(jwt)?;
+        if claims.sub == username && claims.token_type == TokenType::Access {
             Ok(())
         } else {
-            if entry.0 < DEFAULT_AUTH_RATE_LIMIT_PER_MINUTE {
-                entry.0 += 1;
-                Ok(())
-            } else {
-                Err(AuthError::RateLimitExceeded)
-            }
+            Err(AuthError::Unauthorized)
         }
     }
 
-    /// Per-IP rate limiting (requests per minute).
-    fn check_ip_rate_limit(&self, ip: &str) -> Result<(), AuthError> {
-        let mut limits = self.ip_rate_limits.lock().unwrap();
-        let now = Instant::now();
-        let entry = limits.entry(ip.to_string()).or_insert((0, now));
-        let window = Duration::from_secs(60);
+    /// Resolve the user an access JWT belongs to, including their id for permission checks.
+    /// Fails with `Unauthorized` if the token is invalid, expired, revoked or not an access token,
+    /// and `UserNotFound` if the account no longer exists.
+    pub fn user_from_jwt(&self, jwt: &str) -> Result<SafeUser, AuthError> {
+        self.user_and_expiry_from_jwt(jwt).map(|(user, _)| user)
+    }
 
-        if now.duration_since(entry.1) > window {
-            // Reset window
-            entry.0 = 1;
-            entry.1 = now;
-            Ok(())
-        } else {
-            if entry.0 < DEFAULT_AUTH_RATE_LIMIT_PER_MINUTE {
-                entry.0 += 1;
-                Ok(())
-            } else {
-                Err(AuthError::RateLimitExceeded)
-            }
+    /// Like `user_from_jwt`, also returning when the token expires (unix seconds), for
+    /// websocket connections that outlive the token they logged in with.
+    pub fn user_and_expiry_from_jwt(&self, jwt: &str) -> Result<(SafeUser, u64), AuthError> {
+        let claims = self.decode_claims(jwt)?;
+        if claims.token_type != TokenType::Access {
+            return Err(AuthError::Unauthorized);
+        }
+        match self.conn()?.get_user_by_username(&claims.sub) {
+            Ok(Some(user)) => Ok((SafeUser::from(user), claims.exp as u64)),
+            Ok(None) => Err(AuthError::UserNotFound),
+            Err(e) => Err(AuthError::DbError(e)),
         }
     }
 
+    /// Per-user rate limiting (requests per window).
+    fn check_rate_limit(&self, username: &str) -> Result<(), AuthError> {
+        check_limit(
+            &self.rate_limits,
+            username.to_string(),
+            self.rate_limit,
+            self.rate_limit_window,
+        )
+    }
+
+    /// Per-IP rate limiting (requests per window).
+    fn check_ip_rate_limit(&self, ip: &str) -> Result<(), AuthError> {
+        check_limit(
+            &self.ip_rate_limits,
+            ip.to_string(),
+            self.rate_limit,
+            self.rate_limit_window,
+        )
+    }
+
     /// Optionally, get user info (without password hash or salt).
     pub fn get_user(&self, username: &str, ip: &str) -> Result<Option<SafeUser>, AuthError> {
         self.check_ip_rate_limit(ip)?;
-        match self.db.get_user_by_username(username) {
+        match self.conn()?.get_user_by_username(username) {
             Ok(Some(user)) => Ok(Some(SafeUser::from(user))),
             Ok(None) => Ok(None),
-            Err(e) => Err(AuthError::DbError(format!("{:?}", e))),
+            Err(e) => Err(AuthError::DbError(e)),
         }
     }
 }
 
-/// A safe user struct that does not expose password hash or salt.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct SafeUser {
-    pub id: i64,
-    pub username: String,
-    pub email: String,
-    pub created_at: String,
-    pub updated_at: String,
-}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::EncodingKey;
 
-impl From<AuthUser> for SafeUser {
-    fn from(user: AuthUser) -> Self {
-        Self {
-            id: user.id,
-            username: user.username,
-            email: user.email,
-            created_at: user.created_at,
-            updated_at: user.updated_at,
+    fn service(mode: HashingMode) -> AuthService {
+        service_with_lockout(mode, LockoutPolicy::default())
+    }
+
+    fn service_with_lockout(mode: HashingMode, lockout: LockoutPolicy) -> AuthService {
+        AuthService::new(
+            DbPool::new_in_memory(2).unwrap(),
+            "secret",
+            None,
+            None,
+            mode,
+            lockout,
+            None,
+            None,
+        )
+    }
+
+    #[test]
+    fn test_status_code_for_each_error() {
+        let pool = DbPool::new_in_memory(1).unwrap();
+        let _held = pool.get().unwrap();
+        let Err(pool_error) = pool.get_timeout(Duration::from_millis(1)) else {
+            panic!("the only connection is checked out");
+        };
+        let cases = [
+            (AuthError::UserAlreadyExists, 409),
+            (AuthError::UserNotFound, 404),
+            (AuthError::InvalidPassword, 401),
+            (AuthError::DbError(db::Error::NotFound), 500),
+            (AuthError::PoolError(pool_error), 500),
+            (AuthError::JwtError("bad signature".to_string()), 500),
+            (AuthError::RateLimitExceeded, 429),
+            (AuthError::Unauthorized, 401),
+            (AuthError::AccountLocked, 423),
+            (AuthError::InvalidEmail, 400),
+            (AuthError::InvalidUsername, 400),
+            (AuthError::InviteAlreadyUsed, 409),
+            (AuthError::SessionNotFound, 404),
+            (AuthError::EmailNotVerified, 403),
+        ];
+        for (error, status) in cases {
+            assert_eq!(error.status_code(), status, "{error}");
+        }
+    }
+
+    #[test]
+    fn test_db_errors_keep_their_source() {
+        use std::error::Error;
+        let error = AuthError::from(db::Error::NotFound);
+        assert!(matches!(error, AuthError::DbError(db::Error::NotFound)));
+        assert_eq!(error.to_string(), "no matching row in database");
+        assert!(
+            error
+                .source()
+                .and_then(|e| e.downcast_ref::<db::Error>())
+                .is_some()
+        );
+        assert!(AuthError::UserNotFound.source().is_none());
+    }
+
+    #[test]
+    fn test_server_hashed_password_verifies() {
+        let auth = service(HashingMode::ServerHashed);
+        auth.register_user("alice", "correct horse", None, "a@x.com", "10.0.0.1")
+            .unwrap();
+
+        // Only an Argon2id hash is stored, never the password
+        let stored = auth
+            .conn()
+            .unwrap()
+            .get_user_by_username("alice")
+            .unwrap()
+            .unwrap();
+        assert!(stored.password_hash.starts_with("$argon2id$"));
+        assert!(!stored.password_hash.contains("correct horse"));
+
+        assert!(
+            auth.authenticate_user("alice", "correct horse", "10.0.0.1")
+                .is_ok()
+        );
+        assert!(matches!(
+            auth.authenticate_user("alice", "wrong horse", "10.0.0.1"),
+            Err(AuthError::InvalidPassword)
+        ));
+    }
+
+    #[test]
+    fn test_refresh_issues_new_access_token() {
+        let auth = service(HashingMode::ServerHashed);
+        let refresh = auth.issue_refresh_token("alice").unwrap();
+        let access = auth.refresh_access_token(&refresh).unwrap();
+        assert!(auth.validate_jwt(&access, "alice").is_ok());
+    }
+
+    #[test]
+    fn test_expired_refresh_token_rejected() {
+        let auth = service(HashingMode::ServerHashed);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        // Past the default 60s validation leeway
+        let claims = Claims {
+            sub: "alice".to_owned(),
+            exp: now - 3600,
+            token_type: TokenType::Refresh,
+            jti: uuid::Uuid::new_v4().to_string(),
+        };
+        let expired = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(matches!(
+            auth.refresh_access_token(&expired),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_token_types_are_not_interchangeable() {
+        let auth = service(HashingMode::ServerHashed);
+        let access = auth.issue_jwt("alice").unwrap();
+        let refresh = auth.issue_refresh_token("alice").unwrap();
+        assert!(matches!(
+            auth.refresh_access_token(&access),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(matches!(
+            auth.validate_jwt(&refresh, "alice"),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_revoking_a_session_invalidates_only_its_token() {
+        let auth = service(HashingMode::ClientHashed);
+        let laptop = auth
+            .register_user("alice", "hash", Some("salt"), "a@x.com", "10.4.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let phone = auth.authenticate_user("alice", "hash", "10.4.0.1").unwrap();
+        let refresh = auth.issue_refresh_token("alice").unwrap();
+        let alice = auth.user_from_jwt(&laptop).unwrap();
+
+        let sessions = auth.list_sessions("alice").unwrap();
+        let jti_of = |jwt: &str| auth.decode_claims(jwt).unwrap().jti;
+        let (laptop_jti, phone_jti) = (jti_of(&laptop), jti_of(&phone));
+        let mut listed: Vec<_> = sessions.iter().map(|s| s.jti.clone()).collect();
+        let mut issued = vec![laptop_jti.clone(), phone_jti.clone(), jti_of(&refresh)];
+        listed.sort();
+        issued.sort();
+        assert_eq!(listed, issued);
+        assert!(sessions.iter().all(|s| s.username == "alice"));
+        assert_eq!(
+            sessions
+                .iter()
+                .filter(|s| s.token_type == "refresh")
+                .count(),
+            1
+        );
+
+        // Someone else can't even tell the session exists
+        let bob_token = auth
+            .register_user("bob", "hash", Some("salt"), "b@x.com", "10.4.0.2")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let bob = auth.user_from_jwt(&bob_token).unwrap();
+        assert!(matches!(
+            auth.revoke_session(&phone_jti, &bob),
+            Err(AuthError::SessionNotFound)
+        ));
+
+        auth.revoke_session(&phone_jti, &alice).unwrap();
+        assert!(matches!(
+            auth.validate_jwt(&phone, "alice"),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(auth.validate_jwt(&laptop, "alice").is_ok());
+        assert!(auth.refresh_access_token(&refresh).is_ok());
+        assert!(
+            auth.list_sessions("alice")
+                .unwrap()
+                .iter()
+                .all(|s| s.jti != phone_jti)
+        );
+        assert!(matches!(
+            auth.revoke_session(&phone_jti, &alice),
+            Err(AuthError::SessionNotFound)
+        ));
+
+        // A global admin can revoke anyone's session
+        auth.conn().unwrap().set_global_admin(bob.id, true).unwrap();
+        auth.revoke_session(&laptop_jti, &bob).unwrap();
+        assert!(auth.validate_jwt(&laptop, "alice").is_err());
+
+        // Changing the password ends every session
+        auth.change_password("bob", "hash2", &bob_token).unwrap();
+        assert!(auth.list_sessions("bob").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_revoked_token_fails_validation() {
+        let auth = service(HashingMode::ServerHashed);
+        let revoked = auth.issue_jwt("alice").unwrap();
+        let other = auth.issue_jwt("alice").unwrap();
+        auth.revoke_token(&revoked).unwrap();
+
+        assert!(matches!(
+            auth.validate_jwt(&revoked, "alice"),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(auth.validate_jwt(&other, "alice").is_ok());
+        let fresh = auth.issue_jwt("alice").unwrap();
+        assert!(auth.validate_jwt(&fresh, "alice").is_ok());
+    }
+
+    #[test]
+    fn test_token_without_jti_rejected() {
+        let auth = service(HashingMode::ServerHashed);
+        auth.issue_jwt("alice").unwrap();
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        let claims = Claims {
+            sub: "alice".to_owned(),
+            exp: now + 3600,
+            token_type: TokenType::Access,
+            jti: String::new(),
+        };
+        let unrevocable = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(matches!(
+            auth.validate_jwt(&unrevocable, "alice"),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_change_password_revokes_existing_tokens() {
+        let auth = service(HashingMode::ServerHashed);
+        let access = auth
+            .register_user("alice", "old password", None, "a@x.com", "10.0.0.3")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let refresh = auth.issue_refresh_token("alice").unwrap();
+        let bob = auth.issue_jwt("bob").unwrap();
+
+        auth.change_password("alice", "new password", &access)
+            .unwrap();
+        assert!(auth.validate_jwt(&access, "alice").is_err());
+        assert!(auth.refresh_access_token(&refresh).is_err());
+        // Other users are unaffected
+        assert!(auth.validate_jwt(&bob, "bob").is_ok());
+
+        let fresh = auth
+            .authenticate_user("alice", "new password", "10.0.0.3")
+            .unwrap();
+        assert!(auth.validate_jwt(&fresh, "alice").is_ok());
+    }
+
+    #[test]
+    fn test_account_locks_after_repeated_failures() {
+        let auth = service(HashingMode::ClientHashed);
+        auth.register_user("alice", "hash", Some("salt"), "a@x.com", "10.1.0.0")
+            .unwrap();
+
+        for i in 0..5 {
+            assert!(matches!(
+                auth.authenticate_user("alice", "wrong", &format!("10.1.0.{}", i + 1)),
+                Err(AuthError::InvalidPassword)
+            ));
+        }
+        // Even the right password is refused while locked
+        assert!(matches!(
+            auth.authenticate_user("alice", "hash", "10.1.1.0"),
+            Err(AuthError::AccountLocked)
+        ));
+    }
+
+    #[test]
+    fn test_lockout_expires_and_success_resets_counter() {
+        let auth = service_with_lockout(
+            HashingMode::ClientHashed,
+            LockoutPolicy {
+                threshold: 2,
+                duration: Duration::from_millis(100),
+            },
+        );
+        auth.register_user("alice", "hash", Some("salt"), "a@x.com", "10.2.0.0")
+            .unwrap();
+
+        assert!(
+            auth.authenticate_user("alice", "wrong", "10.2.0.1")
+                .is_err()
+        );
+        // A success in between resets the consecutive count
+        assert!(auth.authenticate_user("alice", "hash", "10.2.0.2").is_ok());
+        assert!(matches!(
+            auth.authenticate_user("alice", "wrong", "10.2.0.3"),
+            Err(AuthError::InvalidPassword)
+        ));
+        assert!(matches!(
+            auth.authenticate_user("alice", "wrong", "10.2.0.4"),
+            Err(AuthError::InvalidPassword)
+        ));
+        assert!(matches!(
+            auth.authenticate_user("alice", "hash", "10.2.0.5"),
+            Err(AuthError::AccountLocked)
+        ));
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(auth.authenticate_user("alice", "hash", "10.2.0.6").is_ok());
+    }
+
+    #[test]
+    fn test_old_failures_stop_counting() {
+        let auth = service_with_lockout(
+            HashingMode::ClientHashed,
+            LockoutPolicy {
+                threshold: 2,
+                duration: Duration::from_millis(100),
+            },
+        );
+        auth.register_user("alice", "hash", Some("salt"), "a@x.com", "10.2.1.0")
+            .unwrap();
+
+        assert!(
+            auth.authenticate_user("alice", "wrong", "10.2.1.1")
+                .is_err()
+        );
+        // The first failure has aged out of the window by the second
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(matches!(
+            auth.authenticate_user("alice", "wrong", "10.2.1.2"),
+            Err(AuthError::InvalidPassword)
+        ));
+        assert!(auth.authenticate_user("alice", "hash", "10.2.1.3").is_ok());
+    }
+
+    #[test]
+    fn test_user_from_jwt() {
+        let auth = service(HashingMode::ClientHashed);
+        let jwt = auth
+            .register_user("alice", "hash", Some("salt"), "a@x.com", "10.3.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let id = auth
+            .conn()
+            .unwrap()
+            .get_user_by_username("alice")
+            .unwrap()
+            .unwrap()
+            .id;
+
+        let user = auth.user_from_jwt(&jwt).unwrap();
+        assert_eq!(user.id, id);
+        assert_eq!(user.username, "alice");
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as usize;
+        let claims = Claims {
+            sub: "alice".to_owned(),
+            exp: now - 3600,
+            token_type: TokenType::Access,
+            jti: uuid::Uuid::new_v4().to_string(),
+        };
+        let expired = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(b"secret"),
+        )
+        .unwrap();
+        assert!(matches!(
+            auth.user_from_jwt(&expired),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    fn service_with_keys(keys: JwtKeys) -> AuthService {
+        AuthService::new(
+            DbPool::new_in_memory(2).unwrap(),
+            keys,
+            None,
+            None,
+            HashingMode::ServerHashed,
+            LockoutPolicy::default(),
+            None,
+            None,
+        )
+    }
+
+    fn rsa_keys() -> JwtKeys {
+        JwtKeys::Rsa {
+            private_pem: include_str!("../testdata/rsa_private.pem").to_owned(),
+            public_pem: include_str!("../testdata/rsa_public.pem").to_owned(),
+        }
+    }
+
+    fn token_algorithm(jwt: &str) -> jsonwebtoken::Algorithm {
+        jsonwebtoken::decode_header(jwt).unwrap().alg
+    }
+
+    #[test]
+    fn test_hs256_sign_and_verify() {
+        let auth = service_with_keys(JwtKeys::Hmac("secret".to_owned()));
+        let jwt = auth.issue_jwt("alice").unwrap();
+        assert_eq!(token_algorithm(&jwt), jsonwebtoken::Algorithm::HS256);
+        assert!(auth.validate_jwt(&jwt, "alice").is_ok());
+
+        let other = service_with_keys(JwtKeys::Hmac("other secret".to_owned()));
+        assert!(other.validate_jwt(&jwt, "alice").is_err());
+    }
+
+    #[test]
+    fn test_rs256_sign_and_verify() {
+        let auth = service_with_keys(rsa_keys());
+        let jwt = auth.issue_jwt("alice").unwrap();
+        assert_eq!(token_algorithm(&jwt), jsonwebtoken::Algorithm::RS256);
+        assert!(auth.validate_jwt(&jwt, "alice").is_ok());
+        let refresh = auth.issue_refresh_token("alice").unwrap();
+        assert!(auth.refresh_access_token(&refresh).is_ok());
+
+        // An HS256 service doesn't accept RS256 tokens and vice versa
+        let hmac = service_with_keys(JwtKeys::Hmac("secret".to_owned()));
+        assert!(hmac.validate_jwt(&jwt, "alice").is_err());
+        let hs_jwt = hmac.issue_jwt("alice").unwrap();
+        assert!(auth.validate_jwt(&hs_jwt, "alice").is_err());
+    }
+
+    #[test]
+    fn test_password_reset_is_single_use() {
+        let auth = service(HashingMode::ServerHashed);
+        let registration = auth
+            .register_user("alice", "forgotten", None, "a@x.com", "10.4.0.1")
+            .unwrap();
+        let session = registration.access_token.unwrap();
+        auth.verify_email(&registration.verification_token).unwrap();
+
+        let token = auth
+            .create_password_reset_token("a@x.com")
+            .unwrap()
+            .expect("account exists");
+        // A reset token is not an access token
+        assert!(auth.validate_jwt(&token, "alice").is_err());
+
+        auth.reset_password(&token, "remembered").unwrap();
+        assert!(
+            auth.authenticate_user("alice", "remembered", "10.4.0.2")
+                .is_ok()
+        );
+        assert!(auth.validate_jwt(&session, "alice").is_err());
+
+        assert!(matches!(
+            auth.reset_password(&token, "hijacked"),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(
+            auth.authenticate_user("alice", "hijacked", "10.4.0.3")
+                .is_err()
+        );
+
+        // Still used after a restart, which starts with an empty revocation store
+        let restarted = AuthService::new(
+            auth.db.clone(),
+            "secret",
+            None,
+            None,
+            HashingMode::ServerHashed,
+            LockoutPolicy::default(),
+            None,
+            None,
+        );
+        assert!(matches!(
+            restarted.reset_password(&token, "hijacked"),
+            Err(AuthError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_unverified_email_cannot_reset_password() {
+        let auth = service(HashingMode::ServerHashed);
+        let registration = auth
+            .register_user("alice", "forgotten", None, "a@x.com", "10.4.0.1")
+            .unwrap();
+        let verified = || {
+            auth.conn()
+                .unwrap()
+                .get_user_by_username("alice")
+                .unwrap()
+                .unwrap()
+                .email_verified
+        };
+        assert!(!verified());
+        // Same answer as for an address nobody registered
+        assert_eq!(auth.create_password_reset_token("a@x.com").unwrap(), None);
+
+        // Other tokens don't verify anything
+        let access = registration.access_token.unwrap();
+        assert!(matches!(
+            auth.verify_email(&access),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(!verified());
+
+        auth.verify_email(&registration.verification_token).unwrap();
+        assert!(verified());
+        assert!(
+            auth.create_password_reset_token("a@x.com")
+                .unwrap()
+                .is_some()
+        );
+
+        // A token for the old address can't verify a new one
+        auth.change_email("alice", "new@x.com", &access).unwrap();
+        assert!(!verified());
+        assert!(matches!(
+            auth.verify_email(&registration.verification_token),
+            Err(AuthError::Unauthorized)
+        ));
+        let fresh = auth.create_email_verification_token("alice").unwrap();
+        auth.verify_email(&fresh).unwrap();
+        assert!(verified());
+    }
+
+    #[test]
+    fn test_login_can_require_verified_email() {
+        let auth = service(HashingMode::ClientHashed).with_email_verification_required(true);
+        let registration = auth
+            .register_user("alice", "hash", Some("salt"), "a@x.com", "10.4.0.1")
+            .unwrap();
+        assert!(registration.access_token.is_none());
+        assert!(matches!(
+            auth.authenticate_user("alice", "hash", "10.4.0.1"),
+            Err(AuthError::EmailNotVerified)
+        ));
+        // A wrong password is still just a wrong password
+        assert!(matches!(
+            auth.authenticate_user("alice", "nope", "10.4.0.1"),
+            Err(AuthError::InvalidPassword)
+        ));
+
+        auth.verify_email(&registration.verification_token).unwrap();
+        assert!(auth.authenticate_user("alice", "hash", "10.4.0.1").is_ok());
+    }
+
+    /// Register `username` and return their user id.
+    fn register(auth: &AuthService, username: &str) -> i64 {
+        auth.register_user(
+            username,
+            "password",
+            None,
+            &format!("{username}@x.com"),
+            "10.6.0.1",
+        )
+        .unwrap();
+        auth.conn()
+            .unwrap()
+            .get_user_by_username(username)
+            .unwrap()
+            .unwrap()
+            .id
+    }
+
+    fn viewer() -> CalendarCapabilities {
+        CalendarCapabilities {
+            can_view: true,
+            can_read: true,
+            ..Default::default()
         }
     }
+
+    #[test]
+    fn test_calendar_invite_is_single_use() {
+        let auth = service(HashingMode::ServerHashed);
+        let owner = register(&auth, "alice");
+        let guest = register(&auth, "bob");
+        let other = register(&auth, "carol");
+        let calendar_id = auth
+            .conn()
+            .unwrap()
+            .create_calendar_with_owner("Family", colorlab::Color::from_rgb8(1, 2, 3), owner)
+            .unwrap();
+
+        // Only someone who can administer the calendar may invite to it
+        assert!(matches!(
+            auth.create_calendar_invite(calendar_id, viewer(), guest),
+            Err(AuthError::Unauthorized)
+        ));
+        let token = auth
+            .create_calendar_invite(calendar_id, viewer(), owner)
+            .unwrap();
+        // An invite is not an access token
+        assert!(auth.user_from_jwt(&token).is_err());
+
+        let perm = auth.accept_calendar_invite(&token, guest).unwrap();
+        assert_eq!(perm, viewer().for_user(guest, calendar_id));
+        assert_eq!(
+            auth.conn()
+                .unwrap()
+                .get_calendar_permission(guest, calendar_id)
+                .unwrap(),
+            Some(perm)
+        );
+
+        assert!(matches!(
+            auth.accept_calendar_invite(&token, other),
+            Err(AuthError::InviteAlreadyUsed)
+        ));
+        assert!(matches!(
+            auth.accept_calendar_invite(&token, guest),
+            Err(AuthError::InviteAlreadyUsed)
+        ));
+        assert_eq!(
+            auth.conn()
+                .unwrap()
+                .get_calendar_permission(other, calendar_id)
+                .unwrap(),
+            None
+        );
+
+        // Accepting never takes away capabilities the user already had
+        let token = auth
+            .create_calendar_invite(calendar_id, viewer(), owner)
+            .unwrap();
+        assert!(
+            auth.accept_calendar_invite(&token, owner)
+                .unwrap()
+                .can_admin
+        );
+    }
+
+    #[test]
+    fn test_expired_calendar_invite_rejected() {
+        let auth = service(HashingMode::ServerHashed);
+        let owner = register(&auth, "alice");
+        let guest = register(&auth, "bob");
+        let calendar_id = auth
+            .conn()
+            .unwrap()
+            .create_calendar_with_owner("Family", colorlab::Color::from_rgb8(1, 2, 3), owner)
+            .unwrap();
+
+        // Past the default 60s validation leeway
+        let claims = InviteClaims {
+            exp: unix_now() - 3600,
+            token_type: TokenType::CalendarInvite,
+            jti: uuid::Uuid::new_v4().to_string(),
+            calendar_id,
+            issued_by: owner,
+            caps: viewer(),
+        };
+        let expired = auth.sign_claims(&claims).unwrap();
+        assert!(matches!(
+            auth.accept_calendar_invite(&expired, guest),
+            Err(AuthError::Unauthorized)
+        ));
+
+        // Nor is a token that was never recorded as issued
+        let unrecorded = auth
+            .sign_claims(&InviteClaims {
+                exp: unix_now() + 3600,
+                ..claims
+            })
+            .unwrap();
+        assert!(matches!(
+            auth.accept_calendar_invite(&unrecorded, guest),
+            Err(AuthError::InviteAlreadyUsed)
+        ));
+        assert_eq!(
+            auth.conn()
+                .unwrap()
+                .get_calendar_permission(guest, calendar_id)
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_password_reset_unknown_email() {
+        let auth = service(HashingMode::ServerHashed);
+        assert_eq!(
+            auth.create_password_reset_token("nobody@x.com").unwrap(),
+            None
+        );
+        // Access tokens can't be used to reset a password
+        let access = auth.issue_jwt("alice").unwrap();
+        assert!(auth.reset_password(&access, "whatever").is_err());
+    }
+
+    #[test]
+    fn test_change_email() {
+        let auth = service(HashingMode::ClientHashed);
+        let jwt = auth
+            .register_user("alice", "hash", Some("salt"), "a@x.com", "10.5.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let email = |auth: &AuthService| {
+            auth.conn()
+                .unwrap()
+                .get_user_by_username("alice")
+                .unwrap()
+                .unwrap()
+                .email
+        };
+
+        auth.change_email("alice", "alice@example.org", &jwt)
+            .unwrap();
+        assert_eq!(email(&auth), "alice@example.org");
+
+        for bad in [
+            "alice",
+            "alice@",
+            "@example.org",
+            "alice@localhost",
+            "a b@x.com",
+            "a@x..com",
+        ] {
+            assert!(matches!(
+                auth.change_email("alice", bad, &jwt),
+                Err(AuthError::InvalidEmail)
+            ));
+        }
+
+        let bob = auth.issue_jwt("bob").unwrap();
+        assert!(matches!(
+            auth.change_email("alice", "bob@example.org", &bob),
+            Err(AuthError::Unauthorized)
+        ));
+        assert_eq!(email(&auth), "alice@example.org");
+    }
+
+    #[test]
+    fn test_delete_account() {
+        let auth = service(HashingMode::ClientHashed);
+        let alice = auth
+            .register_user("alice", "hash", Some("salt"), "a@x.com", "10.6.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let bob = auth
+            .register_user("bob", "hash", Some("salt"), "b@x.com", "10.6.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+
+        // Needs the user's own valid token
+        assert!(matches!(
+            auth.delete_account("alice", &bob),
+            Err(AuthError::Unauthorized)
+        ));
+        assert!(matches!(
+            auth.delete_account("alice", "not a jwt"),
+            Err(AuthError::Unauthorized)
+        ));
+
+        auth.delete_account("alice", &alice).unwrap();
+        assert!(auth.validate_jwt(&alice, "alice").is_err());
+        assert!(matches!(
+            auth.authenticate_user("alice", "hash", "10.6.0.2"),
+            Err(AuthError::UserNotFound)
+        ));
+        assert!(auth.validate_jwt(&bob, "bob").is_ok());
+    }
+
+    #[test]
+    fn test_configurable_rate_limit() {
+        let auth = AuthService::new(
+            DbPool::new_in_memory(2).unwrap(),
+            "secret",
+            None,
+            None,
+            HashingMode::ServerHashed,
+            LockoutPolicy::default(),
+            Some(2),
+            Some(Duration::from_millis(100)),
+        );
+        assert!(auth.get_user("alice", "10.7.0.1").is_ok());
+        assert!(auth.get_user("alice", "10.7.0.1").is_ok());
+        assert!(matches!(
+            auth.get_user("alice", "10.7.0.1"),
+            Err(AuthError::RateLimitExceeded)
+        ));
+        // Other IPs have their own budget
+        assert!(auth.get_user("alice", "10.7.0.2").is_ok());
+
+        std::thread::sleep(Duration::from_millis(150));
+        assert!(auth.get_user("alice", "10.7.0.1").is_ok());
+    }
+
+    #[test]
+    fn test_validate_username() {
+        let policy = UsernamePolicy::default();
+        for good in ["alice", "Alice_99", "a.b-c", "x", &"a".repeat(64)] {
+            assert!(validate_username(good, &policy).is_ok(), "{good:?}");
+        }
+        for bad in [
+            "",
+            &"a".repeat(65),
+            " alice",
+            "alice ",
+            "al ice",
+            "al\u{7}ice",
+            "alice!",
+            "élise",
+        ] {
+            assert!(
+                matches!(
+                    validate_username(bad, &policy),
+                    Err(AuthError::InvalidUsername)
+                ),
+                "{bad:?}"
+            );
+        }
+
+        // A looser policy still can't allow padding or control characters
+        let loose = UsernamePolicy {
+            max_length: 8,
+            allowed_char: |_| true,
+        };
+        assert!(validate_username("al ice", &loose).is_ok());
+        assert!(validate_username("élise", &loose).is_ok());
+        assert!(validate_username(" alice", &loose).is_err());
+        assert!(validate_username("al\tice", &loose).is_err());
+        assert!(validate_username("alice_long", &loose).is_err());
+    }
+
+    #[test]
+    fn test_register_rejects_invalid_username() {
+        let auth = service(HashingMode::ClientHashed);
+        assert!(matches!(
+            auth.register_user(" alice", "hash", Some("salt"), "a@x.com", "10.8.0.1"),
+            Err(AuthError::InvalidUsername)
+        ));
+        assert!(auth.get_user(" alice", "10.8.0.1").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_registration_limited_per_ip() {
+        let auth = AuthService::new(
+            DbPool::new_in_memory(2).unwrap(),
+            "secret",
+            None,
+            None,
+            HashingMode::ClientHashed,
+            LockoutPolicy::default(),
+            Some(100),
+            None,
+        )
+        .with_registration_limit(3, Duration::from_secs(3600));
+        let attacker: IpAddr = "203.0.113.7".parse().unwrap();
+        let other: IpAddr = "198.51.100.1".parse().unwrap();
+
+        for i in 0..3 {
+            auth.register_user_from(
+                attacker,
+                &format!("bot{i}"),
+                "hash",
+                Some("salt"),
+                &format!("bot{i}@x.com"),
+            )
+            .unwrap();
+        }
+        // Fresh usernames don't help, the cap is on the source IP
+        assert!(matches!(
+            auth.register_user_from(attacker, "bot3", "hash", Some("salt"), "bot3@x.com"),
+            Err(AuthError::RateLimitExceeded)
+        ));
+        assert!(auth.get_user("bot3", "10.9.0.1").unwrap().is_none());
+
+        auth.register_user_from(other, "alice", "hash", Some("salt"), "a@x.com")
+            .unwrap();
+    }
+
+    #[test]
+    fn test_hashes_match() {
+        assert!(hashes_match("abcdef", "abcdef"));
+        assert!(!hashes_match("abcdef", "abcdeg"));
+        assert!(!hashes_match("abcdef", "zbcdef"));
+        assert!(!hashes_match("abcdef", "abc"));
+        assert!(!hashes_match("abcdef", ""));
+        assert!(hashes_match("", ""));
+    }
+
+    #[test]
+    fn test_client_hashed_password_verifies() {
+        let auth = service(HashingMode::ClientHashed);
+        auth.register_user(
+            "bob",
+            "client-hash",
+            Some("client-salt"),
+            "b@x.com",
+            "10.0.0.2",
+        )
+        .unwrap();
+        assert_eq!(auth.get_salt("bob", "10.0.0.2").unwrap(), "client-salt");
+
+        assert!(
+            auth.authenticate_user("bob", "client-hash", "10.0.0.2")
+                .is_ok()
+        );
+        assert!(matches!(
+            auth.authenticate_user("bob", "other-hash", "10.0.0.2"),
+            Err(AuthError::InvalidPassword)
+        ));
+    }
 }
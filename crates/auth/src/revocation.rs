@@ -0,0 +1,104 @@
+//! Tracking of revoked JWTs, so logging out or changing a password invalidates tokens before they expire.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Storage for issued and revoked token ids (`jti` claims).
+/// The in-memory store forgets everything on restart, a DB-backed store can implement this to persist it.
+pub trait RevocationStore: Send + Sync {
+    /// Remember that `jti` was issued to `username` and expires at `exp` (unix seconds),
+    /// so it can be revoked along with the rest of the user's tokens.
+    fn record_issued(&self, username: &str, jti: &str, exp: usize);
+
+    /// Revoke a single token expiring at `exp` (unix seconds). Once it has expired it's
+    /// rejected anyway, so the revocation can be forgotten.
+    fn revoke(&self, jti: &str, exp: usize);
+
+    /// Revoke every token issued to `username` that hasn't expired yet.
+    fn revoke_all_for_user(&self, username: &str);
+
+    /// Whether the token has been revoked.
+    fn is_revoked(&self, jti: &str) -> bool;
+}
+
+/// Process-local `RevocationStore`. Tokens are dropped from it once they expire, so it only
+/// grows with the tokens that are still valid.
+#[derive(Default)]
+pub struct InMemoryRevocationStore {
+    revoked: Mutex<HashMap<String, usize>>, // jti -> exp
+    issued: Mutex<HashMap<String, Vec<(String, usize)>>>, // username -> [(jti, exp)]
+}
+
+impl InMemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+fn unix_now() -> usize {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Time went backwards")
+        .as_secs() as usize
+}
+
+/// Forget revocations of tokens that have expired, they're rejected anyway.
+fn prune_revoked(revoked: &mut HashMap<String, usize>, now: usize) {
+    revoked.retain(|_, exp| *exp > now);
+}
+
+impl RevocationStore for InMemoryRevocationStore {
+    fn record_issued(&self, username: &str, jti: &str, exp: usize) {
+        let now = unix_now();
+        let mut issued = self.issued.lock().unwrap();
+        // Expired tokens are rejected anyway, no need to keep tracking them
+        issued.retain(|_, tokens| {
+            tokens.retain(|(_, exp)| *exp > now);
+            !tokens.is_empty()
+        });
+        issued
+            .entry(username.to_string())
+            .or_default()
+            .push((jti.to_string(), exp));
+    }
+
+    fn revoke(&self, jti: &str, exp: usize) {
+        let mut revoked = self.revoked.lock().unwrap();
+        prune_revoked(&mut revoked, unix_now());
+        revoked.insert(jti.to_string(), exp);
+    }
+
+    fn revoke_all_for_user(&self, username: &str) {
+        let tokens = self.issued.lock().unwrap().remove(username);
+        let mut revoked = self.revoked.lock().unwrap();
+        prune_revoked(&mut revoked, unix_now());
+        revoked.extend(tokens.into_iter().flatten());
+    }
+
+    fn is_revoked(&self, jti: &str) -> bool {
+        self.revoked.lock().unwrap().contains_key(jti)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expired_tokens_are_forgotten() {
+        let store = InMemoryRevocationStore::new();
+        let now = unix_now();
+        store.record_issued("alice", "old", now - 10);
+        store.record_issued("bob", "current", now + 3600);
+        // Recording bob's token dropped alice's expired one, and alice with it
+        assert_eq!(store.issued.lock().unwrap().len(), 1);
+
+        store.revoke("expired", now - 10);
+        assert!(store.is_revoked("expired"));
+        store.revoke_all_for_user("bob");
+        assert!(store.is_revoked("current"));
+        assert!(!store.is_revoked("expired"));
+        assert_eq!(store.revoked.lock().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,81 @@
+//! `create-admin`: bootstrap a global admin from the command line, e.g. the first one on a
+//! fresh install, where there's nobody yet who could grant it through the API.
+
+use appstate::AppState;
+use auth::AuthError;
+
+/// Register `username` and make them a global admin, returning their user id.
+/// Their email counts as verified, whoever can run this already controls the server.
+pub fn create_admin(
+    state: &AppState,
+    username: &str,
+    email: &str,
+    password: &str,
+) -> Result<i64, AuthError> {
+    let registration = state
+        .auth
+        .register_user(username, password, None, email, "127.0.0.1")?;
+    state.auth.verify_email(&registration.verification_token)?;
+    let conn = state.database.get()?;
+    let user = conn
+        .get_user_by_username(username)?
+        .ok_or(AuthError::UserNotFound)?;
+    conn.set_global_admin(user.id, true)?;
+    Ok(user.id)
+}
+
+/// Ask for the new admin's password twice on the terminal, without echoing it.
+pub fn prompt_password() -> std::io::Result<String> {
+    loop {
+        let password = rpassword::prompt_password("Password: ")?;
+        if password.is_empty() {
+            eprintln!("The password can't be empty.");
+            continue;
+        }
+        if rpassword::prompt_password("Confirm password: ")? == password {
+            return Ok(password);
+        }
+        eprintln!("Passwords don't match, try again.");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_state(require_email_verification: bool) -> AppState {
+        let mut config = config::Config::default();
+        config.auth.require_email_verification = require_email_verification;
+        AppState::new_in_memory(config)
+    }
+
+    #[test]
+    fn test_create_admin() {
+        let state = test_state(true);
+        let id = create_admin(&state, "root", "root@example.com", "hunter2").unwrap();
+
+        {
+            let conn = state.database.get().unwrap();
+            assert!(conn.is_global_admin(id).unwrap());
+            let user = conn.get_user_by_username("root").unwrap().unwrap();
+            assert!(user.email_verified);
+        }
+        // Can log in straight away, even with verification required
+        assert!(
+            state
+                .auth
+                .authenticate_user("root", "hunter2", "127.0.0.1")
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_create_admin_refuses_existing_user() {
+        let state = test_state(false);
+        create_admin(&state, "root", "root@example.com", "hunter2").unwrap();
+        assert!(matches!(
+            create_admin(&state, "root", "other@example.com", "pw"),
+            Err(AuthError::UserAlreadyExists)
+        ));
+    }
+}
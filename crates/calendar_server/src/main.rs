@@ -1,18 +1,100 @@
 use appstate::{await_any_task, spawn_tasks};
+use clap::{Parser, Subcommand};
 use configman::ConfigMan;
 use global_constants::LOGS_PATH;
 use tracing::*;
 use webserver::start_web_server;
+use websockets::run_reminders;
+
+mod admin;
+
+#[derive(Parser)]
+#[command(about = "Self-hosted calendar server, runs the server when no command is given")]
+struct Cli {
+    /// Load and validate config.json, then exit (non-zero if it has problems) without starting
+    #[arg(long)]
+    check_config: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Create a global admin (prompting for their password), then exit
+    CreateAdmin {
+        #[arg(long)]
+        username: String,
+        #[arg(long)]
+        email: String,
+    },
+}
 
 #[tokio::main]
 async fn main() {
-    logging::init_logging();
-    info!("Initializing config...");
-    let conf = ConfigMan::load_or_init_config("config.json");
+    let cli = Cli::parse();
+    if cli.check_config {
+        match ConfigMan::check_config("config.json") {
+            Ok(_) => {
+                println!("config.json is valid");
+                std::process::exit(0);
+            }
+            Err(e) => {
+                eprintln!("config.json is not valid: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+    // Logging is configured by the config file, so it's loaded first
+    // (with the default log settings standing in if it can't be)
+    let loaded = ConfigMan::try_load_or_init_config("config.json");
+    let log_config = loaded
+        .as_ref()
+        .map(|conf| conf.logs.clone())
+        .unwrap_or_default();
+    logging::init_logging(&log_config);
+    let conf = match loaded {
+        Ok(conf) => conf,
+        Err(e) => {
+            error!("Could not load config.json: {}", e);
+            error!("Fix or remove the file and start again.");
+            logging::flush_logs();
+            std::process::exit(1);
+        }
+    };
+    if let Some(Command::CreateAdmin { username, email }) = cli.command {
+        let state = appstate::AppState::new(conf);
+        let created = admin::prompt_password()
+            .map_err(|e| format!("couldn't read the password: {e}"))
+            .and_then(|password| {
+                admin::create_admin(&state, &username, &email, &password).map_err(|e| e.to_string())
+            });
+        let code = match created {
+            Ok(id) => {
+                info!("Created global admin {:?} (user id {})", username, id);
+                0
+            }
+            Err(e) => {
+                error!("Could not create admin {:?}: {}", username, e);
+                1
+            }
+        };
+        logging::flush_logs();
+        std::process::exit(code);
+    }
     info!("Checking for old logs to clean...");
     logging::cleanup_old_logs(LOGS_PATH, conf.logs.keep_for.clone());
     let state = appstate::AppState::new(conf);
-    let count = spawn_tasks!(state, start_web_server);
+    // Kept alive for the life of the process, dropping it stops the reloads
+    let _config_watcher = state
+        .watch_config(std::path::Path::new("config.json"))
+        .inspect_err(|e| warn!("Config changes won't be picked up until restart: {}", e))
+        .ok();
+    {
+        // Ctrl-C and SIGTERM drain connections instead of killing the process outright
+        let state = state.clone();
+        tokio::spawn(async move { state.shutdown_on(appstate::shutdown_signal()).await });
+    }
+    let count = spawn_tasks!(state, start_web_server, run_reminders);
     info!(
         "Spawned {} task{}",
         count,
@@ -20,4 +102,5 @@ async fn main() {
     );
 
     await_any_task!(state).await;
+    logging::flush_logs();
 }
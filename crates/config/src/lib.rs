@@ -1,12 +1,23 @@
-use global_constants::DEFAULT_CONFIG_VERSION;
+use global_constants::{
+    DEFAULT_AUTH_EXPIRY_WARNING_SECONDS, DEFAULT_BROADCAST_CAPACITY, DEFAULT_CONFIG_VERSION,
+    DEFAULT_DATABASE_POOL_SIZE, DEFAULT_HTTP_REQUEST_BURST, DEFAULT_HTTP_REQUESTS_PER_SECOND,
+    DEFAULT_JWT_EXPIRY_SECONDS, DEFAULT_WS_CHANGE_LOG_CAPACITY, DEFAULT_WS_COMPRESSION_THRESHOLD,
+    DEFAULT_WS_IDLE_TIMEOUT_SECONDS, DEFAULT_WS_MAX_CONNECTIONS, DEFAULT_WS_MESSAGE_BURST,
+    DEFAULT_WS_MESSAGES_PER_SECOND, DEFAULT_WS_PING_INTERVAL_SECONDS,
+};
 use humantime_serde;
 use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
 use std::io::Write;
-use std::path::Path;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
 use std::time::Duration;
 use tracing::*;
 
+mod watch;
+pub use watch::ConfigWatcher;
+
 ///this file specifies the configs and their defaults, as well as the logic to load them from a file or create a new one if it doesn't exist, the defaults are all
 /// designed to be safe and secure for a local only webserver with authentication enabled by default, the user can decide how lax they want security to be
 /// but as a knowledgable person it is my job to make sure the defaults are locked down well enough to prevent accidental exposure to the internet by someone
@@ -16,40 +27,133 @@ use tracing::*;
 pub struct NetworkConfig {
     pub interface: String,
     pub port: u16,
+    /// When `interface` is `::`, accept IPv4 clients as well as IPv6 ones (clears `IPV6_V6ONLY`)
+    #[serde(default)]
+    pub dual_stack: bool,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct AuthConfig {
     pub require_login: bool,
+    /// How long before a websocket connection's token expires the client is told to refresh it
+    #[serde(with = "humantime_serde", default = "default_expiry_warning")]
+    pub expiry_warning: Duration,
+    /// Secret that JWTs are signed with. Generated for new config files, and for existing
+    /// files without one it's generated on load and saved back so tokens survive restarts
+    #[serde(default = "generate_jwt_secret")]
+    pub jwt_secret: String,
+    /// How long an access token stays valid, in seconds
+    #[serde(default = "default_jwt_expiry_seconds")]
+    pub jwt_expiry_seconds: usize,
+    /// Refuse logins until the user has verified their email address.
+    /// New accounts don't get an access token when they register either
+    #[serde(default)]
+    pub require_email_verification: bool,
+}
+
+fn default_jwt_expiry_seconds() -> usize {
+    DEFAULT_JWT_EXPIRY_SECONDS
+}
+
+fn generate_jwt_secret() -> String {
+    // Two v4 UUIDs give 244 random bits
+    format!(
+        "{}{}",
+        uuid::Uuid::new_v4().simple(),
+        uuid::Uuid::new_v4().simple()
+    )
+}
+
+fn default_expiry_warning() -> Duration {
+    Duration::from_secs(DEFAULT_AUTH_EXPIRY_WARNING_SECONDS)
 }
 
 impl Default for AuthConfig {
     fn default() -> Self {
         Self {
             require_login: true,
+            expiry_warning: default_expiry_warning(),
+            jwt_secret: generate_jwt_secret(),
+            jwt_expiry_seconds: default_jwt_expiry_seconds(),
+            require_email_verification: false,
         }
     }
 }
 
+/// Parse an interface to listen on: `localhost` or an IP address, IPv6 ones with or without
+/// brackets (`::1` or `[::1]`). `None` if it's none of those.
+pub fn parse_interface(interface: &str) -> Option<IpAddr> {
+    let interface = interface.trim();
+    if interface == "localhost" {
+        return Some(IpAddr::V4(Ipv4Addr::LOCALHOST));
+    }
+    match interface
+        .strip_prefix('[')
+        .and_then(|rest| rest.strip_suffix(']'))
+    {
+        Some(v6) => v6.parse::<Ipv6Addr>().ok().map(IpAddr::V6),
+        None => interface.parse().ok(),
+    }
+}
+
+impl NetworkConfig {
+    /// The address `interface` names, see `parse_interface`.
+    pub fn ip(&self) -> Option<IpAddr> {
+        parse_interface(&self.interface)
+    }
+}
+
 impl Default for NetworkConfig {
     fn default() -> Self {
         Self {
             interface: "127.0.0.1".to_string(),
             port: 8080,
+            dual_stack: false,
         }
     }
 }
 
+/// How log lines are written, to the console and the log file alike.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line (timestamp, level, target, fields), for log aggregators
+    Json,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct LogConfig {
     #[serde(with = "humantime_serde")]
     pub keep_for: Duration,
+    #[serde(default)]
+    pub format: LogFormat,
+    /// trace, debug, info, warn, error or off. The RUST_LOG environment variable overrides it
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// Also copy warnings and errors to this file, for a quick look at what went wrong
+    #[serde(default)]
+    pub error_file: Option<String>,
+}
+
+/// Everything in debug builds, info and up in release builds.
+fn default_log_level() -> String {
+    if cfg!(debug_assertions) {
+        "trace".to_string()
+    } else {
+        "info".to_string()
+    }
 }
 
 impl Default for LogConfig {
     fn default() -> Self {
         Self {
             keep_for: Duration::from_secs(60 * 60 * 24 * 7), // 1 week
+            format: LogFormat::default(),
+            log_level: default_log_level(),
+            error_file: None,
         }
     }
 }
@@ -57,16 +161,165 @@ impl Default for LogConfig {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DatabaseConfig {
     pub path: String,
+    /// Maximum number of pooled connections to the database
+    #[serde(default = "default_pool_size")]
+    pub pool_size: u32,
+}
+
+fn default_pool_size() -> u32 {
+    DEFAULT_DATABASE_POOL_SIZE
 }
 
 impl Default for DatabaseConfig {
     fn default() -> Self {
         Self {
             path: "database.db".to_string(),
+            pool_size: default_pool_size(),
         }
     }
 }
 
+/// Websocket keepalive: the server pings every `ping_interval` and drops connections
+/// that haven't answered for `idle_timeout`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WebsocketConfig {
+    #[serde(with = "humantime_serde", default = "default_ping_interval")]
+    pub ping_interval: Duration,
+    #[serde(with = "humantime_serde", default = "default_idle_timeout")]
+    pub idle_timeout: Duration,
+    /// Upgrades beyond this many open connections are refused with 503
+    #[serde(default = "default_max_connections")]
+    pub max_connections: usize,
+    /// How many global broadcast messages are kept for clients that fall behind (rounded up to a
+    /// power of two). Clients further behind than this skip ahead and miss messages, but every
+    /// slot is held in memory for as long as the slowest client needs it, so raise it with care
+    #[serde(default = "default_broadcast_capacity")]
+    pub broadcast_capacity: usize,
+    /// How many messages per second each connection may send on average, 0 for no limit.
+    /// Messages beyond it are dropped and answered with a `rate_limited` error
+    #[serde(default = "default_messages_per_second")]
+    pub messages_per_second: u32,
+    /// How many messages a connection may send at once before `messages_per_second` applies
+    #[serde(default = "default_message_burst")]
+    pub message_burst: u32,
+    /// How many recent event changes are kept for clients catching up after a reconnect.
+    /// A client that missed more than this has to fetch its calendars again
+    #[serde(default = "default_change_log_capacity")]
+    pub change_log_capacity: usize,
+    /// Binary messages to clients at least this many bytes are deflated, 0 to never compress
+    #[serde(default = "default_compression_threshold")]
+    pub compression_threshold: usize,
+}
+
+fn default_max_connections() -> usize {
+    DEFAULT_WS_MAX_CONNECTIONS
+}
+
+fn default_broadcast_capacity() -> usize {
+    DEFAULT_BROADCAST_CAPACITY
+}
+
+fn default_messages_per_second() -> u32 {
+    DEFAULT_WS_MESSAGES_PER_SECOND
+}
+
+fn default_message_burst() -> u32 {
+    DEFAULT_WS_MESSAGE_BURST
+}
+
+fn default_change_log_capacity() -> usize {
+    DEFAULT_WS_CHANGE_LOG_CAPACITY
+}
+
+fn default_compression_threshold() -> usize {
+    DEFAULT_WS_COMPRESSION_THRESHOLD
+}
+
+fn default_ping_interval() -> Duration {
+    Duration::from_secs(DEFAULT_WS_PING_INTERVAL_SECONDS)
+}
+
+fn default_idle_timeout() -> Duration {
+    Duration::from_secs(DEFAULT_WS_IDLE_TIMEOUT_SECONDS)
+}
+
+impl Default for WebsocketConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: default_ping_interval(),
+            idle_timeout: default_idle_timeout(),
+            max_connections: default_max_connections(),
+            broadcast_capacity: default_broadcast_capacity(),
+            messages_per_second: default_messages_per_second(),
+            message_burst: default_message_burst(),
+            change_log_capacity: default_change_log_capacity(),
+            compression_threshold: default_compression_threshold(),
+        }
+    }
+}
+
+/// Which other origins (e.g. a frontend dev server or app) may call the API from a browser.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CorsConfig {
+    /// Origins like `http://localhost:5173`. Empty means same-origin only, no CORS headers are sent
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Whether those origins may send cookies/credentials along
+    #[serde(default)]
+    pub allow_credentials: bool,
+}
+
+/// Per-IP limit on HTTP requests, on top of the auth service's own limits.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct HttpRateLimitConfig {
+    /// How many requests per second each IP may make on average, 0 for no limit.
+    /// Requests beyond it are answered 429 with a `Retry-After` header
+    #[serde(default = "default_http_requests_per_second")]
+    pub requests_per_second: u32,
+    /// How many requests an IP may make at once before `requests_per_second` applies
+    #[serde(default = "default_http_request_burst")]
+    pub burst: u32,
+    /// Take the client's IP from the last `X-Forwarded-For` entry instead of the peer address.
+    /// Only turn this on behind a reverse proxy that sets the header, otherwise clients can
+    /// pick their own IP and dodge the limit
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+fn default_http_requests_per_second() -> u32 {
+    DEFAULT_HTTP_REQUESTS_PER_SECOND
+}
+
+fn default_http_request_burst() -> u32 {
+    DEFAULT_HTTP_REQUEST_BURST
+}
+
+impl Default for HttpRateLimitConfig {
+    fn default() -> Self {
+        Self {
+            requests_per_second: default_http_requests_per_second(),
+            burst: default_http_request_burst(),
+            trust_forwarded_for: false,
+        }
+    }
+}
+
+/// Restrictions on how calendars may look.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct CalendarConfig {
+    /// Only let calendars use these colors (`#RGB`, `#RRGGBB` or CSS names), any color if unset.
+    /// Entries that aren't colors are logged and ignored
+    #[serde(default)]
+    pub allowed_palette: Option<Vec<String>>,
+}
+
+/// Certificate and private key (both PEM) to serve HTTPS with.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Config {
     pub version: usize,
@@ -74,6 +327,25 @@ pub struct Config {
     pub network: NetworkConfig,
     pub auth: AuthConfig,
     pub database: DatabaseConfig,
+    #[serde(default)]
+    pub cors: CorsConfig,
+    #[serde(default)]
+    pub websocket: WebsocketConfig,
+    #[serde(default)]
+    pub http_rate_limit: HttpRateLimitConfig,
+    #[serde(default)]
+    pub calendars: CalendarConfig,
+    /// Serve HTTPS instead of plain HTTP when set
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Serve the frontend from this directory instead of the copy built into the binary,
+    /// files missing from it still fall back to the built-in copy
+    #[serde(default)]
+    pub static_dir: Option<String>,
+    /// Serve `/metrics` on a listener of its own at this address instead of alongside
+    /// everything else, e.g. to keep it on an internal interface
+    #[serde(default)]
+    pub metrics_listen: Option<NetworkConfig>,
 }
 
 impl Default for Config {
@@ -84,12 +356,94 @@ impl Default for Config {
             network: NetworkConfig::default(),
             auth: AuthConfig::default(),
             database: DatabaseConfig::default(),
+            cors: CorsConfig::default(),
+            websocket: WebsocketConfig::default(),
+            http_rate_limit: HttpRateLimitConfig::default(),
+            calendars: CalendarConfig::default(),
+            tls: None,
+            static_dir: None,
+            metrics_listen: None,
+        }
+    }
+}
+
+/// Why a config file couldn't be loaded.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// Reading or writing the file (or creating its directory) failed
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// The file isn't valid JSON or doesn't match the config's shape
+    Parse {
+        path: PathBuf,
+        source: serde_json::Error,
+    },
+    /// The file is for a config version this build can't read (and couldn't upgrade)
+    UnsupportedVersion {
+        found: Option<usize>,
+        expected: usize,
+    },
+    /// The file couldn't be watched for changes
+    Watch {
+        path: PathBuf,
+        source: notify::Error,
+    },
+    /// The file parsed, but some values can't work, one message per problem
+    Invalid(Vec<String>),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "failed to access config file {:?}: {}", path, source)
+            }
+            ConfigError::Parse { path, source } => {
+                write!(f, "failed to parse config file {:?}: {}", path, source)
+            }
+            ConfigError::UnsupportedVersion { found, expected } => match found {
+                Some(found) => write!(
+                    f,
+                    "unsupported config version {} (expected {})",
+                    found, expected
+                ),
+                None => write!(f, "config has no version (expected {})", expected),
+            },
+            ConfigError::Watch { path, source } => {
+                write!(f, "failed to watch config file {:?}: {}", path, source)
+            }
+            ConfigError::Invalid(problems) => write!(f, "invalid config: {}", problems.join("; ")),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::UnsupportedVersion { .. } => None,
+            ConfigError::Watch { source, .. } => Some(source),
+            ConfigError::Invalid(_) => None,
         }
     }
 }
 
 impl Config {
+    /// Load the config at `path`, creating it with the defaults if it doesn't exist.
+    /// Panics on any error, see `try_from_path` to handle them instead.
     pub fn from_path(path: &Path) -> Self {
+        Self::try_from_path(path).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Load the config at `path`, creating it (and its directory) with the defaults if it doesn't exist.
+    pub fn try_from_path(path: &Path) -> Result<Self, ConfigError> {
+        let io_error = |source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
         if !path.exists() {
             warn!(
                 "Config not found at {:?}, using default and creating new config file",
@@ -98,30 +452,327 @@ impl Config {
             let def: Config = Config::default();
             // Try to create the parent directory if it doesn't exist
             if let Some(parent) = path.parent() {
-                if let Err(e) = fs::create_dir_all(parent) {
-                    panic!("Failed to create config directory {:?}: {}", parent, e);
-                }
+                fs::create_dir_all(parent).map_err(io_error)?;
             }
             // Try to write the default config to the file
             let pretty = serde_json::to_string_pretty(&def)
                 .expect("Failed to serialize default config to JSON");
-            match fs::File::create(path) {
-                Ok(mut file) => {
-                    if let Err(e) = file.write_all(pretty.as_bytes()) {
-                        panic!("Failed to write default config to file {:?}: {}", path, e);
-                    }
-                }
-                Err(e) => {
-                    panic!("Failed to create config file {:?}: {}", path, e);
+            let mut file = fs::File::create(path).map_err(io_error)?;
+            file.write_all(pretty.as_bytes()).map_err(io_error)?;
+            Ok(def)
+        } else {
+            let (conf, generated_secret) = Self::parse_existing(path)?;
+            if generated_secret {
+                // Save the new secret, otherwise every restart would invalidate all tokens
+                info!("Saving a newly generated JWT secret to {:?}", path);
+                let pretty = serde_json::to_string_pretty(&conf)
+                    .expect("Failed to serialize config to JSON");
+                fs::write(path, pretty).map_err(io_error)?;
+            }
+            Ok(conf)
+        }
+    }
+
+    /// Read and parse a config file that's known to exist, without creating or changing anything.
+    fn read_existing(path: &Path) -> Result<Self, ConfigError> {
+        Self::parse_existing(path).map(|(conf, _)| conf)
+    }
+
+    /// Like `read_existing`, also saying whether the file had no JWT secret (so one was generated).
+    fn parse_existing(path: &Path) -> Result<(Self, bool), ConfigError> {
+        let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        };
+        let value: serde_json::Value = serde_json::from_str(&data).map_err(parse_error)?;
+        let has_secret = value
+            .get("auth")
+            .and_then(|auth| auth.get("jwt_secret"))
+            .is_some_and(|secret| secret.is_string());
+        let conf: Config = serde_json::from_value(value).map_err(parse_error)?;
+        if conf.version != DEFAULT_CONFIG_VERSION {
+            return Err(ConfigError::UnsupportedVersion {
+                found: Some(conf.version),
+                expected: DEFAULT_CONFIG_VERSION,
+            });
+        }
+        Ok((conf, !has_secret))
+    }
+}
+
+impl Config {
+    /// Check the values serde can't: that the listen addresses are usable, the TLS files can be
+    /// read and the log level is one `logging` understands. Every problem found is reported.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut problems = Vec::new();
+        let mut check_network = |name: &str, network: &NetworkConfig| {
+            if network.port == 0 {
+                problems.push(format!("{name}.port must be from 1 to 65535"));
+            }
+            if network.ip().is_none() {
+                problems.push(format!(
+                    "{name}.interface {:?} is not an IP address or localhost",
+                    network.interface
+                ));
+            }
+        };
+        check_network("network", &self.network);
+        if let Some(metrics) = &self.metrics_listen {
+            check_network("metrics_listen", metrics);
+        }
+        if let Some(tls) = &self.tls {
+            for (name, path) in [("cert_path", &tls.cert_path), ("key_path", &tls.key_path)] {
+                if let Err(e) = fs::File::open(path) {
+                    problems.push(format!("tls.{name} {path:?} can't be read: {e}"));
                 }
             }
-            def
+        }
+        if self
+            .logs
+            .log_level
+            .trim()
+            .parse::<level_filters::LevelFilter>()
+            .is_err()
+        {
+            problems.push(format!(
+                "logs.log_level {:?} is not one of trace, debug, info, warn, error or off",
+                self.logs.log_level
+            ));
+        }
+        if problems.is_empty() {
+            Ok(())
         } else {
-            // Try to read and deserialize the config file
-            let data = fs::read_to_string(path)
-                .unwrap_or_else(|e| panic!("Failed to read config file {:?}: {}", path, e));
-            serde_json::from_str(&data)
-                .unwrap_or_else(|e| panic!("Failed to parse config file {:?}: {}", path, e))
+            Err(ConfigError::Invalid(problems))
         }
     }
 }
+
+/// Environment variables that override config file values, for container deployments.
+pub const ENV_NETWORK_PORT: &str = "CORECAL_NETWORK_PORT";
+pub const ENV_NETWORK_INTERFACE: &str = "CORECAL_NETWORK_INTERFACE";
+pub const ENV_AUTH_REQUIRE_LOGIN: &str = "CORECAL_AUTH_REQUIRE_LOGIN";
+
+impl Config {
+    /// Apply overrides from the `CORECAL_*` environment variables.
+    /// Invalid values are logged and the file's value is kept.
+    pub fn apply_env_overrides(&mut self) {
+        self.apply_overrides_from(|name| std::env::var(name).ok());
+    }
+
+    /// Apply overrides looked up by variable name (`std::env::var` outside of tests).
+    fn apply_overrides_from(&mut self, lookup: impl Fn(&str) -> Option<String>) {
+        if let Some(value) = lookup(ENV_NETWORK_PORT) {
+            match value.trim().parse::<u16>() {
+                Ok(port) if port != 0 => {
+                    info!("{} overrides network.port to {}", ENV_NETWORK_PORT, port);
+                    self.network.port = port;
+                }
+                _ => warn!(
+                    "Ignoring {}={:?}, expected a port number from 1 to 65535",
+                    ENV_NETWORK_PORT, value
+                ),
+            }
+        }
+        if let Some(value) = lookup(ENV_NETWORK_INTERFACE) {
+            let interface = value.trim();
+            if parse_interface(interface).is_some() {
+                info!(
+                    "{} overrides network.interface to {}",
+                    ENV_NETWORK_INTERFACE, interface
+                );
+                self.network.interface = interface.to_string();
+            } else {
+                warn!(
+                    "Ignoring {}={:?}, expected an IP address or localhost",
+                    ENV_NETWORK_INTERFACE, value
+                );
+            }
+        }
+        if let Some(value) = lookup(ENV_AUTH_REQUIRE_LOGIN) {
+            match value.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" => self.auth.require_login = true,
+                "false" | "0" => self.auth.require_login = false,
+                _ => {
+                    warn!(
+                        "Ignoring {}={:?}, expected true or false",
+                        ENV_AUTH_REQUIRE_LOGIN, value
+                    );
+                    return;
+                }
+            }
+            info!(
+                "{} overrides auth.require_login to {}",
+                ENV_AUTH_REQUIRE_LOGIN, self.auth.require_login
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_malformed_json_is_a_parse_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, "{ \"version\": 2, \"logs\": ").unwrap();
+        assert!(matches!(
+            Config::try_from_path(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+
+    #[test]
+    fn test_uncreatable_parent_directory_is_an_io_error() {
+        let dir = tempfile::tempdir().unwrap();
+        // The parent "directory" is a regular file, so it can't be created
+        let blocker = dir.path().join("not_a_dir");
+        fs::write(&blocker, "").unwrap();
+        let path = blocker.join("config.json");
+        assert!(matches!(
+            Config::try_from_path(&path),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn test_default_config_is_valid() {
+        assert!(Config::default().validate().is_ok());
+
+        let dir = tempfile::tempdir().unwrap();
+        let cert = dir.path().join("cert.pem");
+        fs::write(&cert, "").unwrap();
+        let mut config = Config::default();
+        config.network.interface = "[::]".to_string();
+        config.tls = Some(TlsConfig {
+            cert_path: cert.to_string_lossy().into_owned(),
+            key_path: cert.to_string_lossy().into_owned(),
+        });
+        config.logs.log_level = "Debug".to_string();
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_invalid_configs_are_reported() {
+        let problems = |change: fn(&mut Config)| {
+            let mut config = Config::default();
+            change(&mut config);
+            match config.validate() {
+                Err(ConfigError::Invalid(problems)) => problems,
+                other => panic!("expected Invalid, got {other:?}"),
+            }
+        };
+
+        assert_eq!(
+            problems(|c| c.network.port = 0),
+            vec!["network.port must be from 1 to 65535"]
+        );
+        assert_eq!(
+            problems(|c| c.network.interface = "my-laptop".to_string()),
+            vec![r#"network.interface "my-laptop" is not an IP address or localhost"#]
+        );
+        assert_eq!(
+            problems(|c| c.logs.log_level = "loud".to_string()),
+            vec![r#"logs.log_level "loud" is not one of trace, debug, info, warn, error or off"#]
+        );
+        let tls = problems(|c| {
+            c.tls = Some(TlsConfig {
+                cert_path: "/nonexistent/cert.pem".to_string(),
+                key_path: "/nonexistent/key.pem".to_string(),
+            })
+        });
+        assert_eq!(tls.len(), 2);
+        assert!(tls[0].starts_with(r#"tls.cert_path "/nonexistent/cert.pem" can't be read: "#));
+        assert!(tls[1].starts_with(r#"tls.key_path "/nonexistent/key.pem" can't be read: "#));
+
+        // Every problem is reported at once, metrics listener included
+        let all = problems(|c| {
+            c.network.port = 0;
+            c.metrics_listen = Some(NetworkConfig {
+                interface: "999.0.0.1".to_string(),
+                port: 9100,
+                dual_stack: false,
+            });
+        });
+        assert_eq!(all.len(), 2);
+        assert!(all[1].starts_with("metrics_listen.interface"));
+        assert!(
+            ConfigError::Invalid(all)
+                .to_string()
+                .starts_with("invalid config: network.port must be from 1 to 65535; ")
+        );
+    }
+
+    #[test]
+    fn test_old_version_is_unsupported() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let old = Config {
+            version: DEFAULT_CONFIG_VERSION - 1,
+            ..Config::default()
+        };
+        fs::write(&path, serde_json::to_string(&old).unwrap()).unwrap();
+        assert!(matches!(
+            Config::try_from_path(&path),
+            Err(ConfigError::UnsupportedVersion { found: Some(v), .. }) if v == old.version
+        ));
+    }
+
+    #[test]
+    fn test_missing_jwt_secret_is_generated_and_saved() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let mut value = serde_json::to_value(Config::default()).unwrap();
+        value["auth"].as_object_mut().unwrap().remove("jwt_secret");
+        fs::write(&path, value.to_string()).unwrap();
+
+        let first = Config::try_from_path(&path).unwrap();
+        assert!(!first.auth.jwt_secret.is_empty());
+        // The next start reads the same secret back instead of generating another
+        let second = Config::try_from_path(&path).unwrap();
+        assert_eq!(second.auth.jwt_secret, first.auth.jwt_secret);
+    }
+
+    #[test]
+    fn test_env_overrides() {
+        // Nothing else reads these variables, so setting them can't race other tests
+        unsafe {
+            std::env::set_var(ENV_NETWORK_PORT, "9443");
+            std::env::set_var(ENV_NETWORK_INTERFACE, "0.0.0.0");
+            std::env::set_var(ENV_AUTH_REQUIRE_LOGIN, "false");
+        }
+        let mut conf = Config::default();
+        conf.apply_env_overrides();
+        unsafe {
+            std::env::remove_var(ENV_NETWORK_PORT);
+            std::env::remove_var(ENV_NETWORK_INTERFACE);
+            std::env::remove_var(ENV_AUTH_REQUIRE_LOGIN);
+        }
+        assert_eq!(conf.network.port, 9443);
+        assert_eq!(conf.network.interface, "0.0.0.0");
+        assert!(!conf.auth.require_login);
+    }
+
+    #[test]
+    fn test_invalid_env_overrides_keep_file_values() {
+        let mut conf = Config::default();
+        conf.apply_overrides_from(|name| {
+            match name {
+                ENV_NETWORK_PORT => Some("eighty"),
+                ENV_NETWORK_INTERFACE => Some("my laptop"),
+                ENV_AUTH_REQUIRE_LOGIN => Some("maybe"),
+                _ => None,
+            }
+            .map(String::from)
+        });
+        let defaults = Config::default();
+        assert_eq!(conf.network.port, defaults.network.port);
+        assert_eq!(conf.network.interface, defaults.network.interface);
+        assert!(conf.auth.require_login);
+    }
+}
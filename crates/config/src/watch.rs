@@ -0,0 +1,107 @@
+use crate::{Config, ConfigError};
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::Path;
+use tracing::*;
+
+/// Keeps a config file watched, watching stops when this is dropped.
+pub type ConfigWatcher = notify::RecommendedWatcher;
+
+impl Config {
+    /// Re-read the config at `path` whenever it changes and hand the new config to `callback`.
+    /// Environment overrides are applied to it just like at startup. A file that no longer parses
+    /// is logged and skipped, so callers keep the config they already have.
+    /// One save can fire the callback more than once (editors often write in several steps).
+    pub fn watch<F>(path: &Path, callback: F) -> Result<ConfigWatcher, ConfigError>
+    where
+        F: Fn(Config) + Send + 'static,
+    {
+        let watch_error = |source| ConfigError::Watch {
+            path: path.to_path_buf(),
+            source,
+        };
+        let path = std::path::absolute(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let file_name = path.file_name().map(|name| name.to_os_string());
+        let watched = path.clone();
+        let mut watcher = notify::recommended_watcher(move |res: notify::Result<notify::Event>| {
+            let event = match res {
+                Ok(event) => event,
+                Err(e) => {
+                    warn!("Error watching config {:?}: {}", watched, e);
+                    return;
+                }
+            };
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+            // The whole directory is watched, only react to our file
+            if !event
+                .paths
+                .iter()
+                .any(|p| p.file_name().map(|n| n.to_os_string()) == file_name)
+            {
+                return;
+            }
+            match Config::read_existing(&watched) {
+                Ok(mut conf) => {
+                    info!("Config {:?} changed, reloading", watched);
+                    conf.apply_env_overrides();
+                    callback(conf);
+                }
+                Err(e) => warn!("Keeping the current config, reload failed: {}", e),
+            }
+        })
+        .map_err(watch_error)?;
+        // Watch the directory rather than the file, editors that save by replacing the file
+        // would otherwise leave us watching the old, deleted one
+        let dir = path.parent().unwrap_or(Path::new("."));
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(watch_error)?;
+        Ok(watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_watch_reports_changed_config() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let conf = Config::default();
+        std::fs::write(&path, serde_json::to_string_pretty(&conf).unwrap()).unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let _watcher = Config::watch(&path, move |conf| {
+            let _ = tx.send(conf.logs.keep_for);
+        })
+        .unwrap();
+
+        // A broken save is skipped, then a good one comes through
+        std::fs::write(&path, "{ not json").unwrap();
+        let changed = Config {
+            logs: crate::LogConfig {
+                keep_for: Duration::from_secs(3600),
+                ..Default::default()
+            },
+            ..conf
+        };
+        std::fs::write(&path, serde_json::to_string_pretty(&changed).unwrap()).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match rx.recv_timeout(remaining) {
+                Ok(keep_for) if keep_for == Duration::from_secs(3600) => break,
+                Ok(_) => continue,
+                Err(e) => panic!("callback never saw the changed config: {e}"),
+            }
+        }
+    }
+}
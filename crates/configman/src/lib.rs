@@ -1,5 +1,8 @@
+use config::ConfigError;
 use global_constants::DEFAULT_CONFIG_VERSION;
 
+pub mod upgraders;
+
 use serde_json;
 
 use std::any::Any;
@@ -134,52 +137,276 @@ impl ConfigMan {
     /// Loads the config from the given path, handling versioning and upgrades.
     /// If the file does not exist, creates it with the default config.
     /// If the version is current, loads as normal.
-    /// If the version is not current, attempts to upgrade (future).
-    /// Panics on unrecoverable errors.
+    /// If the version is not current, runs it through the upgrade chain and writes the result back,
+    /// only falling back to the default config when there is no upgrade path.
+    /// Panics on unrecoverable errors, see `try_load_or_init_config` to handle them instead.
     pub fn load_or_init_config<P: AsRef<std::path::Path>>(path: P) -> config::Config {
+        Self::try_load_or_init_config(path).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Same as `load_or_init_config`, but returns IO and parse errors instead of panicking.
+    /// `CORECAL_*` environment overrides are applied to the result (never written to the file).
+    pub fn try_load_or_init_config<P: AsRef<std::path::Path>>(
+        path: P,
+    ) -> Result<config::Config, ConfigError> {
+        let mut conf = Self::try_load_or_init_config_with(path, &upgraders::default_upgraders())?;
+        conf.apply_env_overrides();
+        Ok(conf)
+    }
+
+    /// Same as `try_load_or_init_config`, upgrading old configs with the given upgraders.
+    pub fn try_load_or_init_config_with<P: AsRef<std::path::Path>>(
+        path: P,
+        upgraders: &[Box<dyn upgraders::DynConfigUpdater>],
+    ) -> Result<config::Config, ConfigError> {
         use tracing::*;
 
         let path = path.as_ref();
 
-        // Try to read and parse the config file
-        let data = fs::read_to_string(path).ok();
-
-        // If file doesn't exist or can't be read, or version is current, use Config::from_path
-        if data.is_none() {
-            return config::Config::from_path(path);
+        // If the file doesn't exist, Config::try_from_path creates it
+        if !path.exists() {
+            return config::Config::try_from_path(path);
         }
-        let data = data.unwrap();
-
-        let version: Option<usize> = serde_json::from_str::<serde_json::Value>(&data)
-            .ok()
-            .and_then(|v| {
-                v.get("version")
-                    .and_then(|ver| ver.as_u64().map(|n| n as usize))
-            });
+        let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+
+        // A typo in the file is reported rather than replaced with defaults
+        let value = serde_json::from_str::<serde_json::Value>(&data).map_err(|source| {
+            ConfigError::Parse {
+                path: path.to_path_buf(),
+                source,
+            }
+        })?;
+        let version: Option<usize> = value
+            .get("version")
+            .and_then(|ver| ver.as_u64().map(|n| n as usize));
 
         if version == Some(DEFAULT_CONFIG_VERSION) {
-            return config::Config::from_path(path);
+            return config::Config::try_from_path(path);
+        }
+
+        if let Some(version) = version
+            && let Some(conf) = Self::upgrade(value, version, upgraders)
+        {
+            info!(
+                "Upgraded config {:?} from version {} to {}",
+                path, version, DEFAULT_CONFIG_VERSION
+            );
+            Self::write_config(path, &conf)?;
+            return Ok(conf);
         }
 
-        // If version is not current, here is where you would run upgraders (if any existed)
-        // For now, since we only have version 1, treat as error or fallback to default
         warn!(
-            "Config version mismatch or missing. Expected version {}, got {:?}. Using default config.",
-            DEFAULT_CONFIG_VERSION, version
+            "No upgrade path for config {:?}. Expected version {}, got {:?}. Using default config.",
+            path, DEFAULT_CONFIG_VERSION, version
         );
         let conf = config::Config::default();
-        let pretty = serde_json::to_string_pretty(&conf)
-            .expect("Failed to serialize default config to JSON");
-        match fs::File::create(path) {
-            Ok(mut file) => {
-                if let Err(e) = file.write_all(pretty.as_bytes()) {
-                    panic!("Failed to write default config to file {:?}: {}", path, e);
-                }
+        Self::write_config(path, &conf)?;
+        Ok(conf)
+    }
+
+    /// Load the config at `path` as startup would (upgrading an old version and applying
+    /// `CORECAL_*` overrides) and validate it, without creating or changing the file.
+    /// A missing file or one with no upgrade path is an error here rather than replaced.
+    pub fn check_config<P: AsRef<std::path::Path>>(path: P) -> Result<config::Config, ConfigError> {
+        let path = path.as_ref();
+        let data = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let parse_error = |source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source,
+        };
+        let value = serde_json::from_str::<serde_json::Value>(&data).map_err(parse_error)?;
+        let version: Option<usize> = value
+            .get("version")
+            .and_then(|ver| ver.as_u64().map(|n| n as usize));
+        let mut conf = match version {
+            Some(DEFAULT_CONFIG_VERSION) => {
+                serde_json::from_value::<config::Config>(value).map_err(parse_error)?
             }
-            Err(e) => {
-                panic!("Failed to create config file {:?}: {}", path, e);
+            Some(old) => Self::upgrade(value, old, &upgraders::default_upgraders()).ok_or(
+                ConfigError::UnsupportedVersion {
+                    found: version,
+                    expected: DEFAULT_CONFIG_VERSION,
+                },
+            )?,
+            None => {
+                return Err(ConfigError::UnsupportedVersion {
+                    found: None,
+                    expected: DEFAULT_CONFIG_VERSION,
+                });
             }
+        };
+        conf.apply_env_overrides();
+        conf.validate()?;
+        Ok(conf)
+    }
+
+    /// Run an old config through the upgrade chain, `None` if there's no path to the current version.
+    fn upgrade(
+        value: serde_json::Value,
+        version: usize,
+        upgraders: &[Box<dyn upgraders::DynConfigUpdater>],
+    ) -> Option<config::Config> {
+        upgraders::upgrade_to_version(
+            value,
+            version as u32,
+            DEFAULT_CONFIG_VERSION as u32,
+            upgraders,
+        )
+        .and_then(|v| serde_json::from_value::<config::Config>(v).ok())
+    }
+
+    /// Overwrite the config file at `path`, first copying the existing one aside.
+    /// Fails (leaving the original untouched) if the backup can't be made.
+    fn write_config(path: &std::path::Path, conf: &config::Config) -> Result<(), ConfigError> {
+        let io_error = |source| ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        };
+        if path.exists() {
+            let backup = Self::backup_config(path).map_err(io_error)?;
+            info!("Backed up previous config {:?} to {:?}", path, backup);
         }
-        conf
+        let pretty =
+            serde_json::to_string_pretty(conf).expect("Failed to serialize config to JSON");
+        let mut file = fs::File::create(path).map_err(io_error)?;
+        file.write_all(pretty.as_bytes()).map_err(io_error)
+    }
+
+    /// Copy the config file to `<name>.bak.<unix timestamp>` next to it, returning the copy's path.
+    fn backup_config(path: &std::path::Path) -> std::io::Result<std::path::PathBuf> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let mut name = path.file_name().unwrap_or_default().to_os_string();
+        name.push(format!(".bak.{}", timestamp));
+        let backup = path.with_file_name(name);
+        fs::copy(path, &backup)?;
+        Ok(backup)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_CONFIG: &str = r#"{
+        "version": 1,
+        "logs": { "keep_for": "3days" },
+        "network": { "interface": "0.0.0.0", "port": 9000 },
+        "auth": { "require_login": false },
+        "database": { "path": "family.db" }
+    }"#;
+
+    #[test]
+    fn test_v1_config_is_upgraded_in_place() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, V1_CONFIG).unwrap();
+
+        let conf = ConfigMan::load_or_init_config(&path);
+        assert_eq!(conf.version, DEFAULT_CONFIG_VERSION);
+        assert_eq!(conf.network.interface, "0.0.0.0");
+        assert_eq!(conf.network.port, 9000);
+        assert!(!conf.auth.require_login);
+        assert_eq!(conf.database.path, "family.db");
+        assert_eq!(
+            conf.logs.keep_for,
+            std::time::Duration::from_secs(3 * 24 * 3600)
+        );
+
+        // The upgraded config was written back, so the next load keeps the same settings
+        let reloaded = ConfigMan::load_or_init_config(&path);
+        assert_eq!(reloaded.version, DEFAULT_CONFIG_VERSION);
+        assert_eq!(reloaded.network.port, 9000);
+        assert_eq!(reloaded.auth.jwt_secret, conf.auth.jwt_secret);
+    }
+
+    #[test]
+    fn test_unknown_version_falls_back_to_default() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, V1_CONFIG.replace("\"version\": 1", "\"version\": 0")).unwrap();
+
+        let conf = ConfigMan::load_or_init_config(&path);
+        assert_eq!(conf.version, DEFAULT_CONFIG_VERSION);
+        assert_eq!(conf.network.port, config::NetworkConfig::default().port);
+    }
+
+    #[test]
+    fn test_malformed_config_is_not_overwritten() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        fs::write(&path, "{ \"version\": 2,, }").unwrap();
+
+        assert!(matches!(
+            ConfigMan::try_load_or_init_config(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "{ \"version\": 2,, }");
+    }
+
+    #[test]
+    fn test_check_config_changes_nothing() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        assert!(matches!(
+            ConfigMan::check_config(&path),
+            Err(ConfigError::Io { .. })
+        ));
+        assert!(!path.exists());
+
+        // An old version is checked as it would be upgraded, but left as it is
+        fs::write(&path, V1_CONFIG).unwrap();
+        let conf = ConfigMan::check_config(&path).unwrap();
+        assert_eq!(conf.network.port, 9000);
+        assert_eq!(fs::read_to_string(&path).unwrap(), V1_CONFIG);
+
+        let unknown = V1_CONFIG.replace("\"version\": 1", "\"version\": 0");
+        fs::write(&path, &unknown).unwrap();
+        assert!(matches!(
+            ConfigMan::check_config(&path),
+            Err(ConfigError::UnsupportedVersion { found: Some(0), .. })
+        ));
+        assert_eq!(fs::read_to_string(&path).unwrap(), unknown);
+
+        fs::write(&path, V1_CONFIG.replace("9000", "0")).unwrap();
+        match ConfigMan::check_config(&path) {
+            Err(e @ ConfigError::Invalid(_)) => {
+                assert!(
+                    e.to_string()
+                        .contains("network.port must be from 1 to 65535")
+                )
+            }
+            other => panic!("expected Invalid, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_overwritten_config_is_backed_up() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.json");
+        let original = V1_CONFIG.replace("\"version\": 1", "\"version\": 0");
+        fs::write(&path, &original).unwrap();
+
+        ConfigMan::load_or_init_config(&path);
+        let backups: Vec<_> = fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|p| {
+                p.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with("config.json.bak.")
+            })
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert_eq!(fs::read_to_string(&backups[0]).unwrap(), original);
     }
 }
@@ -0,0 +1,132 @@
+use crate::{ConfigUpdater, config_upgrader};
+use serde::Deserialize;
+use std::time::Duration;
+
+/// The config file as version 1 wrote it. Frozen here so old files keep parsing
+/// no matter how the current `config::Config` changes.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfigV1 {
+    pub version: usize,
+    pub logs: LogConfigV1,
+    pub network: NetworkConfigV1,
+    pub auth: AuthConfigV1,
+    pub database: DatabaseConfigV1,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LogConfigV1 {
+    #[serde(with = "humantime_serde")]
+    pub keep_for: Duration,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct NetworkConfigV1 {
+    pub interface: String,
+    pub port: u16,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct AuthConfigV1 {
+    pub require_login: bool,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DatabaseConfigV1 {
+    pub path: String,
+}
+
+// Version 2 added the cors/websocket/tls/static_dir sections and a persisted JWT secret.
+// Settings the user had are kept, everything new starts at its default.
+config_upgrader!(
+    V1toV2,
+    ConfigV1,
+    config::Config,
+    1,
+    1,
+    2,
+    |old: ConfigV1| {
+        config::Config {
+            version: 2,
+            logs: config::LogConfig {
+                keep_for: old.logs.keep_for,
+                ..Default::default()
+            },
+            network: config::NetworkConfig {
+                interface: old.network.interface,
+                port: old.network.port,
+                ..config::NetworkConfig::default()
+            },
+            auth: config::AuthConfig {
+                require_login: old.auth.require_login,
+                ..config::AuthConfig::default()
+            },
+            database: config::DatabaseConfig {
+                path: old.database.path,
+                ..config::DatabaseConfig::default()
+            },
+            ..config::Config::default()
+        }
+    }
+);
+
+/// Type-erased `ConfigUpdater`, so updaters with different config types can sit in one list
+/// and be picked by the version found on disk.
+pub trait DynConfigUpdater {
+    /// Whether this updater can upgrade a config of `version`.
+    fn accepts(&self, version: u32) -> bool;
+
+    /// The version this updater upgrades to.
+    fn target(&self) -> u32;
+
+    /// Upgrade a config given as JSON, failing if it doesn't match the old config type.
+    fn upgrade_json(&self, old: serde_json::Value) -> Result<serde_json::Value, serde_json::Error>;
+}
+
+impl<U: ConfigUpdater> DynConfigUpdater for U {
+    fn accepts(&self, version: u32) -> bool {
+        (self.min_version()..=self.max_version()).contains(&version)
+    }
+
+    fn target(&self) -> u32 {
+        self.target_version()
+    }
+
+    fn upgrade_json(&self, old: serde_json::Value) -> Result<serde_json::Value, serde_json::Error> {
+        let old: U::OldConfig = serde_json::from_value(old)?;
+        serde_json::to_value(self.upgrade(old))
+    }
+}
+
+/// Every upgrader the app ships, oldest first.
+pub fn default_upgraders() -> Vec<Box<dyn DynConfigUpdater>> {
+    vec![Box::new(V1toV2)]
+}
+
+/// Run `data` (a config of `version`) through `upgraders` until it reaches `target`.
+/// Returns `None` if some version along the way has no upgrader or an upgrade fails.
+pub fn upgrade_to_version(
+    mut data: serde_json::Value,
+    mut version: u32,
+    target: u32,
+    upgraders: &[Box<dyn DynConfigUpdater>],
+) -> Option<serde_json::Value> {
+    while version != target {
+        let upgrader = upgraders
+            .iter()
+            .find(|u| u.accepts(version) && u.target() > version)?;
+        data = match upgrader.upgrade_json(data) {
+            Ok(data) => data,
+            Err(e) => {
+                tracing::warn!(
+                    "Failed to upgrade config from version {} to {}: {}",
+                    version,
+                    upgrader.target(),
+                    e
+                );
+                return None;
+            }
+        };
+        version = upgrader.target();
+    }
+    Some(data)
+}
@@ -0,0 +1,194 @@
+use crate::{AuthUser, Calendar, DatabaseConnection, DbPool, Error, Event};
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+/// Error from an `AsyncDatabaseConnection` call.
+#[derive(Debug)]
+pub enum AsyncDbError {
+    /// No pooled connection became free in time
+    Pool(r2d2::Error),
+    Db(Error),
+    /// The blocking task panicked or was cancelled
+    Task(tokio::task::JoinError),
+}
+
+impl fmt::Display for AsyncDbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsyncDbError::Pool(e) => write!(f, "failed to get a database connection: {e}"),
+            AsyncDbError::Db(e) => write!(f, "{e}"),
+            AsyncDbError::Task(e) => write!(f, "database task failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AsyncDbError {}
+
+impl From<r2d2::Error> for AsyncDbError {
+    fn from(e: r2d2::Error) -> Self {
+        AsyncDbError::Pool(e)
+    }
+}
+
+impl From<Error> for AsyncDbError {
+    fn from(e: Error) -> Self {
+        AsyncDbError::Db(e)
+    }
+}
+
+/// Async front for a `DbPool`: each call checks out a connection and runs on tokio's blocking
+/// thread pool, so awaiting it never stalls the executor. Cheap to clone.
+#[derive(Clone)]
+pub struct AsyncDatabaseConnection {
+    pool: DbPool,
+}
+
+impl From<DbPool> for AsyncDatabaseConnection {
+    fn from(pool: DbPool) -> Self {
+        Self::new(pool)
+    }
+}
+
+impl AsyncDatabaseConnection {
+    pub fn new(pool: DbPool) -> Self {
+        Self { pool }
+    }
+
+    /// The underlying pool, for code that still uses the sync API.
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    /// Run `f` against a pooled connection on the blocking thread pool.
+    /// Anything without an async wrapper below can go through here.
+    pub async fn call<T, F>(&self, f: F) -> Result<T, AsyncDbError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&DatabaseConnection) -> Result<T, Error> + Send + 'static,
+    {
+        let pool = self.pool.clone();
+        tokio::task::spawn_blocking(move || {
+            let conn = pool.get()?;
+            Ok(f(&conn)?)
+        })
+        .await
+        .map_err(AsyncDbError::Task)?
+    }
+
+    /// See `DatabaseConnection::get_user_by_username`
+    pub async fn get_user_by_username(
+        &self,
+        username: &str,
+    ) -> Result<Option<AuthUser>, AsyncDbError> {
+        let username = username.to_string();
+        self.call(move |conn| conn.get_user_by_username(&username))
+            .await
+    }
+
+    /// See `DatabaseConnection::get_user_by_id`
+    pub async fn get_user_by_id(&self, id: i64) -> Result<Option<AuthUser>, AsyncDbError> {
+        self.call(move |conn| conn.get_user_by_id(id)).await
+    }
+
+    /// See `DatabaseConnection::get_user_by_email`
+    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<AuthUser>, AsyncDbError> {
+        let email = email.to_string();
+        self.call(move |conn| conn.get_user_by_email(&email)).await
+    }
+
+    /// See `DatabaseConnection::get_calendar_by_id`
+    pub async fn get_calendar_by_id(&self, id: i64) -> Result<Option<Calendar>, AsyncDbError> {
+        self.call(move |conn| conn.get_calendar_by_id(id)).await
+    }
+
+    /// See `DatabaseConnection::list_calendars`
+    pub async fn list_calendars(&self) -> Result<Vec<Calendar>, AsyncDbError> {
+        self.call(|conn| conn.list_calendars()).await
+    }
+
+    /// See `DatabaseConnection::get_event_by_id`
+    pub async fn get_event_by_id(&self, id: i64) -> Result<Option<Event>, AsyncDbError> {
+        self.call(move |conn| conn.get_event_by_id(id)).await
+    }
+
+    /// See `DatabaseConnection::list_events_in_range`
+    pub async fn list_events_in_range(
+        &self,
+        calendar_id: i64,
+        range_start: DateTime<Utc>,
+        range_end: DateTime<Utc>,
+    ) -> Result<Vec<Event>, AsyncDbError> {
+        self.call(move |conn| conn.list_events_in_range(calendar_id, range_start, range_end))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colorlab::Color;
+    use std::time::{Duration, Instant};
+
+    #[tokio::test]
+    async fn test_async_queries() {
+        let db = AsyncDatabaseConnection::new(DbPool::new_in_memory(2).unwrap());
+        let id = db
+            .call(|conn| conn.insert_calendar("Family", Color::from_rgb8(1, 2, 3)))
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_calendar_by_id(id).await.unwrap().unwrap().name,
+            "Family"
+        );
+        assert!(db.get_user_by_username("nobody").await.unwrap().is_none());
+        assert!(matches!(
+            db.call(|conn| Ok(conn.conn.execute_batch("NOT SQL")?))
+                .await,
+            Err(AsyncDbError::Db(Error::Sqlite(_)))
+        ));
+    }
+
+    // The default tokio test runtime is single-threaded, so if queries ran on the executor
+    // they would run one after another and starve the ticker below.
+    #[tokio::test]
+    async fn test_concurrent_queries_do_not_block_runtime() {
+        let queries: u32 = 4;
+        let delay = Duration::from_millis(200);
+        let db = AsyncDatabaseConnection::new(DbPool::new_in_memory(queries).unwrap());
+
+        let ticker = tokio::spawn(async {
+            let mut ticks = 0;
+            let mut interval = tokio::time::interval(Duration::from_millis(20));
+            let until = Instant::now() + Duration::from_millis(150);
+            while Instant::now() < until {
+                interval.tick().await;
+                ticks += 1;
+            }
+            ticks
+        });
+
+        let started = Instant::now();
+        let handles: Vec<_> = (0..queries)
+            .map(|_| {
+                let db = db.clone();
+                tokio::spawn(async move {
+                    db.call(move |conn| {
+                        std::thread::sleep(delay);
+                        conn.list_calendars()
+                    })
+                    .await
+                })
+            })
+            .collect();
+        for handle in handles {
+            assert!(handle.await.unwrap().unwrap().is_empty());
+        }
+        let elapsed = started.elapsed();
+
+        assert!(
+            elapsed < delay * queries / 2,
+            "queries ran one after another ({elapsed:?})"
+        );
+        assert!(ticker.await.unwrap() >= 5);
+    }
+}
@@ -0,0 +1,349 @@
+use crate::{DatabaseConnection, Error, SCHEMA_VERSION, sql};
+use rusqlite::{
+    Connection, MAIN_DB, OpenFlags, OptionalExtension, backup::Progress, types::ValueRef,
+};
+use std::fmt::{self, Write as _};
+use std::path::Path;
+
+/// Error restoring or importing a database backup.
+#[derive(Debug)]
+pub enum RestoreError {
+    /// The backup file couldn't be read
+    Io(std::io::Error),
+    /// SQLite failed to open, read or apply the backup
+    Sqlite(rusqlite::Error),
+    /// The backup was made with a different schema version (`None` if it records none)
+    IncompatibleSchema { found: Option<i64>, expected: i64 },
+}
+
+impl fmt::Display for RestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RestoreError::Io(e) => write!(f, "failed to read backup: {e}"),
+            RestoreError::Sqlite(e) => write!(f, "failed to restore backup: {e}"),
+            RestoreError::IncompatibleSchema { found, expected } => match found {
+                Some(found) => write!(
+                    f,
+                    "backup has schema version {found}, this build needs {expected}"
+                ),
+                None => write!(
+                    f,
+                    "backup has no schema version, this build needs {expected}"
+                ),
+            },
+        }
+    }
+}
+
+impl std::error::Error for RestoreError {}
+
+impl From<std::io::Error> for RestoreError {
+    fn from(e: std::io::Error) -> Self {
+        RestoreError::Io(e)
+    }
+}
+
+impl From<rusqlite::Error> for RestoreError {
+    fn from(e: rusqlite::Error) -> Self {
+        RestoreError::Sqlite(e)
+    }
+}
+
+/// Read the schema version recorded in a database, `None` if it has none.
+fn schema_version_of(conn: &Connection) -> Result<Option<i64>, rusqlite::Error> {
+    let has_table: bool = conn.query_row(
+        "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'schema_version')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(None);
+    }
+    conn.query_row(sql::SCHEMA_VERSION_SELECT, [], |row| row.get(0))
+        .optional()
+}
+
+/// Fail unless `found` is the schema version this build uses.
+fn check_schema_version(found: Option<i64>) -> Result<(), RestoreError> {
+    if found == Some(SCHEMA_VERSION) {
+        Ok(())
+    } else {
+        Err(RestoreError::IncompatibleSchema {
+            found,
+            expected: SCHEMA_VERSION,
+        })
+    }
+}
+
+impl DatabaseConnection {
+    /// Copy the whole database to `dest` with SQLite's online backup API.
+    /// Safe while other connections keep reading and writing, the copy is a consistent snapshot.
+    /// An existing file at `dest` is overwritten.
+    pub fn backup_to(&self, dest: &Path) -> Result<(), Error> {
+        Ok(self.conn.backup(MAIN_DB, dest, None)?)
+    }
+
+    /// Write the schema and every row as a plain `.sql` script to `dest`.
+    /// The script drops and recreates each table, so running it restores exactly this content.
+    pub fn export_sql(&self, dest: &Path) -> Result<(), Error> {
+        std::fs::write(dest, self.dump_sql()?)?;
+        Ok(())
+    }
+
+    /// Replace the whole database with the backup at `src` (as written by `backup_to`),
+    /// using the online backup API in reverse. The backup's schema version must match this build's,
+    /// otherwise nothing is changed.
+    pub fn restore_from(&mut self, src: &Path) -> Result<(), RestoreError> {
+        let backup = Connection::open_with_flags(src, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        check_schema_version(schema_version_of(&backup)?)?;
+        drop(backup);
+        self.conn.restore(MAIN_DB, src, None::<fn(Progress)>)?;
+        Ok(())
+    }
+
+    /// Run an `.sql` script written by `export_sql` inside a transaction, replacing the current
+    /// contents. If the script fails or leaves a different schema version, everything is rolled back.
+    pub fn import_sql(&mut self, src: &Path) -> Result<(), RestoreError> {
+        let script = std::fs::read_to_string(src)?;
+        let tx = self.conn.transaction()?;
+        tx.execute_batch(&script)?;
+        // Dropping the transaction without committing rolls the import back
+        check_schema_version(schema_version_of(&tx)?)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Build the script written by `export_sql`.
+    fn dump_sql(&self) -> Result<String, rusqlite::Error> {
+        // Creation order, so tables come after the tables they reference
+        let mut stmt = self.conn.prepare(
+            "SELECT type, name, sql FROM sqlite_master \
+             WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' ORDER BY rowid",
+        )?;
+        let entries = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        // Virtual tables (the event search index) create their own backing tables and are
+        // filled by triggers as the rows are inserted, so neither is dumped directly
+        let virtual_tables: Vec<String> = entries
+            .iter()
+            .filter(|(kind, _, sql)| {
+                kind == "table" && sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE")
+            })
+            .map(|(_, name, _)| name.clone())
+            .collect();
+        let entries: Vec<_> = entries
+            .into_iter()
+            .filter(|(kind, name, _)| {
+                kind != "table"
+                    || !virtual_tables
+                        .iter()
+                        .any(|vt| name.starts_with(&format!("{vt}_")))
+            })
+            .collect();
+        let tables: Vec<&str> = entries
+            .iter()
+            .filter(|(kind, _, _)| kind == "table")
+            .map(|(_, name, _)| name.as_str())
+            .collect();
+
+        let mut out = String::new();
+        // Referencing tables go first so foreign keys never block a drop
+        for table in tables.iter().rev() {
+            let _ = writeln!(out, "DROP TABLE IF EXISTS {};", quote_ident(table));
+        }
+        for (_, _, sql) in &entries {
+            let _ = writeln!(out, "{sql};");
+        }
+        for table in tables
+            .iter()
+            .filter(|t| !virtual_tables.iter().any(|vt| vt == *t))
+        {
+            self.dump_rows(table, &mut out)?;
+        }
+        // Keep AUTOINCREMENT counters so deleted ids aren't handed out again
+        let has_sequence: bool = self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'sqlite_sequence')",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_sequence {
+            out.push_str("DELETE FROM sqlite_sequence;\n");
+            self.dump_rows("sqlite_sequence", &mut out)?;
+        }
+        Ok(out)
+    }
+
+    /// Append an `INSERT` for every row of `table` to `out`.
+    fn dump_rows(&self, table: &str, out: &mut String) -> Result<(), rusqlite::Error> {
+        let table = quote_ident(table);
+        let mut stmt = self.conn.prepare(&format!("SELECT * FROM {table}"))?;
+        let columns = stmt.column_count();
+        let mut rows = stmt.query([])?;
+        while let Some(row) = rows.next()? {
+            let values = (0..columns)
+                .map(|i| row.get_ref(i).map(sql_literal))
+                .collect::<Result<Vec<_>, _>>()?;
+            let _ = writeln!(out, "INSERT INTO {table} VALUES ({});", values.join(", "));
+        }
+        Ok(())
+    }
+}
+
+/// Quote a table name for use in generated SQL.
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Render a stored value as an SQL literal that reads back as the same value.
+fn sql_literal(value: ValueRef<'_>) -> String {
+    match value {
+        ValueRef::Null => "NULL".to_string(),
+        ValueRef::Integer(i) => i.to_string(),
+        // Debug keeps a decimal point, so whole numbers stay REAL
+        ValueRef::Real(f) => format!("{f:?}"),
+        ValueRef::Text(text) => {
+            format!("'{}'", String::from_utf8_lossy(text).replace('\'', "''"))
+        }
+        ValueRef::Blob(bytes) => {
+            let hex: String = bytes.iter().map(|b| format!("{b:02X}")).collect();
+            format!("X'{hex}'")
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colorlab::Color;
+
+    fn sample_db() -> DatabaseConnection {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "a@x.com").unwrap();
+        db.insert_user("o'brien", "hash", "salt", "o@x.com")
+            .unwrap();
+        let calendar_id = db
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let at = chrono::Utc::now();
+        db.insert_event(calendar_id, "Dentist", None, at, at)
+            .unwrap();
+        db
+    }
+
+    fn usernames(db: &DatabaseConnection) -> Vec<String> {
+        db.list_users(10, 0)
+            .unwrap()
+            .into_iter()
+            .map(|u| u.username)
+            .collect()
+    }
+
+    #[test]
+    fn test_backup_to_file_keeps_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.db");
+        let db = sample_db();
+        db.backup_to(&path).unwrap();
+
+        let restored = DatabaseConnection::from_path(&path).unwrap();
+        assert_eq!(usernames(&restored), vec!["alice", "o'brien"]);
+        assert_eq!(restored.list_calendars().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_backup_then_restore_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("backup.db");
+        let db = sample_db();
+        db.backup_to(&path).unwrap();
+
+        let mut target = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        target
+            .insert_user("bob", "hash", "salt", "b@x.com")
+            .unwrap();
+        target.restore_from(&path).unwrap();
+        assert_eq!(usernames(&target), usernames(&db));
+        assert_eq!(
+            target.list_calendars().unwrap(),
+            db.list_calendars().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_import_sql_round_trips() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.sql");
+        let db = sample_db();
+        db.export_sql(&path).unwrap();
+
+        let mut target = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        target
+            .insert_user("bob", "hash", "salt", "b@x.com")
+            .unwrap();
+        target.import_sql(&path).unwrap();
+        assert_eq!(usernames(&target), usernames(&db));
+        assert_eq!(
+            target.list_calendars().unwrap(),
+            db.list_calendars().unwrap()
+        );
+        // The search index is rebuilt from the imported events
+        assert_eq!(target.search_events(None, "dentist", 10).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_incompatible_schema_is_rejected() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = sample_db();
+        db.conn
+            .execute(
+                "UPDATE schema_version SET version = ?1",
+                [SCHEMA_VERSION + 1],
+            )
+            .unwrap();
+        let backup = dir.path().join("backup.db");
+        let dump = dir.path().join("dump.sql");
+        db.backup_to(&backup).unwrap();
+        db.export_sql(&dump).unwrap();
+
+        let mut target = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        target
+            .insert_user("bob", "hash", "salt", "b@x.com")
+            .unwrap();
+        for result in [target.restore_from(&backup), target.import_sql(&dump)] {
+            assert!(matches!(
+                result,
+                Err(RestoreError::IncompatibleSchema { found: Some(v), expected })
+                    if v == SCHEMA_VERSION + 1 && expected == SCHEMA_VERSION
+            ));
+        }
+        // Nothing was replaced
+        assert_eq!(usernames(&target), vec!["bob"]);
+    }
+
+    #[test]
+    fn test_export_sql_recreates_rows() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("dump.sql");
+        let db = sample_db();
+        db.export_sql(&path).unwrap();
+
+        // Runs over a database that already has the schema
+        let target = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        target
+            .insert_user("bob", "hash", "salt", "b@x.com")
+            .unwrap();
+        target
+            .conn
+            .execute_batch(&std::fs::read_to_string(&path).unwrap())
+            .unwrap();
+        assert_eq!(usernames(&target), vec!["alice", "o'brien"]);
+        assert_eq!(target.list_calendars().unwrap().len(), 1);
+    }
+}
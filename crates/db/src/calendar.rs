@@ -0,0 +1,673 @@
+use crate::{
+    Calendar, CalendarPermission, DatabaseConnection, Error, datetime_from_sql, datetime_to_sql,
+    sql,
+};
+use chrono::{DateTime, Utc};
+use colorlab::Color;
+use rusqlite::{Connection, OptionalExtension, Row, params, types::Type};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::fmt;
+
+/// Error for colors that can't be parsed from their stored/user supplied form.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorError {
+    /// Not a `#RRGGBB` hex string
+    InvalidHex(String),
+    /// Neither a `#RGB`/`#RRGGBB` hex string nor a known CSS color name
+    UnknownColor(String),
+    /// A valid color, but not one of the deployment's allowed palette
+    NotInPalette(String),
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ColorError::InvalidHex(s) => write!(f, "invalid hex color {s:?}, expected #RRGGBB"),
+            ColorError::UnknownColor(s) => write!(
+                f,
+                "unrecognised color {s:?}, expected #RGB, #RRGGBB or a CSS color name"
+            ),
+            ColorError::NotInPalette(s) => {
+                write!(f, "color {s:?} is not in the allowed calendar palette")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ColorError {}
+
+/// Serialize a color for the `calendars.color` TEXT column as uppercase `#RRGGBB`.
+/// Alpha is not stored, calendar colors are always opaque.
+pub fn color_to_hex(color: &Color) -> String {
+    let (r, g, b) = color.to_rgb8();
+    format!("#{r:02X}{g:02X}{b:02X}")
+}
+
+/// Parse a `#RRGGBB` string (as written by `color_to_hex`) back into a color.
+pub fn hex_to_color(hex: &str) -> Result<Color, ColorError> {
+    let invalid = || ColorError::InvalidHex(hex.to_string());
+    let digits = hex.strip_prefix('#').ok_or_else(invalid)?;
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let channel = |i: usize| u8::from_str_radix(&digits[i..i + 2], 16).map_err(|_| invalid());
+    Ok(Color::from_rgb8(channel(0)?, channel(2)?, channel(4)?))
+}
+
+/// CSS color names accepted for calendar colors: the basic 16 plus some common extended ones.
+const NAMED_COLORS: &[(&str, (u8, u8, u8))] = &[
+    ("black", (0x00, 0x00, 0x00)),
+    ("silver", (0xC0, 0xC0, 0xC0)),
+    ("gray", (0x80, 0x80, 0x80)),
+    ("grey", (0x80, 0x80, 0x80)),
+    ("white", (0xFF, 0xFF, 0xFF)),
+    ("maroon", (0x80, 0x00, 0x00)),
+    ("red", (0xFF, 0x00, 0x00)),
+    ("purple", (0x80, 0x00, 0x80)),
+    ("fuchsia", (0xFF, 0x00, 0xFF)),
+    ("magenta", (0xFF, 0x00, 0xFF)),
+    ("green", (0x00, 0x80, 0x00)),
+    ("lime", (0x00, 0xFF, 0x00)),
+    ("olive", (0x80, 0x80, 0x00)),
+    ("yellow", (0xFF, 0xFF, 0x00)),
+    ("navy", (0x00, 0x00, 0x80)),
+    ("blue", (0x00, 0x00, 0xFF)),
+    ("teal", (0x00, 0x80, 0x80)),
+    ("aqua", (0x00, 0xFF, 0xFF)),
+    ("cyan", (0x00, 0xFF, 0xFF)),
+    ("orange", (0xFF, 0xA5, 0x00)),
+    ("gold", (0xFF, 0xD7, 0x00)),
+    ("pink", (0xFF, 0xC0, 0xCB)),
+    ("brown", (0xA5, 0x2A, 0x2A)),
+    ("coral", (0xFF, 0x7F, 0x50)),
+    ("salmon", (0xFA, 0x80, 0x72)),
+    ("crimson", (0xDC, 0x14, 0x3C)),
+    ("indigo", (0x4B, 0x00, 0x82)),
+    ("violet", (0xEE, 0x82, 0xEE)),
+    ("lavender", (0xE6, 0xE6, 0xFA)),
+    ("turquoise", (0x40, 0xE0, 0xD0)),
+    ("skyblue", (0x87, 0xCE, 0xEB)),
+    ("steelblue", (0x46, 0x82, 0xB4)),
+    ("royalblue", (0x41, 0x69, 0xE1)),
+    ("forestgreen", (0x22, 0x8B, 0x22)),
+    ("seagreen", (0x2E, 0x8B, 0x57)),
+    ("khaki", (0xF0, 0xE6, 0x8C)),
+    ("chocolate", (0xD2, 0x69, 0x1E)),
+    ("tomato", (0xFF, 0x63, 0x47)),
+    ("slategray", (0x70, 0x80, 0x90)),
+    ("slategrey", (0x70, 0x80, 0x90)),
+];
+
+/// Parse a calendar color as a user would type it: `#RGB`, `#RRGGBB` or a CSS color name,
+/// ignoring case and surrounding whitespace.
+pub fn parse_calendar_color(input: &str) -> Result<Color, ColorError> {
+    let trimmed = input.trim();
+    if let Some(digits) = trimmed.strip_prefix('#') {
+        if digits.len() == 3 && digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            let expanded: String = digits.chars().flat_map(|c| [c, c]).collect();
+            return hex_to_color(&format!("#{expanded}"));
+        }
+        return hex_to_color(trimmed).map_err(|_| ColorError::InvalidHex(input.to_string()));
+    }
+    NAMED_COLORS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(trimmed))
+        .map(|&(_, (r, g, b))| Color::from_rgb8(r, g, b))
+        .ok_or_else(|| ColorError::UnknownColor(input.to_string()))
+}
+
+/// The colors a deployment restricts calendars to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorPalette {
+    colors: Vec<Color>,
+}
+
+impl ColorPalette {
+    pub fn new(colors: Vec<Color>) -> Self {
+        Self { colors }
+    }
+
+    /// Build a palette from entries in any form `parse_calendar_color` accepts.
+    pub fn parse<S: AsRef<str>>(entries: &[S]) -> Result<Self, ColorError> {
+        let colors = entries
+            .iter()
+            .map(|entry| parse_calendar_color(entry.as_ref()))
+            .collect::<Result<_, _>>()?;
+        Ok(Self::new(colors))
+    }
+
+    pub fn colors(&self) -> &[Color] {
+        &self.colors
+    }
+
+    /// Whether `color` is in the palette. Compared as stored, i.e. by its `#RRGGBB` form.
+    pub fn allows(&self, color: &Color) -> bool {
+        let hex = color_to_hex(color);
+        self.colors.iter().any(|c| color_to_hex(c) == hex)
+    }
+
+    /// Parse `input` like `parse_calendar_color`, then reject it unless it's in the palette.
+    pub fn parse_color(&self, input: &str) -> Result<Color, ColorError> {
+        let color = parse_calendar_color(input)?;
+        if self.allows(&color) {
+            Ok(color)
+        } else {
+            Err(ColorError::NotInPalette(input.to_string()))
+        }
+    }
+}
+
+/// WCAG 2 relative luminance of a color, from 0 for black to 1 for white.
+pub fn relative_luminance(color: &Color) -> f64 {
+    let (r, g, b) = color.to_rgb8();
+    // Undo the sRGB transfer curve to get linear light
+    let linear = |channel: u8| {
+        let c = f64::from(channel) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Black or white, whichever has the higher WCAG contrast ratio against `background`.
+pub fn contrasting_text_color(background: &Color) -> Color {
+    let luminance = relative_luminance(background);
+    // Contrast ratio is (lighter + 0.05) / (darker + 0.05), black and white have luminance 0 and 1
+    let against_black = (luminance + 0.05) / 0.05;
+    let against_white = 1.05 / (luminance + 0.05);
+    if against_black >= against_white {
+        Color::from_rgb8(0, 0, 0)
+    } else {
+        Color::from_rgb8(255, 255, 255)
+    }
+}
+
+impl Calendar {
+    /// Text color that stays readable on top of the calendar's color.
+    pub fn contrasting_text_color(&self) -> Color {
+        contrasting_text_color(&self.color)
+    }
+}
+
+/// Colors go out as `#RRGGBB`, along with the `text_color` to draw on top of `color`
+/// so frontends don't have to work it out themselves.
+impl Serialize for Calendar {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut calendar = serializer.serialize_struct("Calendar", 6)?;
+        calendar.serialize_field("id", &self.id)?;
+        calendar.serialize_field("name", &self.name)?;
+        calendar.serialize_field("color", &color_to_hex(&self.color))?;
+        calendar.serialize_field("text_color", &color_to_hex(&self.contrasting_text_color()))?;
+        calendar.serialize_field("created_at", &self.created_at)?;
+        calendar.serialize_field("updated_at", &self.updated_at)?;
+        calendar.end()
+    }
+}
+
+/// Map a row selected as `id, name, color, created_at, updated_at`.
+fn calendar_from_row(row: &Row) -> Result<Calendar, rusqlite::Error> {
+    let color: String = row.get(2)?;
+    Ok(Calendar {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        color: hex_to_color(&color)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(2, Type::Text, Box::new(e)))?,
+        created_at: datetime_from_sql(row, 3)?,
+        updated_at: datetime_from_sql(row, 4)?,
+    })
+}
+
+/// Map a row selected in the column order used by `sql::calendar::CALENDAR_PERMISSIONS_SELECT`.
+fn calendar_permission_from_row(row: &Row) -> Result<CalendarPermission, rusqlite::Error> {
+    Ok(CalendarPermission {
+        user_id: row.get(0)?,
+        calendar_id: row.get(1)?,
+        can_admin: row.get(2)?,
+        can_view: row.get(3)?,
+        can_read: row.get(4)?,
+        can_add_event: row.get(5)?,
+        can_modify_event: row.get(6)?,
+        can_add_recurring_event: row.get(7)?,
+        can_modify_recurring_event: row.get(8)?,
+    })
+}
+
+fn set_calendar_permission_on(
+    conn: &Connection,
+    perm: &CalendarPermission,
+) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        sql::calendar::CALENDAR_PERMISSIONS_UPSERT,
+        params![
+            perm.user_id,
+            perm.calendar_id,
+            perm.can_admin,
+            perm.can_view,
+            perm.can_read,
+            perm.can_add_event,
+            perm.can_modify_event,
+            perm.can_add_recurring_event,
+            perm.can_modify_recurring_event,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Insert a calendar on `conn`, shared by the plain and transactional insert paths.
+fn insert_calendar_on(conn: &Connection, name: &str, color: Color) -> Result<i64, rusqlite::Error> {
+    let now = datetime_to_sql(&chrono::Utc::now());
+    conn.execute(
+        sql::calendar::CALENDAR_INSERT,
+        params![name, color_to_hex(&color), now],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+impl DatabaseConnection {
+    // --- CALENDARS API ---
+
+    /// Insert a new calendar, returning its row id.
+    pub fn insert_calendar(&self, name: &str, color: Color) -> Result<i64, Error> {
+        Ok(insert_calendar_on(&self.conn, name, color)?)
+    }
+
+    /// Create a calendar and grant `owner_user_id` every capability on it, atomically.
+    /// If either insert fails neither the calendar nor the permission row is kept.
+    pub fn create_calendar_with_owner(
+        &mut self,
+        name: &str,
+        color: Color,
+        owner_user_id: i64,
+    ) -> Result<i64, Error> {
+        self.with_transaction(|tx| {
+            let calendar_id = insert_calendar_on(tx, name, color)?;
+            tx.execute(
+                sql::calendar::CALENDAR_PERMISSIONS_INSERT_OWNER,
+                params![owner_user_id, calendar_id],
+            )?;
+            Ok(calendar_id)
+        })
+    }
+
+    /// Select a calendar by id.
+    pub fn get_calendar_by_id(&self, id: i64) -> Result<Option<Calendar>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::calendar::CALENDAR_SELECT_BY_ID,
+                params![id],
+                calendar_from_row,
+            )
+            .optional()?)
+    }
+
+    /// List all calendars ordered by id.
+    pub fn list_calendars(&self) -> Result<Vec<Calendar>, Error> {
+        let mut stmt = self.conn.prepare(sql::calendar::CALENDAR_LIST)?;
+        let rows = stmt.query_map([], calendar_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Update a calendar's name and color. Returns false if no calendar has the given id.
+    pub fn update_calendar(&self, id: i64, name: &str, color: Color) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::calendar::CALENDAR_UPDATE,
+            params![
+                id,
+                name,
+                color_to_hex(&color),
+                datetime_to_sql(&chrono::Utc::now())
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Delete a calendar by id. Returns false if no calendar has the given id.
+    pub fn delete_calendar_by_id(&self, id: i64) -> Result<bool, Error> {
+        let changed = self
+            .conn
+            .execute(sql::calendar::CALENDAR_DELETE, params![id])?;
+        Ok(changed > 0)
+    }
+
+    // --- CALENDAR PERMISSIONS API ---
+
+    /// Set a user's capabilities on a calendar, overwriting any they already had.
+    pub fn set_calendar_permission(&self, perm: &CalendarPermission) -> Result<(), Error> {
+        Ok(set_calendar_permission_on(&self.conn, perm)?)
+    }
+
+    /// Get a user's capabilities on a calendar, `None` if they have no row for it.
+    pub fn get_calendar_permission(
+        &self,
+        user_id: i64,
+        calendar_id: i64,
+    ) -> Result<Option<CalendarPermission>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::calendar::CALENDAR_PERMISSIONS_SELECT,
+                params![user_id, calendar_id],
+                calendar_permission_from_row,
+            )
+            .optional()?)
+    }
+
+    /// List the capabilities of every user with access to a calendar, ordered by user id.
+    pub fn list_users_for_calendar(
+        &self,
+        calendar_id: i64,
+    ) -> Result<Vec<CalendarPermission>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(sql::calendar::CALENDAR_PERMISSIONS_LIST_BY_CALENDAR)?;
+        let rows = stmt.query_map(params![calendar_id], calendar_permission_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    // --- CALENDAR INVITES API ---
+
+    /// Record an invite to a calendar, identified by the unique id of its token.
+    pub fn insert_calendar_invite(
+        &self,
+        invite_id: &str,
+        calendar_id: i64,
+        issued_by: i64,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), Error> {
+        self.conn.execute(
+            sql::calendar::CALENDAR_INVITES_INSERT,
+            params![
+                invite_id,
+                calendar_id,
+                issued_by,
+                datetime_to_sql(&expires_at)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Accept an invite on behalf of `perm.user_id`, granting them `perm`, atomically.
+    /// Returns false (granting nothing) if the invite is unknown, already accepted, expired,
+    /// or for a different calendar than `perm.calendar_id`.
+    pub fn accept_calendar_invite(
+        &mut self,
+        invite_id: &str,
+        perm: &CalendarPermission,
+    ) -> Result<bool, Error> {
+        self.with_transaction(|tx| {
+            let accepted = tx.execute(
+                sql::calendar::CALENDAR_INVITES_ACCEPT,
+                params![
+                    invite_id,
+                    perm.calendar_id,
+                    perm.user_id,
+                    datetime_to_sql(&Utc::now())
+                ],
+            )?;
+            if accepted == 0 {
+                return Ok(false);
+            }
+            set_calendar_permission_on(tx, perm)?;
+            Ok(true)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn test_db() -> DatabaseConnection {
+        DatabaseConnection::from_path(Path::new(":memory:")).unwrap()
+    }
+
+    #[test]
+    fn test_hex_round_trip() {
+        let color = Color::from_rgb8(0x12, 0xAB, 0xEF);
+        assert_eq!(color_to_hex(&color), "#12ABEF");
+        assert_eq!(hex_to_color("#12ABEF").unwrap(), color);
+        assert_eq!(hex_to_color("#12abef").unwrap(), color);
+
+        for bad in ["12ABEF", "#12ABE", "#12ABEFF", "#GGGGGG", ""] {
+            assert_eq!(
+                hex_to_color(bad),
+                Err(ColorError::InvalidHex(bad.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_calendar_color_formats() {
+        let orange = Color::from_rgb8(0xFF, 0xA5, 0x00);
+        assert_eq!(parse_calendar_color("#FFA500").unwrap(), orange);
+        assert_eq!(parse_calendar_color("#ffa500").unwrap(), orange);
+        assert_eq!(parse_calendar_color("orange").unwrap(), orange);
+        assert_eq!(parse_calendar_color("  Orange ").unwrap(), orange);
+        assert_eq!(
+            parse_calendar_color("#F80").unwrap(),
+            Color::from_rgb8(0xFF, 0x88, 0x00)
+        );
+        assert_eq!(
+            parse_calendar_color("#abc").unwrap(),
+            Color::from_rgb8(0xAA, 0xBB, 0xCC)
+        );
+        assert_eq!(parse_calendar_color("grey"), parse_calendar_color("gray"));
+    }
+
+    #[test]
+    fn test_parse_calendar_color_rejects_garbage() {
+        for bad in ["#12", "#12345", "#GGG", "#12ABEFF", "#"] {
+            assert_eq!(
+                parse_calendar_color(bad),
+                Err(ColorError::InvalidHex(bad.to_string()))
+            );
+        }
+        for bad in ["", "notacolor", "FFA500", "rgb(1, 2, 3)"] {
+            assert_eq!(
+                parse_calendar_color(bad),
+                Err(ColorError::UnknownColor(bad.to_string()))
+            );
+        }
+    }
+
+    #[test]
+    fn test_palette_restricts_colors() {
+        let palette = ColorPalette::parse(&["red", "#00F", "#336699"]).unwrap();
+        assert_eq!(palette.colors().len(), 3);
+        assert!(palette.parse_color("#FF0000").is_ok());
+        assert!(palette.parse_color("blue").is_ok());
+        assert!(palette.parse_color("#369").is_ok());
+        assert_eq!(
+            palette.parse_color("orange"),
+            Err(ColorError::NotInPalette("orange".to_string()))
+        );
+        // Bad input is still reported as such, not as outside the palette
+        assert_eq!(
+            palette.parse_color("nope"),
+            Err(ColorError::UnknownColor("nope".to_string()))
+        );
+        assert_eq!(
+            ColorPalette::parse(&["red", "bogus"]),
+            Err(ColorError::UnknownColor("bogus".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_text_color_contrasts_with_background() {
+        let black = Color::from_rgb8(0, 0, 0);
+        let white = Color::from_rgb8(255, 255, 255);
+        for dark in ["#000000", "#1A237E", "#B71C1C", "#336699", "#6D4C41"] {
+            let background = hex_to_color(dark).unwrap();
+            assert_eq!(contrasting_text_color(&background), white, "{dark}");
+        }
+        for light in ["#FFFFFF", "#FFEB3B", "#A5D6A7", "#90CAF9", "#FF9800"] {
+            let background = hex_to_color(light).unwrap();
+            assert_eq!(contrasting_text_color(&background), black, "{light}");
+        }
+        assert!(relative_luminance(&black).abs() < 1e-9);
+        assert!((relative_luminance(&white) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_calendar_json_includes_text_color() {
+        let db = test_db();
+        let id = db
+            .insert_calendar("Work", Color::from_rgb8(0x1A, 0x23, 0x7E))
+            .unwrap();
+        let calendar = db.get_calendar_by_id(id).unwrap().unwrap();
+        let json = serde_json::to_value(&calendar).unwrap();
+        assert_eq!(json["name"], "Work");
+        assert_eq!(json["color"], "#1A237E");
+        assert_eq!(json["text_color"], "#FFFFFF");
+    }
+
+    #[test]
+    fn test_calendar_round_trip() {
+        let db = test_db();
+        let color = Color::from_rgb8(0x33, 0x66, 0x99);
+        let id = db.insert_calendar("Family", color).unwrap();
+
+        let calendar = db.get_calendar_by_id(id).unwrap().expect("calendar exists");
+        assert_eq!(calendar.name, "Family");
+        assert_eq!(calendar.color, color);
+        assert_eq!(color_to_hex(&calendar.color), "#336699");
+
+        let other = Color::from_rgb8(0xFF, 0x00, 0x80);
+        assert!(db.update_calendar(id, "Household", other).unwrap());
+        let calendar = db.get_calendar_by_id(id).unwrap().unwrap();
+        assert_eq!(calendar.name, "Household");
+        assert_eq!(calendar.color, other);
+
+        db.insert_calendar("Work", color).unwrap();
+        let names: Vec<String> = db
+            .list_calendars()
+            .unwrap()
+            .into_iter()
+            .map(|c| c.name)
+            .collect();
+        assert_eq!(names, vec!["Household", "Work"]);
+
+        assert!(db.delete_calendar_by_id(id).unwrap());
+        assert!(db.get_calendar_by_id(id).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_create_calendar_with_owner() {
+        let mut db = test_db();
+        db.insert_user("alice", "hash", "salt", "alice@example.com")
+            .unwrap();
+        let owner = db.get_user_by_username("alice").unwrap().unwrap().id;
+
+        let id = db
+            .create_calendar_with_owner("Family", Color::from_rgb8(1, 2, 3), owner)
+            .unwrap();
+        assert!(db.get_calendar_by_id(id).unwrap().is_some());
+        let can_admin: bool = db
+            .conn
+            .query_row(
+                "SELECT can_admin FROM calendar_permissions WHERE user_id = ?1 AND calendar_id = ?2",
+                params![owner, id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(can_admin);
+    }
+
+    #[test]
+    fn test_create_calendar_with_owner_rolls_back() {
+        let mut db = test_db();
+        // No such user, so the permission insert fails its foreign key after the calendar insert
+        let result = db.create_calendar_with_owner("Family", Color::from_rgb8(1, 2, 3), 404);
+        assert!(result.is_err());
+
+        assert!(db.list_calendars().unwrap().is_empty());
+        let permission_rows: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM calendar_permissions", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(permission_rows, 0);
+    }
+
+    #[test]
+    fn test_calendar_permissions_persist_individually() {
+        let db = test_db();
+        db.insert_user("alice", "hash", "salt", "alice@example.com")
+            .unwrap();
+        db.insert_user("bob", "hash", "salt", "bob@example.com")
+            .unwrap();
+        let alice = db.get_user_by_username("alice").unwrap().unwrap().id;
+        let bob = db.get_user_by_username("bob").unwrap().unwrap().id;
+        let calendar_id = db
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        assert_eq!(
+            db.get_calendar_permission(alice, calendar_id).unwrap(),
+            None
+        );
+
+        let mut perm = CalendarPermission {
+            user_id: alice,
+            calendar_id,
+            can_admin: false,
+            can_view: true,
+            can_read: false,
+            can_add_event: true,
+            can_modify_event: false,
+            can_add_recurring_event: false,
+            can_modify_recurring_event: false,
+        };
+        db.set_calendar_permission(&perm).unwrap();
+        assert_eq!(
+            db.get_calendar_permission(alice, calendar_id).unwrap(),
+            Some(perm.clone())
+        );
+
+        // Re-setting overwrites every capability rather than merging
+        perm.can_add_event = false;
+        perm.can_modify_recurring_event = true;
+        db.set_calendar_permission(&perm).unwrap();
+        assert_eq!(
+            db.get_calendar_permission(alice, calendar_id).unwrap(),
+            Some(perm.clone())
+        );
+
+        let bob_perm = CalendarPermission {
+            user_id: bob,
+            can_admin: true,
+            ..perm.clone()
+        };
+        db.set_calendar_permission(&bob_perm).unwrap();
+        assert_eq!(
+            db.list_users_for_calendar(calendar_id).unwrap(),
+            vec![perm, bob_perm]
+        );
+    }
+
+    #[test]
+    fn test_invalid_stored_color_is_an_error() {
+        let db = test_db();
+        let id = db
+            .insert_calendar("Broken", Color::from_rgb8(0, 0, 0))
+            .unwrap();
+        db.conn
+            .execute(
+                "UPDATE calendars SET color = 'not a color' WHERE id = ?1",
+                params![id],
+            )
+            .unwrap();
+
+        match db.get_calendar_by_id(id) {
+            Err(Error::Serialization(e)) => {
+                assert!(e.to_string().contains("not a color"))
+            }
+            other => panic!("expected conversion failure, got {:?}", other.map(|_| ())),
+        }
+    }
+}
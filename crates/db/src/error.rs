@@ -0,0 +1,126 @@
+use std::fmt;
+
+/// Error from a database call.
+#[derive(Debug)]
+pub enum Error {
+    /// SQLite rejected the statement, e.g. a constraint violation or invalid SQL
+    Sqlite(rusqlite::Error),
+    /// Applying the migration to `version` failed, the database stays at the version before it
+    Migration {
+        version: i64,
+        source: rusqlite::Error,
+    },
+    /// A stored value couldn't be converted to or from its Rust type
+    Serialization(Box<dyn std::error::Error + Send + Sync>),
+    /// A query expecting a row found none
+    NotFound,
+    /// No pooled connection could be opened
+    Pool(r2d2::Error),
+    /// Reading or writing a file (e.g. an `.sql` export) failed
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Whether SQLite refused the write for breaking a UNIQUE, FOREIGN KEY, CHECK or NOT NULL
+    /// constraint.
+    pub fn is_constraint_violation(&self) -> bool {
+        matches!(
+            self,
+            Error::Sqlite(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation
+        )
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Sqlite(e) => write!(f, "database error: {e}"),
+            Error::Migration { version, source } => {
+                write!(
+                    f,
+                    "failed to migrate database to version {version}: {source}"
+                )
+            }
+            Error::Serialization(e) => write!(f, "invalid value in database: {e}"),
+            Error::NotFound => write!(f, "no matching row in database"),
+            Error::Pool(e) => write!(f, "failed to open a database connection: {e}"),
+            Error::Io(e) => write!(f, "database file error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Sqlite(e) | Error::Migration { source: e, .. } => Some(e),
+            Error::Serialization(e) => Some(e.as_ref()),
+            Error::NotFound => None,
+            Error::Pool(e) => Some(e),
+            Error::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<rusqlite::Error> for Error {
+    fn from(e: rusqlite::Error) -> Self {
+        match e {
+            rusqlite::Error::QueryReturnedNoRows => Error::NotFound,
+            rusqlite::Error::FromSqlConversionFailure(_, _, e)
+            | rusqlite::Error::ToSqlConversionFailure(e) => Error::Serialization(e),
+            e => Error::Sqlite(e),
+        }
+    }
+}
+
+impl From<r2d2::Error> for Error {
+    fn from(e: r2d2::Error) -> Self {
+        Error::Pool(e)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(e: std::io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DatabaseConnection;
+    use std::error::Error as _;
+    use std::path::Path;
+
+    #[test]
+    fn test_rusqlite_errors_map_to_variants() {
+        assert!(matches!(
+            Error::from(rusqlite::Error::QueryReturnedNoRows),
+            Error::NotFound
+        ));
+
+        let err = Error::from(rusqlite::Error::ToSqlConversionFailure("bad value".into()));
+        assert!(matches!(err, Error::Serialization(_)));
+        assert_eq!(err.source().unwrap().to_string(), "bad value");
+
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let err = Error::from(db.conn.execute_batch("NOT SQL").unwrap_err());
+        assert!(matches!(err, Error::Sqlite(_)));
+        assert!(!err.is_constraint_violation());
+        assert!(err.source().unwrap().is::<rusqlite::Error>());
+    }
+
+    #[test]
+    fn test_unique_violation_is_a_constraint_violation() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "a@x.com").unwrap();
+        let err = db
+            .insert_user("alice", "hash", "salt", "b@x.com")
+            .unwrap_err();
+        assert!(err.is_constraint_violation());
+        assert!(
+            err.to_string()
+                .starts_with("database error: UNIQUE constraint failed")
+        );
+    }
+}
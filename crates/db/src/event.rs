@@ -0,0 +1,1024 @@
+use crate::timezone::{day_span, stored_timezone, timezone_to_sql};
+use crate::{
+    DEFAULT_TIMEZONE, DatabaseConnection, Error, Event, datetime_from_sql, datetime_to_sql, sql,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use std::collections::HashMap;
+use tracing::*;
+
+/// The fields of an event to insert, the calendar is passed separately.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewEvent {
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// IANA time zone name, see `Event::timezone`
+    pub timezone: String,
+    /// See `Event::all_day`
+    pub all_day: bool,
+}
+
+impl NewEvent {
+    /// A timed event in UTC.
+    pub fn new(
+        title: &str,
+        description: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            title: title.to_string(),
+            description: description.map(str::to_string),
+            start_time,
+            end_time,
+            timezone: DEFAULT_TIMEZONE.to_string(),
+            all_day: false,
+        }
+    }
+}
+
+/// New details for an existing event, see `update_event_details`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventUpdate {
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// New IANA time zone, `None` keeps the current one
+    pub timezone: Option<String>,
+    /// Whether the event is now all-day, `None` keeps it as it is
+    pub all_day: Option<bool>,
+}
+
+/// An event created, updated or deleted since some point, see `list_event_changes_since`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChangedEvent {
+    /// The event as it was last stored (before its deletion, for tombstones)
+    pub event: Event,
+    /// When the event was soft-deleted, `None` if it still exists
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl ChangedEvent {
+    /// When the event last changed: its deletion, or else its last update.
+    pub fn changed_at(&self) -> DateTime<Utc> {
+        self.deleted_at.unwrap_or(self.event.updated_at)
+    }
+}
+
+/// Map a row selected as
+/// `id, calendar_id, title, description, start_time, end_time, created_at, updated_at, timezone, all_day`.
+pub(crate) fn event_from_row(row: &Row) -> Result<Event, rusqlite::Error> {
+    Ok(Event {
+        id: row.get(0)?,
+        calendar_id: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        start_time: datetime_from_sql(row, 4)?,
+        end_time: datetime_from_sql(row, 5)?,
+        created_at: datetime_from_sql(row, 6)?,
+        updated_at: datetime_from_sql(row, 7)?,
+        timezone: row.get(8)?,
+        all_day: row.get(9)?,
+    })
+}
+
+fn insert_event_on(
+    conn: &Connection,
+    calendar_id: i64,
+    event: &NewEvent,
+) -> Result<i64, rusqlite::Error> {
+    conn.execute(
+        sql::event::EVENT_INSERT,
+        params![
+            calendar_id,
+            event.title,
+            event.description,
+            datetime_to_sql(&event.start_time),
+            datetime_to_sql(&event.end_time),
+            datetime_to_sql(&Utc::now()),
+            timezone_to_sql(&event.timezone)?,
+            event.all_day,
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+    store_day_span_on(conn, id)?;
+    Ok(id)
+}
+
+/// Store the whole days the event covers when all-day (see `Event::day_span`), which range and
+/// reminder queries compare against. Needed whenever its times or time zone change.
+fn store_day_span_on(conn: &Connection, id: i64) -> Result<(), rusqlite::Error> {
+    let (start, end, timezone): (_, _, String) =
+        conn.query_row(sql::event::EVENT_DAY_SPAN_SOURCE, params![id], |row| {
+            Ok((
+                datetime_from_sql(row, 0)?,
+                datetime_from_sql(row, 1)?,
+                row.get(2)?,
+            ))
+        })?;
+    let (day_start, day_end) = day_span(stored_timezone(&timezone), start, end);
+    conn.execute(
+        sql::event::EVENT_DAY_SPAN_UPDATE,
+        params![id, datetime_to_sql(&day_start), datetime_to_sql(&day_end)],
+    )?;
+    Ok(())
+}
+
+/// Turn user input into an FTS5 query matching events containing every word,
+/// quoting each word so characters like `"` or `-` are never read as query syntax.
+fn fts_query(query: &str) -> Option<String> {
+    let terms: Vec<String> = query
+        .split_whitespace()
+        .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Turn user input into a LIKE pattern matching it anywhere, escaping `%`, `_` and `\`.
+fn like_pattern(query: &str) -> String {
+    let mut pattern = String::from("%");
+    for c in query.trim().chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+impl DatabaseConnection {
+    /// Create the full-text index over events if the linked SQLite has FTS5, indexing any events
+    /// that already exist. Without FTS5 nothing is created and `search_events` falls back to LIKE.
+    pub(crate) fn init_event_search_schema(&self) -> Result<(), rusqlite::Error> {
+        let fts5: bool = self.conn.query_row(
+            "SELECT sqlite_compileoption_used('ENABLE_FTS5')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !fts5 || self.has_event_search_index()? {
+            return Ok(());
+        }
+        self.conn.execute_batch(sql::event::EVENT_FTS_SCHEMA)?;
+        self.conn.execute_batch(sql::event::EVENT_FTS_REBUILD)
+    }
+
+    /// Whether the full-text index over events exists.
+    fn has_event_search_index(&self) -> Result<bool, rusqlite::Error> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'events_fts')",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    // --- EVENTS API ---
+
+    /// Insert a new timed event in UTC, returning its row id.
+    pub fn insert_event(
+        &self,
+        calendar_id: i64,
+        title: &str,
+        description: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<i64, Error> {
+        self.insert_new_event(
+            calendar_id,
+            &NewEvent::new(title, description, start_time, end_time),
+        )
+    }
+
+    /// Insert a new event with every field given, returning its row id.
+    /// Fails without inserting anything if the time zone is unknown.
+    pub fn insert_new_event(&self, calendar_id: i64, event: &NewEvent) -> Result<i64, Error> {
+        Ok(insert_event_on(&self.conn, calendar_id, event)?)
+    }
+
+    /// Insert a new event created in the IANA time zone `timezone`, returning its row id.
+    /// Fails without inserting anything if the time zone is unknown.
+    pub fn insert_event_in_timezone(
+        &self,
+        calendar_id: i64,
+        title: &str,
+        description: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        timezone: &str,
+    ) -> Result<i64, Error> {
+        self.insert_new_event(
+            calendar_id,
+            &NewEvent {
+                timezone: timezone.to_string(),
+                ..NewEvent::new(title, description, start_time, end_time)
+            },
+        )
+    }
+
+    /// Change the IANA time zone of an event. Returns false if no event has the given id.
+    pub fn set_event_timezone(&self, id: i64, timezone: &str) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::event::EVENT_UPDATE_TIMEZONE,
+            params![id, timezone_to_sql(timezone)?, datetime_to_sql(&Utc::now())],
+        )?;
+        if changed > 0 {
+            store_day_span_on(&self.conn, id)?;
+        }
+        Ok(changed > 0)
+    }
+
+    /// Store the all-day span of every event that doesn't have one yet, see `run_migrations`.
+    pub(crate) fn fill_missing_day_spans(&self) -> Result<(), Error> {
+        let ids: Vec<i64> = {
+            let mut stmt = self.conn.prepare(sql::event::EVENT_DAY_SPAN_MISSING)?;
+            let rows = stmt.query_map([], |row| row.get(0))?;
+            rows.collect::<Result<_, _>>()?
+        };
+        if ids.is_empty() {
+            return Ok(());
+        }
+        info!("Storing the all-day spans of {} existing events", ids.len());
+        let tx = self.conn.unchecked_transaction()?;
+        for id in ids {
+            store_day_span_on(&tx, id)?;
+        }
+        Ok(tx.commit()?)
+    }
+
+    /// Make an event all-day, or timed again. Returns false if no event has the given id.
+    pub fn set_event_all_day(&self, id: i64, all_day: bool) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::event::EVENT_UPDATE_ALL_DAY,
+            params![id, all_day, datetime_to_sql(&Utc::now())],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Insert many events into a calendar in one transaction, returning their ids in the same order.
+    /// Much faster than calling `insert_event` in a loop; if any insert fails none are kept.
+    pub fn insert_events(
+        &mut self,
+        calendar_id: i64,
+        events: &[NewEvent],
+    ) -> Result<Vec<i64>, Error> {
+        let created_at = datetime_to_sql(&Utc::now());
+        self.with_transaction(|tx| {
+            let mut stmt = tx.prepare(sql::event::EVENT_INSERT)?;
+            Ok(events
+                .iter()
+                .map(|event| -> Result<i64, rusqlite::Error> {
+                    let id = stmt.insert(params![
+                        calendar_id,
+                        event.title,
+                        event.description,
+                        datetime_to_sql(&event.start_time),
+                        datetime_to_sql(&event.end_time),
+                        created_at,
+                        timezone_to_sql(&event.timezone)?,
+                        event.all_day,
+                    ])?;
+                    store_day_span_on(tx, id)?;
+                    Ok(id)
+                })
+                .collect::<Result<_, _>>()?)
+        })
+    }
+
+    /// Insert an event imported from an iCalendar file, remembering its `uid` so importing it again is a no-op.
+    /// Returns the new event's id, or `None` if an event with that UID was already imported into the calendar.
+    pub fn import_event(
+        &mut self,
+        calendar_id: i64,
+        uid: &str,
+        event: &NewEvent,
+    ) -> Result<Option<i64>, Error> {
+        self.with_transaction(|tx| {
+            let existing: Option<i64> = tx
+                .query_row(
+                    sql::event::EVENT_UID_SELECT,
+                    params![calendar_id, uid],
+                    |row| row.get(0),
+                )
+                .optional()?;
+            if existing.is_some() {
+                return Ok(None);
+            }
+            let id = insert_event_on(tx, calendar_id, event)?;
+            tx.execute(sql::event::EVENT_UID_INSERT, params![calendar_id, uid, id])?;
+            Ok(Some(id))
+        })
+    }
+
+    /// The iCalendar UIDs events in a calendar were imported with, by event id.
+    /// Events created here rather than imported have none.
+    pub fn list_event_uids(&self, calendar_id: i64) -> Result<HashMap<i64, String>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(sql::event::EVENT_UID_SELECT_BY_CALENDAR)?;
+        let rows = stmt.query_map(params![calendar_id], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Select an event by id.
+    pub fn get_event_by_id(&self, id: i64) -> Result<Option<Event>, Error> {
+        Ok(self
+            .conn
+            .query_row(sql::event::EVENT_SELECT_BY_ID, params![id], event_from_row)
+            .optional()?)
+    }
+
+    /// Update an event's details. Returns false if no event has the given id.
+    pub fn update_event(
+        &self,
+        id: i64,
+        title: &str,
+        description: Option<&str>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::event::EVENT_UPDATE,
+            params![
+                id,
+                title,
+                description,
+                datetime_to_sql(&start_time),
+                datetime_to_sql(&end_time),
+                datetime_to_sql(&Utc::now()),
+            ],
+        )?;
+        if changed > 0 {
+            store_day_span_on(&self.conn, id)?;
+        }
+        Ok(changed > 0)
+    }
+
+    /// Apply an `EventUpdate` in one transaction, so either every field changes or none does
+    /// (e.g. when the time zone is unknown). Returns false if no event has the given id.
+    pub fn update_event_details(&mut self, id: i64, update: &EventUpdate) -> Result<bool, Error> {
+        let updated_at = datetime_to_sql(&Utc::now());
+        self.with_transaction(|tx| {
+            let changed = tx.execute(
+                sql::event::EVENT_UPDATE,
+                params![
+                    id,
+                    update.title,
+                    update.description,
+                    datetime_to_sql(&update.start_time),
+                    datetime_to_sql(&update.end_time),
+                    updated_at,
+                ],
+            )?;
+            if changed == 0 {
+                return Ok(false);
+            }
+            if let Some(timezone) = &update.timezone {
+                tx.execute(
+                    sql::event::EVENT_UPDATE_TIMEZONE,
+                    params![id, timezone_to_sql(timezone)?, updated_at],
+                )?;
+            }
+            if let Some(all_day) = update.all_day {
+                tx.execute(
+                    sql::event::EVENT_UPDATE_ALL_DAY,
+                    params![id, all_day, updated_at],
+                )?;
+            }
+            store_day_span_on(tx, id)?;
+            Ok(true)
+        })
+    }
+
+    /// List the events in a calendar overlapping `[range_start, range_end)`, ordered by start time.
+    /// An event overlaps when it starts before the range ends and ends after the range starts.
+    pub fn list_events_in_range(
+        &self,
+        calendar_id: i64,
+        range_start: DateTime<Utc>,
+        range_end: DateTime<Utc>,
+    ) -> Result<Vec<Event>, Error> {
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SELECT_IN_RANGE)?;
+        let rows = stmt.query_map(
+            params![
+                calendar_id,
+                datetime_to_sql(&range_start),
+                datetime_to_sql(&range_end)
+            ],
+            event_from_row,
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// List every event in a calendar, ordered by start time.
+    pub fn list_events_by_calendar(&self, calendar_id: i64) -> Result<Vec<Event>, Error> {
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SELECT_BY_CALENDAR)?;
+        let rows = stmt.query_map(params![calendar_id], event_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// List the events in a calendar created, updated or deleted after `since`, oldest change
+    /// first, for clients syncing by polling. Deleted events come back as tombstones until
+    /// they're purged. Changes made in the current millisecond are left for the next call, so a
+    /// client passing back the last change it saw neither gets it again nor misses one made in
+    /// the same millisecond.
+    pub fn list_event_changes_since(
+        &self,
+        calendar_id: i64,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<ChangedEvent>, Error> {
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SELECT_CHANGED_SINCE)?;
+        let params = params![
+            calendar_id,
+            datetime_to_sql(&since),
+            datetime_to_sql(&Utc::now())
+        ];
+        let rows = stmt.query_map(params, |row| {
+            let deleted_at: Option<String> = row.get(10)?;
+            Ok(ChangedEvent {
+                event: event_from_row(row)?,
+                deleted_at: match deleted_at {
+                    Some(_) => Some(datetime_from_sql(row, 10)?),
+                    None => None,
+                },
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Find the events in a calendar overlapping `[start, end)`, ordered by start time, so callers
+    /// can warn about double-booking. Pass the id of the event being edited as `exclude_event_id`
+    /// so it doesn't clash with itself. Events that merely touch the range are not overlaps.
+    pub fn find_overlapping_events(
+        &self,
+        calendar_id: i64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        exclude_event_id: Option<i64>,
+    ) -> Result<Vec<Event>, Error> {
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SELECT_OVERLAPPING)?;
+        let rows = stmt.query_map(
+            params![
+                calendar_id,
+                datetime_to_sql(&start),
+                datetime_to_sql(&end),
+                exclude_event_id
+            ],
+            event_from_row,
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Search event titles and descriptions for every word of `query`, in one calendar or (with `None`)
+    /// all of them, returning at most `limit` events with the best matches first.
+    /// Without FTS5 in the linked SQLite this falls back to matching `query` as a plain substring,
+    /// ordered by start time.
+    pub fn search_events(
+        &self,
+        calendar_id: Option<i64>,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<Event>, Error> {
+        if !self.has_event_search_index()? {
+            return Ok(self.search_events_like(calendar_id, query, limit)?);
+        }
+        let Some(fts_query) = fts_query(query) else {
+            return Ok(Vec::new());
+        };
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SEARCH_FTS)?;
+        let rows = stmt.query_map(params![calendar_id, fts_query, limit], event_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// The LIKE based fallback for `search_events`.
+    fn search_events_like(
+        &self,
+        calendar_id: Option<i64>,
+        query: &str,
+        limit: i64,
+    ) -> Result<Vec<Event>, rusqlite::Error> {
+        if query.trim().is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut stmt = self.conn.prepare(sql::event::EVENT_SEARCH_LIKE)?;
+        let rows = stmt.query_map(
+            params![calendar_id, like_pattern(query), limit],
+            event_from_row,
+        )?;
+        rows.collect()
+    }
+
+    /// Soft-delete an event by id: it's hidden from lookups, listings and searches but can be
+    /// brought back with `restore_event` until it's purged.
+    /// Returns false if no (not already deleted) event has the given id.
+    pub fn delete_event_by_id(&self, id: i64) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::event::EVENT_DELETE,
+            params![id, datetime_to_sql(&Utc::now())],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Bring back a soft-deleted event. Returns false if no deleted event has the given id.
+    pub fn restore_event(&self, id: i64) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::event::EVENT_RESTORE,
+            params![id, datetime_to_sql(&Utc::now())],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Permanently remove events soft-deleted before `older_than`, returning how many were removed.
+    pub fn purge_deleted_events(&self, older_than: DateTime<Utc>) -> Result<usize, Error> {
+        Ok(self.conn.execute(
+            sql::event::EVENT_PURGE_DELETED,
+            params![datetime_to_sql(&older_than)],
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use colorlab::Color;
+    use std::path::Path;
+
+    fn test_db() -> (DatabaseConnection, i64) {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let calendar_id = db
+            .insert_calendar("Family", Color::from_rgb8(0x33, 0x66, 0x99))
+            .unwrap();
+        (db, calendar_id)
+    }
+
+    #[test]
+    fn test_event_round_trip() {
+        let (db, calendar_id) = test_db();
+        let start = Utc.with_ymd_and_hms(2025, 3, 14, 9, 30, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 3, 14, 10, 45, 0).unwrap();
+
+        let id = db
+            .insert_event(calendar_id, "Dentist", Some("Bring forms"), start, end)
+            .unwrap();
+        let event = db.get_event_by_id(id).unwrap().expect("event should exist");
+        assert_eq!(event.id, id);
+        assert_eq!(event.calendar_id, calendar_id);
+        assert_eq!(event.title, "Dentist");
+        assert_eq!(event.description.as_deref(), Some("Bring forms"));
+        assert_eq!(event.start_time, start);
+        assert_eq!(event.end_time, end);
+
+        // Update
+        let new_end = Utc.with_ymd_and_hms(2025, 3, 14, 11, 0, 0).unwrap();
+        assert!(
+            db.update_event(id, "Dentist", None, start, new_end)
+                .unwrap()
+        );
+        let event = db.get_event_by_id(id).unwrap().unwrap();
+        assert_eq!(event.description, None);
+        assert_eq!(event.end_time, new_end);
+        assert!(!db.update_event(id + 1, "Nope", None, start, end).unwrap());
+
+        // Delete
+        assert!(db.delete_event_by_id(id).unwrap());
+        assert!(db.get_event_by_id(id).unwrap().is_none());
+        assert!(!db.delete_event_by_id(id).unwrap());
+    }
+
+    fn count_events(db: &DatabaseConnection) -> i64 {
+        db.conn
+            .query_row("SELECT COUNT(*) FROM events", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_insert_events_in_bulk() {
+        let (mut db, calendar_id) = test_db();
+        let start = Utc.with_ymd_and_hms(2025, 1, 1, 9, 0, 0).unwrap();
+        let events: Vec<NewEvent> = (0..1000)
+            .map(|i| NewEvent {
+                title: format!("Event {i}"),
+                description: None,
+                start_time: start + chrono::Duration::hours(i),
+                end_time: start + chrono::Duration::hours(i + 1),
+                timezone: DEFAULT_TIMEZONE.to_string(),
+                all_day: false,
+            })
+            .collect();
+
+        let ids = db.insert_events(calendar_id, &events).unwrap();
+        assert_eq!(ids.len(), 1000);
+        assert!(ids.windows(2).all(|pair| pair[0] < pair[1]));
+        assert_eq!(count_events(&db), 1000);
+        assert_eq!(
+            db.get_event_by_id(ids[999]).unwrap().unwrap().title,
+            "Event 999"
+        );
+    }
+
+    #[test]
+    fn test_insert_events_rolls_back_on_failure() {
+        let (mut db, calendar_id) = test_db();
+        db.conn
+            .execute_batch(
+                "CREATE TEMP TRIGGER fail_insert BEFORE INSERT ON events
+                 WHEN new.title = 'boom' BEGIN SELECT RAISE(ABORT, 'boom'); END;",
+            )
+            .unwrap();
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 9, 0, 0).unwrap();
+        let event = |title: &str| NewEvent::new(title, None, at, at);
+
+        let batch = [
+            event("first"),
+            event("second"),
+            event("boom"),
+            event("last"),
+        ];
+        assert!(db.insert_events(calendar_id, &batch).is_err());
+        assert_eq!(count_events(&db), 0);
+    }
+
+    #[test]
+    fn test_find_overlapping_events() {
+        let (db, calendar_id) = test_db();
+        let other_calendar = db
+            .insert_calendar("Work", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let at = |hour| Utc.with_ymd_and_hms(2025, 3, 14, hour, 0, 0).unwrap();
+        let morning = db
+            .insert_event(calendar_id, "Morning", None, at(8), at(10))
+            .unwrap();
+        let lunch = db
+            .insert_event(calendar_id, "Lunch", None, at(12), at(13))
+            .unwrap();
+        db.insert_event(other_calendar, "Standup", None, at(9), at(10))
+            .unwrap();
+
+        let ids = |start, end, exclude| {
+            db.find_overlapping_events(calendar_id, at(start), at(end), exclude)
+                .unwrap()
+                .into_iter()
+                .map(|e| e.id)
+                .collect::<Vec<_>>()
+        };
+        // Touching either end is not an overlap
+        assert!(ids(10, 12, None).is_empty());
+        assert!(ids(6, 8, None).is_empty());
+        assert_eq!(ids(9, 11, None), vec![morning]);
+        assert_eq!(ids(7, 14, None), vec![morning, lunch]);
+        // An event being edited doesn't clash with itself
+        assert_eq!(ids(7, 14, Some(morning)), vec![lunch]);
+    }
+
+    #[test]
+    fn test_soft_delete_restore_and_purge() {
+        let (db, calendar_id) = test_db();
+        let start = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 3, 14, 10, 0, 0).unwrap();
+        let id = db
+            .insert_event(calendar_id, "Dentist", None, start, end)
+            .unwrap();
+        let in_range = |db: &DatabaseConnection| {
+            db.list_events_in_range(calendar_id, start, end)
+                .unwrap()
+                .len()
+        };
+
+        assert!(db.delete_event_by_id(id).unwrap());
+        assert!(db.get_event_by_id(id).unwrap().is_none());
+        assert_eq!(in_range(&db), 0);
+        assert!(db.search_events(None, "dentist", 10).unwrap().is_empty());
+        assert!(!db.update_event(id, "Dentist", None, start, end).unwrap());
+
+        assert!(db.restore_event(id).unwrap());
+        assert!(!db.restore_event(id).unwrap());
+        assert_eq!(db.get_event_by_id(id).unwrap().unwrap().title, "Dentist");
+        assert_eq!(in_range(&db), 1);
+
+        // Purge only removes events deleted before the cutoff
+        db.delete_event_by_id(id).unwrap();
+        let before_delete = Utc::now() - chrono::Duration::hours(1);
+        assert_eq!(db.purge_deleted_events(before_delete).unwrap(), 0);
+        assert!(db.restore_event(id).unwrap());
+        db.delete_event_by_id(id).unwrap();
+        let after_delete = Utc::now() + chrono::Duration::seconds(1);
+        assert_eq!(db.purge_deleted_events(after_delete).unwrap(), 1);
+        assert!(!db.restore_event(id).unwrap());
+    }
+
+    #[test]
+    fn test_list_event_changes_since() {
+        let (db, calendar_id) = test_db();
+        let other = db
+            .insert_calendar("Work", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let start = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 3, 14, 10, 0, 0).unwrap();
+        let old = db
+            .insert_event(calendar_id, "Old", None, start, end)
+            .unwrap();
+        let gone = db
+            .insert_event(calendar_id, "Gone", None, start, end)
+            .unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let cursor = Utc::now();
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        let new = db
+            .insert_event(calendar_id, "New", None, start, end)
+            .unwrap();
+        db.insert_event(other, "Elsewhere", None, start, end)
+            .unwrap();
+        assert!(db.delete_event_by_id(gone).unwrap());
+        // Nothing from the current millisecond is returned yet
+        std::thread::sleep(std::time::Duration::from_millis(2));
+
+        let changes = db.list_event_changes_since(calendar_id, cursor).unwrap();
+        let mut summary: Vec<(i64, &str, bool)> = changes
+            .iter()
+            .map(|c| (c.event.id, c.event.title.as_str(), c.deleted_at.is_some()))
+            .collect();
+        // Both may land in the same millisecond, so don't rely on their order
+        summary.sort();
+        assert_eq!(summary, vec![(gone, "Gone", true), (new, "New", false)]);
+        assert!(gone < new && !summary.iter().any(|&(id, ..)| id == old));
+
+        // A restore counts as a change too
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let cursor = Utc::now();
+        assert!(
+            db.list_event_changes_since(calendar_id, cursor)
+                .unwrap()
+                .is_empty()
+        );
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert!(db.restore_event(gone).unwrap());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        let changes = db.list_event_changes_since(calendar_id, cursor).unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].event.id, gone);
+        assert_eq!(changes[0].deleted_at, None);
+
+        // The bound is exclusive, the change a client last saw isn't returned again
+        assert!(
+            db.list_event_changes_since(calendar_id, changes[0].changed_at())
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_update_event_details_is_all_or_nothing() {
+        let (mut db, calendar_id) = test_db();
+        let at = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let id = db
+            .insert_event(calendar_id, "Dentist", None, at, at)
+            .unwrap();
+        let update = |timezone: &str| EventUpdate {
+            title: "Dentist (moved)".to_string(),
+            description: Some("bring forms".to_string()),
+            start_time: at,
+            end_time: at,
+            timezone: Some(timezone.to_string()),
+            all_day: Some(true),
+        };
+
+        assert!(
+            db.update_event_details(id, &update("Mars/Olympus_Mons"))
+                .is_err()
+        );
+        let event = db.get_event_by_id(id).unwrap().unwrap();
+        assert_eq!(event.title, "Dentist");
+        assert_eq!(event.timezone, "UTC");
+
+        assert!(
+            db.update_event_details(id, &update("Europe/Paris"))
+                .unwrap()
+        );
+        let event = db.get_event_by_id(id).unwrap().unwrap();
+        assert_eq!(event.title, "Dentist (moved)");
+        assert_eq!(event.timezone, "Europe/Paris");
+        assert!(event.all_day);
+        assert!(!db.update_event_details(id + 1, &update("UTC")).unwrap());
+    }
+
+    #[test]
+    fn test_search_events() {
+        let (db, calendar_id) = test_db();
+        let other_calendar = db
+            .insert_calendar("Work", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let at = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let dentist = db
+            .insert_event(calendar_id, "Dentist", Some("Cleaning"), at, at)
+            .unwrap();
+        let checkup = db
+            .insert_event(
+                calendar_id,
+                "Checkup",
+                Some("Ask the dentist about it"),
+                at,
+                at,
+            )
+            .unwrap();
+        db.insert_event(calendar_id, "Groceries", None, at, at)
+            .unwrap();
+        db.insert_event(other_calendar, "Dentist 100% covered", None, at, at)
+            .unwrap();
+
+        let ids = |events: Vec<Event>| events.into_iter().map(|e| e.id).collect::<Vec<_>>();
+        let mut found = ids(db.search_events(Some(calendar_id), "dentist", 10).unwrap());
+        found.sort();
+        assert_eq!(found, vec![dentist, checkup]);
+        assert_eq!(db.search_events(None, "dentist", 10).unwrap().len(), 3);
+        assert_eq!(db.search_events(None, "dentist", 1).unwrap().len(), 1);
+        assert!(db.search_events(None, "\"-(", 10).unwrap().is_empty());
+        assert!(db.search_events(None, "  ", 10).unwrap().is_empty());
+
+        // The index follows updates and deletes
+        db.update_event(dentist, "Orthodontist", None, at, at)
+            .unwrap();
+        db.delete_event_by_id(checkup).unwrap();
+        assert!(
+            db.search_events(Some(calendar_id), "dentist", 10)
+                .unwrap()
+                .is_empty()
+        );
+
+        // The LIKE fallback finds the same events, treating % literally
+        let like = ids(db.search_events_like(None, "100%", 10).unwrap());
+        assert_eq!(like.len(), 1);
+        assert_eq!(
+            ids(db
+                .search_events_like(Some(calendar_id), "groceries", 10)
+                .unwrap())
+            .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_list_events_in_range() {
+        let (db, calendar_id) = test_db();
+        let other_calendar = db
+            .insert_calendar("Work", Color::from_rgb8(0, 0, 0))
+            .unwrap();
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2025, 6, d, h, 0, 0).unwrap();
+        let (range_start, range_end) = (at(10, 0), at(11, 0));
+
+        let spans_start = db
+            .insert_event(calendar_id, "Starts before", None, at(9, 22), at(10, 2))
+            .unwrap();
+        let contained = db
+            .insert_event(calendar_id, "Inside", None, at(10, 9), at(10, 10))
+            .unwrap();
+        let spans_window = db
+            .insert_event(calendar_id, "Whole window", None, at(9, 0), at(12, 0))
+            .unwrap();
+        // Touching the edges is not overlapping
+        db.insert_event(calendar_id, "Ends at start", None, at(9, 20), at(10, 0))
+            .unwrap();
+        db.insert_event(calendar_id, "Starts at end", None, at(11, 0), at(11, 1))
+            .unwrap();
+        // Other calendars are not included
+        db.insert_event(other_calendar, "Elsewhere", None, at(10, 9), at(10, 10))
+            .unwrap();
+
+        let ids: Vec<i64> = db
+            .list_events_in_range(calendar_id, range_start, range_end)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect();
+        assert_eq!(ids, vec![spans_window, spans_start, contained]);
+    }
+
+    #[test]
+    fn test_all_day_events_cover_whole_days() {
+        let (db, calendar_id) = test_db();
+        // The stored times are ignored, the event covers March 14th and 15th
+        let id = db
+            .insert_new_event(
+                calendar_id,
+                &NewEvent {
+                    all_day: true,
+                    ..NewEvent::new(
+                        "Conference",
+                        None,
+                        Utc.with_ymd_and_hms(2025, 3, 14, 12, 0, 0).unwrap(),
+                        Utc.with_ymd_and_hms(2025, 3, 15, 12, 0, 0).unwrap(),
+                    )
+                },
+            )
+            .unwrap();
+        assert!(db.get_event_by_id(id).unwrap().unwrap().all_day);
+
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2025, 3, d, h, 0, 0).unwrap();
+        let matches = |start, end| {
+            db.list_events_in_range(calendar_id, start, end)
+                .unwrap()
+                .iter()
+                .any(|e| e.id == id)
+        };
+        assert!(matches(at(14, 6), at(14, 7)));
+        assert!(matches(at(15, 22), at(15, 23)));
+        assert!(!matches(at(16, 0), at(16, 1)));
+        assert!(!matches(at(13, 22), at(14, 0)));
+
+        assert!(db.set_event_all_day(id, false).unwrap());
+        assert!(!matches(at(14, 6), at(14, 7)));
+        assert_eq!(db.list_events_by_calendar(calendar_id).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_all_day_events_cover_days_in_their_time_zone() {
+        let (db, calendar_id) = test_db();
+        // 21:00 on March 14th in New York is already the 15th in UTC
+        let evening = Utc.with_ymd_and_hms(2025, 3, 15, 1, 0, 0).unwrap();
+        let id = db
+            .insert_new_event(
+                calendar_id,
+                &NewEvent {
+                    all_day: true,
+                    timezone: "America/New_York".to_string(),
+                    ..NewEvent::new("Pi day", None, evening, evening)
+                },
+            )
+            .unwrap();
+
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2025, 3, d, h, 0, 0).unwrap();
+        let matches = |start, end| {
+            db.list_events_in_range(calendar_id, start, end)
+                .unwrap()
+                .iter()
+                .any(|e| e.id == id)
+        };
+        // March 14th in New York (EDT) runs from 04:00 UTC to 04:00 UTC the next day
+        assert!(matches(at(14, 4), at(14, 5)));
+        assert!(matches(at(15, 3), at(15, 4)));
+        assert!(!matches(at(14, 0), at(14, 4)));
+        assert!(!matches(at(15, 4), at(15, 5)));
+        assert_eq!(
+            db.get_event_by_id(id).unwrap().unwrap().day_span(),
+            (at(14, 4), at(15, 4))
+        );
+
+        // Events stored before the span existed get it when the migrations run
+        db.conn
+            .execute("UPDATE events SET day_start = NULL, day_end = NULL", [])
+            .unwrap();
+        db.run_migrations().unwrap();
+        assert!(matches(at(14, 4), at(14, 5)));
+
+        // In UTC the same instant is on the 15th
+        assert!(db.set_event_timezone(id, "UTC").unwrap());
+        assert!(!matches(at(14, 4), at(14, 5)));
+        assert!(matches(at(15, 12), at(15, 13)));
+    }
+
+    #[test]
+    fn test_import_event_deduplicates_on_uid() {
+        let (mut db, calendar_id) = test_db();
+        let other_calendar = db
+            .insert_calendar("Work", Color::from_rgb8(0, 0, 0))
+            .unwrap();
+        let start = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let end = Utc.with_ymd_and_hms(2025, 3, 14, 10, 0, 0).unwrap();
+        let dentist = NewEvent::new("Dentist", None, start, end);
+
+        let id = db
+            .import_event(calendar_id, "abc@example.com", &dentist)
+            .unwrap()
+            .expect("first import inserts");
+        assert!(
+            db.import_event(calendar_id, "abc@example.com", &dentist)
+                .unwrap()
+                .is_none()
+        );
+        // The same UID in another calendar is a different event
+        assert!(
+            db.import_event(other_calendar, "abc@example.com", &dentist)
+                .unwrap()
+                .is_some()
+        );
+
+        // Deleting the event lets it be imported again
+        assert!(db.delete_event_by_id(id).unwrap());
+        let reimported = db
+            .import_event(calendar_id, "abc@example.com", &dentist)
+            .unwrap()
+            .expect("deleted events can be imported again");
+
+        // The UID now belongs to the new event, and events created here have none
+        let local = db
+            .insert_event(calendar_id, "Local", None, start, end)
+            .unwrap();
+        let uids = db.list_event_uids(calendar_id).unwrap();
+        assert_eq!(uids.len(), 1);
+        assert_eq!(uids[&reimported], "abc@example.com");
+        assert!(!uids.contains_key(&local));
+    }
+}
@@ -1,37 +1,147 @@
-use rusqlite::{Connection, OptionalExtension, params};
-use std::error::Error;
+use rusqlite::{Connection, OptionalExtension, Row, Transaction, params, types::Type};
 use std::path::Path;
 
+mod async_db;
+mod backup;
+mod calendar;
+mod error;
+mod event;
+mod migrations;
+mod pool;
+pub mod recurrence;
+mod recurring_event;
+mod reminder;
+mod session;
 pub mod sql;
+mod timezone;
+
+pub use async_db::{AsyncDatabaseConnection, AsyncDbError};
+pub use backup::RestoreError;
+pub use calendar::{
+    ColorError, ColorPalette, color_to_hex, contrasting_text_color, hex_to_color,
+    parse_calendar_color, relative_luminance,
+};
+pub use error::Error;
+pub use event::{ChangedEvent, EventUpdate, NewEvent};
+pub use migrations::{BASE_SCHEMA_VERSION, MIGRATIONS, Migration};
+pub use pool::{DbConnectionManager, DbPool, PooledConnection};
+pub use recurrence::expand_occurrences;
+pub use recurring_event::NewRecurringEvent;
+pub use reminder::{DueReminder, REMINDER_METHOD_NOTIFICATION, reminder_due_at};
+pub use session::Session;
+pub use timezone::{DEFAULT_TIMEZONE, parse_timezone};
+
+/// Version of the schema this build migrates databases to, stored in the `schema_version` table.
+/// Follows the last entry of `MIGRATIONS`, add a migration to change the schema.
+pub const SCHEMA_VERSION: i64 = migrations::latest_version();
 
 pub struct DatabaseConnection {
     pub conn: Connection,
 }
 
+/// Connection level PRAGMAs applied when a connection is opened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionPragmas {
+    /// `PRAGMA foreign_keys`, enforces the calendar/event/permission relationships
+    pub foreign_keys: bool,
+    /// `PRAGMA journal_mode = WAL`, lets readers run while a write is in progress, which matters
+    /// for the websocket heavy workload where many connections read concurrently.
+    /// In-memory databases ignore this and stay in `memory` journal mode.
+    pub wal: bool,
+}
+
+impl Default for ConnectionPragmas {
+    fn default() -> Self {
+        Self {
+            foreign_keys: true,
+            wal: true,
+        }
+    }
+}
+
+impl ConnectionPragmas {
+    /// Apply these pragmas to an open connection.
+    pub fn apply(&self, conn: &Connection) -> Result<(), Error> {
+        // Always set explicitly, the bundled SQLite may default foreign keys on
+        conn.pragma_update(None, "foreign_keys", self.foreign_keys)?;
+        if self.wal {
+            conn.pragma_update_and_check(None, "journal_mode", "WAL", |row| {
+                row.get::<_, String>(0)
+            })?;
+        }
+        Ok(())
+    }
+}
+
 impl DatabaseConnection {
     /// Open a database connection and initialize all schemas.
-    pub fn from_path(path: &Path) -> Result<Self, Box<dyn Error>> {
+    /// Foreign key enforcement and WAL journaling are enabled, see `ConnectionPragmas`.
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        Self::from_path_with_pragmas(path, ConnectionPragmas::default())
+    }
+
+    /// Open a database connection with the given pragmas and initialize all schemas.
+    /// Mostly useful for tests that need to opt out of foreign key enforcement or WAL.
+    pub fn from_path_with_pragmas(path: &Path, pragmas: ConnectionPragmas) -> Result<Self, Error> {
         let db = Connection::open(path)?;
+        pragmas.apply(&db)?;
         let conn = Self { conn: db };
         conn.init_all_schemas()?;
+        conn.run_migrations()?;
         Ok(conn)
     }
 
+    /// Run `f` inside a transaction, committing if it returns `Ok` and rolling back on `Err`.
+    pub fn with_transaction<F, T>(&mut self, f: F) -> Result<T, Error>
+    where
+        F: FnOnce(&Transaction) -> Result<T, Error>,
+    {
+        let tx = self.conn.transaction()?;
+        // Dropping an uncommitted transaction rolls it back
+        let result = f(&tx)?;
+        tx.commit()?;
+        Ok(result)
+    }
+
+    /// Run a trivial query to check the connection still works.
+    pub fn ping(&self) -> Result<(), Error> {
+        Ok(self.conn.query_row("SELECT 1", [], |_| Ok(()))?)
+    }
+
     /// Initialize all schemas (idempotent, safe to call multiple times)
-    pub fn init_all_schemas(&self) -> Result<(), rusqlite::Error> {
+    pub fn init_all_schemas(&self) -> Result<(), Error> {
+        // Schema version, checked before restoring or importing a backup
+        self.conn.execute_batch(sql::SCHEMA_VERSION_SCHEMA)?;
+        self.conn
+            .execute(sql::SCHEMA_VERSION_INIT, params![BASE_SCHEMA_VERSION])?;
         // Authentication schema
         self.conn.execute_batch(sql::AUTH_SCHEMA)?;
         // Calendar schema
         self.conn.execute_batch(sql::calendar::CALENDAR_SCHEMA)?;
         self.conn
             .execute_batch(sql::calendar::CALENDAR_PERMISSIONS_SCHEMA)?;
+        self.conn
+            .execute_batch(sql::calendar::CALENDAR_INVITES_SCHEMA)?;
         // Event schema
         self.conn.execute_batch(sql::event::EVENT_SCHEMA)?;
+        self.conn.execute_batch(sql::event::EVENT_UID_SCHEMA)?;
+        self.init_event_search_schema()?;
         // Recurring event schema
         self.conn.execute_batch(sql::recurring_event::SCHEMA)?;
+        // Reminder schema
+        self.conn.execute_batch(sql::reminder::SCHEMA)?;
+        // Login session schema
+        self.conn.execute_batch(sql::session::SCHEMA)?;
+        self.conn
+            .execute_batch(sql::session::CONSUMED_RESET_SCHEMA)?;
         // User global permissions schema
         self.conn
             .execute_batch(sql::USER_GLOBAL_PERMISSIONS_SCHEMA)?;
+        // Named permissions schema
+        self.conn
+            .execute_batch(sql::permissions::PERMISSIONS_SCHEMA)?;
+        // Roles schema
+        self.conn.execute_batch(sql::permissions::ROLES_SCHEMA)?;
         Ok(())
     }
 
@@ -45,7 +155,7 @@ impl DatabaseConnection {
     /// --- PERMISSIONS API ---
 
     /// Assign a permission to a user.
-    pub fn assign_permission(&self, user_id: i64, permission: &str) -> Result<(), rusqlite::Error> {
+    pub fn assign_permission(&self, user_id: i64, permission: &str) -> Result<(), Error> {
         self.conn.execute(
             sql::permissions::PERMISSIONS_INSERT,
             params![user_id, permission],
@@ -54,7 +164,7 @@ impl DatabaseConnection {
     }
 
     /// Remove a permission from a user.
-    pub fn remove_permission(&self, user_id: i64, permission: &str) -> Result<(), rusqlite::Error> {
+    pub fn remove_permission(&self, user_id: i64, permission: &str) -> Result<(), Error> {
         self.conn.execute(
             sql::permissions::PERMISSIONS_REMOVE,
             params![user_id, permission],
@@ -63,18 +173,14 @@ impl DatabaseConnection {
     }
 
     /// Check if a user has a specific permission.
-    pub fn check_permission(
-        &self,
-        user_id: i64,
-        permission: &str,
-    ) -> Result<bool, rusqlite::Error> {
+    pub fn check_permission(&self, user_id: i64, permission: &str) -> Result<bool, Error> {
         let mut stmt = self.conn.prepare(sql::permissions::PERMISSIONS_CHECK)?;
         let mut rows = stmt.query(params![user_id, permission])?;
         Ok(rows.next()?.is_some())
     }
 
     /// List all permissions for a user.
-    pub fn list_permissions(&self, user_id: i64) -> Result<Vec<String>, rusqlite::Error> {
+    pub fn list_permissions(&self, user_id: i64) -> Result<Vec<String>, Error> {
         let mut stmt = self.conn.prepare(sql::permissions::PERMISSIONS_LIST)?;
         let rows = stmt.query_map(params![user_id], |row| row.get::<_, String>(0))?;
         let mut result = Vec::new();
@@ -86,6 +192,117 @@ impl DatabaseConnection {
         Ok(result)
     }
 
+    /// Remove every named permission assigned directly to a user (roles and calendar
+    /// capabilities are left alone).
+    pub fn remove_named_permissions(&self, user_id: i64) -> Result<(), Error> {
+        self.conn.execute(
+            sql::permissions::PERMISSIONS_REMOVE_ALL_FOR_USER,
+            params![user_id],
+        )?;
+        Ok(())
+    }
+
+    /// Move every named permission of `from` to `to`, keeping whatever `to` already had.
+    pub fn transfer_permissions(&mut self, from: i64, to: i64) -> Result<(), Error> {
+        self.with_transaction(|tx| {
+            tx.execute(sql::permissions::PERMISSIONS_TRANSFER, params![from, to])?;
+            tx.execute(
+                sql::permissions::PERMISSIONS_REMOVE_ALL_FOR_USER,
+                params![from],
+            )?;
+            Ok(())
+        })
+    }
+
+    /// Create a role with exactly the given permissions, redefining it if it already exists.
+    pub fn create_role(&mut self, name: &str, permissions: &[&str]) -> Result<(), Error> {
+        self.with_transaction(|tx| {
+            tx.execute(sql::permissions::ROLES_INSERT, params![name])?;
+            tx.execute(sql::permissions::ROLES_CLEAR_PERMISSIONS, params![name])?;
+            for permission in permissions {
+                tx.execute(
+                    sql::permissions::ROLES_PERMISSION_INSERT,
+                    params![name, permission],
+                )?;
+            }
+            Ok(())
+        })
+    }
+
+    /// Assign a role to a user, fails if there's no such role.
+    pub fn assign_role(&self, user_id: i64, role: &str) -> Result<(), Error> {
+        self.conn
+            .execute(sql::permissions::ROLES_ASSIGN, params![user_id, role])?;
+        Ok(())
+    }
+
+    /// Take a role away from a user.
+    pub fn revoke_role(&self, user_id: i64, role: &str) -> Result<(), Error> {
+        self.conn
+            .execute(sql::permissions::ROLES_REVOKE, params![user_id, role])?;
+        Ok(())
+    }
+
+    /// Set whether a user is a global admin (allowed everything on every calendar).
+    pub fn set_global_admin(&self, user_id: i64, is_global_admin: bool) -> Result<(), Error> {
+        self.conn.execute(
+            sql::USER_GLOBAL_PERMISSIONS_UPSERT,
+            params![user_id, is_global_admin],
+        )?;
+        Ok(())
+    }
+
+    /// Get a user's global permission flags, `None` if none were ever set.
+    pub fn get_user_global_permissions(
+        &self,
+        user_id: i64,
+    ) -> Result<Option<UserGlobalPermissions>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::USER_GLOBAL_PERMISSIONS_SELECT,
+                params![user_id],
+                |row| {
+                    Ok(UserGlobalPermissions {
+                        user_id: row.get(0)?,
+                        is_global_admin: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+
+    /// Whether a user is a global admin, users without global flags are not.
+    pub fn is_global_admin(&self, user_id: i64) -> Result<bool, Error> {
+        Ok(self
+            .get_user_global_permissions(user_id)?
+            .is_some_and(|perms| perms.is_global_admin))
+    }
+
+    /// Get the ids of every global admin, in ascending order.
+    pub fn list_global_admins(&self) -> Result<Vec<i64>, Error> {
+        let mut stmt = self
+            .conn
+            .prepare(sql::USER_GLOBAL_PERMISSIONS_LIST_ADMINS)?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Remove every permission a user holds: named permissions, roles, global flags and calendar capabilities.
+    pub fn remove_all_permissions_for_user(&self, user_id: i64) -> Result<(), Error> {
+        Ok(remove_all_permissions_on(&self.conn, user_id)?)
+    }
+
+    /// Delete a user and all of their permissions atomically.
+    /// Returns false if no user has the given id.
+    pub fn delete_user_account(&mut self, user_id: i64) -> Result<bool, Error> {
+        self.with_transaction(|tx| {
+            remove_all_permissions_on(tx, user_id)?;
+            let changed = tx.execute(sql::AUTH_DELETE_BY_ID, params![user_id])?;
+            Ok(changed > 0)
+        })
+    }
+
     /// Insert a new user into authentication table
     pub fn insert_user(
         &self,
@@ -93,7 +310,7 @@ impl DatabaseConnection {
         password_hash: &str,
         salt: &str,
         email: &str,
-    ) -> Result<(), rusqlite::Error> {
+    ) -> Result<(), Error> {
         self.conn.execute(
             sql::AUTH_INSERT,
             params![username, password_hash, salt, email],
@@ -106,7 +323,7 @@ impl DatabaseConnection {
         &self,
         username: &str,
         new_password_hash: &str,
-    ) -> Result<(), rusqlite::Error> {
+    ) -> Result<(), Error> {
         self.conn.execute(
             sql::AUTH_UPDATE_PASSWORD,
             params![username, new_password_hash],
@@ -114,56 +331,127 @@ impl DatabaseConnection {
         Ok(())
     }
 
-    /// Update a user's email
-    pub fn update_user_email(
-        &self,
-        username: &str,
-        new_email: &str,
-    ) -> Result<(), rusqlite::Error> {
+    /// Mark a user's email verified, provided it's still `email`.
+    /// Returns false if the user doesn't exist or has changed their address since.
+    pub fn mark_email_verified(&self, username: &str, email: &str) -> Result<bool, Error> {
+        let changed = self
+            .conn
+            .execute(sql::AUTH_VERIFY_EMAIL, params![username, email])?;
+        Ok(changed > 0)
+    }
+
+    /// Update a user's email, which leaves it unverified
+    pub fn update_user_email(&self, username: &str, new_email: &str) -> Result<(), Error> {
         self.conn
             .execute(sql::AUTH_UPDATE_EMAIL, params![username, new_email])?;
         Ok(())
     }
 
     /// Select a user by username
-    pub fn get_user_by_username(
-        &self,
-        username: &str,
-    ) -> Result<Option<AuthUser>, rusqlite::Error> {
-        self.conn
-            .query_row(sql::AUTH_SELECT_BY_USERNAME, params![username], |row| {
-                Ok(AuthUser {
-                    id: row.get(0)?,
-                    username: row.get(1)?,
-                    password_hash: row.get(2)?,
-                    salt: row.get(3)?,
-                    email: row.get(4)?,
-                    created_at: row.get(5)?,
-                    updated_at: row.get(6)?,
-                })
+    pub fn get_user_by_username(&self, username: &str) -> Result<Option<AuthUser>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::AUTH_SELECT_BY_USERNAME,
+                params![username],
+                auth_user_from_row,
+            )
+            .optional()?)
+    }
+
+    /// Select a user by id
+    pub fn get_user_by_id(&self, id: i64) -> Result<Option<AuthUser>, Error> {
+        Ok(self
+            .conn
+            .query_row(sql::AUTH_SELECT_BY_ID, params![id], auth_user_from_row)
+            .optional()?)
+    }
+
+    /// Select a user by email
+    pub fn get_user_by_email(&self, email: &str) -> Result<Option<AuthUser>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::AUTH_SELECT_BY_EMAIL,
+                params![email],
+                auth_user_from_row,
+            )
+            .optional()?)
+    }
+
+    /// List up to `limit` users ordered by id, skipping the first `offset`.
+    /// Password hashes and salts are never selected.
+    pub fn list_users(&self, limit: i64, offset: i64) -> Result<Vec<SafeUser>, Error> {
+        let mut stmt = self.conn.prepare(sql::AUTH_LIST_PAGE)?;
+        let rows = stmt.query_map(params![limit, offset], |row| {
+            Ok(SafeUser {
+                id: row.get(0)?,
+                username: row.get(1)?,
+                email: row.get(2)?,
+                created_at: row.get(3)?,
+                updated_at: row.get(4)?,
             })
-            .optional()
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Count all users, for computing the number of `list_users` pages
+    pub fn count_users(&self) -> Result<i64, Error> {
+        Ok(self.conn.query_row(sql::AUTH_COUNT, [], |row| row.get(0))?)
     }
 
     /// Delete a user by username
-    pub fn delete_user_by_username(&self, username: &str) -> Result<(), rusqlite::Error> {
+    pub fn delete_user_by_username(&self, username: &str) -> Result<(), Error> {
         self.conn
             .execute(sql::AUTH_DELETE_BY_USERNAME, params![username])?;
         Ok(())
     }
 
     /// Get the salt for a user by username
-    pub fn get_salt_by_username(&self, username: &str) -> Result<Option<String>, rusqlite::Error> {
-        self.conn
+    pub fn get_salt_by_username(&self, username: &str) -> Result<Option<String>, Error> {
+        Ok(self
+            .conn
             .query_row(
                 crate::sql::AUTH_SELECT_SALT_BY_USERNAME,
                 params![username],
                 |row| row.get(0),
             )
-            .optional()
+            .optional()?)
     }
 }
 
+/// Delete every permission row of a user on `conn`, shared by the plain and transactional paths.
+fn remove_all_permissions_on(conn: &Connection, user_id: i64) -> Result<(), rusqlite::Error> {
+    conn.execute(
+        sql::permissions::PERMISSIONS_REMOVE_ALL_FOR_USER,
+        params![user_id],
+    )?;
+    conn.execute(
+        sql::permissions::ROLES_REMOVE_ALL_FOR_USER,
+        params![user_id],
+    )?;
+    conn.execute(sql::USER_GLOBAL_PERMISSIONS_DELETE, params![user_id])?;
+    conn.execute(
+        sql::calendar::CALENDAR_PERMISSIONS_DELETE_FOR_USER,
+        params![user_id],
+    )?;
+    Ok(())
+}
+
+/// Map a row selected as `id, username, password_hash, salt, email, created_at, updated_at`.
+fn auth_user_from_row(row: &Row) -> Result<AuthUser, rusqlite::Error> {
+    Ok(AuthUser {
+        id: row.get(0)?,
+        username: row.get(1)?,
+        password_hash: row.get(2)?,
+        salt: row.get(3)?,
+        email: row.get(4)?,
+        created_at: row.get(5)?,
+        updated_at: row.get(6)?,
+        email_verified: row.get(7)?,
+    })
+}
+
 /// Struct representing a user in the authentication table
 
 pub struct AuthUser {
@@ -180,13 +468,54 @@ pub struct AuthUser {
     pub created_at: String,
 
     pub updated_at: String,
+
+    /// Whether the user proved they own `email`, see `mark_email_verified`
+    pub email_verified: bool,
+}
+
+/// A safe user struct that does not expose password hash or salt.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SafeUser {
+    pub id: i64,
+    pub username: String,
+    pub email: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<AuthUser> for SafeUser {
+    fn from(user: AuthUser) -> Self {
+        Self {
+            id: user.id,
+            username: user.username,
+            email: user.email,
+            created_at: user.created_at,
+            updated_at: user.updated_at,
+        }
+    }
+}
+
+/// Format a timestamp for a TEXT column as RFC3339.
+/// Fixed millisecond precision keeps the strings the same width so they sort chronologically.
+pub(crate) fn datetime_to_sql(dt: &DateTime<Utc>) -> String {
+    dt.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// Parse an RFC3339 TEXT column back into a timestamp.
+pub(crate) fn datetime_from_sql(row: &Row, idx: usize) -> Result<DateTime<Utc>, rusqlite::Error> {
+    let text: String = row.get(idx)?;
+    DateTime::parse_from_rfc3339(&text)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(idx, Type::Text, Box::new(e)))
 }
 
 /// Struct representing a calendar
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, SecondsFormat, Utc};
 use colorlab::Color;
 use humantime::Duration as HumanDuration;
+use serde::{Deserialize, Serialize};
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct Calendar {
     pub id: i64,
     pub name: String,
@@ -196,6 +525,7 @@ pub struct Calendar {
 }
 
 /// Struct representing a calendar permission for a user
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CalendarPermission {
     pub user_id: i64,
     pub calendar_id: i64,
@@ -208,7 +538,66 @@ pub struct CalendarPermission {
     pub can_modify_recurring_event: bool,
 }
 
+impl CalendarPermission {
+    /// The capabilities this row grants, without the user and calendar.
+    pub fn capabilities(&self) -> CalendarCapabilities {
+        CalendarCapabilities {
+            can_admin: self.can_admin,
+            can_view: self.can_view,
+            can_read: self.can_read,
+            can_add_event: self.can_add_event,
+            can_modify_event: self.can_modify_event,
+            can_add_recurring_event: self.can_add_recurring_event,
+            can_modify_recurring_event: self.can_modify_recurring_event,
+        }
+    }
+}
+
+/// The capabilities of a `CalendarPermission` on their own, e.g. what a calendar invite grants.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CalendarCapabilities {
+    pub can_admin: bool,
+    pub can_view: bool,
+    pub can_read: bool,
+    pub can_add_event: bool,
+    pub can_modify_event: bool,
+    pub can_add_recurring_event: bool,
+    pub can_modify_recurring_event: bool,
+}
+
+impl CalendarCapabilities {
+    /// A permission row granting these capabilities to `user_id` on `calendar_id`.
+    pub fn for_user(self, user_id: i64, calendar_id: i64) -> CalendarPermission {
+        CalendarPermission {
+            user_id,
+            calendar_id,
+            can_admin: self.can_admin,
+            can_view: self.can_view,
+            can_read: self.can_read,
+            can_add_event: self.can_add_event,
+            can_modify_event: self.can_modify_event,
+            can_add_recurring_event: self.can_add_recurring_event,
+            can_modify_recurring_event: self.can_modify_recurring_event,
+        }
+    }
+
+    /// Every capability held in either `self` or `other`.
+    pub fn union(self, other: CalendarCapabilities) -> CalendarCapabilities {
+        CalendarCapabilities {
+            can_admin: self.can_admin || other.can_admin,
+            can_view: self.can_view || other.can_view,
+            can_read: self.can_read || other.can_read,
+            can_add_event: self.can_add_event || other.can_add_event,
+            can_modify_event: self.can_modify_event || other.can_modify_event,
+            can_add_recurring_event: self.can_add_recurring_event || other.can_add_recurring_event,
+            can_modify_recurring_event: self.can_modify_recurring_event
+                || other.can_modify_recurring_event,
+        }
+    }
+}
+
 /// Struct representing an event in a calendar
+#[derive(Debug, Clone, PartialEq, Serialize)]
 pub struct Event {
     pub id: i64,
     pub calendar_id: i64,
@@ -218,10 +607,16 @@ pub struct Event {
     pub end_time: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    /// IANA time zone the event was created in (e.g. `America/New_York`), used to show it on
+    /// the right local day. The times above are always UTC.
+    pub timezone: String,
+    /// An all-day event covers whole days, from its start date through its end date;
+    /// the time of day in `start_time`/`end_time` is ignored.
+    pub all_day: bool,
 }
 
 /// Struct representing a recurring event in a calendar
-
+#[derive(Debug, Clone, PartialEq)]
 pub struct RecurringEvent {
     pub id: i64,
 
@@ -246,10 +641,246 @@ pub struct RecurringEvent {
     pub created_at: DateTime<Utc>,
 
     pub updated_at: DateTime<Utc>,
+
+    pub timezone: String, // IANA name, occurrences keep the same local time of day
+}
+
+/// Struct representing a reminder before an event
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reminder {
+    pub id: i64,
+    pub event_id: i64,
+    /// How long before the event starts the reminder is due
+    pub offset_seconds: i64,
+    /// How the reminder is delivered, e.g. `notification` for connected clients
+    pub method: String,
+    pub created_at: DateTime<Utc>,
 }
 
 /// Struct representing a user's global permissions (e.g., global admin)
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct UserGlobalPermissions {
     pub user_id: i64,
     pub is_global_admin: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use rusqlite::ErrorCode;
+
+    fn insert_orphan_event(db: &DatabaseConnection) -> Result<i64, Error> {
+        let at = Utc.with_ymd_and_hms(2025, 1, 1, 9, 0, 0).unwrap();
+        db.insert_event(9999, "Orphan", None, at, at)
+    }
+
+    #[test]
+    fn test_foreign_keys_enforced() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        match insert_orphan_event(&db) {
+            Err(err @ Error::Sqlite(rusqlite::Error::SqliteFailure(e, _))) => {
+                assert_eq!(e.code, ErrorCode::ConstraintViolation);
+                assert!(err.is_constraint_violation());
+            }
+            other => panic!("expected a constraint violation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_foreign_keys_opt_out() {
+        let pragmas = ConnectionPragmas {
+            foreign_keys: false,
+            wal: false,
+        };
+        let db =
+            DatabaseConnection::from_path_with_pragmas(Path::new(":memory:"), pragmas).unwrap();
+        assert!(insert_orphan_event(&db).is_ok());
+    }
+
+    #[test]
+    fn test_with_transaction_commits_and_rolls_back() {
+        let mut db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let count = |db: &DatabaseConnection| -> i64 {
+            db.conn
+                .query_row("SELECT COUNT(*) FROM authentication", [], |row| row.get(0))
+                .unwrap()
+        };
+
+        db.with_transaction(|tx| {
+            Ok(tx.execute(
+                sql::AUTH_INSERT,
+                params!["alice", "hash", "salt", "a@x.com"],
+            )?)
+        })
+        .unwrap();
+        assert_eq!(count(&db), 1);
+
+        let result: Result<(), Error> = db.with_transaction(|tx| {
+            tx.execute(sql::AUTH_INSERT, params!["bob", "hash", "salt", "b@x.com"])?;
+            Err(Error::NotFound)
+        });
+        assert!(result.is_err());
+        assert_eq!(count(&db), 1);
+    }
+
+    #[test]
+    fn test_user_lookup_by_username_id_and_email() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "alice@example.com")
+            .unwrap();
+
+        let by_name = db.get_user_by_username("alice").unwrap().unwrap();
+        let by_id = db.get_user_by_id(by_name.id).unwrap().unwrap();
+        let by_email = db.get_user_by_email("alice@example.com").unwrap().unwrap();
+        for user in [&by_name, &by_id, &by_email] {
+            assert_eq!(user.id, by_name.id);
+            assert_eq!(user.username, "alice");
+            assert_eq!(user.email, "alice@example.com");
+            assert_eq!(user.password_hash, "hash");
+        }
+
+        assert!(db.get_user_by_id(by_name.id + 1).unwrap().is_none());
+        assert!(db.get_user_by_email("bob@example.com").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_email_verification_follows_the_address() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "alice@example.com")
+            .unwrap();
+        let verified = |db: &DatabaseConnection| {
+            db.get_user_by_username("alice")
+                .unwrap()
+                .unwrap()
+                .email_verified
+        };
+        assert!(!verified(&db));
+
+        assert!(!db.mark_email_verified("alice", "old@example.com").unwrap());
+        assert!(!verified(&db));
+        assert!(
+            db.mark_email_verified("alice", "alice@example.com")
+                .unwrap()
+        );
+        assert!(verified(&db));
+
+        // A new address has to be verified again
+        db.update_user_email("alice", "new@example.com").unwrap();
+        assert!(!verified(&db));
+    }
+
+    #[test]
+    fn test_duplicate_email_rejected() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "shared@example.com")
+            .unwrap();
+        let err = db
+            .insert_user("bob", "hash", "salt", "shared@example.com")
+            .unwrap_err();
+        assert!(matches!(err, Error::Sqlite(_)), "got {err:?}");
+        assert!(err.is_constraint_violation());
+        assert!(db.get_user_by_username("bob").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_list_users_pages() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        for i in 0..25 {
+            db.insert_user(
+                &format!("user{i}"),
+                "hash",
+                "salt",
+                &format!("user{i}@x.com"),
+            )
+            .unwrap();
+        }
+        assert_eq!(db.count_users().unwrap(), 25);
+
+        let pages: Vec<Vec<SafeUser>> = (0..3)
+            .map(|page| db.list_users(10, page * 10).unwrap())
+            .collect();
+        assert_eq!(
+            pages.iter().map(Vec::len).collect::<Vec<_>>(),
+            vec![10, 10, 5]
+        );
+
+        let ids: Vec<i64> = pages.iter().flatten().map(|u| u.id).collect();
+        let mut unique = ids.clone();
+        unique.dedup();
+        assert_eq!(unique.len(), 25);
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert!(db.list_users(10, 30).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_global_admin_promote_and_demote() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        for name in ["alice", "bob"] {
+            db.insert_user(name, "hash", "salt", &format!("{name}@x.com"))
+                .unwrap();
+        }
+        let alice = db.get_user_by_username("alice").unwrap().unwrap().id;
+        let bob = db.get_user_by_username("bob").unwrap().unwrap().id;
+        assert!(!db.is_global_admin(alice).unwrap());
+
+        db.set_global_admin(alice, true).unwrap();
+        db.set_global_admin(alice, true).unwrap();
+        db.set_global_admin(bob, true).unwrap();
+        assert!(db.is_global_admin(alice).unwrap());
+        assert_eq!(db.list_global_admins().unwrap(), vec![alice, bob]);
+
+        db.set_global_admin(alice, false).unwrap();
+        assert!(!db.is_global_admin(alice).unwrap());
+        assert_eq!(db.list_global_admins().unwrap(), vec![bob]);
+    }
+
+    #[test]
+    fn test_delete_user_account_removes_permissions() {
+        let mut db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        db.insert_user("alice", "hash", "salt", "a@x.com").unwrap();
+        let alice = db.get_user_by_username("alice").unwrap().unwrap().id;
+        db.create_calendar_with_owner("Family", Color::from_rgb8(1, 2, 3), alice)
+            .unwrap();
+        db.assign_permission(alice, "calendar:read").unwrap();
+        db.create_role("member", &["calendar:write"]).unwrap();
+        db.assign_role(alice, "member").unwrap();
+        db.set_global_admin(alice, true).unwrap();
+        assert!(
+            db.get_user_global_permissions(alice)
+                .unwrap()
+                .unwrap()
+                .is_global_admin
+        );
+        assert!(db.check_permission(alice, "calendar:read").unwrap());
+
+        assert!(db.delete_user_account(alice).unwrap());
+        assert!(db.get_user_by_id(alice).unwrap().is_none());
+        for table in [
+            "user_permissions",
+            "user_roles",
+            "user_global_permissions",
+            "calendar_permissions",
+        ] {
+            let rows: i64 = db
+                .conn
+                .query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                    row.get(0)
+                })
+                .unwrap();
+            assert_eq!(rows, 0, "{table} still has rows");
+        }
+        assert!(!db.delete_user_account(alice).unwrap());
+    }
+
+    #[test]
+    fn test_wal_enabled_on_file_databases() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = DatabaseConnection::from_path(&dir.path().join("test.db")).unwrap();
+        let mode: String = db
+            .conn
+            .pragma_query_value(None, "journal_mode", |row| row.get(0))
+            .unwrap();
+        assert_eq!(mode.to_lowercase(), "wal");
+    }
+}
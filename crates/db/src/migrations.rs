@@ -0,0 +1,150 @@
+use crate::{DatabaseConnection, Error, sql};
+use rusqlite::params;
+use tracing::*;
+
+/// One step in evolving the schema, run once on databases older than `version`.
+#[derive(Debug, Clone, Copy)]
+pub struct Migration {
+    /// The schema version the database is at after this step
+    pub version: i64,
+    pub sql: &'static str,
+}
+
+/// The version `init_all_schemas` creates, migrations take it from there.
+pub const BASE_SCHEMA_VERSION: i64 = 1;
+
+/// Every migration, in the order they run. Only ever append to this list,
+/// databases out in the wild have already run the earlier steps.
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 2,
+        sql: sql::migrations::EVENT_TIMEZONE,
+    },
+    Migration {
+        version: 3,
+        sql: sql::migrations::EVENT_SOFT_DELETE,
+    },
+    Migration {
+        version: 4,
+        sql: sql::migrations::RECURRING_EVENT_TIMEZONE,
+    },
+    Migration {
+        version: 5,
+        sql: sql::migrations::EVENT_ALL_DAY,
+    },
+    Migration {
+        version: 6,
+        sql: sql::migrations::EMAIL_VERIFIED,
+    },
+    Migration {
+        version: 7,
+        sql: sql::migrations::EVENT_DAY_SPAN,
+    },
+];
+
+/// The version a database ends up at once every migration has run.
+pub const fn latest_version() -> i64 {
+    match MIGRATIONS.last() {
+        Some(migration) => migration.version,
+        None => BASE_SCHEMA_VERSION,
+    }
+}
+
+impl DatabaseConnection {
+    /// The schema version recorded in the database.
+    pub fn schema_version(&self) -> Result<i64, Error> {
+        Ok(self
+            .conn
+            .query_row(sql::SCHEMA_VERSION_SELECT, [], |row| row.get(0))?)
+    }
+
+    /// Apply every migration newer than the database's schema version, each in its own
+    /// transaction together with recording its version. Databases already at the latest version
+    /// are left untouched, apart from filling in the all-day spans of events from before
+    /// migration 7 (which needs the time zone database, so it can't be done in SQL).
+    pub fn run_migrations(&self) -> Result<(), Error> {
+        let current = self.schema_version()?;
+        for migration in MIGRATIONS.iter().filter(|m| m.version > current) {
+            info!("Migrating database schema to version {}", migration.version);
+            self.apply_migration(migration)
+                .map_err(|source| Error::Migration {
+                    version: migration.version,
+                    source,
+                })?;
+        }
+        self.fill_missing_day_spans()?;
+        Ok(())
+    }
+
+    /// Run one migration and record its version, in a single transaction.
+    fn apply_migration(&self, migration: &Migration) -> Result<(), rusqlite::Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute_batch(migration.sql)?;
+        tx.execute(sql::SCHEMA_VERSION_UPDATE, params![migration.version])?;
+        tx.commit()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SCHEMA_VERSION;
+    use std::path::Path;
+
+    fn event_columns(db: &DatabaseConnection) -> Vec<String> {
+        let mut stmt = db
+            .conn
+            .prepare("SELECT name FROM pragma_table_info('events')")
+            .unwrap();
+        stmt.query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_migrations_are_ordered() {
+        assert!(
+            MIGRATIONS
+                .windows(2)
+                .all(|pair| pair[0].version < pair[1].version)
+        );
+        assert!(MIGRATIONS[0].version > BASE_SCHEMA_VERSION);
+    }
+
+    #[test]
+    fn test_fresh_database_is_at_latest_version() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        assert!(event_columns(&db).contains(&"timezone".to_string()));
+    }
+
+    #[test]
+    fn test_migrated_database_is_a_no_op() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("calendar.db");
+        let before = {
+            let db = DatabaseConnection::from_path(&path).unwrap();
+            event_columns(&db)
+        };
+        // Reopening runs the migrations again, nothing is left to do
+        let db = DatabaseConnection::from_path(&path).unwrap();
+        db.run_migrations().unwrap();
+        assert_eq!(db.schema_version().unwrap(), SCHEMA_VERSION);
+        assert_eq!(event_columns(&db), before);
+    }
+
+    #[test]
+    fn test_failed_migration_reports_its_version() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        // Pretend the columns added since aren't there, so the first migration collides with them
+        db.conn
+            .execute(sql::SCHEMA_VERSION_UPDATE, params![BASE_SCHEMA_VERSION])
+            .unwrap();
+        match db.run_migrations() {
+            Err(Error::Migration { version, .. }) => assert_eq!(version, MIGRATIONS[0].version),
+            other => panic!("expected a migration error, got {other:?}"),
+        }
+        assert_eq!(db.schema_version().unwrap(), BASE_SCHEMA_VERSION);
+    }
+}
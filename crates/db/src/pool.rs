@@ -0,0 +1,153 @@
+use crate::{ConnectionPragmas, DatabaseConnection, Error};
+use rusqlite::Connection;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// r2d2 manager that opens `DatabaseConnection`s with the usual pragmas applied.
+pub struct DbConnectionManager {
+    path: PathBuf,
+    pragmas: ConnectionPragmas,
+}
+
+impl r2d2::ManageConnection for DbConnectionManager {
+    type Connection = DatabaseConnection;
+    type Error = Error;
+
+    fn connect(&self) -> Result<DatabaseConnection, Error> {
+        let conn = Connection::open(&self.path)?;
+        self.pragmas.apply(&conn)?;
+        Ok(DatabaseConnection { conn })
+    }
+
+    fn is_valid(&self, conn: &mut DatabaseConnection) -> Result<(), Error> {
+        Ok(conn.conn.execute_batch("SELECT 1")?)
+    }
+
+    fn has_broken(&self, _conn: &mut DatabaseConnection) -> bool {
+        false
+    }
+}
+
+/// A connection checked out of a `DbPool`, derefs to `DatabaseConnection`.
+pub type PooledConnection = r2d2::PooledConnection<DbConnectionManager>;
+
+/// Pool of database connections so concurrent requests don't serialize behind one connection.
+/// Cheap to clone, every clone shares the same pool.
+#[derive(Clone)]
+pub struct DbPool {
+    pool: r2d2::Pool<DbConnectionManager>,
+}
+
+impl DbPool {
+    /// Open a pool of up to `max_size` connections to the database at `path`, initialize all schemas
+    /// and run any pending migrations.
+    pub fn new(path: &Path, max_size: u32) -> Result<Self, Error> {
+        Self::with_pragmas(path, max_size, ConnectionPragmas::default())
+    }
+
+    /// Open a pool over a fresh in-memory database.
+    /// Every connection in the pool shares the same database (via SQLite's shared cache),
+    /// and it lives as long as the pool does.
+    pub fn new_in_memory(max_size: u32) -> Result<Self, Error> {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        let uri = format!(
+            "file:corecalendar_mem_{}_{}?mode=memory&cache=shared",
+            std::process::id(),
+            NEXT_ID.fetch_add(1, Ordering::SeqCst)
+        );
+        Self::with_pragmas(Path::new(&uri), max_size, ConnectionPragmas::default())
+    }
+
+    /// Open a pool with the given pragmas applied to every connection.
+    pub fn with_pragmas(
+        path: &Path,
+        max_size: u32,
+        pragmas: ConnectionPragmas,
+    ) -> Result<Self, Error> {
+        let manager = DbConnectionManager {
+            path: path.to_path_buf(),
+            pragmas,
+        };
+        let pool = r2d2::Pool::builder().max_size(max_size).build(manager)?;
+        let conn = pool.get()?;
+        conn.init_all_schemas()?;
+        conn.run_migrations()?;
+        drop(conn);
+        Ok(Self { pool })
+    }
+
+    /// Check out a connection, blocking until one is free (or the pool's timeout elapses).
+    pub fn get(&self) -> Result<PooledConnection, r2d2::Error> {
+        self.pool.get()
+    }
+
+    /// Check out a connection, giving up after `timeout` instead of the pool's default.
+    pub fn get_timeout(&self, timeout: Duration) -> Result<PooledConnection, r2d2::Error> {
+        self.pool.get_timeout(timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use colorlab::Color;
+    use std::sync::Arc;
+    use std::sync::Barrier;
+
+    #[test]
+    fn test_in_memory_pool_shares_database() {
+        let pool = DbPool::new_in_memory(4).unwrap();
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+        let id = first
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        assert!(second.get_calendar_by_id(id).unwrap().is_some());
+
+        // Separate pools are separate databases
+        let other = DbPool::new_in_memory(1).unwrap();
+        assert!(other.get().unwrap().list_calendars().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pooled_connections_have_pragmas() {
+        let pool = DbPool::new_in_memory(2).unwrap();
+        let conn = pool.get().unwrap();
+        let fk: bool = conn
+            .conn
+            .pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap();
+        assert!(fk);
+    }
+
+    #[test]
+    fn test_many_concurrent_reads_do_not_deadlock() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = DbPool::new(&dir.path().join("pool.db"), 4).unwrap();
+        let id = pool
+            .get()
+            .unwrap()
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+
+        let threads = 32;
+        let barrier = Arc::new(Barrier::new(threads));
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let pool = pool.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    barrier.wait();
+                    for _ in 0..50 {
+                        let conn = pool.get().unwrap();
+                        assert!(conn.get_calendar_by_id(id).unwrap().is_some());
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+    }
+}
@@ -0,0 +1,310 @@
+use crate::timezone::local_to_utc;
+use crate::{Event, RecurringEvent};
+use chrono::{DateTime, Duration, Months, Utc};
+use tracing::*;
+
+/// How far apart consecutive occurrences of a series are.
+enum Step {
+    Fixed(Duration),
+    Months(u32),
+}
+
+/// Materialize the occurrences of `event` that overlap `[window_start, window_end)`, sorted ascending.
+///
+/// Occurrence `n` starts `n * recurrence_interval` days/weeks/months/years after `start_time` and lasts
+/// as long as the original `end_time - start_time`. Monthly and yearly steps are computed from the
+/// original start, so a series on the 31st clamps to shorter months (Jan 31 -> Feb 28 -> Mar 31).
+/// Steps are taken on the wall clock of the series' time zone, so a 23:30 daily event stays at
+/// 23:30 local time (and on the right local day) across DST changes.
+///
+/// The series ends after `recurrence_count` occurrences and/or once an occurrence would start
+/// `recurrence_duration` or more after the first one. With neither set it is only bounded by the window.
+/// It also ends once an occurrence would fall outside the representable dates, and a series whose
+/// interval is too large to step by at all only has its first occurrence.
+/// Each occurrence carries the series' id, calendar and details.
+pub fn expand_occurrences(
+    event: &RecurringEvent,
+    window_start: DateTime<Utc>,
+    window_end: DateTime<Utc>,
+) -> Vec<Event> {
+    let interval = event.recurrence_interval.max(1);
+    let step = match event.recurrence_type.as_str() {
+        "daily" => Duration::try_days(interval).map(Step::Fixed),
+        "weekly" => Duration::try_weeks(interval).map(Step::Fixed),
+        "monthly" => u32::try_from(interval).ok().map(Step::Months),
+        "yearly" => u32::try_from(interval)
+            .ok()
+            .and_then(|years| years.checked_mul(12))
+            .map(Step::Months),
+        other => {
+            warn!(
+                "Unknown recurrence type {:?} on recurring event {}, treating it as a single event",
+                other, event.id
+            );
+            Some(Step::Fixed(Duration::MAX))
+        }
+    };
+    let step = step.unwrap_or_else(|| {
+        warn!(
+            "Recurrence interval {} on recurring event {} is out of range, treating it as a single event",
+            interval, event.id
+        );
+        Step::Fixed(Duration::MAX)
+    });
+    let tz = event.tz();
+    let local_start = event.start_time.with_timezone(&tz).naive_local();
+    let length = event.end_time - event.start_time;
+    let series_end = event
+        .recurrence_duration
+        .and_then(|d| Duration::from_std(*d).ok())
+        .and_then(|d| event.start_time.checked_add_signed(d));
+
+    // Fixed steps can skip straight to the first occurrence that could reach the window
+    let first_end = event.start_time.checked_add_signed(length);
+    let mut n: i64 = match (&step, first_end) {
+        (Step::Fixed(step), Some(first_end)) if window_start > first_end => {
+            let behind = window_start - first_end;
+            // One step less, a DST change can shift local steps by an hour against UTC
+            (behind.num_seconds() / step.num_seconds().max(1) - 1).max(0)
+        }
+        _ => 0,
+    };
+
+    let mut occurrences = Vec::new();
+    loop {
+        if event.recurrence_count.is_some_and(|count| n >= count) {
+            break;
+        }
+        let start = match step {
+            Step::Fixed(step) => i32::try_from(n)
+                .ok()
+                .and_then(|n| step.checked_mul(n))
+                .and_then(|offset| local_start.checked_add_signed(offset)),
+            Step::Months(months) => u32::try_from(n)
+                .ok()
+                .and_then(|n| months.checked_mul(n))
+                .and_then(|months| local_start.checked_add_months(Months::new(months))),
+        }
+        .and_then(|local| local_to_utc(tz, local));
+        let Some(start) = start else { break };
+        if start >= window_end || series_end.is_some_and(|end| start >= end) {
+            break;
+        }
+        let Some(end) = start.checked_add_signed(length) else {
+            break;
+        };
+        if end > window_start {
+            occurrences.push(Event {
+                id: event.id,
+                calendar_id: event.calendar_id,
+                title: event.title.clone(),
+                description: event.description.clone(),
+                start_time: start,
+                end_time: end,
+                created_at: event.created_at,
+                updated_at: event.updated_at,
+                timezone: event.timezone.clone(),
+                all_day: false,
+            });
+        }
+        n += 1;
+    }
+    occurrences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(y: i32, m: u32, d: u32, h: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(y, m, d, h, 0, 0).unwrap()
+    }
+
+    fn series(recurrence_type: &str, start: DateTime<Utc>, interval: i64) -> RecurringEvent {
+        RecurringEvent {
+            id: 7,
+            calendar_id: 1,
+            title: "Series".to_string(),
+            description: None,
+            start_time: start,
+            end_time: start + Duration::hours(1),
+            recurrence_type: recurrence_type.to_string(),
+            recurrence_interval: interval,
+            recurrence_count: None,
+            recurrence_duration: None,
+            created_at: start,
+            updated_at: start,
+            timezone: "UTC".to_string(),
+        }
+    }
+
+    fn starts(events: &[Event]) -> Vec<DateTime<Utc>> {
+        events.iter().map(|e| e.start_time).collect()
+    }
+
+    #[test]
+    fn test_daily_keeps_local_day_across_dst() {
+        // 23:30 in New York, US clocks spring forward on 2025-03-09
+        let mut event = series("daily", utc(2025, 3, 7, 4) + Duration::minutes(30), 1);
+        event.timezone = "America/New_York".to_string();
+        let occ = expand_occurrences(&event, utc(2025, 3, 7, 0), utc(2025, 3, 12, 12));
+
+        let local: Vec<_> = occ.iter().map(|e| e.local_start().naive_local()).collect();
+        let expected: Vec<_> = (6..=11)
+            .map(|day| {
+                chrono::NaiveDate::from_ymd_opt(2025, 3, day)
+                    .unwrap()
+                    .and_hms_opt(23, 30, 0)
+                    .unwrap()
+            })
+            .collect();
+        assert_eq!(local, expected);
+        // EST is UTC-5, EDT is UTC-4
+        assert_eq!(
+            occ[2].start_time,
+            utc(2025, 3, 9, 4) + Duration::minutes(30)
+        );
+        assert_eq!(
+            occ[3].start_time,
+            utc(2025, 3, 10, 3) + Duration::minutes(30)
+        );
+        assert!(occ.iter().all(|e| e.timezone == "America/New_York"));
+    }
+
+    #[test]
+    fn test_daily() {
+        let event = series("daily", utc(2025, 1, 1, 9), 2);
+        let occ = expand_occurrences(&event, utc(2025, 1, 4, 0), utc(2025, 1, 10, 0));
+        assert_eq!(
+            starts(&occ),
+            vec![utc(2025, 1, 5, 9), utc(2025, 1, 7, 9), utc(2025, 1, 9, 9)]
+        );
+        assert!(
+            occ.iter()
+                .all(|e| e.end_time - e.start_time == Duration::hours(1))
+        );
+        assert!(occ.iter().all(|e| e.id == 7 && e.calendar_id == 1));
+    }
+
+    #[test]
+    fn test_weekly() {
+        let event = series("weekly", utc(2025, 1, 6, 17), 1);
+        let occ = expand_occurrences(&event, utc(2025, 1, 1, 0), utc(2025, 2, 1, 0));
+        assert_eq!(
+            starts(&occ),
+            vec![
+                utc(2025, 1, 6, 17),
+                utc(2025, 1, 13, 17),
+                utc(2025, 1, 20, 17),
+                utc(2025, 1, 27, 17)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_monthly_clamps_day_of_month() {
+        let event = series("monthly", utc(2025, 1, 31, 12), 1);
+        let occ = expand_occurrences(&event, utc(2025, 1, 1, 0), utc(2025, 5, 1, 0));
+        assert_eq!(
+            starts(&occ),
+            vec![
+                utc(2025, 1, 31, 12),
+                utc(2025, 2, 28, 12),
+                utc(2025, 3, 31, 12),
+                utc(2025, 4, 30, 12)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_yearly() {
+        let event = series("yearly", utc(2024, 2, 29, 8), 1);
+        let occ = expand_occurrences(&event, utc(2024, 1, 1, 0), utc(2029, 1, 1, 0));
+        assert_eq!(
+            starts(&occ),
+            vec![
+                utc(2024, 2, 29, 8),
+                utc(2025, 2, 28, 8),
+                utc(2026, 2, 28, 8),
+                utc(2027, 2, 28, 8),
+                utc(2028, 2, 29, 8)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_infinite_series_bounded_by_window() {
+        let event = series("daily", utc(2000, 1, 1, 0), 1);
+        let occ = expand_occurrences(&event, utc(2025, 6, 1, 0), utc(2025, 6, 8, 0));
+        assert_eq!(occ.len(), 7);
+        assert_eq!(occ[0].start_time, utc(2025, 6, 1, 0));
+        assert!(occ.windows(2).all(|w| w[0].start_time < w[1].start_time));
+    }
+
+    #[test]
+    fn test_occurrence_overlapping_window_start_is_included() {
+        let mut event = series("daily", utc(2025, 1, 1, 23), 1);
+        event.end_time = utc(2025, 1, 2, 1);
+        let occ = expand_occurrences(&event, utc(2025, 1, 3, 0), utc(2025, 1, 3, 12));
+        assert_eq!(starts(&occ), vec![utc(2025, 1, 2, 23)]);
+    }
+
+    #[test]
+    fn test_huge_interval_stops_expanding() {
+        let start = utc(2025, 1, 1, 9);
+        let window = (utc(2024, 1, 1, 0), utc(2026, 1, 1, 0));
+        for (recurrence_type, interval) in [
+            ("daily", i64::MAX),
+            ("weekly", i64::MAX / 2),
+            ("monthly", i64::from(u32::MAX) + 1),
+            ("yearly", i64::from(u32::MAX / 6)),
+            ("monthly", i64::from(u32::MAX)),
+        ] {
+            let event = series(recurrence_type, start, interval);
+            let occ = expand_occurrences(&event, window.0, window.1);
+            assert_eq!(starts(&occ), vec![start], "{recurrence_type} {interval}");
+        }
+
+        // An infinite daily series stops once it runs out of representable dates
+        let event = series("daily", start, 1_000_000);
+        let occ = expand_occurrences(&event, window.0, DateTime::<Utc>::MAX_UTC);
+        assert!(!occ.is_empty());
+        assert!(occ.windows(2).all(|w| w[0].start_time < w[1].start_time));
+
+        // The occurrence on the last representable day would end past it, so expansion stops there
+        let last_day = DateTime::<Utc>::MAX_UTC.date_naive();
+        let start = last_day.and_hms_opt(23, 0, 0).unwrap().and_utc() - Duration::days(5);
+        let mut event = series("daily", start, 1);
+        event.end_time = start + Duration::hours(2);
+        let occ = expand_occurrences(&event, utc(2025, 1, 1, 0), DateTime::<Utc>::MAX_UTC);
+        assert_eq!(occ.len(), 5);
+        assert_eq!(occ[4].start_time, start + Duration::days(4));
+    }
+
+    #[test]
+    fn test_count_and_duration_end_conditions() {
+        let mut event = series("daily", utc(2025, 1, 1, 9), 1);
+        event.recurrence_count = Some(3);
+        let occ = expand_occurrences(&event, utc(2024, 1, 1, 0), utc(2026, 1, 1, 0));
+        assert_eq!(occ.len(), 3);
+
+        event.recurrence_count = None;
+        event.recurrence_duration = Some(std::time::Duration::from_secs(4 * 24 * 60 * 60).into());
+        let occ = expand_occurrences(&event, utc(2024, 1, 1, 0), utc(2026, 1, 1, 0));
+        assert_eq!(
+            starts(&occ),
+            vec![
+                utc(2025, 1, 1, 9),
+                utc(2025, 1, 2, 9),
+                utc(2025, 1, 3, 9),
+                utc(2025, 1, 4, 9)
+            ]
+        );
+
+        // Whichever condition ends the series first wins
+        event.recurrence_count = Some(2);
+        let occ = expand_occurrences(&event, utc(2024, 1, 1, 0), utc(2026, 1, 1, 0));
+        assert_eq!(occ.len(), 2);
+    }
+}
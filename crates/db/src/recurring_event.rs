@@ -0,0 +1,223 @@
+use crate::timezone::timezone_to_sql;
+use crate::{DatabaseConnection, Error, RecurringEvent, datetime_from_sql, datetime_to_sql, sql};
+use chrono::{DateTime, Utc};
+use humantime::Duration as HumanDuration;
+use rusqlite::{OptionalExtension, Row, params, types::Type};
+
+/// The fields needed to create or update a recurring event.
+/// `calendar_id` is only used on insert, updates never move an event between calendars.
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewRecurringEvent {
+    pub calendar_id: i64,
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub recurrence_type: String,
+    pub recurrence_interval: i64,
+    pub recurrence_count: Option<i64>,
+    pub recurrence_duration: Option<HumanDuration>,
+    /// IANA time zone the series repeats in, see `RecurringEvent::timezone`
+    pub timezone: String,
+}
+
+/// Map a row selected in the column order used by `sql::recurring_event::SELECT_BY_ID`.
+fn recurring_event_from_row(row: &Row) -> Result<RecurringEvent, rusqlite::Error> {
+    let duration: Option<String> = row.get(9)?;
+    let recurrence_duration = duration
+        .map(|d| {
+            d.parse::<HumanDuration>()
+                .map_err(|e| rusqlite::Error::FromSqlConversionFailure(9, Type::Text, Box::new(e)))
+        })
+        .transpose()?;
+    Ok(RecurringEvent {
+        id: row.get(0)?,
+        calendar_id: row.get(1)?,
+        title: row.get(2)?,
+        description: row.get(3)?,
+        start_time: datetime_from_sql(row, 4)?,
+        end_time: datetime_from_sql(row, 5)?,
+        recurrence_type: row.get(6)?,
+        recurrence_interval: row.get(7)?,
+        recurrence_count: row.get(8)?,
+        recurrence_duration,
+        created_at: datetime_from_sql(row, 10)?,
+        updated_at: datetime_from_sql(row, 11)?,
+        timezone: row.get(12)?,
+    })
+}
+
+impl DatabaseConnection {
+    // --- RECURRING EVENTS API ---
+
+    /// Insert a new recurring event, returning its row id.
+    pub fn insert_recurring_event(&self, event: &NewRecurringEvent) -> Result<i64, Error> {
+        self.conn.execute(
+            sql::recurring_event::INSERT,
+            params![
+                event.calendar_id,
+                event.title,
+                event.description,
+                datetime_to_sql(&event.start_time),
+                datetime_to_sql(&event.end_time),
+                event.recurrence_type,
+                event.recurrence_interval,
+                event.recurrence_count,
+                event.recurrence_duration.map(|d| d.to_string()),
+                datetime_to_sql(&Utc::now()),
+                timezone_to_sql(&event.timezone)?,
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Select a recurring event by id.
+    pub fn get_recurring_event_by_id(&self, id: i64) -> Result<Option<RecurringEvent>, Error> {
+        Ok(self
+            .conn
+            .query_row(
+                sql::recurring_event::SELECT_BY_ID,
+                params![id],
+                recurring_event_from_row,
+            )
+            .optional()?)
+    }
+
+    /// List all recurring events in a calendar, ordered by start time.
+    pub fn list_recurring_events_by_calendar(
+        &self,
+        calendar_id: i64,
+    ) -> Result<Vec<RecurringEvent>, Error> {
+        let mut stmt = self.conn.prepare(sql::recurring_event::LIST_BY_CALENDAR)?;
+        let rows = stmt.query_map(params![calendar_id], recurring_event_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Update a recurring event's details. Returns false if no recurring event has the given id.
+    pub fn update_recurring_event(
+        &self,
+        id: i64,
+        event: &NewRecurringEvent,
+    ) -> Result<bool, Error> {
+        let changed = self.conn.execute(
+            sql::recurring_event::UPDATE,
+            params![
+                id,
+                event.title,
+                event.description,
+                datetime_to_sql(&event.start_time),
+                datetime_to_sql(&event.end_time),
+                event.recurrence_type,
+                event.recurrence_interval,
+                event.recurrence_count,
+                event.recurrence_duration.map(|d| d.to_string()),
+                datetime_to_sql(&Utc::now()),
+                timezone_to_sql(&event.timezone)?,
+            ],
+        )?;
+        Ok(changed > 0)
+    }
+
+    /// Delete a recurring event by id. Returns false if no recurring event has the given id.
+    pub fn delete_recurring_event_by_id(&self, id: i64) -> Result<bool, Error> {
+        let changed = self
+            .conn
+            .execute(sql::recurring_event::DELETE, params![id])?;
+        Ok(changed > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use colorlab::Color;
+    use std::path::Path;
+    use std::time::Duration;
+
+    fn test_db() -> (DatabaseConnection, i64) {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let calendar_id = db
+            .insert_calendar("Family", Color::from_rgb8(0x33, 0x66, 0x99))
+            .unwrap();
+        (db, calendar_id)
+    }
+
+    fn weekly(calendar_id: i64) -> NewRecurringEvent {
+        NewRecurringEvent {
+            calendar_id,
+            title: "Soccer practice".to_string(),
+            description: None,
+            start_time: Utc.with_ymd_and_hms(2025, 1, 6, 17, 0, 0).unwrap(),
+            end_time: Utc.with_ymd_and_hms(2025, 1, 6, 18, 30, 0).unwrap(),
+            recurrence_type: "weekly".to_string(),
+            recurrence_interval: 1,
+            recurrence_count: None,
+            recurrence_duration: None,
+            timezone: "Europe/Berlin".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_infinite_recurring_event_round_trip() {
+        let (db, calendar_id) = test_db();
+        let new = weekly(calendar_id);
+        let id = db.insert_recurring_event(&new).unwrap();
+
+        let event = db.get_recurring_event_by_id(id).unwrap().expect("exists");
+        assert_eq!(event.title, new.title);
+        assert_eq!(event.start_time, new.start_time);
+        assert_eq!(event.end_time, new.end_time);
+        assert_eq!(event.recurrence_type, "weekly");
+        assert_eq!(event.timezone, "Europe/Berlin");
+        assert_eq!(event.recurrence_interval, 1);
+        assert_eq!(event.recurrence_count, None);
+        assert_eq!(event.recurrence_duration, None);
+
+        // NULL really is stored, not an empty string
+        let raw: Option<String> = db
+            .conn
+            .query_row(
+                "SELECT recurrence_duration FROM recurring_events WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(raw, None);
+    }
+
+    #[test]
+    fn test_recurring_event_with_duration_round_trip() {
+        let (db, calendar_id) = test_db();
+        let mut new = weekly(calendar_id);
+        new.recurrence_count = Some(10);
+        new.recurrence_duration = Some(Duration::from_secs(90 * 60).into());
+        let id = db.insert_recurring_event(&new).unwrap();
+
+        let raw: String = db
+            .conn
+            .query_row(
+                "SELECT recurrence_duration FROM recurring_events WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(raw, "1h 30m");
+
+        let event = db.get_recurring_event_by_id(id).unwrap().unwrap();
+        assert_eq!(event.recurrence_count, Some(10));
+        assert_eq!(event.recurrence_duration, new.recurrence_duration);
+
+        // Update and list
+        new.title = "Soccer".to_string();
+        new.recurrence_duration = None;
+        assert!(db.update_recurring_event(id, &new).unwrap());
+        let listed = db.list_recurring_events_by_calendar(calendar_id).unwrap();
+        assert_eq!(listed.len(), 1);
+        assert_eq!(listed[0].title, "Soccer");
+        assert_eq!(listed[0].recurrence_duration, None);
+
+        assert!(db.delete_recurring_event_by_id(id).unwrap());
+        assert!(db.get_recurring_event_by_id(id).unwrap().is_none());
+    }
+}
@@ -0,0 +1,228 @@
+use crate::{DatabaseConnection, Error, Event, Reminder, datetime_from_sql, datetime_to_sql, sql};
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{OptionalExtension, Row, params};
+
+/// Reminders delivered to the connected clients of the event's calendar.
+pub const REMINDER_METHOD_NOTIFICATION: &str = "notification";
+
+/// A reminder together with the instant it falls due.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DueReminder {
+    pub reminder: Reminder,
+    pub due_at: DateTime<Utc>,
+}
+
+/// When a reminder `offset_seconds` before `event` falls due. All-day events start at midnight
+/// of their first day in the event's time zone. Must agree with `sql::reminder::LIST_DUE`.
+pub fn reminder_due_at(event: &Event, offset_seconds: i64) -> DateTime<Utc> {
+    let start = if event.all_day {
+        event.day_span().0
+    } else {
+        event.start_time
+    };
+    start - Duration::seconds(offset_seconds)
+}
+
+/// Map a row selected as `id, event_id, offset_seconds, method, created_at`.
+fn reminder_from_row(row: &Row) -> Result<Reminder, rusqlite::Error> {
+    Ok(Reminder {
+        id: row.get(0)?,
+        event_id: row.get(1)?,
+        offset_seconds: row.get(2)?,
+        method: row.get(3)?,
+        created_at: datetime_from_sql(row, 4)?,
+    })
+}
+
+impl DatabaseConnection {
+    // --- REMINDERS API ---
+
+    /// Remind `offset_seconds` before an event starts, returning the reminder's id.
+    /// Setting a reminder the event already has returns the existing one.
+    pub fn set_reminder(
+        &self,
+        event_id: i64,
+        offset_seconds: i64,
+        method: &str,
+    ) -> Result<i64, Error> {
+        Ok(self.conn.query_row(
+            sql::reminder::UPSERT,
+            params![
+                event_id,
+                offset_seconds,
+                method,
+                datetime_to_sql(&Utc::now())
+            ],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Select a reminder by id.
+    pub fn get_reminder_by_id(&self, id: i64) -> Result<Option<Reminder>, Error> {
+        Ok(self
+            .conn
+            .query_row(sql::reminder::SELECT_BY_ID, params![id], reminder_from_row)
+            .optional()?)
+    }
+
+    /// List an event's reminders, the one due first first.
+    pub fn list_reminders_for_event(&self, event_id: i64) -> Result<Vec<Reminder>, Error> {
+        let mut stmt = self.conn.prepare(sql::reminder::LIST_BY_EVENT)?;
+        let rows = stmt.query_map(params![event_id], reminder_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Delete a reminder by id. Returns false if there was no such reminder.
+    pub fn delete_reminder(&self, id: i64) -> Result<bool, Error> {
+        let changed = self.conn.execute(sql::reminder::DELETE, params![id])?;
+        Ok(changed > 0)
+    }
+
+    /// List the reminders of non-deleted events falling due in `[from, to)`, soonest first.
+    pub fn list_due_reminders(
+        &self,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<DueReminder>, Error> {
+        let mut stmt = self.conn.prepare(sql::reminder::LIST_DUE)?;
+        let rows = stmt.query_map(
+            params![datetime_to_sql(&from), datetime_to_sql(&to)],
+            |row| {
+                Ok(DueReminder {
+                    reminder: reminder_from_row(row)?,
+                    due_at: datetime_from_sql(row, 5)?,
+                })
+            },
+        )?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::NewEvent;
+    use chrono::TimeZone;
+    use colorlab::Color;
+    use std::path::Path;
+
+    fn test_db() -> (DatabaseConnection, i64) {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let calendar_id = db
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        (db, calendar_id)
+    }
+
+    #[test]
+    fn test_reminder_is_due_before_event() {
+        let (db, calendar_id) = test_db();
+        let at = |h: u32, m: u32| Utc.with_ymd_and_hms(2025, 3, 14, h, m, 0).unwrap();
+        let event_id = db
+            .insert_event(calendar_id, "Dentist", None, at(9, 0), at(10, 0))
+            .unwrap();
+        let id = db
+            .set_reminder(event_id, 600, REMINDER_METHOD_NOTIFICATION)
+            .unwrap();
+        assert_eq!(
+            db.set_reminder(event_id, 600, REMINDER_METHOD_NOTIFICATION)
+                .unwrap(),
+            id
+        );
+        db.set_reminder(event_id, 3600, REMINDER_METHOD_NOTIFICATION)
+            .unwrap();
+
+        let event = db.get_event_by_id(event_id).unwrap().unwrap();
+        assert_eq!(reminder_due_at(&event, 600), at(8, 50));
+        let offsets: Vec<i64> = db
+            .list_reminders_for_event(event_id)
+            .unwrap()
+            .iter()
+            .map(|r| r.offset_seconds)
+            .collect();
+        assert_eq!(offsets, vec![3600, 600]);
+
+        // Due exactly at 8:50, so in a window starting then but not one ending then
+        let due = db.list_due_reminders(at(8, 50), at(8, 51)).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].reminder.id, id);
+        assert_eq!(due[0].due_at, at(8, 50));
+        assert_eq!(db.list_due_reminders(at(8, 0), at(8, 50)).unwrap().len(), 1);
+        assert!(
+            db.list_due_reminders(at(8, 51), at(9, 0))
+                .unwrap()
+                .is_empty()
+        );
+
+        // Deleted events have no reminders due
+        db.delete_event_by_id(event_id).unwrap();
+        assert!(
+            db.list_due_reminders(at(8, 0), at(9, 0))
+                .unwrap()
+                .is_empty()
+        );
+        db.restore_event(event_id).unwrap();
+        assert!(db.delete_reminder(id).unwrap());
+        assert!(!db.delete_reminder(id).unwrap());
+        assert_eq!(db.list_due_reminders(at(8, 0), at(9, 0)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_all_day_reminder_is_due_before_midnight() {
+        let (db, calendar_id) = test_db();
+        let event_id = db
+            .insert_new_event(
+                calendar_id,
+                &NewEvent {
+                    all_day: true,
+                    ..NewEvent::new(
+                        "Holiday",
+                        None,
+                        Utc.with_ymd_and_hms(2025, 7, 4, 15, 0, 0).unwrap(),
+                        Utc.with_ymd_and_hms(2025, 7, 4, 15, 0, 0).unwrap(),
+                    )
+                },
+            )
+            .unwrap();
+        db.set_reminder(event_id, 3600, REMINDER_METHOD_NOTIFICATION)
+            .unwrap();
+
+        let due_at = Utc.with_ymd_and_hms(2025, 7, 3, 23, 0, 0).unwrap();
+        let event = db.get_event_by_id(event_id).unwrap().unwrap();
+        assert_eq!(reminder_due_at(&event, 3600), due_at);
+        let due = db
+            .list_due_reminders(due_at, due_at + Duration::seconds(1))
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].due_at, due_at);
+    }
+
+    #[test]
+    fn test_all_day_reminder_is_due_before_local_midnight() {
+        let (db, calendar_id) = test_db();
+        // 20:00 on July 4th in Los Angeles is already the 5th in UTC
+        let evening = Utc.with_ymd_and_hms(2025, 7, 5, 3, 0, 0).unwrap();
+        let event_id = db
+            .insert_new_event(
+                calendar_id,
+                &NewEvent {
+                    all_day: true,
+                    timezone: "America/Los_Angeles".to_string(),
+                    ..NewEvent::new("Holiday", None, evening, evening)
+                },
+            )
+            .unwrap();
+        db.set_reminder(event_id, 3600, REMINDER_METHOD_NOTIFICATION)
+            .unwrap();
+
+        // An hour before midnight starting July 4th in Los Angeles (PDT)
+        let due_at = Utc.with_ymd_and_hms(2025, 7, 4, 6, 0, 0).unwrap();
+        let event = db.get_event_by_id(event_id).unwrap().unwrap();
+        assert_eq!(reminder_due_at(&event, 3600), due_at);
+        let due = db
+            .list_due_reminders(due_at, due_at + Duration::seconds(1))
+            .unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].due_at, due_at);
+    }
+}
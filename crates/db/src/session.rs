@@ -0,0 +1,177 @@
+use crate::{DatabaseConnection, Error, datetime_from_sql, datetime_to_sql, sql};
+use chrono::{DateTime, Utc};
+use rusqlite::{OptionalExtension, Row, params};
+use serde::Serialize;
+
+/// An issued access or refresh token that hasn't been revoked, identified by its `jti` claim.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Session {
+    pub jti: String,
+    pub username: String,
+    /// `access` or `refresh`
+    pub token_type: String,
+    pub issued_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Map a row selected as `id, username, token_type, issued_at, expires_at`.
+fn session_from_row(row: &Row) -> Result<Session, rusqlite::Error> {
+    Ok(Session {
+        jti: row.get(0)?,
+        username: row.get(1)?,
+        token_type: row.get(2)?,
+        issued_at: datetime_from_sql(row, 3)?,
+        expires_at: datetime_from_sql(row, 4)?,
+    })
+}
+
+impl DatabaseConnection {
+    // --- SESSIONS API ---
+
+    /// Record a newly issued token, forgetting the user's sessions that have expired by now.
+    pub fn insert_session(&self, session: &Session) -> Result<(), Error> {
+        self.conn.execute(
+            sql::session::DELETE_EXPIRED,
+            params![session.username, datetime_to_sql(&session.issued_at)],
+        )?;
+        self.conn.execute(
+            sql::session::INSERT,
+            params![
+                session.jti,
+                session.username,
+                session.token_type,
+                datetime_to_sql(&session.issued_at),
+                datetime_to_sql(&session.expires_at)
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Select a session by its token id.
+    pub fn get_session(&self, jti: &str) -> Result<Option<Session>, Error> {
+        Ok(self
+            .conn
+            .query_row(sql::session::SELECT_BY_ID, params![jti], session_from_row)
+            .optional()?)
+    }
+
+    /// List a user's sessions that are still valid at `now`, newest first.
+    pub fn list_sessions_for_user(
+        &self,
+        username: &str,
+        now: DateTime<Utc>,
+    ) -> Result<Vec<Session>, Error> {
+        let mut stmt = self.conn.prepare(sql::session::LIST_BY_USERNAME)?;
+        let rows = stmt.query_map(params![username, datetime_to_sql(&now)], session_from_row)?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Forget a session. Returns false if there was no such session.
+    pub fn delete_session(&self, jti: &str) -> Result<bool, Error> {
+        let changed = self.conn.execute(sql::session::DELETE, params![jti])?;
+        Ok(changed > 0)
+    }
+
+    /// Forget every session of a user, returning how many there were.
+    pub fn delete_sessions_for_user(&self, username: &str) -> Result<usize, Error> {
+        Ok(self
+            .conn
+            .execute(sql::session::DELETE_BY_USERNAME, params![username])?)
+    }
+
+    /// Mark a password reset token as used. Returns false if it already was, so of two
+    /// concurrent resets with the same token only one gets `true`.
+    /// Used tokens that have expired by `now` are forgotten.
+    pub fn consume_password_reset(
+        &self,
+        jti: &str,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Result<bool, Error> {
+        self.conn.execute(
+            sql::session::CONSUMED_RESET_DELETE_EXPIRED,
+            params![datetime_to_sql(&now)],
+        )?;
+        let inserted = self.conn.execute(
+            sql::session::CONSUMED_RESET_INSERT,
+            params![jti, datetime_to_sql(&expires_at)],
+        )?;
+        Ok(inserted > 0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+    use std::path::Path;
+
+    fn session(jti: &str, username: &str, issued_at: DateTime<Utc>) -> Session {
+        Session {
+            jti: jti.to_string(),
+            username: username.to_string(),
+            token_type: "access".to_string(),
+            issued_at,
+            expires_at: issued_at + Duration::hours(1),
+        }
+    }
+
+    #[test]
+    fn test_sessions_are_listed_until_expired_or_deleted() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let t0 = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        db.insert_session(&session("a", "alice", t0)).unwrap();
+        db.insert_session(&session("b", "alice", t0 + Duration::minutes(30)))
+            .unwrap();
+        db.insert_session(&session("c", "bob", t0)).unwrap();
+
+        let jtis = |now| -> Vec<String> {
+            db.list_sessions_for_user("alice", now)
+                .unwrap()
+                .into_iter()
+                .map(|s| s.jti)
+                .collect()
+        };
+        assert_eq!(jtis(t0), vec!["b", "a"]);
+        assert_eq!(
+            db.get_session("a").unwrap(),
+            Some(session("a", "alice", t0))
+        );
+        // "a" expires an hour after it was issued
+        assert_eq!(jtis(t0 + Duration::minutes(70)), vec!["b"]);
+
+        assert!(db.delete_session("b").unwrap());
+        assert!(!db.delete_session("b").unwrap());
+        assert_eq!(jtis(t0), vec!["a"]);
+
+        // Issuing a new token clears out the user's expired ones
+        db.insert_session(&session("d", "alice", t0 + Duration::hours(2)))
+            .unwrap();
+        assert_eq!(db.get_session("a").unwrap(), None);
+
+        assert_eq!(db.delete_sessions_for_user("alice").unwrap(), 1);
+        assert!(jtis(t0).is_empty());
+        assert!(db.get_session("c").unwrap().is_some());
+    }
+
+    #[test]
+    fn test_password_reset_is_consumed_once() {
+        let db = DatabaseConnection::from_path(Path::new(":memory:")).unwrap();
+        let t0 = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let expires_at = t0 + Duration::hours(1);
+        assert!(db.consume_password_reset("r1", expires_at, t0).unwrap());
+        assert!(!db.consume_password_reset("r1", expires_at, t0).unwrap());
+        assert!(db.consume_password_reset("r2", expires_at, t0).unwrap());
+
+        // Once expired the row is dropped, the token itself is rejected by then
+        let later = t0 + Duration::hours(2);
+        assert!(db.consume_password_reset("r3", later, later).unwrap());
+        let remaining: i64 = db
+            .conn
+            .query_row("SELECT COUNT(*) FROM consumed_password_resets", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(remaining, 1);
+    }
+}
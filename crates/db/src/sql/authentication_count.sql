@@ -0,0 +1,6 @@
+-- ===========================================
+-- Count all users in authentication table
+-- For use with rusqlite in Rust
+-- ===========================================
+
+SELECT COUNT(*) FROM authentication;
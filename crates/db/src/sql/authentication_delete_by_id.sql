@@ -0,0 +1,7 @@
+-- ===========================================
+-- Delete user by id from authentication table
+-- For use with rusqlite in Rust
+-- ===========================================
+
+DELETE FROM authentication
+WHERE id = ?1;
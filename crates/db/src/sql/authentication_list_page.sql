@@ -0,0 +1,10 @@
+-- ===========================================
+-- List one page of users ordered by id
+-- Password hash and salt are deliberately not selected
+-- For use with rusqlite in Rust
+-- ===========================================
+
+SELECT id, username, email, created_at, updated_at
+FROM authentication
+ORDER BY id
+LIMIT ?1 OFFSET ?2;
@@ -12,3 +12,6 @@ CREATE TABLE IF NOT EXISTS authentication (
     created_at      DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP,
     updated_at      DATETIME NOT NULL DEFAULT CURRENT_TIMESTAMP
 );
+
+-- Email lookups (password reset) and uniqueness
+CREATE UNIQUE INDEX IF NOT EXISTS idx_authentication_email ON authentication (email);
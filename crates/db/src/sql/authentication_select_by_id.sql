@@ -0,0 +1,8 @@
+-- ===========================================
+-- Select user by id from authentication table
+-- For use with rusqlite in Rust
+-- ===========================================
+
+SELECT id, username, password_hash, salt, email, created_at, updated_at, email_verified
+FROM authentication
+WHERE id = ?1;
@@ -3,6 +3,6 @@
 -- For use with rusqlite in Rust
 -- ===========================================
 
-SELECT id, username, password_hash, salt, email, created_at, updated_at
+SELECT id, username, password_hash, salt, email, created_at, updated_at, email_verified
 FROM authentication
 WHERE username = ?1;
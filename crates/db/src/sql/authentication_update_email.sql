@@ -1,9 +1,11 @@
 -- ===========================================
 -- Update User Email in Authentication Table
+-- The new address hasn't been verified yet
 -- For use with rusqlite in Rust
 -- ===========================================
 
 UPDATE authentication
 SET email = ?2,
+    email_verified = 0,
     updated_at = CURRENT_TIMESTAMP
 WHERE username = ?1;
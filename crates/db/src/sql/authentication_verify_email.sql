@@ -0,0 +1,10 @@
+-- ===========================================
+-- Mark a user's email verified
+-- Changes nothing if the address changed since the verification token was issued
+-- ===========================================
+
+UPDATE authentication
+SET email_verified = 1,
+    updated_at = CURRENT_TIMESTAMP
+WHERE username = ?1
+  AND email = ?2;
@@ -0,0 +1,6 @@
+-- ===========================================
+-- Delete a calendar by id
+-- ===========================================
+
+DELETE FROM calendars
+WHERE id = ?1;
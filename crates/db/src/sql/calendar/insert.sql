@@ -0,0 +1,7 @@
+-- ===========================================
+-- Insert a new calendar
+-- color is "#RRGGBB", times are RFC3339 strings
+-- ===========================================
+
+INSERT INTO calendars (name, color, created_at, updated_at)
+VALUES (?1, ?2, ?3, ?3);
@@ -0,0 +1,11 @@
+-- ===========================================
+-- Mark a calendar invite accepted
+-- Changes nothing if it was already accepted, has expired or is for another calendar
+-- ===========================================
+
+UPDATE calendar_invites
+SET accepted_by = ?3, accepted_at = ?4
+WHERE id = ?1
+  AND calendar_id = ?2
+  AND accepted_at IS NULL
+  AND expires_at > ?4;
@@ -0,0 +1,6 @@
+-- ===========================================
+-- Record a newly issued calendar invite
+-- ===========================================
+
+INSERT INTO calendar_invites (id, calendar_id, issued_by, expires_at)
+VALUES (?1, ?2, ?3, ?4);
@@ -0,0 +1,15 @@
+-- ===========================================
+-- Calendar invites, one row per issued invite token so each can only be accepted once
+-- ===========================================
+
+CREATE TABLE IF NOT EXISTS calendar_invites (
+    id TEXT PRIMARY KEY, -- the token's unique id
+    calendar_id INTEGER NOT NULL,
+    issued_by INTEGER NOT NULL,
+    expires_at TEXT NOT NULL,
+    accepted_by INTEGER,
+    accepted_at TEXT,
+    FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE,
+    FOREIGN KEY (issued_by) REFERENCES authentication(id) ON DELETE CASCADE,
+    FOREIGN KEY (accepted_by) REFERENCES authentication(id) ON DELETE SET NULL
+);
@@ -0,0 +1,7 @@
+-- ===========================================
+-- List all calendars ordered by id
+-- ===========================================
+
+SELECT id, name, color, created_at, updated_at
+FROM calendars
+ORDER BY id;
@@ -3,7 +3,18 @@
 
 pub const CALENDAR_SCHEMA: &str = include_str!("schema.sql");
 pub const CALENDAR_PERMISSIONS_SCHEMA: &str = include_str!("permissions_schema.sql");
-
-// You can add more constants here for calendar-specific queries as needed, e.g.:
-// pub const CALENDAR_INSERT: &str = include_str!("insert.sql");
-// pub const CALENDAR_SELECT_BY_NAME: &str = include_str!("select_by_name.sql");
+pub const CALENDAR_INSERT: &str = include_str!("insert.sql");
+pub const CALENDAR_SELECT_BY_ID: &str = include_str!("select_by_id.sql");
+pub const CALENDAR_LIST: &str = include_str!("list.sql");
+pub const CALENDAR_UPDATE: &str = include_str!("update.sql");
+pub const CALENDAR_DELETE: &str = include_str!("delete.sql");
+pub const CALENDAR_PERMISSIONS_INSERT_OWNER: &str = include_str!("permissions_insert_owner.sql");
+pub const CALENDAR_PERMISSIONS_UPSERT: &str = include_str!("permissions_upsert.sql");
+pub const CALENDAR_PERMISSIONS_SELECT: &str = include_str!("permissions_select.sql");
+pub const CALENDAR_PERMISSIONS_DELETE_FOR_USER: &str =
+    include_str!("permissions_delete_for_user.sql");
+pub const CALENDAR_PERMISSIONS_LIST_BY_CALENDAR: &str =
+    include_str!("permissions_list_by_calendar.sql");
+pub const CALENDAR_INVITES_SCHEMA: &str = include_str!("invites_schema.sql");
+pub const CALENDAR_INVITES_INSERT: &str = include_str!("invites_insert.sql");
+pub const CALENDAR_INVITES_ACCEPT: &str = include_str!("invites_accept.sql");
@@ -0,0 +1,6 @@
+-- ===========================================
+-- Remove a user's capabilities on every calendar
+-- ===========================================
+
+DELETE FROM calendar_permissions
+WHERE user_id = ?1;
@@ -0,0 +1,10 @@
+-- ===========================================
+-- Grant a user every capability on a calendar
+-- Used when a calendar is created to make the creator its owner
+-- ===========================================
+
+INSERT INTO calendar_permissions (
+    user_id, calendar_id, can_admin, can_view, can_read, can_add_event,
+    can_modify_event, can_add_recurring_event, can_modify_recurring_event
+)
+VALUES (?1, ?2, 1, 1, 1, 1, 1, 1, 1);
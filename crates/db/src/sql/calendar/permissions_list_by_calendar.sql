@@ -0,0 +1,9 @@
+-- ===========================================
+-- List every user's capabilities on a calendar ordered by user id
+-- ===========================================
+
+SELECT user_id, calendar_id, can_admin, can_view, can_read, can_add_event,
+    can_modify_event, can_add_recurring_event, can_modify_recurring_event
+FROM calendar_permissions
+WHERE calendar_id = ?1
+ORDER BY user_id;
@@ -9,6 +9,6 @@ CREATE TABLE IF NOT EXISTS calendar_permissions (
     can_add_recurring_event BOOLEAN NOT NULL DEFAULT 0,
     can_modify_recurring_event BOOLEAN NOT NULL DEFAULT 0,
     PRIMARY KEY (user_id, calendar_id),
-    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES authentication(id) ON DELETE CASCADE,
     FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE
 );
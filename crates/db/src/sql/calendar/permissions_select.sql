@@ -0,0 +1,8 @@
+-- ===========================================
+-- Select a user's capabilities on a calendar
+-- ===========================================
+
+SELECT user_id, calendar_id, can_admin, can_view, can_read, can_add_event,
+    can_modify_event, can_add_recurring_event, can_modify_recurring_event
+FROM calendar_permissions
+WHERE user_id = ?1 AND calendar_id = ?2;
@@ -0,0 +1,18 @@
+-- ===========================================
+-- Set a user's capabilities on a calendar
+-- Replaces every capability if the user already has a row for the calendar
+-- ===========================================
+
+INSERT INTO calendar_permissions (
+    user_id, calendar_id, can_admin, can_view, can_read, can_add_event,
+    can_modify_event, can_add_recurring_event, can_modify_recurring_event
+)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+ON CONFLICT (user_id, calendar_id) DO UPDATE SET
+    can_admin = excluded.can_admin,
+    can_view = excluded.can_view,
+    can_read = excluded.can_read,
+    can_add_event = excluded.can_add_event,
+    can_modify_event = excluded.can_modify_event,
+    can_add_recurring_event = excluded.can_add_recurring_event,
+    can_modify_recurring_event = excluded.can_modify_recurring_event;
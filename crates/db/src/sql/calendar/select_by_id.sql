@@ -0,0 +1,7 @@
+-- ===========================================
+-- Select a calendar by id
+-- ===========================================
+
+SELECT id, name, color, created_at, updated_at
+FROM calendars
+WHERE id = ?1;
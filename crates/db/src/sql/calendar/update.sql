@@ -0,0 +1,9 @@
+-- ===========================================
+-- Update a calendar's name and color by id
+-- ===========================================
+
+UPDATE calendars
+SET name = ?2,
+    color = ?3,
+    updated_at = ?4
+WHERE id = ?1;
@@ -0,0 +1,5 @@
+-- ===========================================
+-- List the events whose all-day span hasn't been stored yet (created before migration 7)
+-- ===========================================
+
+SELECT id FROM events WHERE day_start IS NULL OR day_end IS NULL;
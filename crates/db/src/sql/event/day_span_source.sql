@@ -0,0 +1,7 @@
+-- ===========================================
+-- Select what an event's all-day span is computed from, by id (soft-deleted events included)
+-- ===========================================
+
+SELECT start_time, end_time, COALESCE(timezone, 'UTC')
+FROM events
+WHERE id = ?1;
@@ -0,0 +1,9 @@
+-- ===========================================
+-- Store an event's all-day span, see migration 7
+-- Derived from the event's times and zone, so updated_at stays as it is
+-- ===========================================
+
+UPDATE events
+SET day_start = ?2,
+    day_end = ?3
+WHERE id = ?1;
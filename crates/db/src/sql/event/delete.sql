@@ -0,0 +1,9 @@
+-- ===========================================
+-- Soft-delete an event by id
+-- ?2 = deletion time (RFC3339 string)
+-- ===========================================
+
+UPDATE events
+SET deleted_at = ?2
+WHERE id = ?1
+  AND deleted_at IS NULL;
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Index every existing event (run once when the index is first created)
+-- ===========================================
+
+INSERT INTO events_fts (events_fts) VALUES ('rebuild');
@@ -0,0 +1,29 @@
+-- ===========================================
+-- Full-text index over event titles and descriptions
+-- Only created when the linked SQLite has FTS5, see `search_events`
+-- ===========================================
+
+CREATE VIRTUAL TABLE IF NOT EXISTS events_fts USING fts5(
+    title,
+    description,
+    content = 'events',
+    content_rowid = 'id'
+);
+
+-- Keep the index in step with the events table
+CREATE TRIGGER IF NOT EXISTS events_search_insert AFTER INSERT ON events BEGIN
+    INSERT INTO events_fts (rowid, title, description)
+    VALUES (new.id, new.title, new.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_search_delete AFTER DELETE ON events BEGIN
+    INSERT INTO events_fts (events_fts, rowid, title, description)
+    VALUES ('delete', old.id, old.title, old.description);
+END;
+
+CREATE TRIGGER IF NOT EXISTS events_search_update AFTER UPDATE OF title, description ON events BEGIN
+    INSERT INTO events_fts (events_fts, rowid, title, description)
+    VALUES ('delete', old.id, old.title, old.description);
+    INSERT INTO events_fts (rowid, title, description)
+    VALUES (new.id, new.title, new.description);
+END;
@@ -0,0 +1,7 @@
+-- ===========================================
+-- Insert a new event into the events table
+-- Times are RFC3339 strings, ?7 is the IANA time zone it was created in, ?8 whether it's all-day
+-- ===========================================
+
+INSERT INTO events (calendar_id, title, description, start_time, end_time, created_at, updated_at, timezone, all_day)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?6, ?7, ?8);
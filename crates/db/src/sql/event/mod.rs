@@ -2,8 +2,26 @@
 /// These are embedded at compile time using `include_str!` for easy editing and single binary output.
 
 pub const EVENT_SCHEMA: &str = include_str!("schema.sql");
-// Add more constants for event queries as you create them, e.g.:
-// pub const EVENT_INSERT: &str = include_str!("insert.sql");
-// pub const EVENT_SELECT_BY_ID: &str = include_str!("select_by_id.sql");
-// pub const EVENT_UPDATE: &str = include_str!("update.sql");
-// pub const EVENT_DELETE: &str = include_str!("delete.sql");
+pub const EVENT_INSERT: &str = include_str!("insert.sql");
+pub const EVENT_SELECT_BY_ID: &str = include_str!("select_by_id.sql");
+pub const EVENT_UPDATE: &str = include_str!("update.sql");
+pub const EVENT_DELETE: &str = include_str!("delete.sql");
+pub const EVENT_SELECT_IN_RANGE: &str = include_str!("select_in_range.sql");
+pub const EVENT_UID_SCHEMA: &str = include_str!("uid_schema.sql");
+pub const EVENT_UID_SELECT: &str = include_str!("uid_select.sql");
+pub const EVENT_UID_INSERT: &str = include_str!("uid_insert.sql");
+pub const EVENT_UID_SELECT_BY_CALENDAR: &str = include_str!("uid_select_by_calendar.sql");
+pub const EVENT_FTS_SCHEMA: &str = include_str!("fts_schema.sql");
+pub const EVENT_FTS_REBUILD: &str = include_str!("fts_rebuild.sql");
+pub const EVENT_SEARCH_FTS: &str = include_str!("search_fts.sql");
+pub const EVENT_SEARCH_LIKE: &str = include_str!("search_like.sql");
+pub const EVENT_RESTORE: &str = include_str!("restore.sql");
+pub const EVENT_PURGE_DELETED: &str = include_str!("purge_deleted.sql");
+pub const EVENT_SELECT_OVERLAPPING: &str = include_str!("select_overlapping.sql");
+pub const EVENT_UPDATE_TIMEZONE: &str = include_str!("update_timezone.sql");
+pub const EVENT_UPDATE_ALL_DAY: &str = include_str!("update_all_day.sql");
+pub const EVENT_SELECT_BY_CALENDAR: &str = include_str!("select_by_calendar.sql");
+pub const EVENT_SELECT_CHANGED_SINCE: &str = include_str!("select_changed_since.sql");
+pub const EVENT_DAY_SPAN_SOURCE: &str = include_str!("day_span_source.sql");
+pub const EVENT_DAY_SPAN_UPDATE: &str = include_str!("day_span_update.sql");
+pub const EVENT_DAY_SPAN_MISSING: &str = include_str!("day_span_missing.sql");
@@ -0,0 +1,8 @@
+-- ===========================================
+-- Permanently remove events soft-deleted before a cutoff
+-- ?1 = cutoff (RFC3339 string, which compares chronologically)
+-- ===========================================
+
+DELETE FROM events
+WHERE deleted_at IS NOT NULL
+  AND deleted_at < ?1;
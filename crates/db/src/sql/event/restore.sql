@@ -0,0 +1,10 @@
+-- ===========================================
+-- Bring back a soft-deleted event by id
+-- ?2 = restore time (RFC3339 string), so polling clients see it again
+-- ===========================================
+
+UPDATE events
+SET deleted_at = NULL,
+    updated_at = ?2
+WHERE id = ?1
+  AND deleted_at IS NOT NULL;
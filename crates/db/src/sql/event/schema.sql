@@ -9,3 +9,11 @@ CREATE TABLE IF NOT EXISTS events (
     updated_at TEXT NOT NULL,   -- ISO 8601 string
     FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE
 );
+
+-- Range queries filter by calendar and compare start_time
+CREATE INDEX IF NOT EXISTS idx_events_calendar_start
+    ON events (calendar_id, start_time);
+
+-- Polling clients ask for what changed in a calendar since their last sync
+CREATE INDEX IF NOT EXISTS idx_events_calendar_updated
+    ON events (calendar_id, updated_at);
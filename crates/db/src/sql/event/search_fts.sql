@@ -0,0 +1,14 @@
+-- ===========================================
+-- Search events with the full-text index, best matches first
+-- ?1 = calendar id (NULL for every calendar), ?2 = FTS5 query, ?3 = limit
+-- ===========================================
+
+SELECT e.id, e.calendar_id, e.title, e.description, e.start_time, e.end_time, e.created_at, e.updated_at,
+       COALESCE(e.timezone, 'UTC'), e.all_day
+FROM events_fts
+JOIN events e ON e.id = events_fts.rowid
+WHERE events_fts MATCH ?2
+  AND (?1 IS NULL OR e.calendar_id = ?1)
+  AND e.deleted_at IS NULL
+ORDER BY events_fts.rank, e.start_time, e.id
+LIMIT ?3;
@@ -0,0 +1,13 @@
+-- ===========================================
+-- Search events by substring, for SQLite builds without FTS5
+-- ?1 = calendar id (NULL for every calendar), ?2 = LIKE pattern escaped with '\', ?3 = limit
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day
+FROM events
+WHERE (title LIKE ?2 ESCAPE '\' OR description LIKE ?2 ESCAPE '\')
+  AND (?1 IS NULL OR calendar_id = ?1)
+  AND deleted_at IS NULL
+ORDER BY start_time, id
+LIMIT ?3;
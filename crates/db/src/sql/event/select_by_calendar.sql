@@ -0,0 +1,10 @@
+-- ===========================================
+-- Select every event in a calendar, ordered by start time
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day
+FROM events
+WHERE calendar_id = ?1
+  AND deleted_at IS NULL
+ORDER BY start_time, id;
@@ -0,0 +1,9 @@
+-- ===========================================
+-- Select an event by id (soft-deleted events are skipped)
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day
+FROM events
+WHERE id = ?1
+  AND deleted_at IS NULL;
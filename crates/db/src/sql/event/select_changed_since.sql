@@ -0,0 +1,14 @@
+-- ===========================================
+-- Select events in a calendar created, updated or soft-deleted after one point in time and
+-- before another
+-- ?2 = since, ?3 = until (RFC3339 strings, which compare chronologically), both exclusive
+-- Soft-deleted rows are included as tombstones, oldest change first
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day, deleted_at
+FROM events
+WHERE calendar_id = ?1
+  AND COALESCE(deleted_at, updated_at) > ?2
+  AND COALESCE(deleted_at, updated_at) < ?3
+ORDER BY COALESCE(deleted_at, updated_at), id;
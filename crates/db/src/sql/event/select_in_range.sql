@@ -0,0 +1,15 @@
+-- ===========================================
+-- Select events in a calendar overlapping a time range
+-- ?2 = range start, ?3 = range end (RFC3339 strings, which compare chronologically)
+-- All-day events span whole days in their own time zone, from midnight of their start date to the
+-- end of their end date (day_start and day_end)
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day
+FROM events
+WHERE calendar_id = ?1
+  AND deleted_at IS NULL
+  AND CASE WHEN all_day THEN day_start ELSE start_time END < ?3
+  AND CASE WHEN all_day THEN day_end ELSE end_time END > ?2
+ORDER BY start_time, id;
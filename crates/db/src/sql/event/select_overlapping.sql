@@ -0,0 +1,17 @@
+-- ===========================================
+-- Select events in a calendar whose time span intersects a range, e.g. to warn about double-booking
+-- ?2 = range start, ?3 = range end (RFC3339 strings), ?4 = event id to leave out (NULL for none)
+-- Events that only touch the range (ending exactly at its start or starting at its end) don't count
+-- All-day events span whole days in their own time zone, from midnight of their start date to the
+-- end of their end date (day_start and day_end)
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time, created_at, updated_at,
+       COALESCE(timezone, 'UTC'), all_day
+FROM events
+WHERE calendar_id = ?1
+  AND deleted_at IS NULL
+  AND CASE WHEN all_day THEN day_start ELSE start_time END < ?3
+  AND CASE WHEN all_day THEN day_end ELSE end_time END > ?2
+  AND (?4 IS NULL OR id != ?4)
+ORDER BY start_time, id;
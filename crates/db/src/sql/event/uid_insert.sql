@@ -0,0 +1,7 @@
+-- ===========================================
+-- Remember the UID an event was imported with
+-- Replaces the mapping left behind by a soft-deleted event with the same UID
+-- ===========================================
+
+INSERT OR REPLACE INTO event_uids (calendar_id, uid, event_id)
+VALUES (?1, ?2, ?3);
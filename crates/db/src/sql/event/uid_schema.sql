@@ -0,0 +1,13 @@
+-- ===========================================
+-- iCalendar UIDs of imported events, so importing the same file again doesn't duplicate them
+-- Deleting the event forgets its UID (soft-deleted events are ignored when looking one up)
+-- ===========================================
+
+CREATE TABLE IF NOT EXISTS event_uids (
+    calendar_id INTEGER NOT NULL,
+    uid TEXT NOT NULL,
+    event_id INTEGER NOT NULL,
+    PRIMARY KEY (calendar_id, uid),
+    FOREIGN KEY (calendar_id) REFERENCES calendars(id) ON DELETE CASCADE,
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- ===========================================
+-- Find the event imported with a UID into a calendar
+-- Soft-deleted events don't count, importing them again makes a new event
+-- ===========================================
+
+SELECT event_uids.event_id
+FROM event_uids
+JOIN events ON events.id = event_uids.event_id
+WHERE event_uids.calendar_id = ?1
+  AND event_uids.uid = ?2
+  AND events.deleted_at IS NULL;
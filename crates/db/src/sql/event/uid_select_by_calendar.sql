@@ -0,0 +1,7 @@
+-- ===========================================
+-- UIDs of the events imported into a calendar, so exports keep them
+-- ===========================================
+
+SELECT event_id, uid
+FROM event_uids
+WHERE calendar_id = ?1;
@@ -0,0 +1,13 @@
+-- ===========================================
+-- Update an event's details by id
+-- Times are RFC3339 strings
+-- ===========================================
+
+UPDATE events
+SET title = ?2,
+    description = ?3,
+    start_time = ?4,
+    end_time = ?5,
+    updated_at = ?6
+WHERE id = ?1
+  AND deleted_at IS NULL;
@@ -0,0 +1,9 @@
+-- ===========================================
+-- Mark an event as all-day (or timed again) by id
+-- ===========================================
+
+UPDATE events
+SET all_day = ?2,
+    updated_at = ?3
+WHERE id = ?1
+  AND deleted_at IS NULL;
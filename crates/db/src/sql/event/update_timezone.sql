@@ -0,0 +1,9 @@
+-- ===========================================
+-- Change the IANA time zone of an event by id
+-- ===========================================
+
+UPDATE events
+SET timezone = ?2,
+    updated_at = ?3
+WHERE id = ?1
+  AND deleted_at IS NULL;
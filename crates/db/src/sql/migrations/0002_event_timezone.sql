@@ -0,0 +1,5 @@
+-- ===========================================
+-- Migration 2: store the time zone an event was created in
+-- ===========================================
+
+ALTER TABLE events ADD COLUMN timezone TEXT;
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Migration 3: soft-delete events, deleted_at is set instead of removing the row
+-- ===========================================
+
+ALTER TABLE events ADD COLUMN deleted_at TEXT; -- ISO 8601 string, NULL while not deleted
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Migration 4: store the time zone a recurring event repeats in
+-- ===========================================
+
+ALTER TABLE recurring_events ADD COLUMN timezone TEXT;
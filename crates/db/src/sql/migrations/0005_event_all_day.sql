@@ -0,0 +1,5 @@
+-- ===========================================
+-- Migration 5: all-day events, whose start and end only carry a date
+-- ===========================================
+
+ALTER TABLE events ADD COLUMN all_day BOOLEAN NOT NULL DEFAULT 0;
@@ -0,0 +1,6 @@
+-- ===========================================
+-- Migration 6: track whether a user proved they own their email address
+-- Existing accounts start unverified, they never proved it either
+-- ===========================================
+
+ALTER TABLE authentication ADD COLUMN email_verified BOOLEAN NOT NULL DEFAULT 0;
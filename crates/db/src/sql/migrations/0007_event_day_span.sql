@@ -0,0 +1,8 @@
+-- ===========================================
+-- Migration 7: the whole days an event covers when it's all-day, as the instants of midnight
+-- starting its first day and midnight after its last day in the event's own time zone
+-- Existing events get theirs when the migrations have run, it takes the time zone database
+-- ===========================================
+
+ALTER TABLE events ADD COLUMN day_start TEXT;   -- ISO 8601 string
+ALTER TABLE events ADD COLUMN day_end TEXT;     -- ISO 8601 string
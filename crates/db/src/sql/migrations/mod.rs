@@ -0,0 +1,6 @@
+pub const EVENT_TIMEZONE: &str = include_str!("0002_event_timezone.sql");
+pub const EVENT_SOFT_DELETE: &str = include_str!("0003_event_soft_delete.sql");
+pub const RECURRING_EVENT_TIMEZONE: &str = include_str!("0004_recurring_event_timezone.sql");
+pub const EVENT_ALL_DAY: &str = include_str!("0005_event_all_day.sql");
+pub const EMAIL_VERIFIED: &str = include_str!("0006_email_verified.sql");
+pub const EVENT_DAY_SPAN: &str = include_str!("0007_event_day_span.sql");
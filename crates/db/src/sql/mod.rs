@@ -8,14 +8,32 @@ pub const AUTH_SCHEMA: &str = include_str!("authentication_schema.sql");
 pub const AUTH_INSERT: &str = include_str!("authentication_insert.sql");
 pub const AUTH_UPDATE_PASSWORD: &str = include_str!("authentication_update_password.sql");
 pub const AUTH_UPDATE_EMAIL: &str = include_str!("authentication_update_email.sql");
+pub const AUTH_VERIFY_EMAIL: &str = include_str!("authentication_verify_email.sql");
 pub const AUTH_SELECT_BY_USERNAME: &str = include_str!("authentication_select_by_username.sql");
+pub const AUTH_SELECT_BY_ID: &str = include_str!("authentication_select_by_id.sql");
+pub const AUTH_SELECT_BY_EMAIL: &str = include_str!("authentication_select_by_email.sql");
 pub const AUTH_DELETE_BY_USERNAME: &str = include_str!("authentication_delete_by_username.sql");
+pub const AUTH_DELETE_BY_ID: &str = include_str!("authentication_delete_by_id.sql");
+pub const AUTH_LIST_PAGE: &str = include_str!("authentication_list_page.sql");
+pub const AUTH_COUNT: &str = include_str!("authentication_count.sql");
 pub const AUTH_SELECT_SALT_BY_USERNAME: &str =
     include_str!("authentication_select_salt_by_username.sql");
 
 pub mod calendar;
 pub mod event;
+pub mod migrations;
 pub mod permissions;
 pub mod recurring_event;
+pub mod reminder;
+pub mod session;
 
 pub const USER_GLOBAL_PERMISSIONS_SCHEMA: &str = include_str!("user_global_permissions.sql");
+pub const USER_GLOBAL_PERMISSIONS_DELETE: &str = include_str!("user_global_permissions_delete.sql");
+pub const USER_GLOBAL_PERMISSIONS_SELECT: &str = include_str!("user_global_permissions_select.sql");
+pub const USER_GLOBAL_PERMISSIONS_UPSERT: &str = include_str!("user_global_permissions_upsert.sql");
+pub const USER_GLOBAL_PERMISSIONS_LIST_ADMINS: &str =
+    include_str!("user_global_permissions_list_admins.sql");
+pub const SCHEMA_VERSION_SCHEMA: &str = include_str!("schema_version_schema.sql");
+pub const SCHEMA_VERSION_INIT: &str = include_str!("schema_version_init.sql");
+pub const SCHEMA_VERSION_SELECT: &str = include_str!("schema_version_select.sql");
+pub const SCHEMA_VERSION_UPDATE: &str = include_str!("schema_version_update.sql");
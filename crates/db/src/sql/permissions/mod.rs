@@ -6,3 +6,13 @@ pub const PERMISSIONS_INSERT: &str = include_str!("permissions_insert.sql");
 pub const PERMISSIONS_REMOVE: &str = include_str!("permissions_remove.sql");
 pub const PERMISSIONS_CHECK: &str = include_str!("permissions_check.sql");
 pub const PERMISSIONS_LIST: &str = include_str!("permissions_list.sql");
+pub const PERMISSIONS_REMOVE_ALL_FOR_USER: &str =
+    include_str!("permissions_remove_all_for_user.sql");
+pub const PERMISSIONS_TRANSFER: &str = include_str!("permissions_transfer.sql");
+pub const ROLES_SCHEMA: &str = include_str!("roles_schema.sql");
+pub const ROLES_INSERT: &str = include_str!("roles_insert.sql");
+pub const ROLES_CLEAR_PERMISSIONS: &str = include_str!("roles_clear_permissions.sql");
+pub const ROLES_PERMISSION_INSERT: &str = include_str!("roles_permission_insert.sql");
+pub const ROLES_ASSIGN: &str = include_str!("roles_assign.sql");
+pub const ROLES_REVOKE: &str = include_str!("roles_revoke.sql");
+pub const ROLES_REMOVE_ALL_FOR_USER: &str = include_str!("roles_remove_all_for_user.sql");
@@ -1,9 +1,23 @@
 -- permissions_check.sql
--- Checks if a user has a specific permission.
+-- Checks if a user has a specific permission, directly or through one of their roles.
+-- A held permission ending in `.*` also grants every permission under that prefix
+-- (`calendar.*` grants `calendar.events.create`, but not `calendar` itself).
 -- Returns 1 row if the user has the permission, 0 rows otherwise.
 
+WITH held(permission) AS (
+    SELECT permission
+    FROM user_permissions
+    WHERE user_id = ?1
+    UNION ALL
+    SELECT role_permissions.permission
+    FROM user_roles
+    JOIN role_permissions ON role_permissions.role = user_roles.role
+    WHERE user_roles.user_id = ?1
+)
 SELECT 1
-FROM user_permissions
-WHERE user_id = ?1
-  AND permission = ?2
+FROM held
+WHERE permission = ?2
+   OR (substr(permission, -2) = '.*'
+       AND length(?2) > length(permission) - 1
+       AND substr(?2, 1, length(permission) - 1) = substr(permission, 1, length(permission) - 1))
 LIMIT 1;
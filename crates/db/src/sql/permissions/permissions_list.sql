@@ -1,4 +1,9 @@
--- List all permissions for a given user ID
+-- List all permissions for a given user ID, their own and those of their roles
 SELECT permission
 FROM user_permissions
-WHERE user_id = ?;
+WHERE user_id = ?1
+UNION
+SELECT role_permissions.permission
+FROM user_roles
+JOIN role_permissions ON role_permissions.role = user_roles.role
+WHERE user_roles.user_id = ?1;
@@ -0,0 +1,3 @@
+-- Remove every global permission of a user.
+DELETE FROM user_permissions
+WHERE user_id = ?1;
@@ -0,0 +1,6 @@
+-- Copy every permission of user ?1 to user ?2.
+-- Permissions the target already has are left as they are.
+INSERT OR IGNORE INTO user_permissions (user_id, permission)
+SELECT ?2, permission
+FROM user_permissions
+WHERE user_id = ?1;
@@ -0,0 +1,4 @@
+-- Assign a role to a user.
+-- If the user already has this role, do nothing.
+INSERT OR IGNORE INTO user_roles (user_id, role)
+VALUES (?1, ?2);
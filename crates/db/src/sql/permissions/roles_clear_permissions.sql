@@ -0,0 +1,3 @@
+-- Remove every permission of a role, before redefining it.
+DELETE FROM role_permissions
+WHERE role = ?1;
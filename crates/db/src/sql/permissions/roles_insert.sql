@@ -0,0 +1,4 @@
+-- Create a role by name.
+-- If the role already exists, do nothing.
+INSERT OR IGNORE INTO roles (name)
+VALUES (?1);
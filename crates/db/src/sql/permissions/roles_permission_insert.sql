@@ -0,0 +1,3 @@
+-- Add a permission to a role.
+INSERT OR IGNORE INTO role_permissions (role, permission)
+VALUES (?1, ?2);
@@ -0,0 +1,3 @@
+-- Take every role away from a user.
+DELETE FROM user_roles
+WHERE user_id = ?1;
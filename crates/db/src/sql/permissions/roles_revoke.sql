@@ -0,0 +1,3 @@
+-- Take a role away from a user.
+DELETE FROM user_roles
+WHERE user_id = ?1 AND role = ?2;
@@ -0,0 +1,22 @@
+-- Schema for named roles, which bundle permissions
+-- A user holds every permission of every role assigned to them, on top of their own
+
+CREATE TABLE IF NOT EXISTS roles (
+    name            TEXT PRIMARY KEY
+);
+
+CREATE TABLE IF NOT EXISTS role_permissions (
+    role            TEXT NOT NULL,
+    permission      TEXT NOT NULL,
+    PRIMARY KEY (role, permission),
+    FOREIGN KEY (role) REFERENCES roles(name) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS user_roles (
+    user_id         INTEGER NOT NULL,
+    role            TEXT NOT NULL,
+    granted_at      TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (user_id, role),
+    FOREIGN KEY (user_id) REFERENCES authentication(id) ON DELETE CASCADE,
+    FOREIGN KEY (role) REFERENCES roles(name) ON DELETE CASCADE
+);
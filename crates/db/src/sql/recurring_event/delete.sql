@@ -0,0 +1,6 @@
+-- ===========================================
+-- Delete a recurring event by id
+-- ===========================================
+
+DELETE FROM recurring_events
+WHERE id = ?1;
@@ -0,0 +1,12 @@
+-- ===========================================
+-- Insert a new recurring event
+-- Times are RFC3339 strings, recurrence_duration is a humantime string or NULL,
+-- timezone is the IANA time zone occurrences repeat in
+-- ===========================================
+
+INSERT INTO recurring_events (
+    calendar_id, title, description, start_time, end_time,
+    recurrence_type, recurrence_interval, recurrence_count, recurrence_duration,
+    created_at, updated_at, timezone
+)
+VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?10, ?11);
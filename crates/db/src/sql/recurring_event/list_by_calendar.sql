@@ -0,0 +1,10 @@
+-- ===========================================
+-- List all recurring events in a calendar
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time,
+       recurrence_type, recurrence_interval, recurrence_count, recurrence_duration,
+       created_at, updated_at, COALESCE(timezone, 'UTC')
+FROM recurring_events
+WHERE calendar_id = ?1
+ORDER BY start_time, id;
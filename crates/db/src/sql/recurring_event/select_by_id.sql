@@ -0,0 +1,9 @@
+-- ===========================================
+-- Select a recurring event by id
+-- ===========================================
+
+SELECT id, calendar_id, title, description, start_time, end_time,
+       recurrence_type, recurrence_interval, recurrence_count, recurrence_duration,
+       created_at, updated_at, COALESCE(timezone, 'UTC')
+FROM recurring_events
+WHERE id = ?1;
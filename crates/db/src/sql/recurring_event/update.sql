@@ -0,0 +1,16 @@
+-- ===========================================
+-- Update a recurring event's details by id
+-- ===========================================
+
+UPDATE recurring_events
+SET title = ?2,
+    description = ?3,
+    start_time = ?4,
+    end_time = ?5,
+    recurrence_type = ?6,
+    recurrence_interval = ?7,
+    recurrence_count = ?8,
+    recurrence_duration = ?9,
+    updated_at = ?10,
+    timezone = ?11
+WHERE id = ?1;
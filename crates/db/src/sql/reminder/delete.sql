@@ -0,0 +1,6 @@
+-- ===========================================
+-- Delete a reminder by id
+-- ===========================================
+
+DELETE FROM reminders
+WHERE id = ?1;
@@ -0,0 +1,8 @@
+-- ===========================================
+-- List an event's reminders, earliest first
+-- ===========================================
+
+SELECT id, event_id, offset_seconds, method, created_at
+FROM reminders
+WHERE event_id = ?1
+ORDER BY offset_seconds DESC, id;
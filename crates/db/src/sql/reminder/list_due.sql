@@ -0,0 +1,21 @@
+-- ===========================================
+-- List the reminders falling due in [?1, ?2), soonest first, with when each is due
+-- A reminder is due offset_seconds before its event starts; all-day events start at midnight of
+-- their first day in the event's time zone (day_start)
+-- ===========================================
+
+SELECT id, event_id, offset_seconds, method, created_at, due_at
+FROM (
+    SELECT r.id, r.event_id, r.offset_seconds, r.method, r.created_at,
+           strftime(
+               '%Y-%m-%dT%H:%M:%fZ',
+               CASE WHEN e.all_day THEN e.day_start ELSE e.start_time END,
+               printf('%+d seconds', -r.offset_seconds)
+           ) AS due_at
+    FROM reminders r
+    JOIN events e ON e.id = r.event_id
+    WHERE e.deleted_at IS NULL
+)
+WHERE due_at >= ?1
+  AND due_at < ?2
+ORDER BY due_at, id;
@@ -0,0 +1,9 @@
+//! SQL constants for event reminder queries and schema.
+//! These are embedded at compile time using `include_str!` for easy editing and single binary output.
+
+pub const SCHEMA: &str = include_str!("schema.sql");
+pub const UPSERT: &str = include_str!("upsert.sql");
+pub const SELECT_BY_ID: &str = include_str!("select_by_id.sql");
+pub const LIST_BY_EVENT: &str = include_str!("list_by_event.sql");
+pub const LIST_DUE: &str = include_str!("list_due.sql");
+pub const DELETE: &str = include_str!("delete.sql");
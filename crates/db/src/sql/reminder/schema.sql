@@ -0,0 +1,9 @@
+CREATE TABLE IF NOT EXISTS reminders (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    event_id INTEGER NOT NULL,
+    offset_seconds INTEGER NOT NULL,  -- how long before the event starts to remind
+    method TEXT NOT NULL,             -- how to deliver it, e.g. 'notification'
+    created_at TEXT NOT NULL,         -- ISO 8601 string
+    UNIQUE (event_id, offset_seconds, method),
+    FOREIGN KEY (event_id) REFERENCES events(id) ON DELETE CASCADE
+);
@@ -0,0 +1,7 @@
+-- ===========================================
+-- Select a reminder by id
+-- ===========================================
+
+SELECT id, event_id, offset_seconds, method, created_at
+FROM reminders
+WHERE id = ?1;
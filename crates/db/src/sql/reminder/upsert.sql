@@ -0,0 +1,9 @@
+-- ===========================================
+-- Add a reminder to an event, returning its id
+-- Setting the same reminder again keeps the existing row
+-- ===========================================
+
+INSERT INTO reminders (event_id, offset_seconds, method, created_at)
+VALUES (?1, ?2, ?3, ?4)
+ON CONFLICT (event_id, offset_seconds, method) DO UPDATE SET method = excluded.method
+RETURNING id;
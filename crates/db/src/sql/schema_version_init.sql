@@ -0,0 +1,6 @@
+-- ===========================================
+-- Record the schema version of a new database (existing ones keep theirs)
+-- ===========================================
+
+INSERT OR IGNORE INTO schema_version (id, version)
+VALUES (1, ?1);
@@ -0,0 +1,8 @@
+-- ===========================================
+-- Version of the schema the database was created with
+-- ===========================================
+
+CREATE TABLE IF NOT EXISTS schema_version (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL
+);
@@ -0,0 +1,7 @@
+-- ===========================================
+-- Get the schema version of the database
+-- ===========================================
+
+SELECT version
+FROM schema_version
+WHERE id = 1;
@@ -0,0 +1,7 @@
+-- ===========================================
+-- Record that the database was migrated to a new schema version
+-- ===========================================
+
+UPDATE schema_version
+SET version = ?1
+WHERE id = 1;
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Forget used reset tokens that expired by ?1, they're rejected anyway
+-- ===========================================
+
+DELETE FROM consumed_password_resets WHERE expires_at <= ?1;
@@ -0,0 +1,6 @@
+-- ===========================================
+-- Mark a reset token as used, ignored if it already was
+-- ===========================================
+
+INSERT OR IGNORE INTO consumed_password_resets (jti, expires_at)
+VALUES (?1, ?2);
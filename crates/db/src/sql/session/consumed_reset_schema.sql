@@ -0,0 +1,9 @@
+-- ===========================================
+-- Password reset tokens that have been used, so each one sets a password only once
+-- Kept in the database so a restart doesn't make used tokens valid again
+-- ===========================================
+
+CREATE TABLE IF NOT EXISTS consumed_password_resets (
+    jti TEXT PRIMARY KEY,        -- the reset token's unique id (jti claim)
+    expires_at TEXT NOT NULL     -- ISO 8601 string, the row can go once the token has expired
+);
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Forget a session by its token id
+-- ===========================================
+
+DELETE FROM sessions WHERE id = ?1;
@@ -0,0 +1,5 @@
+-- ===========================================
+-- Forget every session of a user
+-- ===========================================
+
+DELETE FROM sessions WHERE username = ?1;
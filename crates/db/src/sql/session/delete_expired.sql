@@ -0,0 +1,5 @@
+-- ===========================================
+-- Forget a user's sessions that expired by ?2, their tokens are rejected anyway
+-- ===========================================
+
+DELETE FROM sessions WHERE username = ?1 AND expires_at <= ?2;
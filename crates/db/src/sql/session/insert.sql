@@ -0,0 +1,6 @@
+-- ===========================================
+-- Record a newly issued token
+-- ===========================================
+
+INSERT INTO sessions (id, username, token_type, issued_at, expires_at)
+VALUES (?1, ?2, ?3, ?4, ?5);
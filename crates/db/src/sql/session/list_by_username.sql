@@ -0,0 +1,9 @@
+-- ===========================================
+-- List a user's sessions that haven't expired at ?2, newest first
+-- ===========================================
+
+SELECT id, username, token_type, issued_at, expires_at
+FROM sessions
+WHERE username = ?1
+  AND expires_at > ?2
+ORDER BY issued_at DESC, id;
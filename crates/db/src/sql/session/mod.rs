@@ -0,0 +1,13 @@
+//! SQL constants for login session queries and schema.
+//! These are embedded at compile time using `include_str!` for easy editing and single binary output.
+
+pub const SCHEMA: &str = include_str!("schema.sql");
+pub const INSERT: &str = include_str!("insert.sql");
+pub const SELECT_BY_ID: &str = include_str!("select_by_id.sql");
+pub const LIST_BY_USERNAME: &str = include_str!("list_by_username.sql");
+pub const DELETE: &str = include_str!("delete.sql");
+pub const DELETE_BY_USERNAME: &str = include_str!("delete_by_username.sql");
+pub const DELETE_EXPIRED: &str = include_str!("delete_expired.sql");
+pub const CONSUMED_RESET_SCHEMA: &str = include_str!("consumed_reset_schema.sql");
+pub const CONSUMED_RESET_INSERT: &str = include_str!("consumed_reset_insert.sql");
+pub const CONSUMED_RESET_DELETE_EXPIRED: &str = include_str!("consumed_reset_delete_expired.sql");
@@ -0,0 +1,14 @@
+-- ===========================================
+-- Login sessions, one row per access or refresh token still in use
+-- Rows go away when the token is revoked, so listing a user's rows lists what can still be revoked
+-- ===========================================
+
+CREATE TABLE IF NOT EXISTS sessions (
+    id TEXT PRIMARY KEY,         -- the token's unique id (jti claim)
+    username TEXT NOT NULL,      -- the token's subject
+    token_type TEXT NOT NULL,    -- 'access' or 'refresh'
+    issued_at TEXT NOT NULL,     -- ISO 8601 string
+    expires_at TEXT NOT NULL     -- ISO 8601 string
+);
+
+CREATE INDEX IF NOT EXISTS idx_sessions_username ON sessions(username);
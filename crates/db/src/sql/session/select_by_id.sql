@@ -0,0 +1,7 @@
+-- ===========================================
+-- Select a session by its token id
+-- ===========================================
+
+SELECT id, username, token_type, issued_at, expires_at
+FROM sessions
+WHERE id = ?1;
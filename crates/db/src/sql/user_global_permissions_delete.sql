@@ -0,0 +1,6 @@
+-- ===========================================
+-- Delete a user's global permission flags
+-- ===========================================
+
+DELETE FROM user_global_permissions
+WHERE user_id = ?1;
@@ -0,0 +1,8 @@
+-- ===========================================
+-- List every global admin
+-- ===========================================
+
+SELECT user_id
+FROM user_global_permissions
+WHERE is_global_admin = 1
+ORDER BY user_id;
@@ -0,0 +1,7 @@
+-- ===========================================
+-- Get a user's global permission flags
+-- ===========================================
+
+SELECT user_id, is_global_admin
+FROM user_global_permissions
+WHERE user_id = ?1;
@@ -0,0 +1,8 @@
+-- ===========================================
+-- Set whether a user is a global admin
+-- ===========================================
+
+INSERT INTO user_global_permissions (user_id, is_global_admin)
+VALUES (?1, ?2)
+ON CONFLICT (user_id) DO UPDATE SET
+    is_global_admin = excluded.is_global_admin;
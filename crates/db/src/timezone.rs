@@ -0,0 +1,107 @@
+use crate::{Event, RecurringEvent};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use tracing::*;
+
+/// Time zone of events stored without one (created before zones were recorded).
+pub const DEFAULT_TIMEZONE: &str = "UTC";
+
+/// Parse an IANA time zone name such as `America/New_York`.
+pub fn parse_timezone(name: &str) -> Result<Tz, chrono_tz::ParseError> {
+    name.parse()
+}
+
+/// Check a time zone name before it's stored, so reading it back can't fail.
+pub(crate) fn timezone_to_sql(name: &str) -> Result<&str, rusqlite::Error> {
+    parse_timezone(name)
+        .map(|_| name)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))
+}
+
+/// Parse a stored zone name, falling back to UTC (with a warning) for names this build doesn't know.
+pub(crate) fn stored_timezone(name: &str) -> Tz {
+    parse_timezone(name).unwrap_or_else(|_| {
+        warn!("Unknown time zone {:?} stored on an event, using UTC", name);
+        Tz::UTC
+    })
+}
+
+/// Turn a wall clock time in `tz` into an instant. Times skipped by a DST jump are moved
+/// forward by the size of the jump (02:30 on a spring-forward day becomes 03:30), repeated
+/// times take their first occurrence.
+pub(crate) fn local_to_utc(tz: Tz, local: NaiveDateTime) -> Option<DateTime<Utc>> {
+    (0..=3)
+        .filter_map(|hours| local.checked_add_signed(chrono::Duration::hours(hours)))
+        .find_map(|shifted| tz.from_local_datetime(&shifted).earliest())
+        .map(|dt| dt.with_timezone(&Utc))
+}
+
+/// The whole days in `tz` from `start`'s date through `end`'s date, as the instants of midnight
+/// starting the first day and midnight after the last one. Only the very last representable
+/// days lack a midnight, the times themselves are used then.
+pub(crate) fn day_span(
+    tz: Tz,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> (DateTime<Utc>, DateTime<Utc>) {
+    let midnight = |date: NaiveDate| local_to_utc(tz, date.and_time(NaiveTime::MIN));
+    let first = start.with_timezone(&tz).date_naive();
+    let last = end.with_timezone(&tz).date_naive();
+    (
+        midnight(first).unwrap_or(start),
+        last.succ_opt().and_then(midnight).unwrap_or(end),
+    )
+}
+
+impl Event {
+    /// The event's time zone, UTC if it names one this build doesn't know.
+    pub fn tz(&self) -> Tz {
+        stored_timezone(&self.timezone)
+    }
+
+    /// When the event starts, on the clock of its own time zone.
+    pub fn local_start(&self) -> DateTime<Tz> {
+        self.start_time.with_timezone(&self.tz())
+    }
+
+    /// When the event ends, on the clock of its own time zone.
+    pub fn local_end(&self) -> DateTime<Tz> {
+        self.end_time.with_timezone(&self.tz())
+    }
+
+    /// What the event covers when it's all-day: from midnight of its start date to midnight
+    /// after its end date, both on the clock of its own time zone.
+    pub fn day_span(&self) -> (DateTime<Utc>, DateTime<Utc>) {
+        day_span(self.tz(), self.start_time, self.end_time)
+    }
+}
+
+impl RecurringEvent {
+    /// The time zone occurrences repeat in, UTC if it names one this build doesn't know.
+    pub fn tz(&self) -> Tz {
+        stored_timezone(&self.timezone)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    #[test]
+    fn test_local_to_utc_across_dst() {
+        let tz = parse_timezone("America/New_York").unwrap();
+        let at = |d, h, m| {
+            NaiveDate::from_ymd_opt(2025, 3, d)
+                .unwrap()
+                .and_hms_opt(h, m, 0)
+                .unwrap()
+        };
+        let utc = |d, h, m| Utc.with_ymd_and_hms(2025, 3, d, h, m, 0).unwrap();
+        // EST before the change, EDT after, and the skipped 02:30 moves to 03:30 EDT
+        assert_eq!(local_to_utc(tz, at(8, 9, 0)), Some(utc(8, 14, 0)));
+        assert_eq!(local_to_utc(tz, at(10, 9, 0)), Some(utc(10, 13, 0)));
+        assert_eq!(local_to_utc(tz, at(9, 2, 30)), Some(utc(9, 7, 30)));
+        assert!(parse_timezone("Mars/Olympus_Mons").is_err());
+    }
+}
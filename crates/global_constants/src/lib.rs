@@ -3,18 +3,104 @@
 /// project-wide constants that may be used across multiple subcrates.
 
 /// The default configuration version for the application.
-pub const DEFAULT_CONFIG_VERSION: usize = 1;
+pub const DEFAULT_CONFIG_VERSION: usize = 2;
 
 /// The default JWT expiry time in seconds (e.g., 1 hour).
 pub const DEFAULT_JWT_EXPIRY_SECONDS: usize = 3600;
 
+/// The default refresh token expiry time in seconds (e.g., 30 days).
+pub const DEFAULT_JWT_REFRESH_EXPIRY_SECONDS: usize = 30 * 24 * 3600;
+
+/// How long a password reset token stays valid, in seconds (e.g., 15 minutes).
+pub const DEFAULT_PASSWORD_RESET_EXPIRY_SECONDS: usize = 15 * 60;
+
+/// How long an email verification token stays valid, in seconds (e.g., 2 days).
+pub const DEFAULT_EMAIL_VERIFICATION_EXPIRY_SECONDS: usize = 2 * 24 * 3600;
+
+/// How long a calendar invite can be accepted for, in seconds (e.g., 7 days).
+pub const DEFAULT_CALENDAR_INVITE_EXPIRY_SECONDS: usize = 7 * 24 * 3600;
+
+/// How long before a websocket connection's token expires the client is warned to refresh it.
+pub const DEFAULT_AUTH_EXPIRY_WARNING_SECONDS: u64 = 300;
+
+/// How often the server pings each websocket connection, in seconds.
+pub const DEFAULT_WS_PING_INTERVAL_SECONDS: u64 = 30;
+
+/// How often the reminder task looks for reminders falling due, in seconds.
+pub const REMINDER_TICK_SECONDS: u64 = 30;
+
+/// How long a websocket connection can go without answering before it's closed, in seconds.
+pub const DEFAULT_WS_IDLE_TIMEOUT_SECONDS: u64 = 90;
+
+/// How long a websocket client that has to log in gets to send its token before it's closed, in seconds.
+pub const WS_AUTH_GRACE_SECONDS: u64 = 10;
+
+/// How often an authenticated websocket connection checks whether its token has expired, in seconds.
+pub const WS_AUTH_EXPIRY_CHECK_SECONDS: u64 = 1;
+
+/// The default maximum number of simultaneous websocket connections.
+pub const DEFAULT_WS_MAX_CONNECTIONS: usize = 1024;
+
+/// How many messages per second one websocket connection may send, on average.
+pub const DEFAULT_WS_MESSAGES_PER_SECOND: u32 = 20;
+
+/// How many messages one websocket connection may send in a burst above its per-second rate.
+pub const DEFAULT_WS_MESSAGE_BURST: u32 = 40;
+
+/// How many global broadcast messages are buffered for slow websocket clients.
+pub const DEFAULT_BROADCAST_CAPACITY: usize = 1024;
+
+/// Binary websocket messages at least this many bytes are sent compressed.
+pub const DEFAULT_WS_COMPRESSION_THRESHOLD: usize = 8 * 1024;
+
+/// The most a compressed websocket message may inflate to (16 MiB), so a tiny frame can't
+/// balloon into an enormous allocation.
+pub const MAX_DECOMPRESSED_FRAME_BYTES: u64 = 16 * 1024 * 1024;
+
+/// How many recent event changes are kept for websocket clients resuming after a reconnect.
+pub const DEFAULT_WS_CHANGE_LOG_CAPACITY: usize = 1024;
+
+/// The default maximum number of pooled database connections.
+pub const DEFAULT_DATABASE_POOL_SIZE: u32 = 8;
+
+/// How long the readiness probe waits for a database connection before reporting not ready, in milliseconds.
+pub const READINESS_DB_TIMEOUT_MILLIS: u64 = 2000;
+
 /// The default rate limit for authentication requests (requests per minute).
 pub const DEFAULT_AUTH_RATE_LIMIT_PER_MINUTE: u32 = 5;
 
+/// How many HTTP requests per second one IP may make on average.
+pub const DEFAULT_HTTP_REQUESTS_PER_SECOND: u32 = 10;
+
+/// How many HTTP requests one IP may make in a burst above its per-second rate, enough for
+/// a page load with all its assets.
+pub const DEFAULT_HTTP_REQUEST_BURST: u32 = 60;
+
+/// How many IPs the HTTP rate limiter tracks before forgetting the idle ones.
+pub const HTTP_RATE_LIMIT_MAX_TRACKED_IPS: usize = 10_000;
+
+/// The default number of accounts that can be registered from one IP per registration window.
+pub const DEFAULT_REGISTRATION_LIMIT_PER_IP: u32 = 10;
+
+/// The default registration rate limit window, in seconds (e.g., 1 hour).
+pub const DEFAULT_REGISTRATION_WINDOW_SECONDS: u64 = 3600;
+
+/// The default number of consecutive failed logins before an account is locked.
+pub const DEFAULT_AUTH_LOCKOUT_THRESHOLD: u32 = 5;
+
+/// The default time an account stays locked after too many failed logins, in seconds (e.g., 15 minutes).
+pub const DEFAULT_AUTH_LOCKOUT_SECONDS: u64 = 15 * 60;
+
+/// The default maximum username length, in characters.
+pub const DEFAULT_USERNAME_MAX_LENGTH: usize = 64;
+
 /// The name of the application, for use in logs, configs, etc.
 pub const APP_NAME: &str = "FamilyCalendarRS";
 
 /// The default path for logs.
 pub const LOGS_PATH: &str = "./logs";
 
+/// A log file rolls over to a new numbered file once it reaches this size (10 MiB).
+pub const LOG_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
 pub const HTML_SRC_FOLDER: &str = "./html_src/";
@@ -1,75 +1,236 @@
 use chrono::{Local, LocalResult, NaiveDate, NaiveTime, TimeZone};
 use once_cell::sync::OnceCell;
 
+use config::{LogConfig, LogFormat};
 /// Macro for logging fatal errors (crash-level), matches tracing's error! macro flexibility.
 /// Usage: fatal!("message {}", arg); fatal!(target: "mycrate", "message {}", arg);
-use global_constants::LOGS_PATH;
+use global_constants::{LOG_MAX_BYTES, LOGS_PATH};
 use regex::Regex;
 use std::{
     fs::{self, OpenOptions},
     io::{self, Write},
-    marker::Send,
     path::{Path, PathBuf},
+    sync::{Arc, Mutex, mpsc},
 };
 use tracing_subscriber::{
-    EnvFilter,
+    EnvFilter, Layer,
+    filter::LevelFilter,
     fmt::{format::Writer, writer::MakeWriter},
+    layer::SubscriberExt,
+    registry::LookupSpan,
+    util::SubscriberInitExt,
 };
 
 /// MultiWriter writes logs to both stdout and a file, stripping ANSI codes for the file.
+/// Stdout is written on the logging thread, the file by a background thread so a slow disk
+/// never stalls a log call.
+/// Once the file reaches `max_bytes` it rolls over to `<name>.1.log`, `<name>.2.log`, and so on.
 pub struct MultiWriter {
     pub log_path: PathBuf,
+    file_log: Arc<FileLog>,
+    stdout: bool,
 }
 
-impl<'a> MakeWriter<'a> for MultiWriter {
-    type Writer = MultiWriterHandle;
+/// Shared by a MultiWriter and its background thread, so the current file can be looked up.
+struct Rotation {
+    base_path: PathBuf,
+    max_bytes: Option<u64>,
+    /// How many times the log has rolled over, 0 is the original file
+    index: Mutex<usize>,
+}
 
-    fn make_writer(&'a self) -> Self::Writer {
-        let file = match OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.log_path)
-        {
-            Ok(f) => Some(f),
-            Err(e) => {
-                eprintln!(
-                    "Failed to create or open log file {:?}: {}",
-                    self.log_path, e
-                );
-                None
-            }
-        };
-        MultiWriterHandle { file }
+impl Rotation {
+    fn current_path(&self) -> PathBuf {
+        numbered_log_path(&self.base_path, *self.index.lock().unwrap())
     }
 }
 
-pub struct MultiWriterHandle {
-    file: Option<std::fs::File>,
+/// `logs/app.log` with index 2 becomes `logs/app.2.log`, index 0 is the path itself.
+fn numbered_log_path(base: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return base.to_path_buf();
+    }
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    match base.extension() {
+        Some(ext) => base.with_file_name(format!("{stem}.{index}.{}", ext.to_string_lossy())),
+        None => base.with_file_name(format!("{stem}.{index}")),
+    }
 }
 
-// SAFETY: MultiWriterHandle only contains a File, which is Send + 'static.
-unsafe impl Send for MultiWriterHandle {}
-unsafe impl Sync for MultiWriterHandle {}
+fn open_log_file(path: &Path) -> Option<std::fs::File> {
+    match OpenOptions::new().create(true).append(true).open(path) {
+        Ok(f) => Some(f),
+        Err(e) => {
+            eprintln!("Failed to create or open log file {:?}: {}", path, e);
+            None
+        }
+    }
+}
 
-impl Write for MultiWriterHandle {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        // Write original buffer to stdout
-        if let Err(e) = io::stdout().write_all(buf) {
-            eprintln!("Error writing to stdout: {}", e);
-            return Err(e);
+enum LogCommand {
+    /// Raw log output, ANSI codes still in it
+    Write(Vec<u8>),
+    /// Reply once everything sent before this is written
+    Flush(mpsc::Sender<()>),
+}
+
+/// The sending side of the background file writer.
+struct FileLog {
+    commands: mpsc::Sender<LogCommand>,
+    rotation: Arc<Rotation>,
+}
+
+impl FileLog {
+    /// Start the background thread writing to `rotation`'s files.
+    fn spawn(rotation: Arc<Rotation>) -> Self {
+        let (commands, receiver) = mpsc::channel();
+        let mut sink = FileSink {
+            path: rotation.current_path(),
+            file: None,
+            rotation: rotation.clone(),
+        };
+        sink.file = open_log_file(&sink.path);
+        let spawned = std::thread::Builder::new()
+            .name("log-writer".to_string())
+            .spawn(move || {
+                // Ends once every MultiWriter and handle is gone
+                for command in receiver {
+                    match command {
+                        LogCommand::Write(buf) => sink.write(&buf),
+                        LogCommand::Flush(done) => {
+                            sink.flush();
+                            let _ = done.send(());
+                        }
+                    }
+                }
+            });
+        if let Err(e) = spawned {
+            eprintln!("Failed to start the log writer thread: {}", e);
+        }
+        Self { commands, rotation }
+    }
+
+    fn send(&self, buf: &[u8]) {
+        // Only fails if the writer thread is gone, stdout still has the line
+        let _ = self.commands.send(LogCommand::Write(buf.to_vec()));
+    }
+
+    /// Wait until everything sent so far has been written to the file.
+    fn flush(&self) {
+        let (done, wait) = mpsc::channel();
+        if self.commands.send(LogCommand::Flush(done)).is_ok() {
+            let _ = wait.recv();
         }
+    }
+}
 
-        // Write ANSI-stripped text to file
+/// The file end, owned by the background thread.
+struct FileSink {
+    file: Option<std::fs::File>,
+    path: PathBuf,
+    rotation: Arc<Rotation>,
+}
+
+impl FileSink {
+    /// Write ANSI-stripped text to file, rolling over afterwards if it's full.
+    fn write(&mut self, buf: &[u8]) {
         if let Some(f) = &mut self.file {
             let s = std::str::from_utf8(buf).unwrap_or("");
             let mut parser = ansi_escapers::interpreter::AnsiParser::new(s);
             let text = parser.parse_annotated().text;
             if let Err(e) = f.write_all(text.as_bytes()) {
                 eprintln!("Error writing to log file: {}", e);
+            }
+        }
+        self.rotate_if_full();
+    }
+
+    fn flush(&mut self) {
+        if let Some(f) = &mut self.file {
+            if let Err(e) = f.flush() {
+                eprintln!("Error flushing log file: {}", e);
+            }
+        }
+    }
+
+    /// Roll over to the next numbered file if ours has grown past the limit.
+    fn rotate_if_full(&mut self) {
+        let (Some(max_bytes), Some(f)) = (self.rotation.max_bytes, &self.file) else {
+            return;
+        };
+        if f.metadata().map(|m| m.len()).unwrap_or(0) < max_bytes {
+            return;
+        }
+        let mut index = self.rotation.index.lock().unwrap();
+        *index += 1;
+        self.path = numbered_log_path(&self.rotation.base_path, *index);
+        drop(index);
+        self.file = open_log_file(&self.path);
+    }
+}
+
+impl MultiWriter {
+    /// Log to `log_path`, rolling over to a new numbered file whenever it reaches `max_bytes`
+    /// (`None` never rolls over).
+    pub fn new(log_path: PathBuf, max_bytes: Option<u64>) -> Self {
+        let rotation = Arc::new(Rotation {
+            base_path: log_path.clone(),
+            max_bytes,
+            index: Mutex::new(0),
+        });
+        Self {
+            file_log: Arc::new(FileLog::spawn(rotation)),
+            log_path,
+            stdout: true,
+        }
+    }
+
+    /// Whether to also echo log output to stdout (on by default).
+    pub fn with_stdout(mut self, enabled: bool) -> Self {
+        self.stdout = enabled;
+        self
+    }
+
+    /// The file currently being written to.
+    pub fn current_log_path(&self) -> PathBuf {
+        self.file_log.rotation.current_path()
+    }
+
+    /// Wait until everything logged so far has reached the file.
+    pub fn flush_file(&self) {
+        self.file_log.flush();
+    }
+}
+
+impl<'a> MakeWriter<'a> for MultiWriter {
+    type Writer = MultiWriterHandle;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        MultiWriterHandle {
+            file_log: self.file_log.clone(),
+            stdout: self.stdout,
+        }
+    }
+}
+
+pub struct MultiWriterHandle {
+    file_log: Arc<FileLog>,
+    stdout: bool,
+}
+
+impl Write for MultiWriterHandle {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Write original buffer to stdout
+        if self.stdout {
+            if let Err(e) = io::stdout().write_all(buf) {
+                eprintln!("Error writing to stdout: {}", e);
                 return Err(e);
             }
         }
 
+        // The file is written in the background
+        self.file_log.send(buf);
+
         Ok(buf.len())
     }
     fn flush(&mut self) -> io::Result<()> {
@@ -77,15 +238,23 @@ impl Write for MultiWriterHandle {
             eprintln!("Error flushing stdout: {}", e);
             return Err(e);
         }
-        if let Some(f) = &mut self.file {
-            if let Err(e) = f.flush() {
-                eprintln!("Error flushing log file: {}", e);
-                return Err(e);
-            }
-        }
+        self.file_log.flush();
         Ok(())
     }
 }
+
+/// The running log file, once `init_logging` has set it up.
+static FILE_LOG: OnceCell<Arc<FileLog>> = OnceCell::new();
+/// The warnings and errors file, if one is configured.
+static ERROR_FILE_LOG: OnceCell<Arc<FileLog>> = OnceCell::new();
+
+/// Wait until every log line so far has been written to the log files.
+/// Call before exiting, lines still queued for the files are lost otherwise.
+pub fn flush_logs() {
+    for file_log in [FILE_LOG.get(), ERROR_FILE_LOG.get()].into_iter().flatten() {
+        file_log.flush();
+    }
+}
 /// Custom timer for 12-hour time format with AM/PM for tracing_subscriber log output.
 struct Custom12HourTimer;
 
@@ -96,16 +265,67 @@ impl tracing_subscriber::fmt::time::FormatTime for Custom12HourTimer {
     }
 }
 
-pub fn init_logging() {
+/// Format records as `format` and write them through `writer`.
+fn format_layer<S, W>(format: LogFormat, writer: W) -> Box<dyn Layer<S> + Send + Sync>
+where
+    S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    match format {
+        LogFormat::Pretty => tracing_subscriber::fmt::layer()
+            .with_writer(writer)
+            .with_timer(Custom12HourTimer)
+            .boxed(),
+        // Aggregators want machine-readable timestamps and no color codes
+        LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_writer(writer)
+            .with_ansi(false)
+            .boxed(),
+    }
+}
+
+/// Build the subscriber for `format`, writing through `writer`, and copying warnings and errors
+/// to `error_writer` when there is one.
+fn build_subscriber<W, E>(
+    format: LogFormat,
+    filter: EnvFilter,
+    writer: W,
+    error_writer: Option<E>,
+) -> Box<dyn tracing::Subscriber + Send + Sync>
+where
+    W: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+    E: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let errors =
+        error_writer.map(|writer| format_layer(format, writer).with_filter(LevelFilter::WARN));
+    Box::new(
+        tracing_subscriber::registry()
+            .with(filter)
+            .with(format_layer(format, writer))
+            .with(errors),
+    )
+}
+
+/// Parse a configured log level (`trace`, `debug`, `info`, `warn`, `error` or `off`).
+fn parse_log_level(level: &str) -> Option<LevelFilter> {
+    level.trim().parse().ok()
+}
+
+/// The filter to log with: `RUST_LOG` when it's set, otherwise the configured level for everything.
+fn build_filter(level: LevelFilter, rust_log: Option<&str>) -> EnvFilter {
     // Set warn for all dependencies by default
     let filter = EnvFilter::builder().with_default_directive(tracing::Level::WARN.into());
+    match rust_log {
+        Some(directives) if !directives.trim().is_empty() => filter.parse_lossy(directives),
+        _ => filter.parse_lossy(level.to_string()),
+    }
+}
 
-    // Allow trace for all crates in debug, info in release (use just "trace" or "info" for global)
-    #[cfg(debug_assertions)]
-    let filter = filter.parse_lossy("trace");
-
-    #[cfg(not(debug_assertions))]
-    let filter = filter.parse_lossy("info");
+pub fn init_logging(config: &LogConfig) {
+    let level = parse_log_level(&config.log_level);
+    let rust_log = std::env::var(EnvFilter::DEFAULT_ENV).ok();
+    let filter = build_filter(level.unwrap_or(LevelFilter::INFO), rust_log.as_deref());
 
     // Use only the subcrate name as the log file name, with .log extension.
     // Get the current date and time at initialization
@@ -113,7 +333,6 @@ pub fn init_logging() {
     let date_str = now.format("%m-%d-%Y").to_string();
     let time_str = now.format("%I-%M-%S_%p").to_string();
 
-    static LOG_FILE_PATH: OnceCell<PathBuf> = OnceCell::new();
     let log_path = {
         let mut path = PathBuf::from(LOGS_PATH);
         // Use CARGO_PKG_NAME for subcrate name, and include date/time for uniqueness
@@ -122,16 +341,15 @@ pub fn init_logging() {
         if let Some(parent) = path.parent() {
             let _ = std::fs::create_dir_all(parent);
         }
-        // Store the log path in the static for panic hook access
-        let _ = LOG_FILE_PATH.set(path.clone());
         path
     };
 
-    // Write the first line: "Logs start on {date} at {time}"
-    if let Ok(mut file) = std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(&log_path)
+    // Write the first line: "Logs start on {date} at {time}" (JSON logs stay one object per line)
+    if config.format == LogFormat::Pretty
+        && let Ok(mut file) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&log_path)
     {
         let _ = writeln!(
             file,
@@ -140,16 +358,31 @@ pub fn init_logging() {
             now.format("%I:%M:%S %p")
         );
     }
-    let writer = MultiWriter { log_path };
+    let writer = MultiWriter::new(log_path, Some(LOG_MAX_BYTES));
+    // Keep the file log reachable for flush_logs and the panic hook
+    let _ = FILE_LOG.set(writer.file_log.clone());
 
-    if let Err(e) = tracing_subscriber::fmt()
-        .with_env_filter(filter)
-        .with_writer(writer)
-        .with_timer(Custom12HourTimer)
-        .try_init()
-    {
+    // Warnings and errors are also copied to their own file, without echoing them twice
+    let error_writer = config.error_file.as_ref().map(|path| {
+        let path = PathBuf::from(path);
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        MultiWriter::new(path, Some(LOG_MAX_BYTES)).with_stdout(false)
+    });
+    if let Some(error_writer) = &error_writer {
+        let _ = ERROR_FILE_LOG.set(error_writer.file_log.clone());
+    }
+
+    if let Err(e) = build_subscriber(config.format, filter, writer, error_writer).try_init() {
         eprintln!("Failed to set tracing subscriber: {}", e);
     }
+    if level.is_none() {
+        tracing::warn!(
+            "Invalid log_level {:?} in config, logging at info instead",
+            config.log_level
+        );
+    }
 
     /// Set a panic hook that logs panics using tracing::error! and [FATAL] prefix, including stacktrace.
     pub fn set_panic_hook() {
@@ -208,12 +441,14 @@ pub fn init_logging() {
                 );
             }
 
-            // Also append the colorless version to the main log file for post-mortem visibility
-            if let Some(log_path) = LOG_FILE_PATH.get() {
+            // Also append the colorless version to the main log file for post-mortem visibility,
+            // after whatever was already queued for it
+            if let Some(file_log) = FILE_LOG.get() {
+                file_log.flush();
                 if let Ok(mut file) = std::fs::OpenOptions::new()
                     .create(true)
                     .append(true)
-                    .open(log_path)
+                    .open(file_log.rotation.current_path())
                 {
                     use std::io::Write;
                     let _ = writeln!(file, "{} FATAL {}: {}", time_str, CRATE_NAME, msg);
@@ -240,10 +475,12 @@ pub fn cleanup_old_logs<P: AsRef<Path>>(logs_dir: P, keep_for: std::time::Durati
     let logs_dir = logs_dir.as_ref();
     let now = Local::now();
 
-    // Regex for schema: {subcrate}_{MM-DD-YYYY}_{HH-MM-SS_AMPM}.log
-    // Example: calendar_server_04-27-2024_09-15-23_PM.log
-    let re = Regex::new(r"^[^_]+_(\d{2})-(\d{2})-(\d{4})_(\d{2})-(\d{2})-(\d{2})_(AM|PM)\.log$")
-        .expect("Failed to compile regex for log file schema");
+    // Regex for schema: {subcrate}_{MM-DD-YYYY}_{HH-MM-SS_AMPM}.log, plus rolled over .{n}.log files
+    // Example: calendar_server_04-27-2024_09-15-23_PM.log, calendar_server_04-27-2024_09-15-23_PM.1.log
+    let re = Regex::new(
+        r"^[^_]+_(\d{2})-(\d{2})-(\d{4})_(\d{2})-(\d{2})-(\d{2})_(AM|PM)(?:\.\d+)?\.log$",
+    )
+    .expect("Failed to compile regex for log file schema");
 
     if let Ok(entries) = fs::read_dir(logs_dir) {
         for entry in entries.flatten() {
@@ -301,3 +538,132 @@ pub fn cleanup_old_logs<P: AsRef<Path>>(logs_dir: P, keep_for: std::time::Durati
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Collects everything written through it, for inspecting formatted output.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_configured_level_sets_filter() {
+        let level = parse_log_level("Debug").unwrap();
+        assert_eq!(build_filter(level, None).to_string(), "debug");
+        assert_eq!(parse_log_level("loud"), None);
+    }
+
+    #[test]
+    fn test_rust_log_wins_over_configured_level() {
+        let filter = build_filter(LevelFilter::DEBUG, Some("webserver=trace"));
+        assert_eq!(filter.to_string(), "webserver=trace");
+        // An empty RUST_LOG is as good as unset
+        assert_eq!(
+            build_filter(LevelFilter::ERROR, Some(" ")).to_string(),
+            "error"
+        );
+    }
+
+    #[test]
+    fn test_json_format_writes_json_lines() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = build_subscriber(
+            LogFormat::Json,
+            EnvFilter::new("info"),
+            move || writer.clone(),
+            None::<fn() -> Captured>,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::warn!(calendar_id = 7, "calendar went missing");
+        });
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line: serde_json::Value = serde_json::from_str(output.lines().next().unwrap()).unwrap();
+        assert_eq!(line["level"], "WARN");
+        assert_eq!(line["fields"]["message"], "calendar went missing");
+        assert_eq!(line["fields"]["calendar_id"], 7);
+        assert!(line["target"].is_string());
+        assert!(line["timestamp"].is_string());
+    }
+
+    #[test]
+    fn test_warnings_and_errors_are_copied_to_error_file() {
+        let main = Captured::default();
+        let errors = Captured::default();
+        let (main_writer, error_writer) = (main.clone(), errors.clone());
+        let subscriber = build_subscriber(
+            LogFormat::Pretty,
+            EnvFilter::new("info"),
+            move || main_writer.clone(),
+            Some(move || error_writer.clone()),
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!("calendar synced");
+            tracing::error!("calendar sync failed");
+        });
+
+        let main = String::from_utf8(main.0.lock().unwrap().clone()).unwrap();
+        let errors = String::from_utf8(errors.0.lock().unwrap().clone()).unwrap();
+        assert!(main.contains("calendar synced"));
+        assert!(main.contains("calendar sync failed"));
+        assert!(errors.contains("calendar sync failed"));
+        assert!(!errors.contains("calendar synced"));
+    }
+
+    #[test]
+    fn test_rolls_over_past_max_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = dir.path().join("app_01-02-2025_03-04-05_PM.log");
+        let writer = MultiWriter::new(base.clone(), Some(64)).with_stdout(false);
+
+        let mut handle = writer.make_writer();
+        handle.write_all(&[b'a'; 40]).unwrap();
+        writer.flush_file();
+        assert_eq!(writer.current_log_path(), base);
+        handle.write_all(&[b'b'; 40]).unwrap();
+        writer.flush_file();
+
+        // The first file went over the limit, later writes land in the next one
+        let rolled = dir.path().join("app_01-02-2025_03-04-05_PM.1.log");
+        assert_eq!(writer.current_log_path(), rolled);
+        writer.make_writer().write_all(b"after").unwrap();
+        writer.flush_file();
+        assert_eq!(fs::metadata(&base).unwrap().len(), 80);
+        assert_eq!(fs::read_to_string(&rolled).unwrap(), "after");
+    }
+
+    #[test]
+    fn test_file_writes_do_not_block_logging() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("app.log");
+        let writer = MultiWriter::new(path.clone(), None).with_stdout(false);
+
+        let lines = 20_000;
+        let started = std::time::Instant::now();
+        for i in 0..lines {
+            writeln!(writer.make_writer(), "\x1b[32mline {i}\x1b[0m").unwrap();
+        }
+        // Queuing is all the caller waits for, however long the disk takes
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+
+        writer.flush_file();
+        let written = fs::read_to_string(&path).unwrap();
+        assert_eq!(written.lines().count(), lines);
+        assert_eq!(
+            written.lines().last().unwrap(),
+            format!("line {}", lines - 1)
+        );
+    }
+}
@@ -1,11 +1,12 @@
 extern crate async_trait;
 use ::async_trait::async_trait;
 use db;
-use std::{
-    collections::{HashMap, HashSet},
-    sync::Arc,
-};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::sync::{Arc, RwLock};
 use tokio::sync::Mutex;
+use tracing::error;
 
 /// Represents a unique user identifier.
 /// In a real system, this could be a UUID, i64, or String.
@@ -13,15 +14,107 @@ pub type UserId = i64;
 
 /// Represents a permission.
 /// You can extend this enum as needed for your application.
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// Serializes to the same plain string that is stored in the database, so `Custom("x")` is just
+/// `"x"`; a custom name matching a built-in one reads back as the built-in permission.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum Permission {
     Read,
     Write,
     Delete,
     Admin,
+    #[serde(untagged)]
     Custom(String), // For extensibility
 }
 
+impl Permission {
+    /// Whether holding this permission grants `requested`. Built-in permissions only grant
+    /// themselves, a custom permission ending in `.*` also grants every custom permission
+    /// under its prefix (`calendar.*` grants `calendar.events.create`).
+    pub fn grants(&self, requested: &Permission) -> bool {
+        if self == requested {
+            return true;
+        }
+        match (self, requested) {
+            (Permission::Custom(held), Permission::Custom(requested)) => held
+                .strip_suffix('*')
+                .filter(|prefix| prefix.ends_with('.'))
+                .is_some_and(|prefix| {
+                    requested.len() > prefix.len() && requested.starts_with(prefix)
+                }),
+            _ => false,
+        }
+    }
+}
+
+/// One of the capabilities a user can hold on a single calendar,
+/// mirroring the flags of `db::CalendarPermission`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CalendarCapability {
+    Admin,
+    View,
+    Read,
+    AddEvent,
+    ModifyEvent,
+    AddRecurringEvent,
+    ModifyRecurringEvent,
+}
+
+impl CalendarCapability {
+    /// Whether a user's calendar permission row grants this capability.
+    pub fn granted_by(self, perm: &db::CalendarPermission) -> bool {
+        match self {
+            CalendarCapability::Admin => perm.can_admin,
+            CalendarCapability::View => perm.can_view,
+            CalendarCapability::Read => perm.can_read,
+            CalendarCapability::AddEvent => perm.can_add_event,
+            CalendarCapability::ModifyEvent => perm.can_modify_event,
+            CalendarCapability::AddRecurringEvent => perm.can_add_recurring_event,
+            CalendarCapability::ModifyRecurringEvent => perm.can_modify_recurring_event,
+        }
+    }
+}
+
+/// Returned by the `require*` checks when the user lacks what was asked for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PermissionError {
+    /// The user doesn't hold a global permission.
+    Denied {
+        user: UserId,
+        permission: Permission,
+    },
+    /// The user doesn't hold a capability on a calendar.
+    CalendarDenied {
+        user: UserId,
+        calendar_id: i64,
+        capability: CalendarCapability,
+    },
+}
+
+impl fmt::Display for PermissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PermissionError::Denied { user, permission } => write!(
+                f,
+                "user {} lacks the {} permission",
+                user,
+                permission_to_string(permission)
+            ),
+            PermissionError::CalendarDenied {
+                user,
+                calendar_id,
+                capability,
+            } => write!(
+                f,
+                "user {} lacks {:?} on calendar {}",
+                user, capability, calendar_id
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PermissionError {}
+
 /// A set of permissions.
 #[derive(Debug, Clone, Default)]
 pub struct PermissionSet {
@@ -43,8 +136,10 @@ impl PermissionSet {
         self.permissions.remove(permission);
     }
 
+    /// Whether the set grants `permission`, exactly or through a `.*` wildcard.
     pub fn contains(&self, permission: &Permission) -> bool {
         self.permissions.contains(permission)
+            || self.permissions.iter().any(|held| held.grants(permission))
     }
 
     pub fn list(&self) -> Vec<Permission> {
@@ -58,8 +153,31 @@ pub trait PermissionBackend: Send + Sync {
 
     async fn remove_permission(&self, user: UserId, permission: &Permission);
 
+    /// Whether the user has the permission, directly or through any of their roles.
     async fn check_permission(&self, user: UserId, permission: &Permission) -> bool;
+    /// Every permission the user has, directly or through their roles.
     async fn list_permissions(&self, user: UserId) -> Vec<Permission>;
+
+    /// Remove every permission assigned directly to the user (their roles are kept).
+    async fn remove_all_permissions(&self, user: UserId);
+
+    /// Give `to` every permission assigned directly to `from`, then clear them from `from`.
+    async fn transfer_permissions(&self, from: UserId, to: UserId);
+
+    /// Create a named role bundling `permissions`, replacing its permissions if it exists.
+    async fn create_role(&self, name: &str, permissions: Vec<Permission>);
+
+    async fn assign_role(&self, user: UserId, role: &str);
+
+    async fn revoke_role(&self, user: UserId, role: &str);
+
+    /// Whether the user holds `cap` on the calendar. Global admins hold every capability.
+    async fn check_calendar_permission(
+        &self,
+        user: UserId,
+        calendar_id: i64,
+        cap: CalendarCapability,
+    ) -> bool;
 }
 
 /// In-memory implementation of PermissionBackend.
@@ -67,14 +185,49 @@ pub trait PermissionBackend: Send + Sync {
 pub struct InMemoryPermissionBackend {
     // Maps user IDs to their set of permissions.
     user_permissions: Mutex<HashMap<UserId, PermissionSet>>,
+    // Maps role names to the permissions they grant.
+    roles: Mutex<HashMap<String, PermissionSet>>,
+    // Maps user IDs to the names of their roles.
+    user_roles: Mutex<HashMap<UserId, HashSet<String>>>,
+    // Maps (user, calendar) to the user's capabilities on that calendar.
+    calendar_permissions: Mutex<HashMap<(UserId, i64), db::CalendarPermission>>,
+    // Users allowed everything on every calendar.
+    global_admins: Mutex<HashSet<UserId>>,
 }
 
 impl InMemoryPermissionBackend {
     pub fn new() -> Self {
-        Self {
-            user_permissions: Mutex::new(HashMap::new()),
+        Self::default()
+    }
+
+    /// Set a user's capabilities on a calendar, overwriting any they already had.
+    pub async fn set_calendar_permission(&self, perm: db::CalendarPermission) {
+        let mut perms = self.calendar_permissions.lock().await;
+        perms.insert((perm.user_id, perm.calendar_id), perm);
+    }
+
+    /// Set whether a user is a global admin.
+    pub async fn set_global_admin(&self, user: UserId, is_global_admin: bool) {
+        let mut admins = self.global_admins.lock().await;
+        if is_global_admin {
+            admins.insert(user);
+        } else {
+            admins.remove(&user);
         }
     }
+
+    /// The permissions a user gets from their roles.
+    async fn role_permissions(&self, user: UserId) -> PermissionSet {
+        let user_roles = self.user_roles.lock().await;
+        let roles = self.roles.lock().await;
+        let mut set = PermissionSet::new();
+        for role in user_roles.get(&user).into_iter().flatten() {
+            for permission in roles.get(role).map_or(vec![], |perms| perms.list()) {
+                set.insert(permission);
+            }
+        }
+        set
+    }
 }
 
 #[async_trait]
@@ -95,61 +248,203 @@ impl PermissionBackend for InMemoryPermissionBackend {
     }
 
     async fn check_permission(&self, user: UserId, permission: &Permission) -> bool {
-        let perms = self.user_permissions.lock().await;
-        perms
-            .get(&user)
-            .map_or(false, |set| set.contains(permission))
+        let direct = {
+            let perms = self.user_permissions.lock().await;
+            perms
+                .get(&user)
+                .map_or(false, |set| set.contains(permission))
+        };
+        direct || self.role_permissions(user).await.contains(permission)
     }
 
     async fn list_permissions(&self, user: UserId) -> Vec<Permission> {
+        let mut set = self.role_permissions(user).await;
         let perms = self.user_permissions.lock().await;
-        perms.get(&user).map_or(vec![], |set| set.list())
+        for permission in perms.get(&user).map_or(vec![], |set| set.list()) {
+            set.insert(permission);
+        }
+        set.list()
+    }
+
+    async fn remove_all_permissions(&self, user: UserId) {
+        self.user_permissions.lock().await.remove(&user);
+    }
+
+    async fn transfer_permissions(&self, from: UserId, to: UserId) {
+        let mut perms = self.user_permissions.lock().await;
+        let Some(moved) = perms.remove(&from) else {
+            return;
+        };
+        let target = perms.entry(to).or_insert_with(PermissionSet::new);
+        for permission in moved.list() {
+            target.insert(permission);
+        }
+    }
+
+    async fn create_role(&self, name: &str, permissions: Vec<Permission>) {
+        let mut set = PermissionSet::new();
+        for permission in permissions {
+            set.insert(permission);
+        }
+        self.roles.lock().await.insert(name.to_string(), set);
+    }
+
+    async fn assign_role(&self, user: UserId, role: &str) {
+        let mut user_roles = self.user_roles.lock().await;
+        user_roles.entry(user).or_default().insert(role.to_string());
+    }
+
+    async fn revoke_role(&self, user: UserId, role: &str) {
+        let mut user_roles = self.user_roles.lock().await;
+        if let Some(roles) = user_roles.get_mut(&user) {
+            roles.remove(role);
+        }
+    }
+
+    async fn check_calendar_permission(
+        &self,
+        user: UserId,
+        calendar_id: i64,
+        cap: CalendarCapability,
+    ) -> bool {
+        if self.global_admins.lock().await.contains(&user) {
+            return true;
+        }
+        let perms = self.calendar_permissions.lock().await;
+        perms
+            .get(&(user, calendar_id))
+            .is_some_and(|perm| cap.granted_by(perm))
     }
 }
 
 /// Database-backed implementation of PermissionBackend.
 pub struct DbPermissionBackend {
-    db: Arc<Mutex<db::DatabaseConnection>>,
+    db: db::DbPool,
 }
 
 impl DbPermissionBackend {
-    pub fn new(db: Arc<Mutex<db::DatabaseConnection>>) -> Self {
+    pub fn new(db: db::DbPool) -> Self {
         Self { db }
     }
+
+    /// Run `f` on a pooled connection on the blocking thread pool, so SQLite doesn't stall the
+    /// async runtime. Failures are logged as failing to `what` and give `None`.
+    async fn run<T: Send + 'static>(
+        &self,
+        what: &'static str,
+        f: impl FnOnce(&mut db::DatabaseConnection) -> Result<T, db::Error> + Send + 'static,
+    ) -> Option<T> {
+        let pool = self.db.clone();
+        let result = tokio::task::spawn_blocking(move || -> Result<T, db::Error> {
+            let mut conn = pool.get()?;
+            f(&mut conn)
+        })
+        .await;
+        match result {
+            Ok(Ok(value)) => Some(value),
+            Ok(Err(e)) => {
+                error!("Failed to {what}: {e}");
+                None
+            }
+            Err(e) => {
+                error!("Failed to {what}: blocking task failed: {e}");
+                None
+            }
+        }
+    }
 }
 
 #[async_trait]
 impl PermissionBackend for DbPermissionBackend {
     async fn assign_permission(&self, user: UserId, permission: Permission) {
         let perm_str = permission_to_string(&permission);
-        let db = self.db.lock().await;
-        let _ = db.assign_permission(user, &perm_str);
+        self.run("assign permission", move |db| {
+            db.assign_permission(user, &perm_str)
+        })
+        .await;
     }
 
     async fn remove_permission(&self, user: UserId, permission: &Permission) {
         let perm_str = permission_to_string(permission);
-        let db = self.db.lock().await;
-        let _ = db.remove_permission(user, &perm_str);
+        self.run("remove permission", move |db| {
+            db.remove_permission(user, &perm_str)
+        })
+        .await;
     }
 
     async fn check_permission(&self, user: UserId, permission: &Permission) -> bool {
         let perm_str = permission_to_string(permission);
-        let db = self.db.lock().await;
-        match db.check_permission(user, &perm_str) {
-            Ok(has) => has,
-            Err(_) => false,
-        }
+        self.run("check permission", move |db| {
+            db.check_permission(user, &perm_str)
+        })
+        .await
+        .unwrap_or(false)
     }
 
     async fn list_permissions(&self, user: UserId) -> Vec<Permission> {
-        let db = self.db.lock().await;
-        match db.list_permissions(user) {
-            Ok(perms) => perms
-                .into_iter()
-                .filter_map(|s| string_to_permission(&s))
-                .collect(),
-            Err(_) => Vec::new(),
-        }
+        self.run("list permissions", move |db| db.list_permissions(user))
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|s| string_to_permission(&s))
+            .collect()
+    }
+
+    async fn remove_all_permissions(&self, user: UserId) {
+        self.run("remove permissions", move |db| {
+            db.remove_named_permissions(user)
+        })
+        .await;
+    }
+
+    async fn transfer_permissions(&self, from: UserId, to: UserId) {
+        self.run("transfer permissions", move |db| {
+            db.transfer_permissions(from, to)
+        })
+        .await;
+    }
+
+    async fn create_role(&self, name: &str, permissions: Vec<Permission>) {
+        let name = name.to_string();
+        let perm_strs: Vec<String> = permissions.iter().map(permission_to_string).collect();
+        self.run("create role", move |db| {
+            let perm_refs: Vec<&str> = perm_strs.iter().map(String::as_str).collect();
+            db.create_role(&name, &perm_refs)
+        })
+        .await;
+    }
+
+    async fn assign_role(&self, user: UserId, role: &str) {
+        let role = role.to_string();
+        self.run("assign role", move |db| db.assign_role(user, &role))
+            .await;
+    }
+
+    async fn revoke_role(&self, user: UserId, role: &str) {
+        let role = role.to_string();
+        self.run("revoke role", move |db| db.revoke_role(user, &role))
+            .await;
+    }
+
+    async fn check_calendar_permission(
+        &self,
+        user: UserId,
+        calendar_id: i64,
+        cap: CalendarCapability,
+    ) -> bool {
+        self.run("check calendar permission", move |db| {
+            if db
+                .get_user_global_permissions(user)?
+                .is_some_and(|global| global.is_global_admin)
+            {
+                return Ok(true);
+            }
+            Ok(db
+                .get_calendar_permission(user, calendar_id)?
+                .is_some_and(|perm| cap.granted_by(&perm)))
+        })
+        .await
+        .unwrap_or(false)
     }
 }
 
@@ -173,25 +468,51 @@ fn string_to_permission(s: &str) -> Option<Permission> {
     }
 }
 
+/// Called with the id of a user whose permissions were just changed through the manager.
+pub type PermissionObserver = Arc<dyn Fn(UserId) + Send + Sync>;
+
 /// The main API for managing permissions.
 pub struct PermissionsManager<B: PermissionBackend> {
     backend: B,
+    observer: RwLock<Option<PermissionObserver>>,
 }
 
 impl<B: PermissionBackend> PermissionsManager<B> {
     /// Create a new PermissionsManager with the given backend.
     pub fn new(backend: B) -> Self {
-        Self { backend }
+        Self {
+            backend,
+            observer: RwLock::new(None),
+        }
+    }
+
+    /// Register the observer told about permission changes, replacing any previous one.
+    /// Lets e.g. the websocket layer push updates without this crate knowing about it.
+    pub fn set_observer(&self, observer: PermissionObserver) {
+        *self.observer.write().unwrap_or_else(|e| e.into_inner()) = Some(observer);
+    }
+
+    fn notify_changed(&self, user: UserId) {
+        let observer = self
+            .observer
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone();
+        if let Some(observer) = observer {
+            observer(user);
+        }
     }
 
     /// Assign a permission to a user.
     pub async fn assign_permission(&self, user: UserId, permission: Permission) {
         self.backend.assign_permission(user, permission).await;
+        self.notify_changed(user);
     }
 
     /// Remove a permission from a user.
     pub async fn remove_permission(&self, user: UserId, permission: &Permission) {
         self.backend.remove_permission(user, permission).await;
+        self.notify_changed(user);
     }
 
     /// Check if a user has a specific permission.
@@ -199,10 +520,108 @@ impl<B: PermissionBackend> PermissionsManager<B> {
         self.backend.check_permission(user, permission).await
     }
 
-    /// List all permissions for a user.
+    /// List all permissions for a user, including those granted through roles.
     pub async fn list_permissions(&self, user: UserId) -> Vec<Permission> {
         self.backend.list_permissions(user).await
     }
+
+    /// Remove every permission assigned directly to a user, e.g. before deleting them.
+    pub async fn remove_all_permissions(&self, user: UserId) {
+        self.backend.remove_all_permissions(user).await;
+        self.notify_changed(user);
+    }
+
+    /// Hand a user's permissions to another user (e.g. a new calendar owner): `to` ends up with
+    /// the union of both users' permissions and `from` with none.
+    pub async fn transfer_permissions(&self, from: UserId, to: UserId) {
+        self.backend.transfer_permissions(from, to).await;
+        self.notify_changed(from);
+        self.notify_changed(to);
+    }
+
+    /// Check several permissions at once with a single fetch of the user's permissions.
+    pub async fn check_permissions(
+        &self,
+        user: UserId,
+        perms: &[Permission],
+    ) -> HashMap<Permission, bool> {
+        let mut held = PermissionSet::new();
+        for permission in self.list_permissions(user).await {
+            held.insert(permission);
+        }
+        perms
+            .iter()
+            .map(|perm| (perm.clone(), held.contains(perm)))
+            .collect()
+    }
+
+    /// Whether the user has every one of `perms` (true for an empty list).
+    pub async fn has_all(&self, user: UserId, perms: &[Permission]) -> bool {
+        self.check_permissions(user, perms)
+            .await
+            .values()
+            .all(|has| *has)
+    }
+
+    /// Create a named role (e.g. "member", "admin") that bundles permissions.
+    /// Creating an existing role replaces its permissions.
+    pub async fn create_role(&self, name: &str, permissions: Vec<Permission>) {
+        self.backend.create_role(name, permissions).await;
+    }
+
+    /// Give a user every permission of a role.
+    pub async fn assign_role(&self, user: UserId, role: &str) {
+        self.backend.assign_role(user, role).await;
+        self.notify_changed(user);
+    }
+
+    /// Take a role away from a user, they keep permissions assigned to them directly.
+    pub async fn revoke_role(&self, user: UserId, role: &str) {
+        self.backend.revoke_role(user, role).await;
+        self.notify_changed(user);
+    }
+
+    /// Check if a user holds a capability on a calendar, global admins hold them all.
+    pub async fn check_calendar_permission(
+        &self,
+        user: UserId,
+        calendar_id: i64,
+        cap: CalendarCapability,
+    ) -> bool {
+        self.backend
+            .check_calendar_permission(user, calendar_id, cap)
+            .await
+    }
+
+    /// Like `check_permission`, but as a `Result` so handlers can bail out with `?`.
+    pub async fn require(&self, user: UserId, perm: &Permission) -> Result<(), PermissionError> {
+        if self.check_permission(user, perm).await {
+            Ok(())
+        } else {
+            Err(PermissionError::Denied {
+                user,
+                permission: perm.clone(),
+            })
+        }
+    }
+
+    /// Like `check_calendar_permission`, but as a `Result` so handlers can bail out with `?`.
+    pub async fn require_calendar(
+        &self,
+        user: UserId,
+        calendar_id: i64,
+        cap: CalendarCapability,
+    ) -> Result<(), PermissionError> {
+        if self.check_calendar_permission(user, calendar_id, cap).await {
+            Ok(())
+        } else {
+            Err(PermissionError::CalendarDenied {
+                user,
+                calendar_id,
+                capability: cap,
+            })
+        }
+    }
 }
 
 #[cfg(test)]
@@ -242,4 +661,307 @@ mod tests {
         assert!(!manager.check_permission(user, &perm_read).await);
         assert!(manager.check_permission(user, &perm_write).await);
     }
+
+    /// `calendar.*` grants everything under `calendar.` and nothing else.
+    async fn check_wildcards<B: PermissionBackend>(manager: PermissionsManager<B>, user: UserId) {
+        let custom = |name: &str| Permission::Custom(name.to_string());
+        manager.assign_permission(user, custom("calendar.*")).await;
+
+        assert!(
+            manager
+                .check_permission(user, &custom("calendar.events.create"))
+                .await
+        );
+        assert!(manager.check_permission(user, &custom("calendar.*")).await);
+        assert!(!manager.check_permission(user, &custom("other.thing")).await);
+        assert!(!manager.check_permission(user, &custom("calendar")).await);
+        assert!(!manager.check_permission(user, &custom("calendarx.y")).await);
+        assert!(!manager.check_permission(user, &Permission::Admin).await);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_wildcard_permissions() {
+        check_wildcards(
+            PermissionsManager::new(InMemoryPermissionBackend::new()),
+            42,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_db_wildcard_permissions() {
+        let pool = db::DbPool::new_in_memory(1).unwrap();
+        let user = {
+            let conn = pool.get().unwrap();
+            conn.insert_user("alice", "hash", "salt", "a@x.com")
+                .unwrap();
+            conn.get_user_by_username("alice").unwrap().unwrap().id
+        };
+        check_wildcards(
+            PermissionsManager::new(DbPermissionBackend::new(pool)),
+            user,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_observer_sees_changed_user() {
+        let manager = PermissionsManager::new(InMemoryPermissionBackend::new());
+        let seen = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        manager.set_observer(Arc::new(move |user| recorder.lock().unwrap().push(user)));
+
+        manager.assign_permission(7, Permission::Read).await;
+        manager.check_permission(7, &Permission::Read).await;
+        manager.remove_permission(9, &Permission::Read).await;
+        assert_eq!(*seen.lock().unwrap(), vec![7, 9]);
+    }
+
+    #[tokio::test]
+    async fn test_require_returns_specific_error() {
+        let backend = InMemoryPermissionBackend::new();
+        backend
+            .set_calendar_permission(db::CalendarPermission {
+                user_id: 7,
+                calendar_id: 3,
+                can_admin: false,
+                can_view: true,
+                can_read: false,
+                can_add_event: false,
+                can_modify_event: false,
+                can_add_recurring_event: false,
+                can_modify_recurring_event: false,
+            })
+            .await;
+        let manager = PermissionsManager::new(backend);
+        manager.assign_permission(7, Permission::Write).await;
+
+        assert_eq!(manager.require(7, &Permission::Write).await, Ok(()));
+        assert_eq!(
+            manager.require(7, &Permission::Admin).await,
+            Err(PermissionError::Denied {
+                user: 7,
+                permission: Permission::Admin
+            })
+        );
+        assert_eq!(
+            manager
+                .require_calendar(7, 3, CalendarCapability::View)
+                .await,
+            Ok(())
+        );
+        assert_eq!(
+            manager
+                .require_calendar(7, 3, CalendarCapability::AddEvent)
+                .await,
+            Err(PermissionError::CalendarDenied {
+                user: 7,
+                calendar_id: 3,
+                capability: CalendarCapability::AddEvent
+            })
+        );
+    }
+
+    #[test]
+    fn test_permission_json_matches_db_strings() {
+        let all = [
+            Permission::Read,
+            Permission::Write,
+            Permission::Delete,
+            Permission::Admin,
+            Permission::Custom("calendar.export".to_string()),
+        ];
+        for permission in all {
+            let json = serde_json::to_string(&permission).unwrap();
+            assert_eq!(
+                json,
+                serde_json::to_string(&permission_to_string(&permission)).unwrap()
+            );
+            let back: Permission = serde_json::from_str(&json).unwrap();
+            assert_eq!(back, permission);
+        }
+
+        // Same as string_to_permission: a custom name shadowing a built-in reads back as it
+        let shadowed = serde_json::to_string(&Permission::Custom("admin".to_string())).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Permission>(&shadowed).unwrap(),
+            string_to_permission("admin").unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_all_permissions() {
+        let manager = PermissionsManager::new(InMemoryPermissionBackend::new());
+        manager.assign_permission(1, Permission::Read).await;
+        manager.assign_permission(1, Permission::Write).await;
+        manager.assign_permission(2, Permission::Read).await;
+
+        manager.remove_all_permissions(1).await;
+        assert!(manager.list_permissions(1).await.is_empty());
+        assert!(manager.check_permission(2, &Permission::Read).await);
+    }
+
+    #[tokio::test]
+    async fn test_transfer_permissions_unions_into_target() {
+        let manager = PermissionsManager::new(InMemoryPermissionBackend::new());
+        manager.assign_permission(1, Permission::Read).await;
+        manager.assign_permission(1, Permission::Admin).await;
+        manager.assign_permission(2, Permission::Read).await;
+        manager.assign_permission(2, Permission::Write).await;
+
+        manager.transfer_permissions(1, 2).await;
+        assert!(manager.list_permissions(1).await.is_empty());
+        let mut perms = manager.list_permissions(2).await;
+        perms.sort_by_key(permission_to_string);
+        assert_eq!(
+            perms,
+            vec![Permission::Admin, Permission::Read, Permission::Write]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_batch_permission_checks() {
+        let manager = PermissionsManager::new(InMemoryPermissionBackend::new());
+        let user = 7;
+        manager.assign_permission(user, Permission::Read).await;
+        manager
+            .assign_permission(user, Permission::Custom("export".into()))
+            .await;
+
+        let requested = [
+            Permission::Read,
+            Permission::Write,
+            Permission::Custom("export".into()),
+        ];
+        let batch = manager.check_permissions(user, &requested).await;
+        assert_eq!(batch.len(), requested.len());
+        for perm in &requested {
+            assert_eq!(batch[perm], manager.check_permission(user, perm).await);
+        }
+
+        assert!(
+            manager
+                .has_all(
+                    user,
+                    &[Permission::Read, Permission::Custom("export".into())]
+                )
+                .await
+        );
+        assert!(!manager.has_all(user, &requested).await);
+        assert!(manager.has_all(user, &[]).await);
+    }
+
+    /// Roles grant permissions on top of direct ones, and revoking the role takes them back.
+    async fn check_roles<B: PermissionBackend>(manager: PermissionsManager<B>, user: UserId) {
+        manager
+            .create_role("member", vec![Permission::Read, Permission::Write])
+            .await;
+        manager.assign_permission(user, Permission::Delete).await;
+        assert!(!manager.check_permission(user, &Permission::Write).await);
+
+        manager.assign_role(user, "member").await;
+        assert!(manager.check_permission(user, &Permission::Write).await);
+        assert!(manager.check_permission(user, &Permission::Delete).await);
+        let mut perms = manager.list_permissions(user).await;
+        perms.sort_by_key(permission_to_string);
+        assert_eq!(
+            perms,
+            vec![Permission::Delete, Permission::Read, Permission::Write]
+        );
+
+        manager.revoke_role(user, "member").await;
+        assert!(!manager.check_permission(user, &Permission::Write).await);
+        assert!(manager.check_permission(user, &Permission::Delete).await);
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_role_permissions() {
+        check_roles(
+            PermissionsManager::new(InMemoryPermissionBackend::new()),
+            42,
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn test_db_role_permissions() {
+        let pool = db::DbPool::new_in_memory(1).unwrap();
+        let user = {
+            let conn = pool.get().unwrap();
+            conn.insert_user("alice", "hash", "salt", "a@x.com")
+                .unwrap();
+            conn.get_user_by_username("alice").unwrap().unwrap().id
+        };
+        check_roles(
+            PermissionsManager::new(DbPermissionBackend::new(pool)),
+            user,
+        )
+        .await;
+    }
+
+    const ALL_CAPABILITIES: [CalendarCapability; 7] = [
+        CalendarCapability::Admin,
+        CalendarCapability::View,
+        CalendarCapability::Read,
+        CalendarCapability::AddEvent,
+        CalendarCapability::ModifyEvent,
+        CalendarCapability::AddRecurringEvent,
+        CalendarCapability::ModifyRecurringEvent,
+    ];
+
+    #[tokio::test]
+    async fn test_db_calendar_permissions() {
+        let pool = db::DbPool::new_in_memory(1).unwrap();
+        let (viewer, admin, calendar_id) = {
+            let conn = pool.get().unwrap();
+            conn.insert_user("viewer", "hash", "salt", "v@x.com")
+                .unwrap();
+            conn.insert_user("admin", "hash", "salt", "a@x.com")
+                .unwrap();
+            let viewer = conn.get_user_by_username("viewer").unwrap().unwrap().id;
+            let admin = conn.get_user_by_username("admin").unwrap().unwrap().id;
+            let calendar_id = conn
+                .insert_calendar("Family", colorlab::Color::from_rgb8(1, 2, 3))
+                .unwrap();
+            conn.set_calendar_permission(&db::CalendarPermission {
+                user_id: viewer,
+                calendar_id,
+                can_admin: false,
+                can_view: true,
+                can_read: false,
+                can_add_event: false,
+                can_modify_event: false,
+                can_add_recurring_event: false,
+                can_modify_recurring_event: false,
+            })
+            .unwrap();
+            conn.set_global_admin(admin, true).unwrap();
+            (viewer, admin, calendar_id)
+        };
+        let manager = PermissionsManager::new(DbPermissionBackend::new(pool));
+
+        assert!(
+            manager
+                .check_calendar_permission(viewer, calendar_id, CalendarCapability::View)
+                .await
+        );
+        assert!(
+            !manager
+                .check_calendar_permission(viewer, calendar_id, CalendarCapability::ModifyEvent)
+                .await
+        );
+        // No row for another calendar means no access
+        assert!(
+            !manager
+                .check_calendar_permission(viewer, calendar_id + 1, CalendarCapability::View)
+                .await
+        );
+        for cap in ALL_CAPABILITIES {
+            assert!(
+                manager
+                    .check_calendar_permission(admin, calendar_id, cap)
+                    .await
+            );
+        }
+    }
 }
@@ -0,0 +1,271 @@
+use super::{ApiError, ClientIp, run_blocking};
+use appstate::AppState;
+use axum::{Json, Router, extract::State, http::StatusCode, routing::post};
+use serde::{Deserialize, Serialize};
+
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/register", post(register))
+        .route("/salt", post(salt))
+        .route("/login", post(login))
+        .route("/change_password", post(change_password))
+        .route("/verify_email", post(verify_email))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RegisterRequest {
+    pub username: String,
+    /// Raw password, or the client-side hash when the server is in client-hashed mode
+    pub password: String,
+    /// Salt the client hashed with, only used in client-hashed mode
+    #[serde(default)]
+    pub salt: Option<String>,
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SaltRequest {
+    pub username: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SaltResponse {
+    pub salt: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LoginRequest {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChangePasswordRequest {
+    pub username: String,
+    pub new_password: String,
+    /// The user's current access token
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct VerifyEmailRequest {
+    /// Token that was sent to the user's email address
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TokenResponse {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RegisterResponse {
+    /// Missing when logins require a verified email, log in once it's verified
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+/// `POST /api/register`: create an account, responds 201 with an access token
+/// (unless the email has to be verified first).
+async fn register(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(body): Json<RegisterRequest>,
+) -> Result<(StatusCode, Json<RegisterResponse>), ApiError> {
+    let auth = state.auth.clone();
+    let registration = run_blocking(move || {
+        auth.register_user_from(
+            ip,
+            &body.username,
+            &body.password,
+            body.salt.as_deref(),
+            &body.email,
+        )
+    })
+    .await?;
+    // The verification token is for the user's inbox, not the response
+    Ok((
+        StatusCode::CREATED,
+        Json(RegisterResponse {
+            token: registration.access_token,
+        }),
+    ))
+}
+
+/// `POST /api/salt`: the salt a client-hashing client needs before logging in.
+async fn salt(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(body): Json<SaltRequest>,
+) -> Result<Json<SaltResponse>, ApiError> {
+    let auth = state.auth.clone();
+    let salt = run_blocking(move || auth.get_salt(&body.username, &ip.to_string())).await?;
+    Ok(Json(SaltResponse { salt }))
+}
+
+/// `POST /api/login`: responds with an access token if the credentials are right.
+async fn login(
+    State(state): State<AppState>,
+    ClientIp(ip): ClientIp,
+    Json(body): Json<LoginRequest>,
+) -> Result<Json<TokenResponse>, ApiError> {
+    let auth = state.auth.clone();
+    let token = run_blocking(move || {
+        auth.authenticate_user(&body.username, &body.password, &ip.to_string())
+    })
+    .await?;
+    Ok(Json(TokenResponse { token }))
+}
+
+/// `POST /api/change_password`: responds 204, the old token (and every other) is revoked.
+async fn change_password(
+    State(state): State<AppState>,
+    Json(body): Json<ChangePasswordRequest>,
+) -> Result<StatusCode, ApiError> {
+    let auth = state.auth.clone();
+    run_blocking(move || auth.change_password(&body.username, &body.new_password, &body.token))
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /api/verify_email`: responds 204 once the email the token was sent to is verified.
+async fn verify_email(
+    State(state): State<AppState>,
+    Json(body): Json<VerifyEmailRequest>,
+) -> Result<StatusCode, ApiError> {
+    let auth = state.auth.clone();
+    run_blocking(move || auth.verify_email(&body.token)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_router;
+    use crate::test_util::{json_request, response_json, test_state};
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_register_then_login() {
+        let state = test_state();
+        let app = build_router(state.clone()).await;
+
+        let registered = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/register",
+                json!({"username": "alice", "password": "hunter22", "email": "a@x.com"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(registered.status(), StatusCode::CREATED);
+
+        let login = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/login",
+                json!({"username": "alice", "password": "hunter22"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(login.status(), StatusCode::OK);
+        let token = response_json(login).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+        assert!(state.auth.validate_jwt(&token, "alice").is_ok());
+
+        let changed = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/change_password",
+                json!({"username": "alice", "new_password": "hunter23", "token": token}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(changed.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_register_rejects_invalid_email() {
+        let state = test_state();
+        let app = build_router(state.clone()).await;
+        let response = app
+            .oneshot(json_request(
+                "POST",
+                "/api/register",
+                json!({"username": "carol", "password": "hunter22", "email": "not-an-email"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert!(
+            state
+                .database
+                .get()
+                .unwrap()
+                .get_user_by_username("carol")
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_auth_errors_map_to_status_codes() {
+        let app = build_router(test_state()).await;
+        let register = json!({"username": "bob", "password": "pw", "email": "b@x.com"});
+        app.clone()
+            .oneshot(json_request("POST", "/api/register", register.clone()))
+            .await
+            .unwrap();
+
+        let duplicate = app
+            .clone()
+            .oneshot(json_request("POST", "/api/register", register))
+            .await
+            .unwrap();
+        assert_eq!(duplicate.status(), StatusCode::CONFLICT);
+
+        let wrong = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/login",
+                json!({"username": "bob", "password": "nope"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+        let bad_token = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/change_password",
+                json!({"username": "bob", "new_password": "x", "token": "garbage"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(bad_token.status(), StatusCode::UNAUTHORIZED);
+
+        // The default limit is 5 requests per minute per IP, and all test requests share one
+        let mut statuses = Vec::new();
+        for _ in 0..5 {
+            let response = app
+                .clone()
+                .oneshot(json_request(
+                    "POST",
+                    "/api/salt",
+                    json!({"username": "bob"}),
+                ))
+                .await
+                .unwrap();
+            statuses.push(response.status());
+        }
+        assert_eq!(statuses.last(), Some(&StatusCode::TOO_MANY_REQUESTS));
+    }
+}
@@ -0,0 +1,629 @@
+use super::{ApiError, AuthenticatedUser, require_calendar, run_blocking};
+use appstate::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, put},
+};
+use chrono::{DateTime, Utc};
+use db::{Event, EventUpdate, NewEvent};
+use permissions::{CalendarCapability, UserId};
+use serde::{Deserialize, Serialize};
+use websockets::{EventChange, notify_event_changed};
+
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route(
+            "/calendars/{id}/events",
+            get(list_events).post(create_event),
+        )
+        .route("/calendars/{id}/events/changes", get(list_event_changes))
+        .route("/events/{id}", put(update_event).delete(delete_event))
+}
+
+/// `?start=&end=` range for listing events, ISO-8601 timestamps.
+#[derive(Debug, Deserialize)]
+pub struct RangeQuery {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
+
+/// `?since=` for listing event changes, an ISO-8601 timestamp (usually the last `cursor`).
+#[derive(Debug, Deserialize)]
+pub struct ChangesQuery {
+    pub since: DateTime<Utc>,
+}
+
+/// What changed in a calendar since the requested point.
+#[derive(Debug, Serialize)]
+pub struct EventChanges {
+    /// Pass back as `since` on the next poll
+    pub cursor: DateTime<Utc>,
+    /// Events created or updated, as they are now
+    pub events: Vec<Event>,
+    /// Events deleted, by id
+    pub deleted: Vec<DeletedEvent>,
+}
+
+/// Tombstone for a deleted event.
+#[derive(Debug, Serialize)]
+pub struct DeletedEvent {
+    pub id: i64,
+    pub deleted_at: DateTime<Utc>,
+}
+
+/// Body of event create/update requests.
+#[derive(Debug, Deserialize)]
+pub struct EventRequest {
+    pub title: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// IANA time zone the event belongs to, UTC on create and unchanged on update if missing
+    #[serde(default)]
+    pub timezone: Option<String>,
+    /// Whether the event covers whole days, timed on create and unchanged on update if missing
+    #[serde(default)]
+    pub all_day: Option<bool>,
+}
+
+impl EventRequest {
+    fn validate(&self) -> Result<(), ApiError> {
+        if self.end_time < self.start_time {
+            return Err(ApiError::BadRequest(
+                "end_time must not be before start_time".to_string(),
+            ));
+        }
+        if let Some(timezone) = &self.timezone
+            && db::parse_timezone(timezone).is_err()
+        {
+            return Err(ApiError::BadRequest(format!(
+                "unknown time zone {timezone:?}"
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// `GET /api/calendars/{id}/events?start=&end=`: events overlapping the range, sorted by start.
+async fn list_events(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(calendar_id): Path<i64>,
+    Query(range): Query<RangeQuery>,
+) -> Result<Json<Vec<Event>>, ApiError> {
+    require_calendar(&state, user.id, calendar_id, CalendarCapability::View).await?;
+    let database = state.database.clone();
+    let events = run_blocking(move || -> Result<_, ApiError> {
+        Ok(database
+            .get()?
+            .list_events_in_range(calendar_id, range.start, range.end)?)
+    })
+    .await?;
+    Ok(Json(events))
+}
+
+/// `GET /api/calendars/{id}/events/changes?since=`: events created, updated or deleted after
+/// `since`, for clients that poll instead of holding a websocket open.
+async fn list_event_changes(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(calendar_id): Path<i64>,
+    Query(query): Query<ChangesQuery>,
+) -> Result<Json<EventChanges>, ApiError> {
+    require_calendar(&state, user.id, calendar_id, CalendarCapability::View).await?;
+    let database = state.database.clone();
+    let changed = run_blocking(move || -> Result<_, ApiError> {
+        Ok(database
+            .get()?
+            .list_event_changes_since(calendar_id, query.since)?)
+    })
+    .await?;
+    let mut changes = EventChanges {
+        cursor: query.since,
+        events: Vec::new(),
+        deleted: Vec::new(),
+    };
+    for change in changed {
+        changes.cursor = changes.cursor.max(change.changed_at());
+        match change.deleted_at {
+            Some(deleted_at) => changes.deleted.push(DeletedEvent {
+                id: change.event.id,
+                deleted_at,
+            }),
+            None => changes.events.push(change.event),
+        }
+    }
+    Ok(Json(changes))
+}
+
+/// `POST /api/calendars/{id}/events`: create an event, responds 201 with the stored event.
+async fn create_event(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(calendar_id): Path<i64>,
+    Json(body): Json<EventRequest>,
+) -> Result<(StatusCode, Json<Event>), ApiError> {
+    body.validate()?;
+    require_calendar(&state, user.id, calendar_id, CalendarCapability::AddEvent).await?;
+    let database = state.database.clone();
+    let event = run_blocking(move || -> Result<_, ApiError> {
+        let conn = database.get()?;
+        let id = conn.insert_new_event(
+            calendar_id,
+            &NewEvent {
+                title: body.title,
+                description: body.description,
+                start_time: body.start_time,
+                end_time: body.end_time,
+                timezone: body
+                    .timezone
+                    .unwrap_or_else(|| db::DEFAULT_TIMEZONE.to_string()),
+                all_day: body.all_day.unwrap_or(false),
+            },
+        )?;
+        conn.get_event_by_id(id)?.ok_or(ApiError::NotFound)
+    })
+    .await?;
+    notify_event_changed(&state, calendar_id, event.id, EventChange::Created).await;
+    Ok((StatusCode::CREATED, Json(event)))
+}
+
+/// Look up an event and check `user` holds `capability` on its calendar.
+async fn require_event(
+    state: &AppState,
+    user: UserId,
+    id: i64,
+    capability: CalendarCapability,
+) -> Result<Event, ApiError> {
+    let database = state.database.clone();
+    let event = run_blocking(move || -> Result<_, ApiError> {
+        database
+            .get()?
+            .get_event_by_id(id)?
+            .ok_or(ApiError::NotFound)
+    })
+    .await?;
+    state
+        .permissions
+        .require_calendar(user, event.calendar_id, capability)
+        .await?;
+    Ok(event)
+}
+
+/// `PUT /api/events/{id}`: replace an event's details, responds with the updated event.
+async fn update_event(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<i64>,
+    Json(body): Json<EventRequest>,
+) -> Result<Json<Event>, ApiError> {
+    body.validate()?;
+    require_event(&state, user.id, id, CalendarCapability::ModifyEvent).await?;
+    let database = state.database.clone();
+    let event = run_blocking(move || -> Result<_, ApiError> {
+        let mut conn = database.get()?;
+        let update = EventUpdate {
+            title: body.title,
+            description: body.description,
+            start_time: body.start_time,
+            end_time: body.end_time,
+            timezone: body.timezone,
+            all_day: body.all_day,
+        };
+        if !conn.update_event_details(id, &update)? {
+            return Err(ApiError::NotFound);
+        }
+        conn.get_event_by_id(id)?.ok_or(ApiError::NotFound)
+    })
+    .await?;
+    notify_event_changed(&state, event.calendar_id, id, EventChange::Updated).await;
+    Ok(Json(event))
+}
+
+/// `DELETE /api/events/{id}`: responds 204, or 404 if there was no such event.
+async fn delete_event(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    // Look the event up first, subscribers need to know which calendar it was in
+    let event = require_event(&state, user.id, id, CalendarCapability::ModifyEvent).await?;
+    let database = state.database.clone();
+    let deleted = run_blocking(move || -> Result<_, ApiError> {
+        Ok(database.get()?.delete_event_by_id(id)?)
+    })
+    .await?;
+    if !deleted {
+        return Err(ApiError::NotFound);
+    }
+    notify_event_changed(&state, event.calendar_id, id, EventChange::Deleted).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_router;
+    use crate::test_util::{
+        authed_request, json_request, owner_of_calendar, response_json, test_state,
+    };
+    use axum::http::StatusCode;
+    use colorlab::Color;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_event_crud() {
+        let state = test_state();
+        let (token, calendar_id) = owner_of_calendar(&state, "alice");
+        let app = build_router(state).await;
+
+        let created = app
+            .clone()
+            .oneshot(authed_request(
+                "POST",
+                &format!("/api/calendars/{calendar_id}/events"),
+                &token,
+                json!({
+                    "title": "Dentist",
+                    "start_time": "2025-03-01T09:00:00Z",
+                    "end_time": "2025-03-01T10:00:00Z"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(created.status(), StatusCode::CREATED);
+        let event = response_json(created).await;
+        let id = event["id"].as_i64().unwrap();
+        assert_eq!(event["title"], "Dentist");
+        assert_eq!(event["start_time"], "2025-03-01T09:00:00Z");
+        assert_eq!(event["timezone"], "UTC");
+
+        let listed = app
+            .clone()
+            .oneshot(authed_request(
+                "GET",
+                &format!(
+                    "/api/calendars/{calendar_id}/events?start=2025-03-01T00:00:00Z&end=2025-03-02T00:00:00Z"
+                ),
+                &token,
+                serde_json::Value::Null,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(listed.status(), StatusCode::OK);
+        assert_eq!(response_json(listed).await.as_array().unwrap().len(), 1);
+
+        let updated = app
+            .clone()
+            .oneshot(authed_request(
+                "PUT",
+                &format!("/api/events/{id}"),
+                &token,
+                json!({
+                    "title": "Dentist (moved)",
+                    "description": "bring forms",
+                    "start_time": "2025-03-02T09:00:00Z",
+                    "end_time": "2025-03-02T10:00:00Z",
+                    "timezone": "America/New_York"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(updated.status(), StatusCode::OK);
+        let event = response_json(updated).await;
+        assert_eq!(event["description"], "bring forms");
+        assert_eq!(event["timezone"], "America/New_York");
+
+        let bad_zone = app
+            .clone()
+            .oneshot(authed_request(
+                "PUT",
+                &format!("/api/events/{id}"),
+                &token,
+                json!({
+                    "title": "Dentist",
+                    "start_time": "2025-03-02T09:00:00Z",
+                    "end_time": "2025-03-02T10:00:00Z",
+                    "timezone": "Mars/Olympus_Mons"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(bad_zone.status(), StatusCode::BAD_REQUEST);
+
+        let deleted = app
+            .clone()
+            .oneshot(authed_request(
+                "DELETE",
+                &format!("/api/events/{id}"),
+                &token,
+                serde_json::Value::Null,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(deleted.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_event_routes_check_calendar_permissions() {
+        let state = test_state();
+        let (alice, calendar_id) = owner_of_calendar(&state, "alice");
+        let (bob, _) = owner_of_calendar(&state, "bob");
+        let app = build_router(state).await;
+        let body = json!({
+            "title": "Dentist",
+            "start_time": "2025-03-01T09:00:00Z",
+            "end_time": "2025-03-01T10:00:00Z"
+        });
+        let created = app
+            .clone()
+            .oneshot(authed_request(
+                "POST",
+                &format!("/api/calendars/{calendar_id}/events"),
+                &alice,
+                body.clone(),
+            ))
+            .await
+            .unwrap();
+        let id = response_json(created).await["id"].as_i64().unwrap();
+
+        let routes = [
+            ("POST", format!("/api/calendars/{calendar_id}/events")),
+            (
+                "GET",
+                format!(
+                    "/api/calendars/{calendar_id}/events?start=2025-03-01T00:00:00Z&end=2025-03-02T00:00:00Z"
+                ),
+            ),
+            (
+                "GET",
+                format!("/api/calendars/{calendar_id}/events/changes?since=2025-03-01T00:00:00Z"),
+            ),
+            ("PUT", format!("/api/events/{id}")),
+            ("DELETE", format!("/api/events/{id}")),
+        ];
+        for (method, uri) in &routes {
+            let response = app
+                .clone()
+                .oneshot(json_request(method, uri, body.clone()))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{method} {uri}"
+            );
+
+            let response = app
+                .clone()
+                .oneshot(authed_request(method, uri, &bob, body.clone()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::FORBIDDEN, "{method} {uri}");
+        }
+
+        // Bob's attempts changed nothing
+        let listed = app
+            .oneshot(authed_request(
+                "GET",
+                &routes[1].1,
+                &alice,
+                serde_json::Value::Null,
+            ))
+            .await
+            .unwrap();
+        let listed = response_json(listed).await;
+        assert_eq!(listed.as_array().unwrap().len(), 1);
+        assert_eq!(listed[0]["title"], "Dentist");
+    }
+
+    #[tokio::test]
+    async fn test_event_changes_notify_subscribers() {
+        use axum::extract::ws::Message;
+        use websockets::{EventChange, ServerMessage};
+
+        let state = test_state();
+        let (token, family) = owner_of_calendar(&state, "alice");
+        let work = state
+            .database
+            .get()
+            .unwrap()
+            .insert_calendar("Work", Color::from_rgb8(4, 5, 6))
+            .unwrap();
+        let (tx_family, mut rx_family) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_work, mut rx_work) = tokio::sync::mpsc::unbounded_channel();
+        let family_conn = state.register_connection(tx_family).await.unwrap();
+        let work_conn = state.register_connection(tx_work).await.unwrap();
+        state.subscribe_to_calendar(family_conn, family).await;
+        state.subscribe_to_calendar(work_conn, work).await;
+        let app = build_router(state).await;
+
+        let body = json!({
+            "title": "Dentist",
+            "start_time": "2025-03-01T09:00:00Z",
+            "end_time": "2025-03-01T10:00:00Z"
+        });
+        let created = app
+            .clone()
+            .oneshot(authed_request(
+                "POST",
+                &format!("/api/calendars/{family}/events"),
+                &token,
+                body.clone(),
+            ))
+            .await
+            .unwrap();
+        let id = response_json(created).await["id"].as_i64().unwrap();
+        app.clone()
+            .oneshot(authed_request(
+                "PUT",
+                &format!("/api/events/{id}"),
+                &token,
+                body,
+            ))
+            .await
+            .unwrap();
+        app.oneshot(authed_request(
+            "DELETE",
+            &format!("/api/events/{id}"),
+            &token,
+            serde_json::Value::Null,
+        ))
+        .await
+        .unwrap();
+
+        for (seq, change) in [
+            (1, EventChange::Created),
+            (2, EventChange::Updated),
+            (3, EventChange::Deleted),
+        ] {
+            match rx_family.try_recv() {
+                Ok(Message::Binary(raw)) => assert_eq!(
+                    ServerMessage::from_msgpack(&raw).unwrap(),
+                    ServerMessage::EventChanged {
+                        seq,
+                        calendar_id: family,
+                        event_id: id,
+                        change
+                    }
+                ),
+                other => panic!("expected EventChanged, got {other:?}"),
+            }
+        }
+        assert!(rx_work.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_event_changes_since_cursor() {
+        let state = test_state();
+        let (token, calendar_id) = owner_of_calendar(&state, "alice");
+        let app = build_router(state).await;
+        let changes = |since: String| {
+            let app = app.clone();
+            let token = token.clone();
+            async move {
+                // Changes from the current millisecond are held back until it has passed
+                tokio::time::sleep(std::time::Duration::from_millis(2)).await;
+                let response = app
+                    .oneshot(authed_request(
+                        "GET",
+                        &format!("/api/calendars/{calendar_id}/events/changes?since={since}"),
+                        &token,
+                        serde_json::Value::Null,
+                    ))
+                    .await
+                    .unwrap();
+                assert_eq!(response.status(), StatusCode::OK);
+                response_json(response).await
+            }
+        };
+        let create = |title: &'static str| {
+            let app = app.clone();
+            let token = token.clone();
+            async move {
+                let response = app
+                    .oneshot(authed_request(
+                        "POST",
+                        &format!("/api/calendars/{calendar_id}/events"),
+                        &token,
+                        json!({
+                            "title": title,
+                            "start_time": "2025-03-01T09:00:00Z",
+                            "end_time": "2025-03-01T10:00:00Z"
+                        }),
+                    ))
+                    .await
+                    .unwrap();
+                response_json(response).await["id"].as_i64().unwrap()
+            }
+        };
+
+        let doomed = create("Doomed").await;
+        let first = changes("2000-01-01T00:00:00Z".to_string()).await;
+        assert_eq!(first["events"].as_array().unwrap().len(), 1);
+        let cursor = first["cursor"].as_str().unwrap().to_string();
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+
+        let added = create("Dentist").await;
+        app.clone()
+            .oneshot(authed_request(
+                "DELETE",
+                &format!("/api/events/{doomed}"),
+                &token,
+                serde_json::Value::Null,
+            ))
+            .await
+            .unwrap();
+
+        let next = changes(cursor.clone()).await;
+        let events = next["events"].as_array().unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0]["id"], added);
+        assert_eq!(events[0]["title"], "Dentist");
+        let deleted = next["deleted"].as_array().unwrap();
+        assert_eq!(deleted.len(), 1);
+        assert_eq!(deleted[0]["id"], doomed);
+        let parse = |cursor: &str| cursor.parse::<chrono::DateTime<chrono::Utc>>().unwrap();
+        let next_cursor = next["cursor"].as_str().unwrap().to_string();
+        assert!(parse(&next_cursor) > parse(&cursor));
+
+        // The cursor is exclusive, so polling again doesn't repeat the last change
+        let again = changes(next_cursor.clone()).await;
+        assert!(again["events"].as_array().unwrap().is_empty());
+        assert!(again["deleted"].as_array().unwrap().is_empty());
+        assert_eq!(again["cursor"], next_cursor.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_missing_event_and_calendar_are_404() {
+        let state = test_state();
+        let (token, _) = owner_of_calendar(&state, "alice");
+        let app = build_router(state).await;
+        let body = json!({
+            "title": "Nothing",
+            "start_time": "2025-03-01T09:00:00Z",
+            "end_time": "2025-03-01T10:00:00Z"
+        });
+
+        for (method, uri) in [
+            ("PUT", "/api/events/404"),
+            ("DELETE", "/api/events/404"),
+            ("POST", "/api/calendars/404/events"),
+            (
+                "GET",
+                "/api/calendars/404/events/changes?since=2025-03-01T00:00:00Z",
+            ),
+        ] {
+            let response = app
+                .clone()
+                .oneshot(authed_request(method, uri, &token, body.clone()))
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{method} {uri}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_end_before_start_is_rejected() {
+        let state = test_state();
+        let (token, calendar_id) = owner_of_calendar(&state, "alice");
+        let response = build_router(state)
+            .await
+            .oneshot(authed_request(
+                "POST",
+                &format!("/api/calendars/{calendar_id}/events"),
+                &token,
+                json!({
+                    "title": "Backwards",
+                    "start_time": "2025-03-01T10:00:00Z",
+                    "end_time": "2025-03-01T09:00:00Z"
+                }),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
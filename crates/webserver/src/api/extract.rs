@@ -0,0 +1,117 @@
+use super::ApiError;
+use appstate::AppState;
+use auth::SafeUser;
+use axum::{
+    extract::FromRequestParts,
+    http::{header, request::Parts},
+};
+
+/// The user a request's `Authorization: Bearer <jwt>` header belongs to.
+/// Taking this as a handler argument makes the route require a valid access token,
+/// anything else is rejected with 401 before the handler runs.
+pub struct AuthenticatedUser(pub SafeUser);
+
+impl FromRequestParts<AppState> for AuthenticatedUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or(ApiError::Unauthorized)?
+            .trim()
+            .to_string();
+        let auth = state.auth.clone();
+        let user = super::run_blocking(move || auth.user_from_jwt(&token))
+            .await
+            .map_err(|e| match e {
+                // A token for a deleted account is no better than a forged one
+                ApiError::NotFound => ApiError::Unauthorized,
+                other => other,
+            })?;
+        Ok(AuthenticatedUser(user))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::test_state;
+    use axum::{Router, body::Body, http::Request, http::StatusCode, routing::get};
+    use tower::ServiceExt;
+
+    async fn whoami(AuthenticatedUser(user): AuthenticatedUser) -> String {
+        user.username
+    }
+
+    fn app(state: AppState) -> Router {
+        Router::new()
+            .route("/whoami", get(whoami))
+            .with_state(state)
+    }
+
+    fn request(authorization: Option<&str>) -> Request<Body> {
+        let mut builder = Request::builder().uri("/whoami");
+        if let Some(value) = authorization {
+            builder = builder.header(header::AUTHORIZATION, value);
+        }
+        builder.body(Body::empty()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_valid_token_populates_user() {
+        let state = test_state();
+        let token = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let response = app(state)
+            .oneshot(request(Some(&format!("Bearer {token}"))))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"alice");
+    }
+
+    #[tokio::test]
+    async fn test_missing_or_invalid_token_is_401() {
+        let state = test_state();
+        let revoked = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        state.auth.revoke_token(&revoked).unwrap();
+        let refresh = state.auth.issue_refresh_token("alice").unwrap();
+
+        for authorization in [
+            None,
+            Some("alice"),
+            Some("Bearer not-a-jwt"),
+            Some(&*format!("Bearer {revoked}")),
+            // Refresh tokens aren't accepted in place of access tokens
+            Some(&*format!("Bearer {refresh}")),
+        ] {
+            let response = app(state.clone())
+                .oneshot(request(authorization))
+                .await
+                .unwrap();
+            assert_eq!(
+                response.status(),
+                StatusCode::UNAUTHORIZED,
+                "{authorization:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,728 @@
+//! iCalendar (RFC 5545) import and export.
+
+use super::{ApiError, AuthenticatedUser, require_calendar, run_blocking};
+use appstate::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::header,
+    response::IntoResponse,
+    routing::{get, post},
+};
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, Offset, TimeZone, Utc};
+use db::{Event, NewEvent};
+use permissions::CalendarCapability;
+use serde::Serialize;
+use std::collections::HashMap;
+use tracing::*;
+use websockets::{EventChange, notify_event_changed};
+
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/calendars/{id}/import", post(import_calendar))
+        .route("/calendars/{id}/export", get(export_calendar))
+}
+
+/// An event read from a VEVENT block.
+#[derive(Debug, Clone, PartialEq)]
+pub struct IcalEvent {
+    pub uid: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// DTSTART was a date, `end_time` is then exclusive like iCalendar's DTEND
+    pub all_day: bool,
+    /// IANA zone of DTSTART's `TZID`, `None` for UTC, floating times and dates
+    pub timezone: Option<String>,
+}
+
+impl IcalEvent {
+    /// The event to store. Stored all-day events end on their last day rather than the day after.
+    fn to_new_event(&self) -> NewEvent {
+        let end_time = if self.all_day {
+            (self.end_time - Duration::days(1)).max(self.start_time)
+        } else {
+            self.end_time
+        };
+        NewEvent {
+            all_day: self.all_day,
+            timezone: self
+                .timezone
+                .clone()
+                .unwrap_or_else(|| db::DEFAULT_TIMEZONE.to_string()),
+            ..NewEvent::new(
+                &self.title,
+                self.description.as_deref(),
+                self.start_time,
+                end_time,
+            )
+        }
+    }
+}
+
+/// Outcome of an import, returned as JSON.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct ImportSummary {
+    /// Events added to the calendar
+    pub imported: usize,
+    /// Events that were already imported (same UID) and left alone
+    pub duplicates: usize,
+    /// Malformed events that couldn't be read
+    pub skipped: usize,
+}
+
+/// `POST /api/calendars/{id}/import`: add the VEVENTs of a `text/calendar` body to the calendar.
+/// Malformed events are skipped rather than failing the whole import.
+async fn import_calendar(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(calendar_id): Path<i64>,
+    body: String,
+) -> Result<Json<ImportSummary>, ApiError> {
+    require_calendar(&state, user.id, calendar_id, CalendarCapability::AddEvent).await?;
+    let (events, skipped) = parse_events(&body);
+    let database = state.database.clone();
+    let (imported, duplicates) = run_blocking(move || -> Result<_, ApiError> {
+        let mut conn = database.get()?;
+        let mut imported = Vec::new();
+        let mut duplicates = 0;
+        for event in events {
+            let new_event = event.to_new_event();
+            let inserted = match &event.uid {
+                Some(uid) => conn.import_event(calendar_id, uid, &new_event)?,
+                // Without a UID there's nothing to deduplicate on
+                None => Some(conn.insert_new_event(calendar_id, &new_event)?),
+            };
+            match inserted {
+                Some(event_id) => imported.push(event_id),
+                None => duplicates += 1,
+            }
+        }
+        Ok((imported, duplicates))
+    })
+    .await?;
+    for &event_id in &imported {
+        notify_event_changed(&state, calendar_id, event_id, EventChange::Created).await;
+    }
+    Ok(Json(ImportSummary {
+        imported: imported.len(),
+        duplicates,
+        skipped,
+    }))
+}
+
+/// `GET /api/calendars/{id}/export`: every event in the calendar as a `text/calendar` document.
+async fn export_calendar(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(calendar_id): Path<i64>,
+) -> Result<impl IntoResponse, ApiError> {
+    require_calendar(&state, user.id, calendar_id, CalendarCapability::View).await?;
+    let database = state.database.clone();
+    let (events, uids) = run_blocking(move || -> Result<_, ApiError> {
+        let conn = database.get()?;
+        Ok((
+            conn.list_events_by_calendar(calendar_id)?,
+            conn.list_event_uids(calendar_id)?,
+        ))
+    })
+    .await?;
+    Ok((
+        [(header::CONTENT_TYPE, "text/calendar; charset=utf-8")],
+        write_calendar(&events, &uids),
+    ))
+}
+
+/// Write events as an iCalendar document. All-day events get DATE values with the exclusive
+/// DTEND iCalendar expects, timed events get UTC DATE-TIME values. Events keep the UID they
+/// were imported with (`uids`, by event id) so other calendars can match them up, the rest
+/// get one made from their id.
+pub fn write_calendar(events: &[Event], uids: &HashMap<i64, String>) -> String {
+    let mut out = String::new();
+    let mut line = |text: String| push_folded(&mut out, &text);
+    line("BEGIN:VCALENDAR".to_string());
+    line("VERSION:2.0".to_string());
+    line("PRODID:-//CoreCalendar//EN".to_string());
+    for event in events {
+        line("BEGIN:VEVENT".to_string());
+        match uids.get(&event.id) {
+            Some(uid) => line(format!("UID:{uid}")),
+            None => line(format!("UID:event-{}@corecalendar", event.id)),
+        }
+        line(format!("DTSTAMP:{}", format_datetime(&event.updated_at)));
+        if event.all_day {
+            // The dates the event covers in its own time zone
+            let end = event.local_end().date_naive() + Duration::days(1);
+            line(format!(
+                "DTSTART;VALUE=DATE:{}",
+                event.local_start().format("%Y%m%d")
+            ));
+            line(format!("DTEND;VALUE=DATE:{}", end.format("%Y%m%d")));
+        } else {
+            line(format!("DTSTART:{}", format_datetime(&event.start_time)));
+            line(format!("DTEND:{}", format_datetime(&event.end_time)));
+        }
+        line(format!("SUMMARY:{}", escape_text(&event.title)));
+        if let Some(description) = &event.description {
+            line(format!("DESCRIPTION:{}", escape_text(description)));
+        }
+        line("END:VEVENT".to_string());
+    }
+    line("END:VCALENDAR".to_string());
+    out
+}
+
+fn format_datetime(time: &DateTime<Utc>) -> String {
+    time.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// Append a content line, folding it so no line is longer than 75 octets.
+fn push_folded(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// TEXT escaping, the inverse of `unescape_text`.
+fn escape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' | ',' | ';' => {
+                out.push('\\');
+                out.push(c);
+            }
+            '\n' => out.push_str("\\n"),
+            '\r' => {}
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Read every VEVENT in an iCalendar document, returning the events that parsed and how many didn't.
+pub fn parse_events(text: &str) -> (Vec<IcalEvent>, usize) {
+    let mut events = Vec::new();
+    let mut skipped = 0;
+    // Properties of the VEVENT being read, `None` outside of one
+    let mut current: Option<Vec<(String, Vec<String>, String)>> = None;
+    // Depth of components nested inside the VEVENT (like VALARM), whose properties are ignored
+    let mut nested: u32 = 0;
+
+    for line in unfold_lines(text) {
+        let Some((name, params, value)) = split_content_line(&line) else {
+            continue;
+        };
+        match (name.as_str(), value.to_ascii_uppercase().as_str()) {
+            ("BEGIN", "VEVENT") if current.is_none() => {
+                current = Some(Vec::new());
+                nested = 0;
+            }
+            ("END", "VEVENT") if nested == 0 => {
+                if let Some(props) = current.take() {
+                    match event_from_props(&props) {
+                        Ok(event) => events.push(event),
+                        Err(e) => {
+                            warn!("Skipping malformed VEVENT in iCalendar import: {e}");
+                            skipped += 1;
+                        }
+                    }
+                }
+            }
+            ("BEGIN", _) if current.is_some() => nested += 1,
+            // A stray END without its BEGIN mustn't hide the rest of the event
+            ("END", _) if current.is_some() => nested = nested.saturating_sub(1),
+            _ => {
+                if let Some(props) = current.as_mut()
+                    && nested == 0
+                {
+                    props.push((name, params, value));
+                }
+            }
+        }
+    }
+    (events, skipped)
+}
+
+/// Undo line folding: a line starting with a space or tab continues the previous one.
+fn unfold_lines(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for raw in text.split('\n') {
+        let raw = raw.strip_suffix('\r').unwrap_or(raw);
+        match (raw.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ if raw.is_empty() => {}
+            _ => lines.push(raw.to_string()),
+        }
+    }
+    lines
+}
+
+/// Split `NAME;PARAM=x;PARAM=y:value` into its upper-cased name, its parameters (with upper-cased
+/// names, values as written) and its value. Colons inside quoted parameter values don't end the
+/// parameters.
+fn split_content_line(line: &str) -> Option<(String, Vec<String>, String)> {
+    let mut in_quotes = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            in_quotes = !in_quotes;
+            None
+        }
+        ':' if !in_quotes => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let mut parts = head.split(';');
+    let name = parts.next()?.trim().to_ascii_uppercase();
+    let params = parts
+        .map(|p| match p.split_once('=') {
+            Some((name, value)) => format!("{}={value}", name.trim().to_ascii_uppercase()),
+            None => p.to_ascii_uppercase(),
+        })
+        .collect();
+    Some((name, params, value.to_string()))
+}
+
+fn event_from_props(props: &[(String, Vec<String>, String)]) -> Result<IcalEvent, String> {
+    let find = |name: &str| props.iter().find(|(n, _, _)| n == name);
+
+    let (_, start_params, start_value) = find("DTSTART").ok_or("missing DTSTART")?;
+    let (start_time, all_day) = parse_datetime(start_params, start_value)?;
+    // A date is stored as midnight UTC, so it's the same day only read in UTC
+    let timezone = tzid(start_params).filter(|_| !all_day).map(str::to_string);
+    let end_time = match find("DTEND") {
+        Some((_, params, value)) => parse_datetime(params, value)?.0,
+        // Per RFC 5545 an all-day event without an end lasts the day, anything else is instantaneous
+        None if all_day => start_time + Duration::days(1),
+        None => start_time,
+    };
+    if end_time < start_time {
+        return Err("DTEND is before DTSTART".to_string());
+    }
+
+    Ok(IcalEvent {
+        all_day,
+        timezone,
+        uid: find("UID")
+            .map(|(_, _, v)| v.trim().to_string())
+            .filter(|uid| !uid.is_empty()),
+        title: find("SUMMARY")
+            .map(|(_, _, v)| unescape_text(v))
+            .unwrap_or_else(|| "Untitled".to_string()),
+        description: find("DESCRIPTION")
+            .map(|(_, _, v)| unescape_text(v))
+            .filter(|d| !d.is_empty()),
+        start_time,
+        end_time,
+    })
+}
+
+/// The zone name of a property's `TZID` parameter, if it has one.
+fn tzid(params: &[String]) -> Option<&str> {
+    params
+        .iter()
+        .find_map(|p| p.strip_prefix("TZID="))
+        .map(|name| name.trim_matches('"'))
+}
+
+/// Parse a DATE or DATE-TIME value, also returning whether it was a date (an all-day event).
+/// UTC times end in `Z`, times with a `TZID` are local to that IANA zone and floating times are
+/// taken as UTC. Local times a DST change makes ambiguous resolve to the earlier instant, and
+/// ones it skips use the offset from before the change, as RFC 5545 says.
+fn parse_datetime(params: &[String], value: &str) -> Result<(DateTime<Utc>, bool), String> {
+    let value = value.trim();
+    let is_date = params.iter().any(|p| p.eq_ignore_ascii_case("VALUE=DATE")) || value.len() == 8;
+    if is_date {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d")
+            .map_err(|e| format!("invalid date {value:?}: {e}"))?;
+        return Ok((date.and_hms_opt(0, 0, 0).unwrap().and_utc(), true));
+    }
+    let (local, is_utc) = match value.strip_suffix(['Z', 'z']) {
+        Some(local) => (local, true),
+        None => (value, false),
+    };
+    let time = NaiveDateTime::parse_from_str(local, "%Y%m%dT%H%M%S")
+        .map_err(|e| format!("invalid date-time {value:?}: {e}"))?;
+    let Some(name) = tzid(params).filter(|_| !is_utc) else {
+        return Ok((time.and_utc(), false));
+    };
+    let tz = db::parse_timezone(name).map_err(|_| format!("unknown TZID {name:?}"))?;
+    let utc = match tz.from_local_datetime(&time) {
+        LocalResult::Single(t) | LocalResult::Ambiguous(t, _) => t.with_timezone(&Utc),
+        LocalResult::None => {
+            // In a gap, a day earlier is safely before it
+            let before = tz
+                .offset_from_utc_datetime(&(time - Duration::days(1)))
+                .fix();
+            (time - Duration::seconds(before.local_minus_utc().into())).and_utc()
+        }
+    };
+    Ok((utc, false))
+}
+
+/// Undo TEXT escaping (`\n`, `\,`, `\;`, `\\`).
+fn unescape_text(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => out.push('\n'),
+            Some(other) => out.push(other),
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_router;
+    use crate::test_util::{owner_of_calendar, response_json, test_state};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode, header};
+    use chrono::TimeZone;
+    use serde_json::json;
+    use tower::ServiceExt;
+
+    const TWO_EVENTS: &str = "BEGIN:VCALENDAR\r\n\
+VERSION:2.0\r\n\
+PRODID:-//Test//EN\r\n\
+BEGIN:VEVENT\r\n\
+UID:dentist@example.com\r\n\
+DTSTART:20250314T093000Z\r\n\
+DTEND:20250314T104500Z\r\n\
+SUMMARY:Dentist\r\n\
+DESCRIPTION:Bring forms\\, insurance card\\nand ID\r\n\
+BEGIN:VALARM\r\n\
+ACTION:DISPLAY\r\n\
+DESCRIPTION:Reminder\r\n\
+END:VALARM\r\n\
+END:VEVENT\r\n\
+BEGIN:VEVENT\r\n\
+UID:picnic@example.com\r\n\
+DTSTART:20250601T120000\r\n\
+DTEND:20250601T15\r\n 0000\r\n\
+SUMMARY:Picnic\r\n\
+END:VEVENT\r\n\
+END:VCALENDAR\r\n";
+
+    #[test]
+    fn test_parse_events() {
+        let (events, skipped) = parse_events(TWO_EVENTS);
+        assert_eq!(skipped, 0);
+        assert_eq!(
+            events,
+            vec![
+                IcalEvent {
+                    uid: Some("dentist@example.com".to_string()),
+                    title: "Dentist".to_string(),
+                    description: Some("Bring forms, insurance card\nand ID".to_string()),
+                    start_time: Utc.with_ymd_and_hms(2025, 3, 14, 9, 30, 0).unwrap(),
+                    end_time: Utc.with_ymd_and_hms(2025, 3, 14, 10, 45, 0).unwrap(),
+                    all_day: false,
+                    timezone: None,
+                },
+                IcalEvent {
+                    uid: Some("picnic@example.com".to_string()),
+                    title: "Picnic".to_string(),
+                    description: None,
+                    start_time: Utc.with_ymd_and_hms(2025, 6, 1, 12, 0, 0).unwrap(),
+                    end_time: Utc.with_ymd_and_hms(2025, 6, 1, 15, 0, 0).unwrap(),
+                    all_day: false,
+                    timezone: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_skips_malformed_events() {
+        let text = "BEGIN:VCALENDAR\n\
+BEGIN:VEVENT\nUID:a\nSUMMARY:No start\nEND:VEVENT\n\
+BEGIN:VEVENT\nUID:b\nDTSTART:not-a-date\nEND:VEVENT\n\
+BEGIN:VEVENT\nUID:c\nDTSTART:20250102T100000Z\nDTEND:20250101T100000Z\nEND:VEVENT\n\
+BEGIN:VEVENT\nUID:d\nDTSTART;VALUE=DATE:20250704\nSUMMARY:Holiday\nEND:VEVENT\n\
+END:VCALENDAR\n";
+        let (events, skipped) = parse_events(text);
+        assert_eq!(skipped, 3);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Holiday");
+        assert_eq!(events[0].end_time - events[0].start_time, Duration::days(1));
+        assert!(events[0].all_day);
+        // Stored, the holiday ends on the day it starts
+        let stored = events[0].to_new_event();
+        assert!(stored.all_day);
+        assert_eq!(stored.end_time, stored.start_time);
+    }
+
+    #[test]
+    fn test_tzid_times_are_local_to_the_zone() {
+        let event = |start: &str, end: &str| {
+            let text = format!("BEGIN:VEVENT\n{start}\n{end}\nEND:VEVENT\n");
+            let (events, skipped) = parse_events(&text);
+            assert_eq!(skipped, 0, "{start}");
+            events.into_iter().next().unwrap()
+        };
+        let utc = |d, h, m| Utc.with_ymd_and_hms(2025, 3, d, h, m, 0).unwrap();
+
+        let dentist = event(
+            "DTSTART;TZID=America/New_York:20250314T093000",
+            "DTEND;TZID=\"Europe/London\":20250314T150000",
+        );
+        assert_eq!(dentist.start_time, utc(14, 13, 30));
+        assert_eq!(dentist.end_time, utc(14, 15, 0));
+        assert_eq!(dentist.timezone.as_deref(), Some("America/New_York"));
+        assert_eq!(dentist.to_new_event().timezone, "America/New_York");
+
+        // 02:30 doesn't exist on the morning clocks spring forward, EST's offset applies
+        let skipped = event(
+            "DTSTART;TZID=America/New_York:20250309T023000",
+            "DTEND:20250309T080000Z",
+        );
+        assert_eq!(skipped.start_time, utc(9, 7, 30));
+        // 01:30 happens twice when they fall back, the first (EDT) one is meant
+        let repeated = event(
+            "DTSTART;TZID=America/New_York:20251102T013000",
+            "DTEND:20251102T060000Z",
+        );
+        assert_eq!(
+            repeated.start_time,
+            Utc.with_ymd_and_hms(2025, 11, 2, 5, 30, 0).unwrap()
+        );
+
+        // A trailing Z wins over the TZID, and untagged events stay in UTC
+        let utc_event = event(
+            "DTSTART;TZID=America/New_York:20250314T093000Z",
+            "DTEND:20250314T100000Z",
+        );
+        assert_eq!(utc_event.start_time, utc(14, 9, 30));
+        assert_eq!(
+            event("DTSTART:20250314T093000Z", "")
+                .to_new_event()
+                .timezone,
+            "UTC"
+        );
+
+        let (events, skipped) = parse_events(
+            "BEGIN:VEVENT\nDTSTART;TZID=Mars/Olympus_Mons:20250314T093000\nEND:VEVENT\n",
+        );
+        assert!(events.is_empty());
+        assert_eq!(skipped, 1);
+    }
+
+    #[test]
+    fn test_stray_end_does_not_hide_the_event() {
+        let text = "BEGIN:VEVENT\n\
+END:VALARM\n\
+DTSTART:20250314T093000Z\n\
+SUMMARY:Dentist\n\
+BEGIN:VALARM\nDESCRIPTION:Reminder\nEND:VALARM\n\
+END:VEVENT\n";
+        let (events, skipped) = parse_events(text);
+        assert_eq!(skipped, 0);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].title, "Dentist");
+    }
+
+    fn import_request(calendar_id: i64, token: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/api/calendars/{calendar_id}/import"))
+            .header(header::CONTENT_TYPE, "text/calendar")
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    fn export_request(calendar_id: i64, token: &str) -> Request<Body> {
+        Request::builder()
+            .uri(format!("/api/calendars/{calendar_id}/export"))
+            .header(header::AUTHORIZATION, format!("Bearer {token}"))
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    async fn response_text(response: axum::response::Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_import_and_reimport() {
+        let state = test_state();
+        let (token, calendar_id) = owner_of_calendar(&state, "alice");
+        let app = build_router(state.clone()).await;
+
+        let response = app
+            .clone()
+            .oneshot(import_request(calendar_id, &token, TWO_EVENTS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response_json(response).await,
+            json!({ "imported": 2, "duplicates": 0, "skipped": 0 })
+        );
+
+        let events = state
+            .database
+            .get()
+            .unwrap()
+            .list_events_in_range(
+                calendar_id,
+                Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+            )
+            .unwrap();
+        let titles: Vec<&str> = events.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, vec!["Dentist", "Picnic"]);
+
+        // Importing the same file again adds nothing
+        let response = app
+            .clone()
+            .oneshot(import_request(calendar_id, &token, TWO_EVENTS))
+            .await
+            .unwrap();
+        assert_eq!(
+            response_json(response).await,
+            json!({ "imported": 0, "duplicates": 2, "skipped": 0 })
+        );
+        let count = state
+            .database
+            .get()
+            .unwrap()
+            .list_events_in_range(
+                calendar_id,
+                Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap(),
+                Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap(),
+            )
+            .unwrap()
+            .len();
+        assert_eq!(count, 2);
+
+        let response = app
+            .clone()
+            .oneshot(import_request(calendar_id + 1, &token, TWO_EVENTS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        // The export gives the events back under the UIDs they were imported with
+        let response = app
+            .oneshot(export_request(calendar_id, &token))
+            .await
+            .unwrap();
+        let text = response_text(response).await;
+        assert!(text.contains("UID:dentist@example.com\r\n"));
+        assert!(text.contains("UID:picnic@example.com\r\n"));
+        assert!(!text.contains("@corecalendar"));
+    }
+
+    #[tokio::test]
+    async fn test_import_and_export_check_calendar_permissions() {
+        let state = test_state();
+        let (_, calendar_id) = owner_of_calendar(&state, "alice");
+        let (bob, _) = owner_of_calendar(&state, "bob");
+        let app = build_router(state.clone()).await;
+
+        let response = app
+            .clone()
+            .oneshot(import_request(calendar_id, &bob, TWO_EVENTS))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        let response = app
+            .clone()
+            .oneshot(export_request(calendar_id, &bob))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let anonymous = Request::builder()
+            .uri(format!("/api/calendars/{calendar_id}/export"))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(anonymous).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            state
+                .database
+                .get()
+                .unwrap()
+                .list_events_by_calendar(calendar_id)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_export_all_day_and_timed_events() {
+        let state = test_state();
+        let (token, calendar_id) = owner_of_calendar(&state, "alice");
+        let conn = state.database.get().unwrap();
+        conn.insert_new_event(
+            calendar_id,
+            &NewEvent {
+                all_day: true,
+                ..NewEvent::new(
+                    "Holiday",
+                    None,
+                    Utc.with_ymd_and_hms(2025, 7, 4, 9, 0, 0).unwrap(),
+                    Utc.with_ymd_and_hms(2025, 7, 5, 9, 0, 0).unwrap(),
+                )
+            },
+        )
+        .unwrap();
+        conn.insert_event(
+            calendar_id,
+            "Dentist",
+            Some("Bring forms, insurance card"),
+            Utc.with_ymd_and_hms(2025, 3, 14, 9, 30, 0).unwrap(),
+            Utc.with_ymd_and_hms(2025, 3, 14, 10, 45, 0).unwrap(),
+        )
+        .unwrap();
+        drop(conn);
+        let app = build_router(state).await;
+
+        let response = app
+            .oneshot(export_request(calendar_id, &token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()[header::CONTENT_TYPE],
+            "text/calendar; charset=utf-8"
+        );
+        let text = response_text(response).await;
+        assert!(text.contains("DTSTART;VALUE=DATE:20250704\r\n"));
+        assert!(text.contains("DTEND;VALUE=DATE:20250706\r\n"));
+        assert!(text.contains("DTSTART:20250314T093000Z\r\n"));
+        assert!(text.contains("DESCRIPTION:Bring forms\\, insurance card\r\n"));
+
+        // The export reads back as the same events
+        let (events, skipped) = parse_events(&text);
+        assert_eq!(skipped, 0);
+        assert_eq!(events.len(), 2);
+        assert!(events[1].all_day);
+        assert_eq!(events[1].title, "Holiday");
+        assert_eq!(
+            events[1].to_new_event().end_time.date_naive(),
+            NaiveDate::from_ymd_opt(2025, 7, 5).unwrap()
+        );
+    }
+}
@@ -0,0 +1,185 @@
+//! JSON API served under `/api`.
+
+use ::auth::AuthError;
+use appstate::AppState;
+use axum::{
+    Json, Router,
+    extract::{ConnectInfo, FromRequestParts},
+    http::{StatusCode, request::Parts},
+    response::{IntoResponse, Response},
+};
+use permissions::{CalendarCapability, PermissionError, UserId};
+use serde::Serialize;
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use tracing::*;
+
+mod auth;
+mod events;
+mod extract;
+mod ical;
+mod sessions;
+
+pub use extract::AuthenticatedUser;
+
+/// All `/api` routes, to be nested under the main router.
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .merge(auth::router())
+        .merge(events::router())
+        .merge(ical::router())
+        .merge(sessions::router())
+}
+
+/// The peer's IP address, for rate limiting.
+/// Unspecified (`0.0.0.0`) when the server wasn't started with connect info, as in tests.
+pub struct ClientIp(pub IpAddr);
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let ip = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        Ok(ClientIp(ip))
+    }
+}
+
+/// Run blocking work (database access, password hashing) off the async runtime.
+pub(crate) async fn run_blocking<T, E>(
+    f: impl FnOnce() -> Result<T, E> + Send + 'static,
+) -> Result<T, ApiError>
+where
+    T: Send + 'static,
+    E: Into<ApiError> + Send + 'static,
+{
+    tokio::task::spawn_blocking(f)
+        .await
+        .map_err(|e| ApiError::Internal(format!("blocking task failed: {e}")))?
+        .map_err(Into::into)
+}
+
+/// Check that a calendar exists (404 if not) and that `user` holds `capability` on it (403 if
+/// not), before a handler touches its contents.
+pub(crate) async fn require_calendar(
+    state: &AppState,
+    user: UserId,
+    calendar_id: i64,
+    capability: CalendarCapability,
+) -> Result<(), ApiError> {
+    let database = state.database.clone();
+    run_blocking(move || -> Result<_, ApiError> {
+        database
+            .get()?
+            .get_calendar_by_id(calendar_id)?
+            .ok_or(ApiError::NotFound)
+    })
+    .await?;
+    Ok(state
+        .permissions
+        .require_calendar(user, calendar_id, capability)
+        .await?)
+}
+
+/// Error returned by API handlers, rendered as `{"error": "..."}` with a matching status code.
+#[derive(Debug)]
+pub enum ApiError {
+    NotFound,
+    BadRequest(String),
+    Unauthorized,
+    /// Authenticated, but not allowed (yet)
+    Forbidden(String),
+    Conflict(String),
+    Locked,
+    TooManyRequests,
+    /// The server is at capacity (e.g. the websocket connection limit), try again later
+    ServiceUnavailable,
+    Internal(String),
+}
+
+#[derive(Serialize)]
+struct ErrorBody {
+    error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let (status, error) = match self {
+            ApiError::NotFound => (StatusCode::NOT_FOUND, "not found".to_string()),
+            ApiError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg),
+            ApiError::Unauthorized => (StatusCode::UNAUTHORIZED, "unauthorized".to_string()),
+            ApiError::Forbidden(msg) => (StatusCode::FORBIDDEN, msg),
+            ApiError::Conflict(msg) => (StatusCode::CONFLICT, msg),
+            ApiError::Locked => (
+                StatusCode::LOCKED,
+                "account locked, try again later".to_string(),
+            ),
+            ApiError::TooManyRequests => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "too many requests".to_string(),
+            ),
+            ApiError::ServiceUnavailable => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "server at capacity, try again later".to_string(),
+            ),
+            ApiError::Internal(msg) => {
+                // Details go to the log, not the client
+                error!("API request failed: {msg}");
+                (
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "internal error".to_string(),
+                )
+            }
+        };
+        (status, Json(ErrorBody { error })).into_response()
+    }
+}
+
+impl From<AuthError> for ApiError {
+    fn from(e: AuthError) -> Self {
+        match e {
+            AuthError::UserAlreadyExists => ApiError::Conflict("user already exists".to_string()),
+            AuthError::UserNotFound => ApiError::NotFound,
+            AuthError::InvalidPassword | AuthError::Unauthorized => ApiError::Unauthorized,
+            AuthError::RateLimitExceeded => ApiError::TooManyRequests,
+            AuthError::AccountLocked => ApiError::Locked,
+            AuthError::InvalidEmail => ApiError::BadRequest("invalid email".to_string()),
+            AuthError::InvalidUsername => ApiError::BadRequest("invalid username".to_string()),
+            AuthError::InviteAlreadyUsed => ApiError::Conflict("invite already used".to_string()),
+            AuthError::SessionNotFound => ApiError::NotFound,
+            AuthError::EmailNotVerified => ApiError::Forbidden("email not verified".to_string()),
+            e @ (AuthError::DbError(_) | AuthError::PoolError(_) | AuthError::JwtError(_)) => {
+                ApiError::Internal(e.to_string())
+            }
+        }
+    }
+}
+
+impl From<PermissionError> for ApiError {
+    fn from(e: PermissionError) -> Self {
+        match e {
+            PermissionError::Denied { .. } => ApiError::Forbidden("permission denied".to_string()),
+            PermissionError::CalendarDenied { .. } => {
+                ApiError::Forbidden("not allowed on this calendar".to_string())
+            }
+        }
+    }
+}
+
+impl From<db::Error> for ApiError {
+    fn from(e: db::Error) -> Self {
+        match e {
+            db::Error::NotFound => ApiError::NotFound,
+            e => ApiError::Internal(e.to_string()),
+        }
+    }
+}
+
+impl From<r2d2::Error> for ApiError {
+    fn from(e: r2d2::Error) -> Self {
+        ApiError::Internal(format!("database pool error: {e}"))
+    }
+}
@@ -0,0 +1,121 @@
+use super::{ApiError, AuthenticatedUser, run_blocking};
+use ::auth::Session;
+use appstate::AppState;
+use axum::{
+    Json, Router,
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get},
+};
+
+pub(super) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/{jti}", delete(revoke_session))
+}
+
+/// `GET /api/sessions`: the caller's access and refresh tokens still in use, newest first.
+async fn list_sessions(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+) -> Result<Json<Vec<Session>>, ApiError> {
+    let auth = state.auth.clone();
+    let sessions = run_blocking(move || auth.list_sessions(&user.username)).await?;
+    Ok(Json(sessions))
+}
+
+/// `DELETE /api/sessions/{jti}`: responds 204 once the session's token is revoked, or 404 if
+/// there's no such session the caller may revoke (their own, or anyone's for global admins).
+async fn revoke_session(
+    State(state): State<AppState>,
+    AuthenticatedUser(user): AuthenticatedUser,
+    Path(jti): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let auth = state.auth.clone();
+    run_blocking(move || auth.revoke_session(&jti, &user)).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::build_router;
+    use crate::test_util::{authed_request, json_request, response_json, test_state};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_revoked_session_stops_working() {
+        let state = test_state();
+        let app = build_router(state.clone()).await;
+        let first = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let login = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/login",
+                json!({"username": "alice", "password": "pw"}),
+            ))
+            .await
+            .unwrap();
+        let second = response_json(login).await["token"]
+            .as_str()
+            .unwrap()
+            .to_string();
+
+        let listed = app
+            .clone()
+            .oneshot(authed_request("GET", "/api/sessions", &first, Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(listed.status(), StatusCode::OK);
+        let sessions = response_json(listed).await;
+        let sessions = sessions.as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+        assert!(sessions.iter().all(|s| s["token_type"] == "access"));
+        // Newest first, so the login's session leads
+        let second_jti = sessions[0]["jti"].as_str().unwrap().to_string();
+
+        let revoked = app
+            .clone()
+            .oneshot(authed_request(
+                "DELETE",
+                &format!("/api/sessions/{second_jti}"),
+                &first,
+                Value::Null,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(revoked.status(), StatusCode::NO_CONTENT);
+
+        let with_revoked = app
+            .clone()
+            .oneshot(authed_request("GET", "/api/sessions", &second, Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(with_revoked.status(), StatusCode::UNAUTHORIZED);
+        let with_other = app
+            .clone()
+            .oneshot(authed_request("GET", "/api/sessions", &first, Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(with_other.status(), StatusCode::OK);
+        assert_eq!(response_json(with_other).await.as_array().unwrap().len(), 1);
+
+        let again = app
+            .oneshot(authed_request(
+                "DELETE",
+                &format!("/api/sessions/{second_jti}"),
+                &first,
+                Value::Null,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(again.status(), StatusCode::NOT_FOUND);
+    }
+}
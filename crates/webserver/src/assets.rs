@@ -0,0 +1,133 @@
+use axum::{
+    Router,
+    http::{StatusCode, Uri, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use tower_http::services::ServeDir;
+
+/// The frontend built into the binary: (path, content type, contents).
+const EMBEDDED_ASSETS: &[(&str, &str, &str)] = &[
+    (
+        "index.html",
+        "text/html",
+        include_str!("../html_src/index.html"),
+    ),
+    (
+        "calendar.js",
+        "text/javascript",
+        include_str!("../html_src/calendar.js"),
+    ),
+    (
+        "calendar-extras.js",
+        "text/javascript",
+        include_str!("../html_src/calendar-extras.js"),
+    ),
+    (
+        "calendar.css",
+        "text/css",
+        include_str!("../html_src/calendar.css"),
+    ),
+    (
+        "components/CustomColorSelect.js",
+        "text/javascript",
+        include_str!("../html_src/components/CustomColorSelect.js"),
+    ),
+];
+
+/// Serve the frontend for every route not otherwise matched. With a `static_dir` files are read
+/// from disk (so the UI can be changed without a rebuild), anything missing there comes from the
+/// embedded copy.
+pub(crate) fn with_static_files<S>(router: Router<S>, static_dir: Option<&str>) -> Router<S>
+where
+    S: Clone + Send + Sync + 'static,
+{
+    match static_dir {
+        Some(dir) => router.fallback_service(
+            ServeDir::new(dir)
+                .append_index_html_on_directories(true)
+                .precompressed_gzip()
+                .precompressed_br()
+                .precompressed_deflate()
+                .fallback(get(embedded_asset)),
+        ),
+        None => router.fallback_service(get(embedded_asset)),
+    }
+}
+
+async fn embedded_asset(uri: Uri) -> Response {
+    let mut path = uri.path().trim_start_matches('/').to_string();
+    if path.is_empty() || path.ends_with('/') {
+        path.push_str("index.html");
+    }
+    match EMBEDDED_ASSETS.iter().find(|(name, _, _)| *name == path) {
+        Some((_, content_type, contents)) => {
+            ([(header::CONTENT_TYPE, *content_type)], *contents).into_response()
+        }
+        None => (StatusCode::NOT_FOUND, "File not found").into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use tower::ServiceExt;
+
+    async fn get_file(router: Router, uri: &str) -> (StatusCode, String, String) {
+        let response = router
+            .oneshot(Request::builder().uri(uri).body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .map(|v| v.to_str().unwrap().to_string())
+            .unwrap_or_default();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            content_type,
+            String::from_utf8(bytes.to_vec()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_file_on_disk_is_served() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("calendar.css"), "body { color: red; }").unwrap();
+        let router = with_static_files(Router::new(), dir.path().to_str());
+
+        let (status, content_type, body) = get_file(router, "/calendar.css").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/css"));
+        assert_eq!(body, "body { color: red; }");
+    }
+
+    #[tokio::test]
+    async fn test_missing_file_falls_back_to_embedded() {
+        let dir = tempfile::tempdir().unwrap();
+        let router = with_static_files(Router::new(), dir.path().to_str());
+
+        let (status, content_type, body) = get_file(router.clone(), "/calendar.js").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/javascript");
+        assert_eq!(body, include_str!("../html_src/calendar.js"));
+
+        let (status, _, _) = get_file(router, "/nope.js").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_embedded_index_without_static_dir() {
+        let (status, content_type, body) =
+            get_file(with_static_files(Router::new(), None), "/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "text/html");
+        assert_eq!(body, include_str!("../html_src/index.html"));
+    }
+}
@@ -0,0 +1,102 @@
+//! Liveness and readiness probes for load balancers and orchestrators. Neither needs authentication.
+
+use appstate::AppState;
+use axum::{Json, Router, extract::State, http::StatusCode, routing::get};
+use global_constants::READINESS_DB_TIMEOUT_MILLIS;
+use serde::Serialize;
+use std::time::Duration;
+use tracing::*;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new()
+        .route("/health", get(health))
+        .route("/ready", get(ready))
+}
+
+#[derive(Serialize)]
+struct Status {
+    status: &'static str,
+}
+
+/// The process is up and serving requests.
+async fn health() -> Json<Status> {
+    Json(Status { status: "ok" })
+}
+
+/// The server can actually do work: a database connection can be checked out and queried.
+async fn ready(State(state): State<AppState>) -> (StatusCode, Json<Status>) {
+    let pool = state.database.clone();
+    let check = tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let conn = pool
+            .get_timeout(Duration::from_millis(READINESS_DB_TIMEOUT_MILLIS))
+            .map_err(|e| e.to_string())?;
+        conn.ping().map_err(|e| e.to_string())
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.to_string()));
+    match check {
+        Ok(()) => (StatusCode::OK, Json(Status { status: "ok" })),
+        Err(e) => {
+            warn!("Readiness check failed, database unavailable: {e}");
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(Status {
+                    status: "unavailable",
+                }),
+            )
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{json_request, response_json, test_state};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_health_is_ok() {
+        let app = crate::build_router(test_state()).await;
+        let response = app
+            .oneshot(json_request("GET", "/health", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response_json(response).await, json!({ "status": "ok" }));
+    }
+
+    #[tokio::test]
+    async fn test_ready_checks_database() {
+        let mut config = config::Config::default();
+        config.database.pool_size = 1;
+        let state = appstate::AppState::new_in_memory(config);
+        let app = crate::build_router(state.clone()).await;
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", "/ready", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Hold the only connection so the probe can't get one
+        let _held = state.database.get().unwrap();
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", "/ready", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(
+            response_json(response).await,
+            json!({ "status": "unavailable" })
+        );
+
+        // Liveness doesn't depend on the database
+        let response = app
+            .oneshot(json_request("GET", "/health", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -1,101 +1,439 @@
 use appstate::AppState;
-use axum::http::StatusCode;
+use axum::http::{Extensions, HeaderMap, HeaderValue, Method, StatusCode, Version, header};
 use axum::{
     Router,
     extract::{
-        State,
-        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade, close_code},
     },
     response::IntoResponse,
     routing::get,
     serve,
 };
+use axum_server::tls_rustls::RustlsConfig;
+use config::{CorsConfig, NetworkConfig, TlsConfig};
 use futures_util::{SinkExt, StreamExt};
-use std::net::SocketAddr;
+use global_constants::{WS_AUTH_EXPIRY_CHECK_SECONDS, WS_AUTH_GRACE_SECONDS};
+use permissions::UserId;
+use serde::Deserialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
 use tokio::{net::TcpListener, sync::mpsc};
-use tower_http::services::ServeDir;
+use tower_http::compression::{
+    CompressionLayer,
+    predicate::{DefaultPredicate, Predicate},
+};
+use tower_http::cors::{AllowOrigin, CorsLayer};
 use tracing::*;
 
+pub mod api;
+mod assets;
+mod health;
+mod metrics;
+mod rate_limit;
+mod request_log;
+
 ///entry point for the web server, gets a copy of state for its own use, state is Arc on everything so its a global state
 
 pub async fn start_web_server(state: AppState) {
-    let static_dir = "crates/webserver/html_src";
-    let app = Router::new()
-        .route("/ws", get(ws_handler))
-        .with_state(state.clone())
-        .fallback_service(
-            ServeDir::new(static_dir)
-                .append_index_html_on_directories(true)
-                .precompressed_gzip()
-                .precompressed_br()
-                .precompressed_deflate()
-                .fallback(axum::routing::get(|| async {
-                    (StatusCode::NOT_FOUND, "File not found")
-                })),
-        );
-
-    // Get interface and port from config in AppState
-    let config_guard = state.config.lock().await;
-    let interface = config_guard.network.interface.clone();
-    let port = config_guard.network.port;
-    drop(config_guard);
-
-    let addr = format!("{}:{}", interface, port)
-        .parse::<SocketAddr>()
-        .expect("Invalid interface or port in config");
-
-    log_listen_address(addr);
-
-    let listener = TcpListener::bind(addr)
+    // Let connected clients know when someone's permissions change
+    websockets::broadcast_permission_changes(&state);
+    // Get interface, port and TLS settings from config in AppState
+    let (network, tls) = {
+        let config = state.config.lock().await;
+        (config.network.clone(), config.tls.clone())
+    };
+    // Load the certificate before binding so a bad path fails before anything listens
+    let tls = match tls {
+        Some(tls) => Some(load_tls_config(&tls).await),
+        None => None,
+    };
+    let listener = bind_listener(&network)
         .await
         .expect("Failed to bind address");
-    serve(listener, app.into_make_service())
+    if let Some(metrics_network) = state.config.lock().await.metrics_listen.clone() {
+        serve_metrics_on(&metrics_network, state.clone()).await;
+    }
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    log_listen_address(
+        listener
+            .local_addr()
+            .expect("Bound listener has no address"),
+        scheme,
+        network.dual_stack,
+    );
+    serve_on(listener, state, tls).await;
+    info!("Web server shut down");
+}
+
+/// Load and validate the certificate chain and private key, panicking with the paths if either
+/// can't be read or parsed, since serving plain HTTP instead would silently drop the protection asked for.
+pub async fn load_tls_config(tls: &TlsConfig) -> RustlsConfig {
+    RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+        .await
+        .unwrap_or_else(|e| {
+            panic!(
+                "Failed to load TLS certificate {:?} and key {:?}: {}",
+                tls.cert_path, tls.key_path, e
+            )
+        })
+}
+
+/// Serve the app on an already bound listener, over HTTPS when `tls` is given,
+/// until the state's shutdown token is cancelled.
+async fn serve_on(listener: TcpListener, state: AppState, tls: Option<RustlsConfig>) {
+    let app = build_router(state.clone())
+        .await
+        .into_make_service_with_connect_info::<SocketAddr>();
+    let shutdown = state.shutdown_token.clone();
+    match tls {
+        None => serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+            .expect("Failed to start Axum server"),
+        Some(tls) => {
+            let handle = axum_server::Handle::new();
+            let shutdown_handle = handle.clone();
+            tokio::spawn(async move {
+                shutdown.cancelled().await;
+                shutdown_handle.graceful_shutdown(None);
+            });
+            let listener = listener
+                .into_std()
+                .expect("Failed to convert listener for TLS");
+            axum_server::from_tcp_rustls(listener, tls)
+                .handle(handle)
+                .serve(app)
+                .await
+                .expect("Failed to start HTTPS server");
+        }
+    }
+}
+
+/// Bind the separate `/metrics` listener and serve it (plain HTTP) in the background until the
+/// state's shutdown token is cancelled.
+async fn serve_metrics_on(network: &NetworkConfig, state: AppState) {
+    let listener = bind_listener(network)
         .await
-        .expect("Failed to start Axum server");
+        .expect("Failed to bind metrics address");
+    let addr = listener
+        .local_addr()
+        .expect("Bound listener has no address");
+    info!("Metrics listening on http://{addr}/metrics");
+    let app = metrics::router().with_state(state.clone());
+    let shutdown = state.shutdown_token.clone();
+    tokio::spawn(async move {
+        if let Err(e) = serve(listener, app)
+            .with_graceful_shutdown(shutdown.cancelled_owned())
+            .await
+        {
+            error!("Metrics server failed: {e}");
+        }
+    });
+}
+
+/// All routes: the JSON API under `/api`, the websocket at `/ws`, `/health` and `/ready` probes,
+/// `/metrics` (unless it has a listener of its own) and the frontend for everything else.
+pub async fn build_router(state: AppState) -> Router {
+    let (cors, static_dir, metrics_separate, rate_limit) = {
+        let config = state.config.lock().await;
+        (
+            config.cors.clone(),
+            config.static_dir.clone(),
+            config.metrics_listen.is_some(),
+            config.http_rate_limit.clone(),
+        )
+    };
+    let mut router = Router::new()
+        .nest("/api", api::router())
+        .merge(health::router())
+        .route("/ws", get(ws_handler));
+    if !metrics_separate {
+        router = router.merge(metrics::router());
+    }
+    let router = router
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            metrics::count_auth_failures,
+        ))
+        .with_state(state);
+    let router = assets::with_static_files(router, static_dir.as_deref())
+        .layer(compression_layer())
+        // Inside CORS, so browsers can read the 429
+        .layer(axum::middleware::from_fn_with_state(
+            Arc::new(rate_limit::HttpRateLimiter::new(rate_limit)),
+            rate_limit::limit_requests,
+        ));
+    let router = match cors_layer(&cors) {
+        Some(layer) => router.layer(layer),
+        None => router,
+    };
+    // Outermost, so every request is logged however it's answered
+    router.layer(axum::middleware::from_fn(request_log::log_requests))
+}
+
+/// Compress responses with gzip or brotli when the client accepts it. Small responses, images and
+/// the like are left alone, and so are websocket upgrades, whose connection isn't an HTTP body.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    let not_upgrade = |status: StatusCode, _: Version, _: &HeaderMap, _: &Extensions| {
+        status != StatusCode::SWITCHING_PROTOCOLS
+    };
+    CompressionLayer::new().compress_when(DefaultPredicate::new().and(not_upgrade))
+}
+
+/// Build the CORS layer for the configured origins, `None` (same-origin only) if there are none.
+/// Origins that aren't valid header values are skipped with a warning.
+fn cors_layer(cors: &CorsConfig) -> Option<CorsLayer> {
+    let origins: Vec<HeaderValue> = cors
+        .allowed_origins
+        .iter()
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                warn!("Ignoring invalid CORS origin {:?} in config", origin);
+                None
+            }
+        })
+        .collect();
+    if origins.is_empty() {
+        return None;
+    }
+    Some(
+        CorsLayer::new()
+            .allow_origin(AllowOrigin::list(origins))
+            .allow_methods([Method::GET, Method::POST, Method::PUT, Method::DELETE])
+            .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE])
+            .allow_credentials(cors.allow_credentials),
+    )
+}
+
+/// Build the address to listen on from the network config.
+/// IPv6 literals may be written with or without brackets (`::1` or `[::1]`).
+/// An interface that isn't an IP address falls back to localhost (with a warning) rather than
+/// refusing to start, so a typo never exposes the server more widely than intended.
+pub fn resolve_bind_addr(network: &NetworkConfig) -> SocketAddr {
+    let ip = network.ip().unwrap_or_else(|| {
+        warn!(
+            "Invalid network interface {:?} in config, falling back to localhost",
+            network.interface
+        );
+        IpAddr::V4(Ipv4Addr::LOCALHOST)
+    });
+    SocketAddr::new(ip, network.port)
 }
 
-async fn ws_handler(ws: WebSocketUpgrade, State(state): State<AppState>) -> impl IntoResponse {
-    ws.on_upgrade(move |socket| websocket_handler(socket, state))
+/// Bind the listener for the configured address. Port 0 binds an ephemeral port,
+/// check `local_addr` for the port actually bound.
+/// IPv6 sockets are set to IPv6 only unless `dual_stack` is on, rather than leaving it to the OS
+/// default (which differs between Linux and Windows).
+pub async fn bind_listener(network: &NetworkConfig) -> std::io::Result<TcpListener> {
+    let addr = resolve_bind_addr(network);
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!network.dual_stack)?;
+    }
+    // Same as `TcpListener::bind`, so a restart can rebind while old connections linger
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(1024)?;
+    TcpListener::from_std(socket.into())
 }
 
-async fn websocket_handler(socket: WebSocket, state: AppState) {
+/// Query string of the websocket upgrade request.
+#[derive(Debug, Deserialize)]
+struct WsQuery {
+    /// Access token to authenticate the connection with. Browsers can't set headers
+    /// on websocket requests, so it can come in the query string instead of `Authorization`.
+    token: Option<String>,
+}
+
+async fn ws_handler(
+    ws: WebSocketUpgrade,
+    State(state): State<AppState>,
+    Query(query): Query<WsQuery>,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, api::ApiError> {
+    if !state.has_connection_capacity().await {
+        return Err(api::ApiError::ServiceUnavailable);
+    }
+    let token = query.token.or_else(|| {
+        headers
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .map(|token| token.trim().to_string())
+    });
+    // Reject a bad token before upgrading, the client gets a plain 401.
+    // Without one the client has to authenticate with its first message instead.
+    let login = match token {
+        Some(token) => {
+            let auth = state.auth.clone();
+            let (user, expires_at) =
+                api::run_blocking(move || auth.user_and_expiry_from_jwt(&token))
+                    .await
+                    .map_err(|_| api::ApiError::Unauthorized)?;
+            Some((user.id, expires_at))
+        }
+        None => None,
+    };
+    Ok(ws.on_upgrade(move |socket| websocket_handler(socket, state, login)))
+}
+
+/// Serve one websocket connection. `login` is the user and token expiry (unix seconds) if the
+/// upgrade request came with a valid token.
+async fn websocket_handler(socket: WebSocket, state: AppState, login: Option<(UserId, u64)>) {
     // Create a channel for sending messages to this socket from other tasks
     let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
 
     // Register a new connection and get its UUID
-    let conn_id = state.register_connection(tx.clone()).await;
+    // The limit was checked before upgrading, but others may have connected since
+    let conn_id = match state.register_connection(tx.clone()).await {
+        Ok(conn_id) => conn_id,
+        Err(e) => {
+            warn!("Refusing websocket connection: {e}");
+            let mut socket = socket;
+            let _ = socket
+                .send(Message::Close(Some(CloseFrame {
+                    code: close_code::AGAIN,
+                    reason: "server at capacity".into(),
+                })))
+                .await;
+            return;
+        }
+    };
+    if let Some((user_id, expires_at)) = login {
+        state.associate_user(&conn_id, user_id).await;
+        state.set_token_expiry(&conn_id, expires_at).await;
+    }
     info!("WebSocket connection registered: {conn_id}");
 
     // Split the socket into sender and receiver
     let (mut ws_sender, mut ws_receiver) = socket.split();
 
     // Spawn a task to forward messages from the channel to the socket
+    let metrics = state.metrics.clone();
     let sender_task = tokio::spawn(async move {
         while let Some(msg) = rx.recv().await {
-            if ws_sender.send(msg).await.is_err() {
+            // Pings, pongs and closes are housekeeping, not messages
+            let is_data = matches!(msg, Message::Text(_) | Message::Binary(_));
+            let is_close = matches!(msg, Message::Close(_));
+            if ws_sender.send(msg).await.is_err() || is_close {
                 break;
             }
+            if is_data {
+                metrics.message_sent();
+            }
         }
     });
 
-    // Main message loop
-    while let Some(Ok(msg)) = ws_receiver.next().await {
+    let (keepalive, require_login, expiry_warning) = {
+        let config = state.config.lock().await;
+        (
+            config.websocket.clone(),
+            config.auth.require_login,
+            config.auth.expiry_warning,
+        )
+    };
+
+    // Forward messages sent to everyone, once the client may see them
+    let forward_global = || {
+        tokio::spawn(websockets::forward_global_messages(
+            state.clone(),
+            conn_id,
+            state.subscribe_global_messages(),
+        ))
+    };
+    // Warn the client before its token expires and close the connection once it has
+    let watch_expiry = |expires_at| {
+        tokio::spawn(websockets::watch_auth_expiry(
+            state.clone(),
+            conn_id,
+            websockets::AuthExpiryWatch::new(expires_at, expiry_warning),
+            Arc::new(websockets::SystemClock),
+            tx.clone(),
+            Duration::from_secs(WS_AUTH_EXPIRY_CHECK_SECONDS),
+        ))
+    };
+    let mut expiry_task = login.map(|(_, expires_at)| watch_expiry(expires_at));
+    let mut awaiting_auth = require_login && login.is_none();
+    let mut global_task = (!awaiting_auth).then(forward_global);
+    let auth_deadline = tokio::time::sleep(Duration::from_secs(WS_AUTH_GRACE_SECONDS));
+    tokio::pin!(auth_deadline);
+
+    // Ping the client periodically and drop it if it stops answering
+    let heartbeat = Arc::new(websockets::Heartbeat::new(
+        keepalive.idle_timeout,
+        Arc::new(websockets::SystemClock),
+    ));
+    let mut heartbeat_task = tokio::spawn(websockets::run_heartbeat(
+        state.clone(),
+        conn_id,
+        heartbeat.clone(),
+        tx.clone(),
+        keepalive.ping_interval,
+    ));
+
+    // Main message loop, until the client leaves or the heartbeat gives up on it
+    loop {
+        let msg = tokio::select! {
+            msg = ws_receiver.next() => match msg {
+                Some(Ok(msg)) => msg,
+                _ => break,
+            },
+            _ = &mut heartbeat_task => break,
+            _ = async { expiry_task.as_mut().unwrap().await }, if expiry_task.is_some() => {
+                info!("WebSocket connection {conn_id}'s token expired, closing it");
+                break;
+            }
+            _ = &mut auth_deadline, if awaiting_auth => {
+                state.metrics.auth_failed();
+                let frame = websockets::policy_violation("authentication timed out");
+                let _ = tx.send(Message::Close(Some(frame)));
+                break;
+            }
+        };
+        heartbeat.record_activity();
+        if awaiting_auth && matches!(msg, Message::Text(_) | Message::Binary(_)) {
+            // Nothing but a valid token is accepted until the client has logged in
+            match websockets::handle_handshake_message(&state, conn_id, &msg).await {
+                Ok(reply) => {
+                    let _ = tx.send(reply);
+                    awaiting_auth = false;
+                    global_task = Some(forward_global());
+                    expiry_task = state.token_expiry(&conn_id).await.map(watch_expiry);
+                    continue;
+                }
+                Err(frame) => {
+                    state.metrics.auth_failed();
+                    let _ = tx.send(Message::Close(Some(frame)));
+                    break;
+                }
+            }
+        }
         match msg {
             Message::Text(txt) => {
-                // Echo text messages for now
-                let _ = tx.send(Message::Text(txt));
+                // JSON protocol messages for clients without MessagePack, answered in JSON
+                if let Some(reply) = websockets::handle_text_message(&state, conn_id, &txt).await {
+                    let _ = tx.send(reply);
+                }
             }
             Message::Binary(data) => {
-                // Stub: handle binary messages here
-                // For now, just echo the binary data back
-                let _ = tx.send(Message::Binary(data));
+                // MessagePack protocol messages, replies go back to this client only
+                if let Some(reply) = websockets::handle_binary_message(&state, conn_id, &data).await
+                {
+                    let _ = tx.send(reply);
+                }
             }
             Message::Ping(payload) => {
                 // Respond to ping with pong
                 let _ = tx.send(Message::Pong(payload));
             }
             Message::Pong(_) => {
-                // Optionally handle pong (usually no-op)
+                // Answer to our heartbeat ping, already recorded above
             }
             Message::Close(frame) => {
                 // Optionally handle close frame
@@ -103,18 +441,36 @@ async fn websocket_handler(socket: WebSocket, state: AppState) {
                 break;
             }
         }
+        // Connections that may stay anonymous can still log in with a later message
+        if expiry_task.is_none() {
+            expiry_task = state.token_expiry(&conn_id).await.map(watch_expiry);
+        }
+    }
+    heartbeat_task.abort();
+    if let Some(global_task) = global_task {
+        global_task.abort();
+    }
+    if let Some(expiry_task) = expiry_task {
+        expiry_task.abort();
     }
 
     // Cleanup: remove connection from AppState
     state.remove_connection(&conn_id).await;
     info!("WebSocket connection cleaned up: {conn_id}");
 
-    // Ensure sender task is finished
-    let _ = sender_task.abort();
+    // Give the sender a moment to deliver a close frame, then make sure it's finished
+    drop(tx);
+    let mut sender_task = sender_task;
+    if tokio::time::timeout(Duration::from_secs(1), &mut sender_task)
+        .await
+        .is_err()
+    {
+        sender_task.abort();
+    }
 }
 
 /// Print fancy listen address messaging for the user. doesn't do much functionally but it does tell the user if/when they are enabling specific features using the interface field in the config
-fn log_listen_address(addr: SocketAddr) {
+fn log_listen_address(addr: SocketAddr, scheme: &str, dual_stack: bool) {
     match addr {
         SocketAddr::V4(v4) => {
             let ip = v4.ip();
@@ -124,67 +480,826 @@ fn log_listen_address(addr: SocketAddr) {
                     "Web server listening on ALL IPv4 interfaces (0.0.0.0:{}) — accessible from any network interface on this machine.",
                     port
                 );
-                info!("Full listen address: http://*:{port}");
+                info!("Full listen address: {scheme}://*:{port}");
             } else if ip.octets()[0] == 127 {
                 info!(
                     "Web server listening on localhost ({}:{}) — only accessible from this machine.",
                     ip, port
                 );
-                info!("Full listen address: http://localhost:{port}");
+                info!("Full listen address: {scheme}://localhost:{port}");
             } else if ip.is_broadcast() {
                 info!(
                     "Web server listening on broadcast address ({}:{}) — this is unusual and may be for special network discovery.",
                     ip, port
                 );
-                info!("Full listen address: http://{}:{}", ip, port);
+                info!("Full listen address: {scheme}://{}:{}", ip, port);
             } else if ip.is_private() {
                 info!(
                     "Web server listening on private network ({}:{}) — accessible from your LAN.",
                     ip, port
                 );
-                info!("Full listen address: http://{}:{}", ip, port);
+                info!("Full listen address: {scheme}://{}:{}", ip, port);
             } else {
                 info!(
                     "Web server listening on specific IPv4 address ({}:{}) — check your network configuration.",
                     ip, port
                 );
-                info!("Full listen address: http://{}:{}", ip, port);
+                info!("Full listen address: {scheme}://{}:{}", ip, port);
             }
         }
         SocketAddr::V6(v6) => {
             let ip = v6.ip();
             let port = v6.port();
-            if ip.is_unspecified() {
+            if ip.is_unspecified() && dual_stack {
+                info!(
+                    "Web server listening on ALL IPv4 and IPv6 interfaces ([::]:{}, dual-stack) — accessible from any network interface on this machine.",
+                    port
+                );
+                info!("Full listen address: {scheme}://*:{port}");
+            } else if ip.is_unspecified() {
                 info!(
                     "Web server listening on ALL IPv6 interfaces ([::]:{}) — accessible from any IPv6 network interface.",
                     port
                 );
-                info!("Full listen address: http://*:{port}");
+                info!("Full listen address: {scheme}://*:{port}");
             } else if ip.is_loopback() {
                 info!(
                     "Web server listening on IPv6 loopback ([::1]:{}) — only accessible from this machine.",
                     port
                 );
-                info!("Full listen address: http://localhost:{port}");
+                info!("Full listen address: {scheme}://localhost:{port}");
             } else if ip.is_multicast() {
                 info!(
                     "Web server listening on IPv6 multicast address ([{}]:{}) — this is unusual and may be for special network discovery.",
                     ip, port
                 );
-                info!("Full listen address: http://[{}]:{}", ip, port);
+                info!("Full listen address: {scheme}://[{}]:{}", ip, port);
             } else if ip.is_unique_local() {
                 info!(
                     "Web server listening on unique local IPv6 address ([{}]:{}) — accessible from your local network.",
                     ip, port
                 );
-                info!("Full listen address: http://[{}]:{}", ip, port);
+                info!("Full listen address: {scheme}://[{}]:{}", ip, port);
             } else {
                 info!(
                     "Web server listening on specific IPv6 address ([{}]:{}) — check your network configuration.",
                     ip, port
                 );
-                info!("Full listen address: http://[{}]:{}", ip, port);
+                info!("Full listen address: {scheme}://[{}]:{}", ip, port);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod test_util {
+    use appstate::AppState;
+    use axum::body::Body;
+    use axum::http::{Request, Response, header};
+
+    /// AppState over a fresh in-memory database.
+    pub fn test_state() -> AppState {
+        AppState::new_in_memory(config::Config::default())
+    }
+
+    /// Build a request with a JSON body (`Value::Null` for none).
+    pub fn json_request(method: &str, uri: &str, body: serde_json::Value) -> Request<Body> {
+        let builder = Request::builder().method(method).uri(uri);
+        if body.is_null() {
+            builder.body(Body::empty()).unwrap()
+        } else {
+            builder
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        }
+    }
+
+    /// `json_request` with a bearer token.
+    pub fn authed_request(
+        method: &str,
+        uri: &str,
+        token: &str,
+        body: serde_json::Value,
+    ) -> Request<Body> {
+        let mut request = json_request(method, uri, body);
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            format!("Bearer {token}").parse().unwrap(),
+        );
+        request
+    }
+
+    /// Register `username` and give them a new calendar of their own, returning an access token
+    /// and the calendar's id.
+    pub fn owner_of_calendar(state: &AppState, username: &str) -> (String, i64) {
+        let token = state
+            .auth
+            .register_user(
+                username,
+                "pw",
+                None,
+                &format!("{username}@x.com"),
+                "127.0.0.1",
+            )
+            .unwrap()
+            .access_token
+            .unwrap();
+        let mut conn = state.database.get().unwrap();
+        let user_id = conn.get_user_by_username(username).unwrap().unwrap().id;
+        let calendar_id = conn
+            .create_calendar_with_owner(
+                &format!("{username}'s calendar"),
+                colorlab::Color::from_rgb8(1, 2, 3),
+                user_id,
+            )
+            .unwrap();
+        (token, calendar_id)
+    }
+
+    pub async fn response_json(response: Response<Body>) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn network(interface: &str, port: u16) -> NetworkConfig {
+        NetworkConfig {
+            interface: interface.to_string(),
+            port,
+            dual_stack: false,
+        }
+    }
+
+    /// State that lets websocket clients connect without logging in.
+    fn anonymous_state() -> AppState {
+        let mut config = config::Config::default();
+        config.auth.require_login = false;
+        AppState::new_in_memory(config)
+    }
+
+    #[test]
+    fn test_resolve_bind_addr() {
+        assert_eq!(
+            resolve_bind_addr(&network("0.0.0.0", 9000)),
+            "0.0.0.0:9000".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_bind_addr(&network("::1", 9000)),
+            "[::1]:9000".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_bind_addr(&network("localhost", 9000)),
+            "127.0.0.1:9000".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_bind_addr(&network("::", 9000)),
+            "[::]:9000".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_bind_addr(&network(" [::1] ", 9000)),
+            "[::1]:9000".parse().unwrap()
+        );
+        assert_eq!(
+            resolve_bind_addr(&network("fd00::2", 9000)),
+            "[fd00::2]:9000".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_bad_interface_falls_back_to_localhost() {
+        for bad in ["not-an-ip", "[127.0.0.1]", "::1]", "[::1", "1.2.3", ""] {
+            assert_eq!(
+                resolve_bind_addr(&network(bad, 9000)),
+                "127.0.0.1:9000".parse().unwrap(),
+                "{bad:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_dual_stack_accepts_ipv4_clients() {
+        let connect_v4 = |port: u16| tokio::net::TcpStream::connect(("127.0.0.1", port));
+
+        let v6_only = bind_listener(&network("::", 0)).await.unwrap();
+        let port = v6_only.local_addr().unwrap().port();
+        assert!(tokio::net::TcpStream::connect(("::1", port)).await.is_ok());
+        assert!(connect_v4(port).await.is_err());
+
+        let dual = bind_listener(&NetworkConfig {
+            dual_stack: true,
+            ..network("::", 0)
+        })
+        .await
+        .unwrap();
+        let port = dual.local_addr().unwrap().port();
+        assert!(connect_v4(port).await.is_ok());
+        assert!(tokio::net::TcpStream::connect(("::1", port)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_stops_server_and_closes_connections() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = config::Config::default();
+        config.network.port = 0;
+        config.database.path = dir.path().join("test.db").to_string_lossy().into_owned();
+        let state = AppState::new(config);
+
+        let (tx, mut rx) = mpsc::unbounded_channel::<Message>();
+        state.register_connection(tx).await.unwrap();
+
+        let server = tokio::spawn(start_web_server(state.clone()));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        state.shutdown().await;
+
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+        match rx.recv().await {
+            Some(Message::Close(Some(frame))) => {
+                assert_eq!(frame.code, axum::extract::ws::close_code::AWAY)
             }
+            other => panic!("expected a close frame, got {other:?}"),
         }
     }
+
+    async fn preflight(app: Router, origin: &str) -> axum::http::Response<axum::body::Body> {
+        use tower::ServiceExt;
+        app.oneshot(
+            axum::http::Request::builder()
+                .method(Method::OPTIONS)
+                .uri("/api/login")
+                .header(header::ORIGIN, origin)
+                .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+                .body(axum::body::Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight() {
+        let state = crate::test_util::test_state();
+        state.config.lock().await.cors = CorsConfig {
+            allowed_origins: vec!["http://localhost:5173".to_string()],
+            allow_credentials: true,
+        };
+        let app = build_router(state).await;
+
+        let allowed = preflight(app.clone(), "http://localhost:5173").await;
+        assert_eq!(
+            allowed.headers()[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "http://localhost:5173"
+        );
+        assert_eq!(
+            allowed.headers()[header::ACCESS_CONTROL_ALLOW_CREDENTIALS],
+            "true"
+        );
+
+        let denied = preflight(app, "https://evil.example").await;
+        assert!(
+            !denied
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_no_cors_headers_by_default() {
+        let app = build_router(crate::test_util::test_state()).await;
+        let response = preflight(app, "http://localhost:5173").await;
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_large_asset_is_gzipped() {
+        use std::io::Read;
+        use tower::ServiceExt;
+
+        let app = build_router(crate::test_util::test_state()).await;
+        let request = axum::http::Request::builder()
+            .uri("/calendar.js")
+            .header(header::ACCEPT_ENCODING, "gzip")
+            .body(axum::body::Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "text/javascript");
+
+        let compressed = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let original = include_str!("../html_src/calendar.js");
+        assert!(compressed.len() < original.len());
+        let mut body = String::new();
+        flate2::read::GzDecoder::new(&compressed[..])
+            .read_to_string(&mut body)
+            .unwrap();
+        assert_eq!(body, original);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_upgrade_ignores_accept_encoding() {
+        use tungstenite::client::IntoClientRequest;
+
+        let state = anonymous_state();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::ACCEPT_ENCODING,
+            HeaderValue::from_static("gzip, br"),
+        );
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, response) = tungstenite::connect(request).unwrap();
+            assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+            let echo = websockets::ClientMessage::Echo {
+                text: "hi".to_string(),
+            };
+            socket
+                .send(tungstenite::Message::Binary(
+                    echo.to_msgpack().unwrap().into(),
+                ))
+                .unwrap();
+            loop {
+                if let tungstenite::Message::Binary(data) = socket.read().unwrap() {
+                    return data.to_vec();
+                }
+            }
+        });
+        let reply = tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client never got the echo")
+            .unwrap();
+        assert_eq!(
+            websockets::ServerMessage::from_msgpack(&reply).unwrap(),
+            websockets::ServerMessage::Echo {
+                text: "hi".to_string()
+            }
+        );
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    fn test_tls_config() -> TlsConfig {
+        TlsConfig {
+            cert_path: concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls_cert.pem").to_string(),
+            key_path: concat!(env!("CARGO_MANIFEST_DIR"), "/testdata/tls_key.pem").to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake() {
+        use rustls::pki_types::{CertificateDer, ServerName, pem::PemObject};
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let tls = test_tls_config();
+        let state = crate::test_util::test_state();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(
+            listener,
+            state.clone(),
+            Some(load_tls_config(&tls).await),
+        ));
+
+        // Trust only the self-signed test certificate
+        let mut roots = rustls::RootCertStore::empty();
+        roots
+            .add(CertificateDer::from_pem_file(&tls.cert_path).unwrap())
+            .unwrap();
+        let client = rustls::ClientConfig::builder()
+            .with_root_certificates(roots)
+            .with_no_client_auth();
+        let connector = tokio_rustls::TlsConnector::from(std::sync::Arc::new(client));
+        let tcp = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let mut stream = connector
+            .connect(ServerName::try_from("localhost").unwrap(), tcp)
+            .await
+            .expect("TLS handshake failed");
+
+        stream
+            .write_all(b"GET /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"), "{response}");
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "Failed to load TLS certificate")]
+    async fn test_missing_tls_cert_panics() {
+        load_tls_config(&TlsConfig {
+            cert_path: "does/not/exist.pem".to_string(),
+            key_path: "does/not/exist.key".to_string(),
+        })
+        .await;
+    }
+
+    /// Open a websocket to the server with the sync client, returning the handshake status.
+    async fn ws_connect(url: String) -> Result<(), u16> {
+        tokio::task::spawn_blocking(move || match tungstenite::connect(url) {
+            Ok((mut socket, _)) => {
+                let _ = socket.close(None);
+                Ok(())
+            }
+            Err(tungstenite::Error::Http(response)) => Err(response.status().as_u16()),
+            Err(e) => panic!("websocket connect failed: {e}"),
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_websocket_token_associates_user() {
+        let state = crate::test_util::test_state();
+        let token = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let user_id = state.auth.user_from_jwt(&token).unwrap().id;
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        assert_eq!(
+            ws_connect(format!("ws://{addr}/ws?token=not-a-token")).await,
+            Err(401)
+        );
+
+        // Keep a connection open and check it was tied to the user
+        let url = format!("ws://{addr}/ws?token={token}");
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            // Blocks until the server closes the connection on shutdown
+            while socket.read().is_ok() {}
+        });
+        let mut associated = false;
+        for _ in 0..100 {
+            let conns = state.connections.lock().await;
+            if conns.values().any(|conn| conn.user_id == Some(user_id)) {
+                associated = true;
+                break;
+            }
+            drop(conns);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(associated, "connection was not associated with the user");
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client was not disconnected")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_is_closed_when_its_token_expires() {
+        let mut config = config::Config::default();
+        config.auth.jwt_expiry_seconds = 2;
+        let state = AppState::new_in_memory(config);
+        let token = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let url = format!("ws://{addr}/ws?token={token}");
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            loop {
+                match socket.read() {
+                    Ok(tungstenite::Message::Close(frame)) => return frame.map(|f| f.code),
+                    Ok(_) => {}
+                    Err(e) => panic!("connection ended without a close frame: {e}"),
+                }
+            }
+        });
+        let code = tokio::time::timeout(std::time::Duration::from_secs(10), client)
+            .await
+            .expect("connection outlived its token")
+            .unwrap();
+        assert_eq!(code, Some(websockets::AUTH_EXPIRED_CLOSE_CODE.into()));
+        for _ in 0..100 {
+            if state.connections.lock().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(state.connections.lock().await.is_empty());
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_first_message_handshake_associates_user() {
+        use tungstenite::client::IntoClientRequest;
+
+        let state = crate::test_util::test_state();
+        let token = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let user_id = state.auth.user_from_jwt(&token).unwrap().id;
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let url = format!("ws://{addr}/ws");
+        let login = websockets::ClientMessage::Authenticate {
+            token: token.clone(),
+        };
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            socket
+                .send(tungstenite::Message::Text(login.to_json().unwrap().into()))
+                .unwrap();
+            loop {
+                if let tungstenite::Message::Text(text) = socket.read().unwrap() {
+                    return (socket, text.to_string());
+                }
+            }
+        });
+        let (socket, reply) = tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client never got a reply")
+            .unwrap();
+        assert_eq!(
+            websockets::ServerMessage::from_json(&reply).unwrap(),
+            websockets::ServerMessage::Authenticated { user_id }
+        );
+        assert_eq!(state.online_users().await, vec![user_id]);
+
+        // The token can also come in the upgrade request's Authorization header
+        let mut request = format!("ws://{addr}/ws").into_client_request().unwrap();
+        request.headers_mut().insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_str(&format!("Bearer {token}")).unwrap(),
+        );
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(request).unwrap();
+            // Blocks until the server closes the connection on shutdown
+            while socket.read().is_ok() {}
+        });
+        let mut associated = false;
+        for _ in 0..100 {
+            let conns = state.connections.lock().await;
+            if conns
+                .values()
+                .filter(|conn| conn.user_id == Some(user_id))
+                .count()
+                == 2
+            {
+                associated = true;
+                break;
+            }
+            drop(conns);
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert!(associated, "connection was not associated with the user");
+
+        drop(socket);
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+        tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client was not disconnected")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_unauthenticated_websocket_is_closed() {
+        let state = crate::test_util::test_state();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let first_messages = [
+            websockets::ClientMessage::Authenticate {
+                token: "not-a-token".to_string(),
+            },
+            websockets::ClientMessage::Echo {
+                text: "hi".to_string(),
+            },
+        ];
+        for first in first_messages {
+            let url = format!("ws://{addr}/ws");
+            let client = tokio::task::spawn_blocking(move || {
+                let (mut socket, _) = tungstenite::connect(url).unwrap();
+                socket
+                    .send(tungstenite::Message::Binary(
+                        first.to_msgpack().unwrap().into(),
+                    ))
+                    .unwrap();
+                loop {
+                    match socket.read() {
+                        Ok(tungstenite::Message::Close(frame)) => return frame,
+                        Ok(_) => continue,
+                        Err(e) => panic!("connection dropped without a close frame: {e}"),
+                    }
+                }
+            });
+            let frame = tokio::time::timeout(std::time::Duration::from_secs(5), client)
+                .await
+                .expect("client was not closed")
+                .unwrap()
+                .expect("close frame without a code");
+            assert_eq!(
+                frame.code,
+                tungstenite::protocol::frame::coding::CloseCode::Policy
+            );
+        }
+        assert!(state.online_users().await.is_empty());
+        assert_eq!(state.metrics.snapshot().auth_failures, 2);
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_global_message_reaches_connected_client() {
+        let state = anonymous_state();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let url = format!("ws://{addr}/ws");
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            loop {
+                match socket.read().unwrap() {
+                    tungstenite::Message::Binary(data) => return data.to_vec(),
+                    _ => continue,
+                }
+            }
+        });
+        // Wait for the connection to be registered (and so subscribed) before sending
+        for _ in 0..100 {
+            if !state.connections.lock().await.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        state
+            .send_global_message(appstate::GlobalMessage::Broadcast {
+                text: "hello everyone".to_string(),
+            })
+            .unwrap();
+
+        let received = tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client never got the message")
+            .unwrap();
+        assert_eq!(
+            websockets::ServerMessage::from_msgpack(&received).unwrap(),
+            websockets::ServerMessage::Broadcast {
+                text: "hello everyone".to_string()
+            }
+        );
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_websocket_traffic_is_counted() {
+        let state = anonymous_state();
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        let url = format!("ws://{addr}/ws");
+        let client = tokio::task::spawn_blocking(move || {
+            let (mut socket, _) = tungstenite::connect(url).unwrap();
+            let echo = websockets::ClientMessage::Echo {
+                text: "hi".to_string(),
+            };
+            socket
+                .send(tungstenite::Message::Binary(
+                    echo.to_msgpack().unwrap().into(),
+                ))
+                .unwrap();
+            loop {
+                if let tungstenite::Message::Binary(_) = socket.read().unwrap() {
+                    return socket;
+                }
+            }
+        });
+        let socket = tokio::time::timeout(std::time::Duration::from_secs(5), client)
+            .await
+            .expect("client never got the echo")
+            .unwrap();
+        // The reply can arrive a moment before it's counted as sent
+        let counted = |expected: appstate::MetricsSnapshot| {
+            let state = state.clone();
+            async move {
+                for _ in 0..100 {
+                    if state.metrics.snapshot() == expected {
+                        break;
+                    }
+                    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                }
+                state.metrics.snapshot()
+            }
+        };
+        let expected = appstate::MetricsSnapshot {
+            active_connections: 1,
+            messages_in: 1,
+            messages_out: 1,
+            auth_failures: 0,
+        };
+        assert_eq!(counted(expected).await, expected);
+
+        drop(socket);
+        let expected = appstate::MetricsSnapshot {
+            active_connections: 0,
+            ..expected
+        };
+        assert_eq!(counted(expected).await, expected);
+
+        state.shutdown().await;
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_upgrade_refused_at_capacity() {
+        let state = crate::test_util::test_state();
+        let (tx, _rx) = mpsc::unbounded_channel::<Message>();
+        for _ in 0..state.max_connections {
+            state.register_connection(tx.clone()).await.unwrap();
+        }
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = tokio::spawn(serve_on(listener, state.clone(), None));
+
+        assert_eq!(ws_connect(format!("ws://{addr}/ws")).await, Err(503));
+
+        state.shutdown_token.cancel();
+        tokio::time::timeout(std::time::Duration::from_secs(5), server)
+            .await
+            .expect("server did not shut down")
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_port_zero_binds_ephemeral_port() {
+        let listener = bind_listener(&network("127.0.0.1", 0)).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.ip().is_loopback());
+        assert_ne!(addr.port(), 0);
+    }
 }
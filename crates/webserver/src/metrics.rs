@@ -0,0 +1,150 @@
+//! Counters for operators in the Prometheus text format. Like the health probes it needs no
+//! authentication, set `metrics_listen` in the config to serve it on an internal interface only.
+
+use appstate::{AppState, MetricsSnapshot};
+use axum::{
+    Router,
+    extract::{Request, State},
+    http::{StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use std::fmt::Write;
+
+pub(crate) fn router() -> Router<AppState> {
+    Router::new().route("/metrics", get(metrics))
+}
+
+/// `GET /metrics`: every counter, scraped by Prometheus.
+async fn metrics(State(state): State<AppState>) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        render(&state.metrics.snapshot()),
+    )
+}
+
+/// Count responses refusing credentials towards `auth_failures`, whichever route refused them.
+pub(crate) async fn count_auth_failures(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        state.metrics.auth_failed();
+    }
+    response
+}
+
+fn render(snapshot: &MetricsSnapshot) -> String {
+    let metrics = [
+        (
+            "calendar_websocket_connections",
+            "gauge",
+            "Open websocket connections.",
+            snapshot.active_connections,
+        ),
+        (
+            "calendar_websocket_messages_in_total",
+            "counter",
+            "Messages received from websocket clients.",
+            snapshot.messages_in,
+        ),
+        (
+            "calendar_websocket_messages_out_total",
+            "counter",
+            "Messages sent to websocket clients.",
+            snapshot.messages_out,
+        ),
+        (
+            "calendar_auth_failures_total",
+            "counter",
+            "Requests rejected for missing or bad credentials.",
+            snapshot.auth_failures,
+        ),
+    ];
+    let mut body = String::new();
+    for (name, kind, help, value) in metrics {
+        // Writing to a String can't fail
+        let _ = writeln!(body, "# HELP {name} {help}");
+        let _ = writeln!(body, "# TYPE {name} {kind}");
+        let _ = writeln!(body, "{name} {value}");
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{json_request, test_state};
+    use axum::http::StatusCode;
+    use serde_json::{Value, json};
+    use tower::ServiceExt;
+
+    async fn scrape(app: &axum::Router) -> String {
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", "/metrics", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_metrics_lists_every_counter() {
+        let state = test_state();
+        let app = crate::build_router(state.clone()).await;
+        let body = scrape(&app).await;
+        for name in [
+            "calendar_websocket_connections",
+            "calendar_websocket_messages_in_total",
+            "calendar_websocket_messages_out_total",
+            "calendar_auth_failures_total",
+        ] {
+            assert!(body.contains(&format!("# TYPE {name} ")), "{name} missing");
+            assert!(body.contains(&format!("\n{name} 0\n")), "{name} not 0");
+        }
+
+        // A rejected login counts as an auth failure
+        state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap();
+        let response = app
+            .clone()
+            .oneshot(json_request(
+                "POST",
+                "/api/login",
+                json!({"username": "alice", "password": "wrong"}),
+            ))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(
+            scrape(&app)
+                .await
+                .contains("\ncalendar_auth_failures_total 1\n")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_metrics_can_move_to_their_own_listener() {
+        let mut config = config::Config::default();
+        config.metrics_listen = Some(config::NetworkConfig {
+            interface: "127.0.0.1".to_string(),
+            port: 0,
+            dual_stack: false,
+        });
+        let state = appstate::AppState::new_in_memory(config);
+        let app = crate::build_router(state).await;
+        let response = app
+            .oneshot(json_request("GET", "/metrics", Value::Null))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+}
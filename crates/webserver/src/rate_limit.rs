@@ -0,0 +1,237 @@
+//! Per-IP token bucket over every HTTP route, so no single client can hammer the server
+//! (e.g. `/api/salt`). The auth service's own, stricter limits still apply on top.
+
+use appstate::TokenBucket;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{HeaderMap, HeaderValue, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use config::HttpRateLimitConfig;
+use global_constants::HTTP_RATE_LIMIT_MAX_TRACKED_IPS;
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// A token bucket for each client IP seen recently.
+pub(crate) struct HttpRateLimiter {
+    config: HttpRateLimitConfig,
+    buckets: Mutex<HashMap<IpAddr, TokenBucket>>,
+}
+
+impl HttpRateLimiter {
+    pub(crate) fn new(config: HttpRateLimitConfig) -> Self {
+        Self {
+            config,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Count a request from `ip`, returning `Err` with how long to wait (in whole seconds,
+    /// at least 1) if it's over the limit.
+    /// While `HTTP_RATE_LIMIT_MAX_TRACKED_IPS` IPs are all mid-burst, IPs not among them are
+    /// limited too, so a flood from many addresses can't grow the table.
+    fn check(&self, ip: IpAddr, now: Instant) -> Result<(), u64> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if buckets.len() >= HTTP_RATE_LIMIT_MAX_TRACKED_IPS && !buckets.contains_key(&ip) {
+            // Refilled buckets are the same as new ones, so forgetting them is free
+            buckets.retain(|_, bucket| !bucket.is_full_at(now));
+            if buckets.len() >= HTTP_RATE_LIMIT_MAX_TRACKED_IPS {
+                return Err(1);
+            }
+        }
+        let bucket = buckets.entry(ip).or_insert_with(|| {
+            TokenBucket::new_at(self.config.requests_per_second, self.config.burst, now)
+        });
+        if bucket.try_acquire_at(now) {
+            return Ok(());
+        }
+        let wait = bucket.retry_after().unwrap_or_default();
+        Err(wait.as_secs_f64().ceil().max(1.0) as u64)
+    }
+
+    /// The IP a request counts against: the peer address, or the last `X-Forwarded-For` entry
+    /// (the one the proxy in front added) when that's trusted.
+    fn client_ip(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> IpAddr {
+        let forwarded = self
+            .config
+            .trust_forwarded_for
+            .then(|| headers.get_all("x-forwarded-for"))
+            .and_then(|values| values.iter().next_back())
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.rsplit(',').next())
+            .and_then(|ip| ip.trim().parse().ok());
+        // Unspecified when the server wasn't started with connect info, as in tests
+        forwarded
+            .or(peer)
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED))
+    }
+}
+
+/// Answer 429 with a `Retry-After` header once the client's IP is over its limit.
+pub(crate) async fn limit_requests(
+    State(limiter): State<Arc<HttpRateLimiter>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if limiter.config.requests_per_second == 0 {
+        return next.run(request).await;
+    }
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let ip = limiter.client_ip(request.headers(), peer);
+    match limiter.check(ip, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(retry_after) => {
+            let mut response = crate::api::ApiError::TooManyRequests.into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+            response
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpRateLimiter;
+    use crate::test_util::json_request;
+    use axum::extract::ConnectInfo;
+    use axum::http::{StatusCode, header};
+    use global_constants::HTTP_RATE_LIMIT_MAX_TRACKED_IPS;
+    use serde_json::Value;
+    use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+    use std::time::{Duration, Instant};
+    use tower::ServiceExt;
+
+    async fn router(
+        requests_per_second: u32,
+        burst: u32,
+        trust_forwarded_for: bool,
+    ) -> axum::Router {
+        let mut config = config::Config::default();
+        config.http_rate_limit = config::HttpRateLimitConfig {
+            requests_per_second,
+            burst,
+            trust_forwarded_for,
+        };
+        let state = appstate::AppState::new_in_memory(config);
+        crate::build_router(state).await
+    }
+
+    async fn status_from(
+        app: &axum::Router,
+        peer: &str,
+        forwarded_for: Option<&str>,
+    ) -> (StatusCode, Option<String>) {
+        let mut request = json_request("GET", "/health", Value::Null);
+        let peer: SocketAddr = peer.parse().unwrap();
+        request.extensions_mut().insert(ConnectInfo(peer));
+        if let Some(forwarded_for) = forwarded_for {
+            request
+                .headers_mut()
+                .insert("x-forwarded-for", forwarded_for.parse().unwrap());
+        }
+        let response = app.clone().oneshot(request).await.unwrap();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .map(|value| value.to_str().unwrap().to_string());
+        (response.status(), retry_after)
+    }
+
+    #[test]
+    fn test_full_table_limits_untracked_ips() {
+        let limiter = HttpRateLimiter::new(config::HttpRateLimitConfig {
+            requests_per_second: 1,
+            burst: 5,
+            trust_forwarded_for: false,
+        });
+        let now = Instant::now();
+        for i in 0..HTTP_RATE_LIMIT_MAX_TRACKED_IPS as u32 {
+            assert!(limiter.check(IpAddr::V4(Ipv4Addr::from(i)), now).is_ok());
+        }
+
+        // Every bucket is mid-burst, so none can be forgotten to make room
+        let newcomer = IpAddr::V4(Ipv4Addr::BROADCAST);
+        assert_eq!(limiter.check(newcomer, now), Err(1));
+        assert_eq!(
+            limiter.buckets.lock().unwrap().len(),
+            HTTP_RATE_LIMIT_MAX_TRACKED_IPS
+        );
+        assert!(limiter.check(IpAddr::V4(Ipv4Addr::from(0)), now).is_ok());
+
+        // Once they've refilled there's room again
+        assert!(
+            limiter
+                .check(newcomer, now + Duration::from_secs(10))
+                .is_ok()
+        );
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rapid_requests_from_one_ip_are_throttled() {
+        let app = router(1, 3, false).await;
+        for _ in 0..3 {
+            assert_eq!(
+                status_from(&app, "10.0.0.1:5000", None).await.0,
+                StatusCode::OK
+            );
+        }
+        assert_eq!(
+            status_from(&app, "10.0.0.1:5001", None).await,
+            (StatusCode::TOO_MANY_REQUESTS, Some("1".to_string()))
+        );
+        // Someone else isn't affected
+        assert_eq!(
+            status_from(&app, "10.0.0.2:5000", None).await.0,
+            StatusCode::OK
+        );
+        // And without trusting the proxy, a forged header changes nothing
+        assert_eq!(
+            status_from(&app, "10.0.0.1:5000", Some("192.0.2.7"))
+                .await
+                .0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_for_is_used_behind_a_trusted_proxy() {
+        let app = router(1, 2, true).await;
+        let proxy = "127.0.0.1:4000";
+        for _ in 0..2 {
+            assert_eq!(
+                status_from(&app, proxy, Some("192.0.2.7")).await.0,
+                StatusCode::OK
+            );
+        }
+        assert_eq!(
+            status_from(&app, proxy, Some("192.0.2.7")).await.0,
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        // The proxy appends the real peer, whatever the client claimed before it
+        assert_eq!(
+            status_from(&app, proxy, Some("192.0.2.7, 192.0.2.8"))
+                .await
+                .0,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_zero_rate_disables_the_limit() {
+        let app = router(0, 1, false).await;
+        for _ in 0..5 {
+            assert_eq!(
+                status_from(&app, "10.0.0.1:5000", None).await.0,
+                StatusCode::OK
+            );
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! One log line per HTTP request (websocket upgrades included), tagged with a request id that's
+//! also returned to the client in the `x-request-id` header.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue, header},
+    middleware::Next,
+    response::Response,
+};
+use std::time::Instant;
+use tracing::*;
+use uuid::Uuid;
+
+pub(crate) static REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Log method, path, status and latency at INFO once the response is ready. A request id the
+/// client (or a proxy in front) sent is kept so logs can be matched up, otherwise one is made up.
+pub(crate) async fn log_requests(request: Request, next: Next) -> Response {
+    let request_id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .filter(|id| !id.is_empty() && id.len() <= 128)
+        .cloned()
+        .unwrap_or_else(|| {
+            HeaderValue::from_str(&Uuid::new_v4().to_string())
+                .expect("a UUID is a valid header value")
+        });
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let websocket = request
+        .headers()
+        .get(header::UPGRADE)
+        .is_some_and(|upgrade| upgrade.as_bytes().eq_ignore_ascii_case(b"websocket"));
+    let id = request_id.to_str().unwrap_or("-").to_string();
+    let started = Instant::now();
+
+    // Anything logged while handling the request carries its id
+    let mut response = next
+        .run(request)
+        .instrument(info_span!("request", request_id = %id))
+        .await;
+
+    let status = response.status().as_u16();
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+    let kind = if websocket { "websocket upgrade " } else { "" };
+    info!(
+        request_id = %id,
+        %method,
+        path,
+        status,
+        latency_ms,
+        websocket,
+        "{kind}{method} {path} {status}"
+    );
+    response
+        .headers_mut()
+        .insert(REQUEST_ID_HEADER.clone(), request_id);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{json_request, test_state};
+    use serde_json::Value;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use tower::ServiceExt;
+
+    /// Log output collected in memory.
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_requests_are_logged_with_an_id() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_writer(move || writer.clone())
+            .with_ansi(false)
+            .finish();
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let app = crate::build_router(test_state()).await;
+        let response = app
+            .clone()
+            .oneshot(json_request("GET", "/health", Value::Null))
+            .await
+            .unwrap();
+        let request_id = response.headers()[&REQUEST_ID_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(Uuid::parse_str(&request_id).is_ok());
+
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let line = output
+            .lines()
+            .find(|line| line.contains(&request_id))
+            .expect("no log line for the request");
+        assert!(line.contains("INFO"));
+        assert!(line.contains("GET /health 200"));
+        assert!(line.contains("latency_ms"));
+
+        // An id the client sent is kept
+        let mut request = json_request("GET", "/nowhere", Value::Null);
+        request.headers_mut().insert(
+            REQUEST_ID_HEADER.clone(),
+            HeaderValue::from_static("abc-123"),
+        );
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.headers()[&REQUEST_ID_HEADER], "abc-123");
+        let output = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        assert!(output.contains("GET /nowhere 404"));
+    }
+}
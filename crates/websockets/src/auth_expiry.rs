@@ -0,0 +1,235 @@
+use crate::encode_message;
+use crate::protocol::{AUTH_EXPIRED_CLOSE_CODE, ServerMessage};
+use appstate::AppState;
+use axum::extract::ws::{CloseFrame, Message};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::*;
+use uuid::Uuid;
+
+/// Source of the current unix time, injectable so expiry logic can be tested without sleeping.
+pub trait Clock: Send + Sync {
+    fn now_unix(&self) -> u64;
+}
+
+/// Clock backed by the system time.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_unix(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .expect("Time went backwards")
+            .as_secs()
+    }
+}
+
+/// What a connection should do after checking its token expiry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExpiryAction {
+    Nothing,
+    Warn { seconds_remaining: u64 },
+    Expire,
+}
+
+/// Tracks the `exp` of the token a websocket connection authenticated with.
+pub struct AuthExpiryWatch {
+    exp: u64,
+    warning_window: u64,
+    warned: bool,
+}
+
+impl AuthExpiryWatch {
+    /// Create a watch for a token expiring at `exp` (unix seconds), warning `warning_window` ahead of time.
+    pub fn new(exp: u64, warning_window: Duration) -> Self {
+        Self {
+            exp,
+            warning_window: warning_window.as_secs(),
+            warned: false,
+        }
+    }
+
+    /// The client refreshed its token, track the new expiry and allow another warning.
+    pub fn refresh(&mut self, exp: u64) {
+        self.exp = exp;
+        self.warned = false;
+    }
+
+    /// Check the expiry against `now`. The warning is only emitted once per token.
+    pub fn poll(&mut self, now: u64) -> ExpiryAction {
+        if now >= self.exp {
+            return ExpiryAction::Expire;
+        }
+        let seconds_remaining = self.exp - now;
+        if !self.warned && seconds_remaining <= self.warning_window {
+            self.warned = true;
+            ExpiryAction::Warn { seconds_remaining }
+        } else {
+            ExpiryAction::Nothing
+        }
+    }
+}
+
+/// Periodically check a connection's token expiry, sending `ServerMessage::AuthExpiringSoon`
+/// when inside the warning window and closing with `AUTH_EXPIRED_CLOSE_CODE` once expired.
+/// Picks up tokens the client refreshed to through `state`'s record of the connection's expiry.
+/// Returns once the connection expired or its sender is gone, the caller should then drop it.
+/// Call this in a spawned task per authenticated websocket connection.
+pub async fn watch_auth_expiry(
+    state: AppState,
+    conn_id: Uuid,
+    mut watch: AuthExpiryWatch,
+    clock: Arc<dyn Clock>,
+    sender: UnboundedSender<Message>,
+    tick: Duration,
+) {
+    let mut interval = tokio::time::interval(tick);
+    loop {
+        interval.tick().await;
+        if let Some(exp) = state.token_expiry(&conn_id).await
+            && exp != watch.exp
+        {
+            watch.refresh(exp);
+        }
+        let action = watch.poll(clock.now_unix());
+        match action {
+            ExpiryAction::Nothing => {}
+            ExpiryAction::Warn { seconds_remaining } => {
+                let msg = ServerMessage::AuthExpiringSoon { seconds_remaining };
+                let encoding = state.connection_encoding(&conn_id).await;
+                match encode_message(&msg, encoding, state.compression_threshold) {
+                    Ok(frame) => {
+                        if sender.send(frame).is_err() {
+                            return;
+                        }
+                    }
+                    Err(e) => error!("Failed to encode AuthExpiringSoon: {e}"),
+                }
+            }
+            ExpiryAction::Expire => {
+                let _ = sender.send(Message::Close(Some(CloseFrame {
+                    code: AUTH_EXPIRED_CLOSE_CODE,
+                    reason: "auth expired".into(),
+                })));
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use tokio::sync::mpsc;
+
+    struct ManualClock(AtomicU64);
+
+    impl Clock for ManualClock {
+        fn now_unix(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_warning_fires_at_threshold() {
+        let mut watch = AuthExpiryWatch::new(1_000, Duration::from_secs(60));
+
+        // Outside the window nothing happens
+        assert_eq!(watch.poll(900), ExpiryAction::Nothing);
+        assert_eq!(watch.poll(939), ExpiryAction::Nothing);
+
+        // Exactly at the threshold the warning fires, and only once
+        assert_eq!(
+            watch.poll(940),
+            ExpiryAction::Warn {
+                seconds_remaining: 60
+            }
+        );
+        assert_eq!(watch.poll(950), ExpiryAction::Nothing);
+
+        // Refreshing re-arms the warning for the new token
+        watch.refresh(2_000);
+        assert_eq!(watch.poll(950), ExpiryAction::Nothing);
+        assert_eq!(
+            watch.poll(1_945),
+            ExpiryAction::Warn {
+                seconds_remaining: 55
+            }
+        );
+    }
+
+    #[test]
+    fn test_expire_fires_at_exp() {
+        let mut watch = AuthExpiryWatch::new(1_000, Duration::from_secs(60));
+        assert_ne!(watch.poll(999), ExpiryAction::Expire);
+        assert_eq!(watch.poll(1_000), ExpiryAction::Expire);
+        assert_eq!(watch.poll(1_001), ExpiryAction::Expire);
+    }
+
+    #[tokio::test]
+    async fn test_watch_task_warns_then_disconnects() {
+        let state = AppState::new_in_memory(config::Config::default());
+        let clock = Arc::new(ManualClock(AtomicU64::new(940)));
+        let watch = AuthExpiryWatch::new(1_000, Duration::from_secs(60));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let task = tokio::spawn(watch_auth_expiry(
+            state,
+            Uuid::nil(),
+            watch,
+            clock.clone(),
+            tx,
+            Duration::from_millis(5),
+        ));
+
+        match rx.recv().await {
+            Some(Message::Binary(raw)) => assert_eq!(
+                ServerMessage::from_msgpack(&raw).unwrap(),
+                ServerMessage::AuthExpiringSoon {
+                    seconds_remaining: 60
+                }
+            ),
+            other => panic!("expected warning, got {other:?}"),
+        }
+
+        clock.0.store(1_000, Ordering::SeqCst);
+        match rx.recv().await {
+            Some(Message::Close(Some(frame))) => assert_eq!(frame.code, AUTH_EXPIRED_CLOSE_CODE),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_watch_task_follows_refreshed_token() {
+        let state = AppState::new_in_memory(config::Config::default());
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx.clone()).await.unwrap();
+        state.set_token_expiry(&conn_id, 1_000).await;
+        let clock = Arc::new(ManualClock(AtomicU64::new(900)));
+        let task = tokio::spawn(watch_auth_expiry(
+            state.clone(),
+            conn_id,
+            AuthExpiryWatch::new(1_000, Duration::from_secs(60)),
+            clock.clone(),
+            tx,
+            Duration::from_millis(5),
+        ));
+
+        // The client refreshed before the old token ran out, so it stays connected past it
+        state.set_token_expiry(&conn_id, 2_000).await;
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        clock.0.store(1_500, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(rx.try_recv().is_err());
+        assert!(!task.is_finished());
+
+        clock.0.store(2_000, Ordering::SeqCst);
+        match rx.recv().await {
+            Some(Message::Close(Some(frame))) => assert_eq!(frame.code, AUTH_EXPIRED_CLOSE_CODE),
+            other => panic!("expected close frame, got {other:?}"),
+        }
+        task.await.unwrap();
+    }
+}
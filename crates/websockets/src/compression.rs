@@ -0,0 +1,121 @@
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use global_constants::MAX_DECOMPRESSED_FRAME_BYTES;
+use std::borrow::Cow;
+use std::fmt;
+use std::io::{self, Read, Write};
+
+/// First byte of a compressed binary frame, the rest is the deflated MessagePack message.
+/// A MessagePack map can't start with it, so uncompressed frames are plain MessagePack and
+/// clients that never compress don't need to know about the envelope at all.
+pub const COMPRESSED_FRAME_MARKER: u8 = 0x01;
+
+/// Why a compressed frame couldn't be unpacked.
+#[derive(Debug)]
+pub enum FrameError {
+    /// The deflate stream was corrupt
+    Decompress(io::Error),
+    /// The frame inflated to more than `MAX_DECOMPRESSED_FRAME_BYTES`
+    TooLarge { limit: u64 },
+}
+
+impl fmt::Display for FrameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrameError::Decompress(e) => write!(f, "corrupt compressed frame: {e}"),
+            FrameError::TooLarge { limit } => {
+                write!(f, "compressed frame inflates past {limit} bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// Wrap an encoded message for the wire, deflating it if it's at least `threshold` bytes
+/// (0 never compresses). Payloads that don't get smaller are sent as they are.
+pub fn compress_frame(payload: Vec<u8>, threshold: usize) -> Vec<u8> {
+    if threshold == 0 || payload.len() < threshold {
+        return payload;
+    }
+    let mut encoder = DeflateEncoder::new(vec![COMPRESSED_FRAME_MARKER], Compression::fast());
+    // Writing into a Vec can't really fail, but sending it raw beats panicking
+    let compressed = match encoder.write_all(&payload).and_then(|_| encoder.finish()) {
+        Ok(compressed) => compressed,
+        Err(_) => return payload,
+    };
+    if compressed.len() < payload.len() {
+        compressed
+    } else {
+        payload
+    }
+}
+
+/// The encoded message inside a binary frame, inflated if the frame was compressed.
+pub fn decompress_frame(raw: &[u8]) -> Result<Cow<'_, [u8]>, FrameError> {
+    let Some((&COMPRESSED_FRAME_MARKER, compressed)) = raw.split_first() else {
+        return Ok(Cow::Borrowed(raw));
+    };
+    let limit = MAX_DECOMPRESSED_FRAME_BYTES;
+    let mut payload = Vec::new();
+    // Read one byte past the limit to tell a frame that fits from one that doesn't
+    DeflateDecoder::new(compressed)
+        .take(limit + 1)
+        .read_to_end(&mut payload)
+        .map_err(FrameError::Decompress)?;
+    if payload.len() as u64 > limit {
+        return Err(FrameError::TooLarge { limit });
+    }
+    Ok(Cow::Owned(payload))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{EventChange, EventChangeRecord, ServerMessage};
+
+    #[test]
+    fn test_large_payload_round_trips_compressed() {
+        let changes = (1..=500)
+            .map(|seq| EventChangeRecord {
+                seq,
+                calendar_id: 3,
+                event_id: seq as i64,
+                change: EventChange::Updated,
+            })
+            .collect();
+        let msg = ServerMessage::Resumed {
+            latest_seq: 500,
+            changes,
+        };
+        let payload = msg.to_msgpack().unwrap();
+
+        let frame = compress_frame(payload.clone(), 1024);
+        assert_eq!(frame[0], COMPRESSED_FRAME_MARKER);
+        assert!(frame.len() < payload.len());
+        let inflated = decompress_frame(&frame).unwrap();
+        assert_eq!(ServerMessage::from_msgpack(&inflated).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_small_payload_stays_uncompressed() {
+        let payload = ServerMessage::Subscribed { calendar_id: 3 }
+            .to_msgpack()
+            .unwrap();
+        let frame = compress_frame(payload.clone(), 1024);
+        assert_eq!(frame, payload);
+        assert!(matches!(decompress_frame(&frame), Ok(Cow::Borrowed(raw)) if raw == payload));
+        // A threshold of 0 turns compression off
+        let large = vec![0x90; 4096];
+        assert_eq!(compress_frame(large.clone(), 0), large);
+    }
+
+    #[test]
+    fn test_corrupt_frame_is_rejected() {
+        assert!(matches!(
+            decompress_frame(&[COMPRESSED_FRAME_MARKER, 0xff, 0xff]),
+            Err(FrameError::Decompress(_))
+        ));
+    }
+}
@@ -0,0 +1,137 @@
+use crate::auth_expiry::Clock;
+use appstate::AppState;
+use axum::body::Bytes;
+use axum::extract::ws::{CloseFrame, Message, close_code};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc::UnboundedSender;
+use tracing::*;
+use uuid::Uuid;
+
+/// Tracks when a websocket connection was last heard from.
+/// Any message counts, not just pongs, a client busy sending is clearly alive.
+pub struct Heartbeat {
+    last_seen: AtomicU64,
+    idle_timeout: u64,
+    clock: Arc<dyn Clock>,
+}
+
+impl Heartbeat {
+    /// Start tracking a connection that was just heard from, idle once silent for `idle_timeout`.
+    pub fn new(idle_timeout: Duration, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            last_seen: AtomicU64::new(clock.now_unix()),
+            idle_timeout: idle_timeout.as_secs(),
+            clock,
+        }
+    }
+
+    /// The client sent something (a pong or any other message).
+    pub fn record_activity(&self) {
+        self.last_seen
+            .store(self.clock.now_unix(), Ordering::SeqCst);
+    }
+
+    /// Whether the client has been silent for the whole idle timeout.
+    pub fn is_idle(&self) -> bool {
+        let silent_for = self
+            .clock
+            .now_unix()
+            .saturating_sub(self.last_seen.load(Ordering::SeqCst));
+        silent_for >= self.idle_timeout
+    }
+}
+
+/// Ping the connection every `ping_interval` until it goes idle, then close it and remove it from
+/// `state`. Returns once the connection is reaped or its sender is gone.
+/// Call this in a spawned task per websocket connection.
+pub async fn run_heartbeat(
+    state: AppState,
+    conn_id: Uuid,
+    heartbeat: Arc<Heartbeat>,
+    sender: UnboundedSender<Message>,
+    ping_interval: Duration,
+) {
+    let mut interval = tokio::time::interval(ping_interval);
+    // The first tick completes immediately, the connection was only just opened
+    interval.tick().await;
+    loop {
+        interval.tick().await;
+        if heartbeat.is_idle() {
+            info!("WebSocket connection {conn_id} stopped answering pings, closing it");
+            let _ = sender.send(Message::Close(Some(CloseFrame {
+                code: close_code::AWAY,
+                reason: "idle timeout".into(),
+            })));
+            state.remove_connection(&conn_id).await;
+            return;
+        }
+        if sender.send(Message::Ping(Bytes::new())).is_err() {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    struct ManualClock(AtomicU64);
+
+    impl Clock for ManualClock {
+        fn now_unix(&self) -> u64 {
+            self.0.load(Ordering::SeqCst)
+        }
+    }
+
+    #[test]
+    fn test_idle_after_timeout_without_activity() {
+        let clock = Arc::new(ManualClock(AtomicU64::new(1_000)));
+        let heartbeat = Heartbeat::new(Duration::from_secs(90), clock.clone());
+        clock.0.store(1_089, Ordering::SeqCst);
+        assert!(!heartbeat.is_idle());
+
+        // Activity pushes the deadline back
+        heartbeat.record_activity();
+        clock.0.store(1_170, Ordering::SeqCst);
+        assert!(!heartbeat.is_idle());
+        clock.0.store(1_179, Ordering::SeqCst);
+        assert!(heartbeat.is_idle());
+    }
+
+    #[tokio::test]
+    async fn test_silent_connection_is_reaped() {
+        let state = AppState::new_in_memory(config::Config::default());
+        // A fake socket: the receiving end of the connection's channel, which never pongs
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx.clone()).await.unwrap();
+        let clock = Arc::new(ManualClock(AtomicU64::new(1_000)));
+        let heartbeat = Arc::new(Heartbeat::new(Duration::from_secs(90), clock.clone()));
+        let task = tokio::spawn(run_heartbeat(
+            state.clone(),
+            conn_id,
+            heartbeat,
+            tx,
+            Duration::from_millis(5),
+        ));
+
+        assert!(matches!(rx.recv().await, Some(Message::Ping(_))));
+        assert!(state.connections.lock().await.contains_key(&conn_id));
+
+        clock.0.store(1_090, Ordering::SeqCst);
+        loop {
+            match rx.recv().await {
+                Some(Message::Ping(_)) => continue,
+                Some(Message::Close(Some(frame))) => {
+                    assert_eq!(frame.code, close_code::AWAY);
+                    break;
+                }
+                other => panic!("expected a close frame, got {other:?}"),
+            }
+        }
+        task.await.unwrap();
+        assert!(!state.connections.lock().await.contains_key(&conn_id));
+    }
+}
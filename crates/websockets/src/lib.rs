@@ -1,66 +1,1037 @@
-use appstate::AppState;
+use appstate::{AppState, GlobalMessage, Outgoing, WireEncoding};
 use axum::body::Bytes;
-use axum::extract::ws::{Message, WebSocket};
-use rmp_serde::{from_slice, to_vec};
-use serde::{Deserialize, Serialize};
-use tokio::sync::broadcast;
+use axum::extract::ws::{CloseFrame, Message, close_code};
+use permissions::CalendarCapability;
+use std::sync::Arc;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::*;
+use uuid::Uuid;
 
-/// Example message structure for binary protocol
-#[derive(Debug, Serialize, Deserialize)]
-pub struct GenericBinaryMessage {
-    pub kind: String,
-    pub payload: Vec<u8>,
-}
+pub mod auth_expiry;
+pub mod compression;
+pub mod heartbeat;
+pub mod protocol;
+pub mod reminders;
+
+pub use auth_expiry::{AuthExpiryWatch, Clock, ExpiryAction, SystemClock, watch_auth_expiry};
+pub use compression::{COMPRESSED_FRAME_MARKER, FrameError, compress_frame, decompress_frame};
+pub use heartbeat::{Heartbeat, run_heartbeat};
+pub use protocol::{
+    AUTH_EXPIRED_CLOSE_CODE, ClientMessage, EventChange, EventChangeRecord, ServerMessage,
+};
+pub use reminders::{fire_reminder, run_reminders};
 
 /// Handles a binary websocket message, with access to AppState.
-/// - `socket`: The websocket connection to the client (for singular responses)
-/// - `state`: Shared AppState (for global messaging)
-/// - `raw`: The raw binary message received
-pub async fn handle_binary_message(socket: &mut WebSocket, state: AppState, raw: Vec<u8>) {
-    // Try to decode the message as MessagePack
-    let msg: Result<GenericBinaryMessage, _> = from_slice(&raw);
+/// - `state`: Shared AppState (for global messaging and the database)
+/// - `conn_id`: The connection the message arrived on
+/// - `raw`: The raw binary message received, a MessagePack `ClientMessage`, possibly compressed
+///
+/// Returns the reply to send back to the sender only, if there is one, as MessagePack
+/// (compressed if it's large).
+/// Messages beyond the connection's rate limit are dropped and answered with a `rate_limited` error.
+pub async fn handle_binary_message(state: &AppState, conn_id: Uuid, raw: &[u8]) -> Option<Message> {
+    let reply = handle_client_message(state, conn_id, WireEncoding::MessagePack, || {
+        let payload = decompress_frame(raw).map_err(|e| e.to_string())?;
+        ClientMessage::from_msgpack(&payload).map_err(|e| format!("Invalid MessagePack: {e}"))
+    })
+    .await?;
+    encode_message(
+        &reply,
+        WireEncoding::MessagePack,
+        state.compression_threshold,
+    )
+    .inspect_err(|e| error!("Failed to encode websocket reply: {e}"))
+    .ok()
+}
+
+/// Handles a text websocket message, the JSON fallback for clients that can't do MessagePack.
+/// Same as `handle_binary_message`, except `raw` is a JSON `ClientMessage` and the reply is JSON too.
+pub async fn handle_text_message(state: &AppState, conn_id: Uuid, raw: &str) -> Option<Message> {
+    let reply = handle_client_message(state, conn_id, WireEncoding::Json, || {
+        ClientMessage::from_json(raw).map_err(|e| format!("Invalid JSON: {e}"))
+    })
+    .await?;
+    encode_message(&reply, WireEncoding::Json, state.compression_threshold)
+        .inspect_err(|e| error!("Failed to encode websocket reply: {e}"))
+        .ok()
+}
+
+/// Encode a message for a client using `encoding`: a MessagePack binary frame (compressed from
+/// `compression_threshold` bytes up) or a JSON text frame.
+pub fn encode_message(
+    msg: &ServerMessage,
+    encoding: WireEncoding,
+    compression_threshold: usize,
+) -> Result<Message, String> {
+    match encoding {
+        WireEncoding::MessagePack => msg
+            .to_frame(compression_threshold)
+            .map(|raw| Message::Binary(Bytes::from(raw)))
+            .map_err(|e| e.to_string()),
+        WireEncoding::Json => msg
+            .to_json()
+            .map(|text| Message::Text(text.into()))
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Encode a message pushed to many clients both ways, so each gets it in the encoding it uses.
+pub fn encode_push(msg: &ServerMessage, compression_threshold: usize) -> Result<Outgoing, String> {
+    Ok(Outgoing::Encoded {
+        binary: encode_message(msg, WireEncoding::MessagePack, compression_threshold)?,
+        text: encode_message(msg, WireEncoding::Json, compression_threshold)?,
+    })
+}
+
+/// Handles the first message on a connection that has to log in before anything else.
+/// It must be a valid `ClientMessage::Authenticate` (in either encoding), which ties the
+/// connection to the token's user and returns the `Authenticated` reply, encoded like the
+/// message was. Anything else returns the policy violation close frame to end the connection with.
+pub async fn handle_handshake_message(
+    state: &AppState,
+    conn_id: Uuid,
+    msg: &Message,
+) -> Result<Message, CloseFrame> {
+    state.metrics.message_received();
+    let (token, encoding) = match msg {
+        Message::Binary(raw) => match decompress_frame(raw)
+            .ok()
+            .and_then(|payload| ClientMessage::from_msgpack(&payload).ok())
+        {
+            Some(ClientMessage::Authenticate { token }) => (token, WireEncoding::MessagePack),
+            _ => return Err(policy_violation("authentication required")),
+        },
+        Message::Text(raw) => match ClientMessage::from_json(raw) {
+            Ok(ClientMessage::Authenticate { token }) => (token, WireEncoding::Json),
+            _ => return Err(policy_violation("authentication required")),
+        },
+        _ => return Err(policy_violation("authentication required")),
+    };
+    let Some(user_id) = authenticate_connection(state, conn_id, token).await else {
+        return Err(policy_violation("invalid token"));
+    };
+    state.set_connection_encoding(&conn_id, encoding).await;
+    let reply = ServerMessage::Authenticated { user_id };
+    encode_message(&reply, encoding, state.compression_threshold).map_err(|e| {
+        error!("Failed to encode websocket reply: {e}");
+        CloseFrame {
+            code: close_code::ERROR,
+            reason: "internal error".into(),
+        }
+    })
+}
+
+/// Close frame for a client that broke the connection's rules.
+pub fn policy_violation(reason: &str) -> CloseFrame {
+    CloseFrame {
+        code: close_code::POLICY,
+        reason: reason.into(),
+    }
+}
+
+/// Validate an access token and tie the connection to its user, returning the user's id,
+/// or `None` if the token isn't valid (or its account is gone).
+pub async fn authenticate_connection(
+    state: &AppState,
+    conn_id: Uuid,
+    token: String,
+) -> Option<i64> {
+    let (user_id, expires_at) = validate_token(state, token).await?;
+    state.associate_user(&conn_id, user_id).await;
+    state.set_token_expiry(&conn_id, expires_at).await;
+    Some(user_id)
+}
+
+/// The user id and expiry (unix seconds) of a valid access token, `None` if it isn't one.
+async fn validate_token(state: &AppState, token: String) -> Option<(i64, u64)> {
+    let auth = state.auth.clone();
+    match tokio::task::spawn_blocking(move || auth.user_and_expiry_from_jwt(&token)).await {
+        Ok(Ok((user, expires_at))) => Some((user.id, expires_at)),
+        Ok(Err(e)) => {
+            debug!("Websocket authentication failed: {e}");
+            None
+        }
+        Err(e) => {
+            error!("Websocket authentication task failed: {e}");
+            None
+        }
+    }
+}
+
+/// Move an authenticated connection onto a fresh token for the same user, so it isn't
+/// closed when the old one expires.
+async fn refresh_connection_auth(state: &AppState, conn_id: Uuid, token: String) -> ServerMessage {
+    let Some(user_id) = state.connection_user(&conn_id).await else {
+        return ServerMessage::error("unauthorized", "authenticate first");
+    };
+    match validate_token(state, token).await {
+        Some((token_user, expires_at)) if token_user == user_id => {
+            state.set_token_expiry(&conn_id, expires_at).await;
+            ServerMessage::AuthRefreshed { expires_at }
+        }
+        Some(_) => ServerMessage::error("forbidden", "token belongs to another user"),
+        None => ServerMessage::error("unauthorized", "invalid token"),
+    }
+}
+
+/// Rate limit, decode and dispatch a client message, whatever its encoding. Pushes to the
+/// connection follow the encoding it last sent in.
+async fn handle_client_message(
+    state: &AppState,
+    conn_id: Uuid,
+    encoding: WireEncoding,
+    decode: impl FnOnce() -> Result<ClientMessage, String>,
+) -> Option<ServerMessage> {
+    state.metrics.message_received();
+    state.set_connection_encoding(&conn_id, encoding).await;
+    if !state.allow_message(&conn_id).await {
+        // Dropped undecoded, so a flood costs as little as possible
+        return Some(ServerMessage::error(
+            "rate_limited",
+            "Too many messages, slow down",
+        ));
+    }
+    match decode() {
+        Ok(msg) => dispatch_client_message(state, conn_id, msg).await,
+        Err(e) => Some(ServerMessage::error("invalid_message", e)),
+    }
+}
+
+/// Record that one of `calendar_id`'s events changed in the change log and tell the connections
+/// subscribed to it, returning how many were notified.
+pub async fn notify_event_changed(
+    state: &AppState,
+    calendar_id: i64,
+    event_id: i64,
+    change: EventChange,
+) -> usize {
+    // Held until sent, so subscribers see changes in `seq` order
+    let mut change_log = state.change_log.lock().await;
+    let record = change_log.record(calendar_id, event_id, change);
+    let msg = ServerMessage::EventChanged {
+        seq: record.seq,
+        calendar_id,
+        event_id,
+        change,
+    };
+    match encode_push(&msg, state.compression_threshold) {
+        Ok(push) => state.notify_calendar(calendar_id, push).await,
+        Err(e) => {
+            error!("Failed to encode EventChanged: {e}");
+            0
+        }
+    }
+}
+
+/// Run database work on a pooled connection on the blocking thread pool, so SQLite doesn't stall
+/// the async runtime. Errors come back as strings to log.
+pub(crate) async fn run_db<T: Send + 'static>(
+    state: &AppState,
+    f: impl FnOnce(&db::DatabaseConnection) -> Result<T, db::Error> + Send + 'static,
+) -> Result<T, String> {
+    let pool = state.database.clone();
+    tokio::task::spawn_blocking(move || {
+        let conn = pool.get().map_err(|e| e.to_string())?;
+        f(&conn).map_err(|e| e.to_string())
+    })
+    .await
+    .map_err(|e| format!("blocking task failed: {e}"))?
+}
+
+/// Check that a calendar exists and the connection's user holds `capability` on it, returning
+/// the error to reply with if not. Connections that haven't authenticated hold no capabilities.
+async fn require_calendar_capability(
+    state: &AppState,
+    conn_id: Uuid,
+    calendar_id: i64,
+    capability: CalendarCapability,
+) -> Result<(), ServerMessage> {
+    let Some(user_id) = state.connection_user(&conn_id).await else {
+        return Err(ServerMessage::error("unauthorized", "authenticate first"));
+    };
+    let exists = run_db(state, move |conn| conn.get_calendar_by_id(calendar_id)).await;
+    match exists {
+        Ok(Some(_)) => {}
+        Ok(None) => return Err(ServerMessage::error("not_found", "no such calendar")),
+        Err(e) => {
+            error!("Failed to look up calendar {calendar_id}: {e}");
+            return Err(ServerMessage::error("internal", "internal error"));
+        }
+    }
+    if !state
+        .permissions
+        .check_calendar_permission(user_id, calendar_id, capability)
+        .await
+    {
+        return Err(ServerMessage::error(
+            "forbidden",
+            "not allowed on this calendar",
+        ));
+    }
+    Ok(())
+}
+
+/// Act on a decoded client message, returning the reply for the sender (if any).
+pub async fn dispatch_client_message(
+    state: &AppState,
+    conn_id: Uuid,
+    msg: ClientMessage,
+) -> Option<ServerMessage> {
     match msg {
-        Ok(parsed) => {
-            // Example: echo back to sender if kind == "echo"
-            if parsed.kind == "echo" {
-                // Echo only to sender
-                if let Ok(reply) = to_vec(&parsed) {
-                    let _ = socket.send(Message::Binary(Bytes::from(reply))).await;
-                }
-            } else if parsed.kind == "broadcast" {
-                // Broadcast to all clients via AppState's global channel
-                let _ = state.send_global_message(raw.clone());
-            } else {
-                // Unknown kind, send error to sender only
-                let err_msg = GenericBinaryMessage {
-                    kind: "error".to_string(),
-                    payload: b"Unknown message kind".to_vec(),
-                };
-                if let Ok(reply) = to_vec(&err_msg) {
-                    let _ = socket.send(Message::Binary(Bytes::from(reply))).await;
+        ClientMessage::Authenticate { token } => {
+            Some(match authenticate_connection(state, conn_id, token).await {
+                Some(user_id) => ServerMessage::Authenticated { user_id },
+                None => ServerMessage::error("unauthorized", "invalid token"),
+            })
+        }
+        ClientMessage::RefreshAuth { token } => {
+            Some(refresh_connection_auth(state, conn_id, token).await)
+        }
+        ClientMessage::Echo { text } => Some(ServerMessage::Echo { text }),
+        ClientMessage::Broadcast { text } => {
+            // No receivers just means nobody else is connected
+            let _ = state.send_global_message(GlobalMessage::Broadcast { text });
+            None
+        }
+        ClientMessage::Subscribe { calendar_id } => Some(
+            match require_calendar_capability(state, conn_id, calendar_id, CalendarCapability::View)
+                .await
+            {
+                Ok(()) => {
+                    state.subscribe_to_calendar(conn_id, calendar_id).await;
+                    ServerMessage::Subscribed { calendar_id }
                 }
-            }
+                Err(reply) => reply,
+            },
+        ),
+        ClientMessage::Resume { last_seq } => {
+            let calendars = state.subscribed_calendars(&conn_id).await;
+            let change_log = state.change_log.lock().await;
+            Some(match change_log.since(last_seq) {
+                Some(changes) => ServerMessage::Resumed {
+                    latest_seq: change_log.latest_seq(),
+                    changes: changes
+                        .into_iter()
+                        .filter(|record| calendars.contains(&record.calendar_id))
+                        .collect(),
+                },
+                None => ServerMessage::error(
+                    "resume_gap",
+                    "changes since last_seq are no longer available, fetch calendars again",
+                ),
+            })
         }
-        Err(_) => {
-            // Failed to decode, send error to sender only
-            let err_msg = GenericBinaryMessage {
-                kind: "error".to_string(),
-                payload: b"Invalid MessagePack".to_vec(),
-            };
-            if let Ok(reply) = to_vec(&err_msg) {
-                let _ = socket.send(Message::Binary(Bytes::from(reply))).await;
+        ClientMessage::CreateEvent {
+            calendar_id,
+            title,
+            description,
+            start_time,
+            end_time,
+        } => {
+            if end_time < start_time {
+                return Some(ServerMessage::error(
+                    "bad_request",
+                    "end_time must not be before start_time",
+                ));
             }
+            if let Err(reply) = require_calendar_capability(
+                state,
+                conn_id,
+                calendar_id,
+                CalendarCapability::AddEvent,
+            )
+            .await
+            {
+                return Some(reply);
+            }
+            let result = run_db(state, move |conn| {
+                conn.insert_event(
+                    calendar_id,
+                    &title,
+                    description.as_deref(),
+                    start_time,
+                    end_time,
+                )
+            })
+            .await;
+            Some(match result {
+                Ok(event_id) => {
+                    notify_event_changed(state, calendar_id, event_id, EventChange::Created).await;
+                    ServerMessage::EventCreated {
+                        calendar_id,
+                        event_id,
+                    }
+                }
+                Err(e) => {
+                    error!("Failed to create event from websocket message: {e}");
+                    ServerMessage::error("internal", "internal error")
+                }
+            })
         }
     }
 }
 
-/// Listen for global messages and forward them to this client.
+/// Listen for global messages and forward them to a client as `ServerMessage`s, in the encoding
+/// its connection uses (MessagePack compressed from `state.compression_threshold` bytes up, or
+/// JSON). A client too slow to keep up misses the messages it lagged behind on rather than
+/// stalling everyone else. Returns once the connection or the global channel is gone.
 /// Call this in a spawned task per websocket connection.
 pub async fn forward_global_messages(
-    mut socket: WebSocket,
-    mut global_rx: broadcast::Receiver<Vec<u8>>,
+    state: AppState,
+    conn_id: Uuid,
+    mut global_rx: broadcast::Receiver<Arc<GlobalMessage>>,
 ) {
-    while let Ok(msg) = global_rx.recv().await {
-        let _ = socket.send(Message::Binary(Bytes::from(msg))).await;
+    loop {
+        match global_rx.recv().await {
+            Ok(msg) => {
+                let encoding = state.connection_encoding(&conn_id).await;
+                let msg = ServerMessage::from(msg.as_ref());
+                let frame = match encode_message(&msg, encoding, state.compression_threshold) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        error!("Failed to encode global message: {e}");
+                        continue;
+                    }
+                };
+                if !state.send_to_connection(&conn_id, frame).await {
+                    return;
+                }
+            }
+            Err(RecvError::Lagged(skipped)) => {
+                warn!(
+                    "WebSocket client fell behind the global channel, dropped {skipped} messages"
+                );
+            }
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
+
+/// Broadcast a `PermissionChanged` to every client whenever a user's permissions change through
+/// `state.permissions`, so UIs showing them can refresh. Call once at startup.
+pub fn broadcast_permission_changes(state: &AppState) {
+    let global_sender = state.global_sender.clone();
+    state.permissions.set_observer(Arc::new(move |user_id| {
+        // Nobody listening is fine, there is no one to refresh
+        let _ = global_sender.send(Arc::new(GlobalMessage::PermissionChanged { user_id }));
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+    use colorlab::Color;
+
+    fn test_state() -> AppState {
+        AppState::new_in_memory(config::Config::default())
+    }
+
+    /// Register a connection logged in as a new user who can view each of `calendars`, and add
+    /// events to them too if `can_add_event`.
+    async fn connect_as(
+        state: &AppState,
+        username: &str,
+        calendars: &[i64],
+        can_add_event: bool,
+    ) -> (Uuid, tokio::sync::mpsc::UnboundedReceiver<Message>) {
+        let user_id = {
+            let conn = state.database.get().unwrap();
+            conn.insert_user(username, "hash", "salt", &format!("{username}@example.com"))
+                .unwrap();
+            let user_id = conn.get_user_by_username(username).unwrap().unwrap().id;
+            for &calendar_id in calendars {
+                let capabilities = db::CalendarCapabilities {
+                    can_view: true,
+                    can_add_event,
+                    ..Default::default()
+                };
+                conn.set_calendar_permission(&capabilities.for_user(user_id, calendar_id))
+                    .unwrap();
+            }
+            user_id
+        };
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        state.associate_user(&conn_id, user_id).await;
+        (conn_id, rx)
+    }
+
+    fn create_event(calendar_id: i64, start_hour: u32, end_hour: u32) -> ClientMessage {
+        ClientMessage::CreateEvent {
+            calendar_id,
+            title: "Dentist".to_string(),
+            description: None,
+            start_time: Utc.with_ymd_and_hms(2025, 3, 14, start_hour, 0, 0).unwrap(),
+            end_time: Utc.with_ymd_and_hms(2025, 3, 14, end_hour, 0, 0).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_permission_changes_are_broadcast() {
+        let state = test_state();
+        broadcast_permission_changes(&state);
+        let mut global_rx = state.subscribe_global_messages();
+
+        state
+            .permissions
+            .assign_permission(5, permissions::Permission::Read)
+            .await;
+        assert_eq!(
+            *global_rx.recv().await.unwrap(),
+            GlobalMessage::PermissionChanged { user_id: 5 }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_echo_and_broadcast() {
+        let state = test_state();
+        let reply = dispatch_client_message(
+            &state,
+            Uuid::nil(),
+            ClientMessage::Echo {
+                text: "hi".to_string(),
+            },
+        )
+        .await;
+        assert_eq!(
+            reply,
+            Some(ServerMessage::Echo {
+                text: "hi".to_string()
+            })
+        );
+
+        let mut global = state.subscribe_global_messages();
+        let reply = dispatch_client_message(
+            &state,
+            Uuid::nil(),
+            ClientMessage::Broadcast {
+                text: "all".to_string(),
+            },
+        )
+        .await;
+        assert_eq!(reply, None);
+        assert_eq!(
+            *global.recv().await.unwrap(),
+            GlobalMessage::Broadcast {
+                text: "all".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_broadcast_flood_is_rate_limited() {
+        let mut config = config::Config::default();
+        config.websocket.messages_per_second = 1;
+        config.websocket.message_burst = 3;
+        let state = AppState::new_in_memory(config);
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        let global = state.subscribe_global_messages();
+
+        let raw = ClientMessage::Broadcast {
+            text: "spam".to_string(),
+        }
+        .to_msgpack()
+        .unwrap();
+        let mut limited = 0;
+        for _ in 0..10 {
+            if let Some(Message::Binary(reply)) = handle_binary_message(&state, conn_id, &raw).await
+            {
+                assert!(matches!(
+                    ServerMessage::from_msgpack(&reply).unwrap(),
+                    ServerMessage::Error { code, .. } if code == "rate_limited"
+                ));
+                limited += 1;
+            }
+        }
+        // Only the burst was fanned out
+        assert_eq!(limited, 7);
+        assert_eq!(global.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_lagging_client_skips_ahead() {
+        let state = test_state();
+        let (global_tx, global_rx) = broadcast::channel(2);
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        // Overflow the channel before the forwarder reads anything
+        for user_id in 0..5 {
+            global_tx
+                .send(Arc::new(GlobalMessage::PermissionChanged { user_id }))
+                .unwrap();
+        }
+        let task = tokio::spawn(forward_global_messages(state, conn_id, global_rx));
+
+        // The oldest messages are dropped, the newest still arrive
+        for expected in [3, 4] {
+            match rx.recv().await {
+                Some(Message::Binary(raw)) => assert_eq!(
+                    ServerMessage::from_msgpack(&raw).unwrap(),
+                    ServerMessage::PermissionChanged { user_id: expected }
+                ),
+                other => panic!("expected a binary message, got {other:?}"),
+            }
+        }
+        drop(global_tx);
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_global_messages_are_encoded_at_the_edge() {
+        let state = test_state();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        let task = tokio::spawn(forward_global_messages(
+            state.clone(),
+            conn_id,
+            state.subscribe_global_messages(),
+        ));
+
+        state
+            .send_global_message(GlobalMessage::Broadcast {
+                text: "all".to_string(),
+            })
+            .unwrap();
+        // One binary frame per message, already in the wire format
+        let Some(Message::Binary(raw)) = rx.recv().await else {
+            panic!("expected a binary message");
+        };
+        assert_eq!(
+            raw.as_ref(),
+            ServerMessage::Broadcast {
+                text: "all".to_string()
+            }
+            .to_msgpack()
+            .unwrap()
+        );
+        assert!(rx.try_recv().is_err());
+
+        // A client talking JSON gets JSON
+        state
+            .set_connection_encoding(&conn_id, WireEncoding::Json)
+            .await;
+        let broadcast = GlobalMessage::Broadcast {
+            text: "json".to_string(),
+        };
+        state.send_global_message(broadcast.clone()).unwrap();
+        let Some(Message::Text(text)) = rx.recv().await else {
+            panic!("expected a text message");
+        };
+        assert_eq!(
+            ServerMessage::from_json(&text).unwrap(),
+            ServerMessage::from(&broadcast)
+        );
+
+        // The forwarder stops once its connection is gone
+        state.remove_connection(&conn_id).await;
+        state.send_global_message(broadcast).unwrap();
+        task.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_create_event() {
+        let state = test_state();
+        let calendar_id = state
+            .database
+            .get()
+            .unwrap()
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let (conn_id, _rx) = connect_as(&state, "alice", &[calendar_id], true).await;
+
+        let Some(ServerMessage::EventCreated { event_id, .. }) =
+            dispatch_client_message(&state, conn_id, create_event(calendar_id, 9, 10)).await
+        else {
+            panic!("expected EventCreated");
+        };
+        let event = state
+            .database
+            .get()
+            .unwrap()
+            .get_event_by_id(event_id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.title, "Dentist");
+
+        assert!(matches!(
+            dispatch_client_message(&state, conn_id, create_event(calendar_id + 1, 9, 10)).await,
+            Some(ServerMessage::Error { code, .. }) if code == "not_found"
+        ));
+        assert!(matches!(
+            dispatch_client_message(&state, conn_id, create_event(calendar_id, 10, 9)).await,
+            Some(ServerMessage::Error { code, .. }) if code == "bad_request"
+        ));
+        // Anonymous connections can't write to any calendar
+        assert!(matches!(
+            dispatch_client_message(&state, Uuid::nil(), create_event(calendar_id, 9, 10)).await,
+            Some(ServerMessage::Error { code, .. }) if code == "unauthorized"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_auth_needs_a_token_for_the_same_user() {
+        let state = test_state();
+        let register = |username: &str| {
+            state
+                .auth
+                .register_user(
+                    username,
+                    "pw",
+                    None,
+                    &format!("{username}@x.com"),
+                    "127.0.0.1",
+                )
+                .unwrap()
+                .access_token
+                .unwrap()
+        };
+        let alice = register("alice");
+        let bob = register("bob");
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        let refresh = |token: &str| ClientMessage::RefreshAuth {
+            token: token.to_string(),
+        };
+
+        assert_eq!(
+            dispatch_client_message(&state, conn_id, refresh(&alice)).await,
+            Some(ServerMessage::error("unauthorized", "authenticate first"))
+        );
+        assert!(
+            authenticate_connection(&state, conn_id, alice.clone())
+                .await
+                .is_some()
+        );
+        let expires_at = state.token_expiry(&conn_id).await.unwrap();
+
+        assert_eq!(
+            dispatch_client_message(&state, conn_id, refresh(&bob)).await,
+            Some(ServerMessage::error(
+                "forbidden",
+                "token belongs to another user"
+            ))
+        );
+        assert_eq!(
+            dispatch_client_message(&state, conn_id, refresh("not-a-token")).await,
+            Some(ServerMessage::error("unauthorized", "invalid token"))
+        );
+        assert_eq!(
+            dispatch_client_message(&state, conn_id, refresh(&alice)).await,
+            Some(ServerMessage::AuthRefreshed { expires_at })
+        );
+        assert_eq!(state.token_expiry(&conn_id).await, Some(expires_at));
+    }
+
+    #[tokio::test]
+    async fn test_calendar_access_requires_permission() {
+        let state = test_state();
+        let calendar_id = state
+            .database
+            .get()
+            .unwrap()
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let (owner, _owner_rx) = connect_as(&state, "alice", &[calendar_id], true).await;
+        let (viewer, _viewer_rx) = connect_as(&state, "bob", &[calendar_id], false).await;
+        let (stranger, mut stranger_rx) = connect_as(&state, "mallory", &[], false).await;
+
+        let subscribe = ClientMessage::Subscribe { calendar_id };
+        assert!(matches!(
+            dispatch_client_message(&state, stranger, subscribe.clone()).await,
+            Some(ServerMessage::Error { code, .. }) if code == "forbidden"
+        ));
+        assert!(matches!(
+            dispatch_client_message(&state, stranger, create_event(calendar_id, 9, 10)).await,
+            Some(ServerMessage::Error { code, .. }) if code == "forbidden"
+        ));
+        // Viewing doesn't allow adding events
+        assert_eq!(
+            dispatch_client_message(&state, viewer, subscribe).await,
+            Some(ServerMessage::Subscribed { calendar_id })
+        );
+        assert!(matches!(
+            dispatch_client_message(&state, viewer, create_event(calendar_id, 9, 10)).await,
+            Some(ServerMessage::Error { code, .. }) if code == "forbidden"
+        ));
+
+        // Nothing about the calendar reaches the refused connection
+        assert!(matches!(
+            dispatch_client_message(&state, owner, create_event(calendar_id, 9, 10)).await,
+            Some(ServerMessage::EventCreated { .. })
+        ));
+        assert!(stranger_rx.try_recv().is_err());
+        let Some(ServerMessage::Resumed { changes, .. }) =
+            dispatch_client_message(&state, stranger, ClientMessage::Resume { last_seq: 0 }).await
+        else {
+            panic!("expected Resumed");
+        };
+        assert!(changes.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_subscriber_is_notified_of_created_events() {
+        let state = test_state();
+        let (family, work) = {
+            let conn = state.database.get().unwrap();
+            (
+                conn.insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+                    .unwrap(),
+                conn.insert_calendar("Work", Color::from_rgb8(4, 5, 6))
+                    .unwrap(),
+            )
+        };
+        let (family_conn, mut rx_family) = connect_as(&state, "alice", &[family], false).await;
+        let (work_conn, mut rx_work) = connect_as(&state, "bob", &[family, work], true).await;
+
+        let subscribe = ClientMessage::Subscribe {
+            calendar_id: family,
+        }
+        .to_msgpack()
+        .unwrap();
+        let Some(Message::Binary(reply)) =
+            handle_binary_message(&state, family_conn, &subscribe).await
+        else {
+            panic!("expected a binary reply");
+        };
+        assert_eq!(
+            ServerMessage::from_msgpack(&reply).unwrap(),
+            ServerMessage::Subscribed {
+                calendar_id: family
+            }
+        );
+        dispatch_client_message(
+            &state,
+            work_conn,
+            ClientMessage::Subscribe { calendar_id: work },
+        )
+        .await;
+
+        let Some(ServerMessage::EventCreated { event_id, .. }) =
+            dispatch_client_message(&state, work_conn, create_event(family, 9, 10)).await
+        else {
+            panic!("expected EventCreated");
+        };
+        match rx_family.try_recv() {
+            Ok(Message::Binary(raw)) => assert_eq!(
+                ServerMessage::from_msgpack(&raw).unwrap(),
+                ServerMessage::EventChanged {
+                    seq: 1,
+                    calendar_id: family,
+                    event_id,
+                    change: EventChange::Created
+                }
+            ),
+            other => panic!("expected EventChanged, got {other:?}"),
+        }
+        assert!(rx_work.try_recv().is_err());
+
+        assert!(matches!(
+            dispatch_client_message(&state, work_conn, ClientMessage::Subscribe { calendar_id: 999 }).await,
+            Some(ServerMessage::Error { code, .. }) if code == "not_found"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_json_subscribe_matches_msgpack() {
+        let state = test_state();
+        let calendar_id = state
+            .database
+            .get()
+            .unwrap()
+            .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+            .unwrap();
+        let (binary_conn, mut rx_binary) = connect_as(&state, "alice", &[calendar_id], false).await;
+        let (text_conn, mut rx_text) = connect_as(&state, "bob", &[calendar_id], false).await;
+
+        let subscribe = ClientMessage::Subscribe { calendar_id };
+        let Some(Message::Binary(binary_reply)) =
+            handle_binary_message(&state, binary_conn, &subscribe.to_msgpack().unwrap()).await
+        else {
+            panic!("expected a binary reply");
+        };
+        let json = format!(r#"{{"type":"Subscribe","calendar_id":{calendar_id}}}"#);
+        let Some(Message::Text(text_reply)) = handle_text_message(&state, text_conn, &json).await
+        else {
+            panic!("expected a text reply");
+        };
+        assert_eq!(
+            ServerMessage::from_json(&text_reply).unwrap(),
+            ServerMessage::from_msgpack(&binary_reply).unwrap()
+        );
+        assert_eq!(
+            ServerMessage::from_json(&text_reply).unwrap(),
+            ServerMessage::Subscribed { calendar_id }
+        );
+        // Both connections ended up subscribed, and get the change in their own encoding
+        assert_eq!(
+            notify_event_changed(&state, calendar_id, 1, EventChange::Updated).await,
+            2
+        );
+        let changed = ServerMessage::EventChanged {
+            seq: 1,
+            calendar_id,
+            event_id: 1,
+            change: EventChange::Updated,
+        };
+        let Ok(Message::Text(text_push)) = rx_text.try_recv() else {
+            panic!("expected the JSON client to get a text message");
+        };
+        assert_eq!(ServerMessage::from_json(&text_push).unwrap(), changed);
+        let Ok(Message::Binary(binary_push)) = rx_binary.try_recv() else {
+            panic!("expected the MessagePack client to get a binary message");
+        };
+        assert_eq!(ServerMessage::from_msgpack(&binary_push).unwrap(), changed);
+
+        // Errors come back as JSON as well
+        let Some(Message::Text(error)) = handle_text_message(&state, text_conn, "{not json").await
+        else {
+            panic!("expected a text reply");
+        };
+        assert!(matches!(
+            ServerMessage::from_json(&error).unwrap(),
+            ServerMessage::Error { code, .. } if code == "invalid_message"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_resume_returns_exactly_the_missed_changes() {
+        let state = test_state();
+        let (family, work) = {
+            let conn = state.database.get().unwrap();
+            (
+                conn.insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+                    .unwrap(),
+                conn.insert_calendar("Work", Color::from_rgb8(4, 5, 6))
+                    .unwrap(),
+            )
+        };
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        state.subscribe_to_calendar(conn_id, family).await;
+        notify_event_changed(&state, family, 1, EventChange::Created).await;
+        let Ok(Message::Binary(raw)) = rx.try_recv() else {
+            panic!("expected EventChanged");
+        };
+        let Ok(ServerMessage::EventChanged { seq: last_seq, .. }) =
+            ServerMessage::from_msgpack(&raw)
+        else {
+            panic!("expected EventChanged");
+        };
+
+        // The client drops, misses two family changes and one to a calendar it doesn't follow
+        state.remove_connection(&conn_id).await;
+        notify_event_changed(&state, family, 1, EventChange::Updated).await;
+        notify_event_changed(&state, work, 2, EventChange::Created).await;
+        notify_event_changed(&state, family, 3, EventChange::Created).await;
+
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+        state.subscribe_to_calendar(conn_id, family).await;
+        let reply =
+            dispatch_client_message(&state, conn_id, ClientMessage::Resume { last_seq }).await;
+        let Some(ServerMessage::Resumed {
+            latest_seq,
+            changes,
+        }) = reply
+        else {
+            panic!("expected Resumed, got {reply:?}");
+        };
+        assert_eq!(latest_seq, last_seq + 3);
+        assert_eq!(
+            changes,
+            vec![
+                EventChangeRecord {
+                    seq: last_seq + 1,
+                    calendar_id: family,
+                    event_id: 1,
+                    change: EventChange::Updated,
+                },
+                EventChangeRecord {
+                    seq: last_seq + 3,
+                    calendar_id: family,
+                    event_id: 3,
+                    change: EventChange::Created,
+                },
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_resume_past_the_change_log_is_a_gap() {
+        let mut config = config::Config::default();
+        config.websocket.change_log_capacity = 2;
+        let state = AppState::new_in_memory(config);
+        for event_id in 1..=3 {
+            notify_event_changed(&state, 1, event_id, EventChange::Updated).await;
+        }
+        assert!(matches!(
+            dispatch_client_message(&state, Uuid::nil(), ClientMessage::Resume { last_seq: 0 }).await,
+            Some(ServerMessage::Error { code, .. }) if code == "resume_gap"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_compressed_messages_are_understood_and_answered_compressed() {
+        let state = test_state();
+        let text = "all day ".repeat(state.compression_threshold);
+        let echo = ClientMessage::Echo { text: text.clone() };
+        let frame = compress_frame(echo.to_msgpack().unwrap(), 1);
+        assert_eq!(frame[0], COMPRESSED_FRAME_MARKER);
+
+        let Some(Message::Binary(reply)) = handle_binary_message(&state, Uuid::nil(), &frame).await
+        else {
+            panic!("expected a binary reply");
+        };
+        assert_eq!(reply[0], COMPRESSED_FRAME_MARKER);
+        assert_eq!(
+            ServerMessage::from_msgpack(&decompress_frame(&reply).unwrap()).unwrap(),
+            ServerMessage::Echo { text }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_handshake_requires_a_valid_token_first() {
+        let state = test_state();
+        let token = state
+            .auth
+            .register_user("alice", "pw", None, "a@x.com", "127.0.0.1")
+            .unwrap()
+            .access_token
+            .unwrap();
+        let user_id = state.auth.user_from_jwt(&token).unwrap().id;
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let conn_id = state.register_connection(tx).await.unwrap();
+
+        let echo = ClientMessage::Echo {
+            text: "hi".to_string(),
+        };
+        let refused = handle_handshake_message(
+            &state,
+            conn_id,
+            &Message::Binary(Bytes::from(echo.to_msgpack().unwrap())),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(refused.code, close_code::POLICY);
+        let forged = r#"{"type":"Authenticate","token":"not-a-token"}"#;
+        let refused = handle_handshake_message(&state, conn_id, &Message::Text(forged.into()))
+            .await
+            .unwrap_err();
+        assert_eq!(refused.code, close_code::POLICY);
+        assert!(state.online_users().await.is_empty());
+
+        let login = format!(r#"{{"type":"Authenticate","token":"{token}"}}"#);
+        let Ok(Message::Text(reply)) =
+            handle_handshake_message(&state, conn_id, &Message::Text(login.into())).await
+        else {
+            panic!("expected a text reply");
+        };
+        assert_eq!(
+            ServerMessage::from_json(&reply).unwrap(),
+            ServerMessage::Authenticated { user_id }
+        );
+        assert_eq!(state.online_users().await, vec![user_id]);
     }
 }
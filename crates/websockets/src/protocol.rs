@@ -0,0 +1,279 @@
+pub use appstate::{EventChange, EventChangeRecord};
+
+use crate::compression::compress_frame;
+use appstate::GlobalMessage;
+use chrono::{DateTime, Utc};
+use rmp_serde::{from_slice, to_vec_named};
+use serde::{Deserialize, Serialize};
+
+/// Close code sent when a connection's auth token expires without being refreshed.
+/// Lives in the 4000-4999 range reserved for application use by RFC 6455.
+pub const AUTH_EXPIRED_CLOSE_CODE: u16 = 4001;
+
+/// Messages websocket clients send to the server.
+/// Encoded as MessagePack maps in binary frames or JSON objects in text frames,
+/// the variant name is in the `type` field.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ClientMessage {
+    /// Log the connection in with an access token. When login is required this has to be
+    /// the first message, unless the token came with the upgrade request.
+    Authenticate { token: String },
+    /// Swap the token an authenticated connection logged in with for a fresh one before it
+    /// expires (see `ServerMessage::AuthExpiringSoon`). It must belong to the same user.
+    RefreshAuth { token: String },
+    /// Reply to the sender with the same text.
+    Echo { text: String },
+    /// Send the text to every connected client.
+    Broadcast { text: String },
+    /// Ask for changes to a calendar's events.
+    Subscribe { calendar_id: i64 },
+    /// After reconnecting and subscribing again, ask for the changes to subscribed calendars
+    /// since the `seq` of the last `EventChanged` the client saw (0 if it saw none).
+    Resume { last_seq: u64 },
+    /// Add an event to a calendar.
+    CreateEvent {
+        calendar_id: i64,
+        title: String,
+        #[serde(default)]
+        description: Option<String>,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    },
+}
+
+/// Messages the server pushes to websocket clients.
+/// Encoded as MessagePack maps so field names survive for non-Rust clients.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ServerMessage {
+    /// Reply to `ClientMessage::Authenticate`, the connection now acts as this user.
+    Authenticated { user_id: i64 },
+    /// The connection's auth token is about to expire, the client should refresh it.
+    AuthExpiringSoon { seconds_remaining: u64 },
+    /// Reply to `ClientMessage::RefreshAuth`, the connection now lasts until `expires_at`
+    /// (unix seconds).
+    AuthRefreshed { expires_at: u64 },
+    /// Reply to `ClientMessage::Echo`.
+    Echo { text: String },
+    /// Text another client broadcast.
+    Broadcast { text: String },
+    /// The client is now subscribed to the calendar.
+    Subscribed { calendar_id: i64 },
+    /// An event in a calendar the client subscribed to was created, updated or deleted.
+    /// `seq` numbers every change on the server, for `ClientMessage::Resume`.
+    EventChanged {
+        seq: u64,
+        calendar_id: i64,
+        event_id: i64,
+        change: EventChange,
+    },
+    /// Reply to `ClientMessage::Resume` with the changes the client missed, oldest first.
+    /// `latest_seq` is where to resume from next time, even if none of the changes were for it.
+    Resumed {
+        latest_seq: u64,
+        changes: Vec<EventChangeRecord>,
+    },
+    /// Reply to `ClientMessage::CreateEvent` with the new event's id.
+    EventCreated { calendar_id: i64, event_id: i64 },
+    /// A reminder for an event in a calendar the user can view fell due.
+    Reminder {
+        calendar_id: i64,
+        event_id: i64,
+        title: String,
+        start_time: DateTime<Utc>,
+        /// How long before the event the reminder was set for
+        offset_seconds: i64,
+    },
+    /// A user's permissions changed, clients showing them should fetch them again.
+    PermissionChanged { user_id: i64 },
+    /// A user came online (their first connection opened) or went offline (their last one closed).
+    PresenceChanged { user_id: i64, online: bool },
+    /// The client's message couldn't be handled. `code` is machine readable (e.g. `invalid_message`),
+    /// `message` is for humans.
+    Error { code: String, message: String },
+}
+
+impl ServerMessage {
+    /// Build an `Error` message.
+    pub fn error(code: &str, message: impl Into<String>) -> Self {
+        ServerMessage::Error {
+            code: code.to_string(),
+            message: message.into(),
+        }
+    }
+
+    /// Encode this message as MessagePack.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        to_vec_named(self)
+    }
+
+    /// Decode a message from MessagePack.
+    pub fn from_msgpack(raw: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        from_slice(raw)
+    }
+
+    /// Encode this message as JSON, for clients using text frames.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Decode a message from JSON.
+    pub fn from_json(raw: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(raw)
+    }
+
+    /// Encode this message as a binary frame: MessagePack, compressed if it's at least
+    /// `compression_threshold` bytes (0 never compresses).
+    pub fn to_frame(
+        &self,
+        compression_threshold: usize,
+    ) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        Ok(compress_frame(self.to_msgpack()?, compression_threshold))
+    }
+}
+
+impl From<&GlobalMessage> for ServerMessage {
+    fn from(msg: &GlobalMessage) -> Self {
+        match msg {
+            GlobalMessage::Broadcast { text } => ServerMessage::Broadcast { text: text.clone() },
+            GlobalMessage::PermissionChanged { user_id } => {
+                ServerMessage::PermissionChanged { user_id: *user_id }
+            }
+            GlobalMessage::PresenceChanged { user_id, online } => ServerMessage::PresenceChanged {
+                user_id: *user_id,
+                online: *online,
+            },
+        }
+    }
+}
+
+impl ClientMessage {
+    /// Encode this message as MessagePack.
+    pub fn to_msgpack(&self) -> Result<Vec<u8>, rmp_serde::encode::Error> {
+        to_vec_named(self)
+    }
+
+    /// Decode a message from MessagePack.
+    pub fn from_msgpack(raw: &[u8]) -> Result<Self, rmp_serde::decode::Error> {
+        from_slice(raw)
+    }
+
+    /// Encode this message as JSON, for clients using text frames.
+    pub fn to_json(&self) -> Result<String, serde_json::Error> {
+        serde_json::to_string(self)
+    }
+
+    /// Decode a message from JSON.
+    pub fn from_json(raw: &str) -> Result<Self, serde_json::Error> {
+        serde_json::from_str(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auth_expiring_soon_round_trip() {
+        let msg = ServerMessage::AuthExpiringSoon {
+            seconds_remaining: 42,
+        };
+        let raw = msg.to_msgpack().unwrap();
+        assert_eq!(ServerMessage::from_msgpack(&raw).unwrap(), msg);
+    }
+
+    #[test]
+    fn test_client_messages_round_trip() {
+        let start = Utc::now();
+        let messages = [
+            ClientMessage::Echo {
+                text: "hello".to_string(),
+            },
+            ClientMessage::Broadcast {
+                text: "hi all".to_string(),
+            },
+            ClientMessage::Subscribe { calendar_id: 3 },
+            ClientMessage::RefreshAuth {
+                token: "jwt".to_string(),
+            },
+            ClientMessage::CreateEvent {
+                calendar_id: 3,
+                title: "Dentist".to_string(),
+                description: Some("Bring forms".to_string()),
+                start_time: start,
+                end_time: start + chrono::Duration::hours(1),
+            },
+        ];
+        for msg in messages {
+            let raw = msg.to_msgpack().unwrap();
+            assert_eq!(ClientMessage::from_msgpack(&raw).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn test_server_messages_round_trip() {
+        let messages = [
+            ServerMessage::Echo {
+                text: "hello".to_string(),
+            },
+            ServerMessage::Broadcast {
+                text: "hi all".to_string(),
+            },
+            ServerMessage::Authenticated { user_id: 42 },
+            ServerMessage::AuthRefreshed {
+                expires_at: 1_700_000_000,
+            },
+            ServerMessage::Subscribed { calendar_id: 3 },
+            ServerMessage::EventCreated {
+                calendar_id: 3,
+                event_id: 9,
+            },
+            ServerMessage::EventChanged {
+                seq: 5,
+                calendar_id: 3,
+                event_id: 9,
+                change: EventChange::Deleted,
+            },
+            ServerMessage::Resumed {
+                latest_seq: 5,
+                changes: vec![EventChangeRecord {
+                    seq: 5,
+                    calendar_id: 3,
+                    event_id: 9,
+                    change: EventChange::Updated,
+                }],
+            },
+            ServerMessage::Reminder {
+                calendar_id: 3,
+                event_id: 9,
+                title: "Dentist".to_string(),
+                start_time: Utc::now(),
+                offset_seconds: 600,
+            },
+            ServerMessage::PermissionChanged { user_id: 42 },
+            ServerMessage::PresenceChanged {
+                user_id: 42,
+                online: true,
+            },
+            ServerMessage::error("not_found", "no such calendar"),
+        ];
+        for msg in messages {
+            let raw = msg.to_msgpack().unwrap();
+            assert_eq!(ServerMessage::from_msgpack(&raw).unwrap(), msg);
+        }
+    }
+
+    #[test]
+    fn test_type_tag_is_the_variant_name() {
+        #[derive(Deserialize)]
+        struct Tagged {
+            r#type: String,
+        }
+        let raw = ClientMessage::Subscribe { calendar_id: 1 }
+            .to_msgpack()
+            .unwrap();
+        let tagged: Tagged = from_slice(&raw).unwrap();
+        assert_eq!(tagged.r#type, "Subscribe");
+    }
+}
@@ -0,0 +1,194 @@
+use crate::protocol::ServerMessage;
+use crate::{encode_push, run_db};
+use appstate::AppState;
+use chrono::{DateTime, Utc};
+use db::{DatabaseConnection, DueReminder, Event, Reminder, reminder_due_at};
+use std::time::Duration;
+use tracing::*;
+
+/// Every `REMINDER_TICK_SECONDS`, look up the reminders falling due before the next tick and
+/// schedule each one, until the app shuts down. Reminders that fell due while the server was
+/// down are not sent late. Spawn once at startup via `spawn_tasks!`.
+pub async fn run_reminders(state: AppState) {
+    let tick = Duration::from_secs(global_constants::REMINDER_TICK_SECONDS);
+    let mut interval = tokio::time::interval(tick);
+    // Each scan covers the time after the previous one, so no reminder is scheduled twice
+    let mut scanned_until = Utc::now();
+    loop {
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => return,
+            _ = interval.tick() => {}
+        }
+        let window_end = Utc::now() + tick;
+        let due = run_db(&state, move |conn| {
+            conn.list_due_reminders(scanned_until, window_end)
+        })
+        .await;
+        match due {
+            Ok(due) => {
+                for reminder in due {
+                    schedule_reminder(state.clone(), reminder);
+                }
+                scanned_until = window_end;
+            }
+            // The same window is scanned again next tick
+            Err(e) => error!("Failed to look up due reminders: {e}"),
+        }
+    }
+}
+
+/// Wait until a reminder is due, then fire it.
+fn schedule_reminder(state: AppState, due: DueReminder) {
+    tokio::spawn(async move {
+        let wait = (due.due_at - Utc::now()).to_std().unwrap_or_default();
+        tokio::select! {
+            _ = state.shutdown_token.cancelled() => return,
+            _ = tokio::time::sleep(wait) => {}
+        }
+        fire_reminder(&state, due.reminder.id, due.due_at).await;
+    });
+}
+
+/// Send a reminder scheduled for `due_at` to the connections of every user who can view its
+/// calendar, returning how many connections it reached.
+/// The reminder and its event are read again first, so nothing is sent if either was deleted
+/// or the event moved and the reminder is no longer due at `due_at`.
+pub async fn fire_reminder(state: &AppState, reminder_id: i64, due_at: DateTime<Utc>) -> usize {
+    let lookup = run_db(state, move |conn| {
+        reminder_recipients(conn, reminder_id, due_at)
+    })
+    .await;
+    let (reminder, event, users) = match lookup {
+        Ok(Some(found)) => found,
+        Ok(None) => {
+            debug!("Reminder {reminder_id} is no longer due at {due_at}, not sending it");
+            return 0;
+        }
+        Err(e) => {
+            error!("Failed to look up reminder {reminder_id}: {e}");
+            return 0;
+        }
+    };
+    let msg = ServerMessage::Reminder {
+        calendar_id: event.calendar_id,
+        event_id: event.id,
+        title: event.title,
+        start_time: event.start_time,
+        offset_seconds: reminder.offset_seconds,
+    };
+    let push = match encode_push(&msg, state.compression_threshold) {
+        Ok(push) => push,
+        Err(e) => {
+            error!("Failed to encode Reminder: {e}");
+            return 0;
+        }
+    };
+    let mut reached = 0;
+    for user_id in users {
+        reached += state.send_to_user(user_id, push.clone()).await;
+    }
+    reached
+}
+
+/// The reminder, its event and the users who can view the event's calendar,
+/// or `None` if the reminder isn't due at `due_at` anymore.
+fn reminder_recipients(
+    conn: &DatabaseConnection,
+    reminder_id: i64,
+    due_at: DateTime<Utc>,
+) -> Result<Option<(Reminder, Event, Vec<i64>)>, db::Error> {
+    let Some(reminder) = conn.get_reminder_by_id(reminder_id)? else {
+        return Ok(None);
+    };
+    let Some(event) = conn.get_event_by_id(reminder.event_id)? else {
+        return Ok(None);
+    };
+    if reminder_due_at(&event, reminder.offset_seconds) != due_at {
+        return Ok(None);
+    }
+    let users = conn
+        .list_users_for_calendar(event.calendar_id)?
+        .into_iter()
+        .filter(|perm| perm.can_view)
+        .map(|perm| perm.user_id)
+        .collect();
+    Ok(Some((reminder, event, users)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::ws::Message;
+    use chrono::TimeZone;
+    use colorlab::Color;
+    use db::{CalendarCapabilities, REMINDER_METHOD_NOTIFICATION};
+
+    #[tokio::test]
+    async fn test_reminder_reaches_calendar_viewers() {
+        let state = AppState::new_in_memory(config::Config::default());
+        let start = Utc.with_ymd_and_hms(2025, 3, 14, 9, 0, 0).unwrap();
+        let due_at = Utc.with_ymd_and_hms(2025, 3, 14, 8, 50, 0).unwrap();
+        let (viewer, outsider, event_id, reminder_id) = {
+            let conn = state.database.get().unwrap();
+            let calendar_id = conn
+                .insert_calendar("Family", Color::from_rgb8(1, 2, 3))
+                .unwrap();
+            let mut users = ["alice", "bob"].map(|name| {
+                conn.insert_user(name, "hash", "salt", &format!("{name}@x.com"))
+                    .unwrap();
+                conn.get_user_by_username(name).unwrap().unwrap().id
+            });
+            users.sort();
+            let viewer_caps = CalendarCapabilities {
+                can_view: true,
+                ..Default::default()
+            };
+            conn.set_calendar_permission(&viewer_caps.for_user(users[0], calendar_id))
+                .unwrap();
+            let event_id = conn
+                .insert_event(calendar_id, "Dentist", None, start, start)
+                .unwrap();
+            let reminder_id = conn
+                .set_reminder(event_id, 600, REMINDER_METHOD_NOTIFICATION)
+                .unwrap();
+            (users[0], users[1], event_id, reminder_id)
+        };
+        let (tx_viewer, mut rx_viewer) = tokio::sync::mpsc::unbounded_channel();
+        let (tx_outsider, mut rx_outsider) = tokio::sync::mpsc::unbounded_channel();
+        let viewer_conn = state.register_connection(tx_viewer).await.unwrap();
+        let outsider_conn = state.register_connection(tx_outsider).await.unwrap();
+        state.associate_user(&viewer_conn, viewer).await;
+        state.associate_user(&outsider_conn, outsider).await;
+
+        assert_eq!(fire_reminder(&state, reminder_id, due_at).await, 1);
+        match rx_viewer.try_recv() {
+            Ok(Message::Binary(raw)) => assert!(matches!(
+                ServerMessage::from_msgpack(&raw).unwrap(),
+                ServerMessage::Reminder { event_id: id, offset_seconds: 600, .. } if id == event_id
+            )),
+            other => panic!("expected a Reminder, got {other:?}"),
+        }
+        assert!(rx_outsider.try_recv().is_err());
+
+        // Once the event moves the old due time is stale
+        let moved = start + chrono::Duration::hours(1);
+        state
+            .database
+            .get()
+            .unwrap()
+            .update_event(event_id, "Dentist", None, moved, moved)
+            .unwrap();
+        assert_eq!(fire_reminder(&state, reminder_id, due_at).await, 0);
+        let new_due = moved - chrono::Duration::minutes(10);
+        assert_eq!(fire_reminder(&state, reminder_id, new_due).await, 1);
+
+        // Deleted events aren't reminded of
+        state
+            .database
+            .get()
+            .unwrap()
+            .delete_event_by_id(event_id)
+            .unwrap();
+        assert_eq!(fire_reminder(&state, reminder_id, new_due).await, 0);
+    }
+}